@@ -0,0 +1,256 @@
+use crate::api;
+use crate::atomic;
+use crate::env;
+use crate::events;
+use crate::i18n::tr;
+use crate::nfc;
+use crate::storage;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::{AchievementStatus, AchievementUnlock, DevcadeGame};
+use devcade_onboard_types::Event;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * The file (relative to the devcade path) that unlocked achievements are stored in
+ */
+const ACHIEVEMENTS_FILE: &str = "achievements.json";
+
+/**
+ * How many unlocks can wait to be uploaded before the oldest are dropped
+ */
+const MAX_PENDING: usize = 1000;
+
+lazy_static! {
+    static ref ACHIEVEMENTS: Mutex<Achievements> = Mutex::new(Achievements::default());
+}
+
+/**
+ * Every badge's unlocked achievements on this cabinet, and the unlocks that haven't been uploaded
+ * yet
+ */
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Achievements {
+    /**
+     * When each achievement was unlocked, by game ID, badge handle, and achievement ID
+     */
+    unlocks: BTreeMap<String, BTreeMap<String, BTreeMap<String, u64>>>,
+    pending: Vec<AchievementUnlock>,
+}
+
+fn achievements_path() -> PathBuf {
+    storage::root().join(ACHIEVEMENTS_FILE)
+}
+
+/**
+ * Load unlocked achievements from the devcade directory. Missing or unreadable achievements are
+ * logged and replaced with empty ones.
+ */
+pub async fn load() {
+    let path = achievements_path();
+    let achievements = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(achievements) => achievements,
+            Err(e) => {
+                log::warn!("Ignoring invalid achievements at {:?}: {e}", path);
+                Achievements::default()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Achievements::default(),
+        Err(e) => {
+            log::warn!("Couldn't read achievements at {:?}: {e}", path);
+            Achievements::default()
+        }
+    };
+    *ACHIEVEMENTS.lock().unwrap() = achievements;
+}
+
+/**
+ * Unlock one of the running game's achievements for a player, telling the frontend so it can show
+ * a toast. Unlocking an achievement again does nothing. Guests see the toast, but their unlocks
+ * aren't kept, since there's nobody to keep them for.
+ *
+ * # Errors
+ * This function will return an error if no game is running, if the game didn't declare the
+ * achievement, if the handle wasn't tapped in this session, or if the unlock can't be written.
+ */
+pub async fn unlock(achievement_id: &str, handle: Option<String>) -> Result<(), Error> {
+    let game = api::current_game().ok_or_else(|| anyhow!(tr("no_game_running", &[])))?;
+    let achievement = game
+        .achievements
+        .iter()
+        .find(|achievement| achievement.id == achievement_id)
+        .cloned()
+        .ok_or_else(|| {
+            anyhow!(tr(
+                "achievement_not_declared",
+                &[("achievement", achievement_id)]
+            ))
+        })?;
+    let Some(handle) = handle else {
+        events::emit(Event::AchievementUnlocked(game.id, achievement));
+        return Ok(());
+    };
+    if !nfc::tapped_this_session(handle.as_str()) {
+        return Err(anyhow!(tr("nfc_user_not_found", &[])));
+    }
+
+    let unlock = AchievementUnlock {
+        game_id: game.id.clone(),
+        achievement_id: achievement_id.to_string(),
+        handle,
+        unlocked_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+    let achievements = {
+        let mut achievements = ACHIEVEMENTS.lock().unwrap();
+        if !record(&mut achievements, &unlock) {
+            return Ok(());
+        }
+        if env::achievement_sync_interval().is_some() {
+            achievements.pending.push(unlock);
+            let overflow = achievements.pending.len().saturating_sub(MAX_PENDING);
+            achievements.pending.drain(..overflow);
+        }
+        achievements.clone()
+    };
+    log::info!("Achievement {achievement_id} of {} unlocked", game.id);
+    events::emit(Event::AchievementUnlocked(game.id, achievement));
+    atomic::write_async(achievements_path(), serde_json::to_string(&achievements)?).await?;
+    Ok(())
+}
+
+/**
+ * Get every achievement a game declares, and whether a player has unlocked each. Guests (`None`)
+ * haven't unlocked any.
+ */
+#[must_use]
+pub fn statuses(game: &DevcadeGame, handle: Option<&str>) -> Vec<AchievementStatus> {
+    let achievements = ACHIEVEMENTS.lock().unwrap();
+    let unlocks = handle.and_then(|handle| achievements.unlocks.get(&game.id)?.get(handle));
+    game.achievements
+        .iter()
+        .map(|achievement| AchievementStatus {
+            achievement: achievement.clone(),
+            unlocked_at: unlocks.and_then(|unlocks| unlocks.get(&achievement.id).copied()),
+        })
+        .collect()
+}
+
+/**
+ * Upload unlocks to the API every `DEVCADE_ACHIEVEMENT_SYNC_MINUTES`. Returns immediately if
+ * unlocks aren't uploaded.
+ */
+pub async fn run() {
+    let Some(period) = env::achievement_sync_interval() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        match sync().await {
+            Ok(0) => {}
+            Ok(uploaded) => log::info!("Uploaded {uploaded} achievement unlocks"),
+            Err(e) => log::warn!("Couldn't upload achievement unlocks: {e}"),
+        }
+    }
+}
+
+/**
+ * Upload the unlocks that haven't been yet, a player at a time, getting how many were. Players
+ * whose unlocks can't be uploaded, like when the cabinet is offline, are tried again next time.
+ *
+ * # Errors
+ * This function will return an error if the unlocks can't be written.
+ */
+pub async fn sync() -> Result<usize, Error> {
+    let pending = ACHIEVEMENTS.lock().unwrap().pending.clone();
+    let mut by_player: BTreeMap<(String, String), Vec<AchievementUnlock>> = BTreeMap::new();
+    for unlock in pending {
+        by_player
+            .entry((unlock.game_id.clone(), unlock.handle.clone()))
+            .or_default()
+            .push(unlock);
+    }
+
+    let mut uploaded = vec![];
+    for ((game_id, handle), unlocks) in by_player {
+        match api::upload_achievements(game_id.as_str(), handle.as_str(), &unlocks).await {
+            Ok(()) => uploaded.extend(unlocks),
+            Err(e) => log::warn!("Couldn't upload achievements of {game_id} for {handle}: {e}"),
+        }
+    }
+    if uploaded.is_empty() {
+        return Ok(0);
+    }
+    let achievements = {
+        let mut achievements = ACHIEVEMENTS.lock().unwrap();
+        achievements
+            .pending
+            .retain(|unlock| !uploaded.contains(unlock));
+        achievements.clone()
+    };
+    atomic::write_async(achievements_path(), serde_json::to_string(&achievements)?).await?;
+    Ok(uploaded.len())
+}
+
+/**
+ * Remember an unlock, getting whether it's new
+ */
+fn record(achievements: &mut Achievements, unlock: &AchievementUnlock) -> bool {
+    let unlocks = achievements
+        .unlocks
+        .entry(unlock.game_id.clone())
+        .or_default()
+        .entry(unlock.handle.clone())
+        .or_default();
+    if unlocks.contains_key(&unlock.achievement_id) {
+        return false;
+    }
+    unlocks.insert(unlock.achievement_id.clone(), unlock.unlocked_at);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devcade_onboard_types::schema::Achievement;
+
+    #[test]
+    fn unlocks_are_only_recorded_once() {
+        let mut achievements = Achievements::default();
+        let unlock = AchievementUnlock {
+            game_id: String::from("game"),
+            achievement_id: String::from("first-win"),
+            handle: String::from("player"),
+            unlocked_at: 100,
+        };
+        assert!(record(&mut achievements, &unlock));
+        assert!(!record(
+            &mut achievements,
+            &AchievementUnlock {
+                unlocked_at: 200,
+                ..unlock.clone()
+            }
+        ));
+        assert_eq!(achievements.unlocks["game"]["player"]["first-win"], 100);
+
+        let game = DevcadeGame {
+            id: String::from("game"),
+            achievements: vec![Achievement {
+                id: String::from("first-win"),
+                ..Achievement::default()
+            }],
+            ..DevcadeGame::default()
+        };
+        *ACHIEVEMENTS.lock().unwrap() = achievements;
+        assert_eq!(statuses(&game, Some("player"))[0].unlocked_at, Some(100));
+        assert_eq!(statuses(&game, None)[0].unlocked_at, None);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::api;
+use crate::atomic;
+use crate::env;
+use crate::storage;
+use anyhow::Error;
+use devcade_onboard_types::schema::{GameSession, PlayEvent};
+use lazy_static::lazy_static;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/**
+ * The file (relative to the devcade path) that play events waiting to be uploaded are stored in
+ */
+const QUEUE_FILE: &str = "analytics.json";
+
+/**
+ * How many play events are uploaded in one request
+ */
+const BATCH_SIZE: usize = 100;
+
+/**
+ * How many play events can wait to be uploaded before the oldest are dropped
+ */
+const MAX_QUEUED: usize = 5000;
+
+/**
+ * The longest the uploader waits between tries while the API can't be reached
+ */
+const MAX_BACKOFF: Duration = Duration::from_secs(6 * 60 * 60);
+
+/**
+ * How precisely play events say when a game was launched, in seconds, so sessions can't be lined
+ * up with who was at the cabinet
+ */
+const START_PRECISION_SECS: u64 = 60 * 60;
+
+lazy_static! {
+    static ref QUEUE: Mutex<Vec<PlayEvent>> = Mutex::new(Vec::new());
+}
+
+fn queue_path() -> PathBuf {
+    storage::root().join(QUEUE_FILE)
+}
+
+/**
+ * Load the play events that weren't uploaded before the backend last stopped. Missing or
+ * unreadable events are logged and dropped.
+ */
+pub async fn load() {
+    let path = queue_path();
+    let queue = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(queue) => queue,
+            Err(e) => {
+                log::warn!("Ignoring invalid analytics queue at {:?}: {e}", path);
+                Vec::new()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            log::warn!("Couldn't read analytics queue at {:?}: {e}", path);
+            Vec::new()
+        }
+    };
+    *QUEUE.lock().unwrap() = queue;
+}
+
+/**
+ * Queue a finished session to be uploaded, if analytics are turned on. Only what game authors need
+ * to see how their game is played is kept: nothing identifies the players.
+ *
+ * # Errors
+ * This function will return an error if the queue can't be written.
+ */
+pub async fn record(session: &GameSession) -> Result<(), Error> {
+    if env::analytics_upload_interval().is_none() {
+        return Ok(());
+    }
+    let queue = {
+        let mut queue = QUEUE.lock().unwrap();
+        queue.push(anonymize(session));
+        let overflow = queue.len().saturating_sub(MAX_QUEUED);
+        queue.drain(..overflow);
+        queue.clone()
+    };
+    atomic::write_async(queue_path(), serde_json::to_string(&queue)?).await?;
+    Ok(())
+}
+
+/**
+ * Upload queued play events every `DEVCADE_ANALYTICS_MINUTES`. While the API can't be reached,
+ * the wait doubles after every failed try, up to 6 hours. Returns immediately if analytics aren't
+ * turned on.
+ */
+pub async fn run() {
+    let Some(period) = env::analytics_upload_interval() else {
+        return;
+    };
+    let mut wait = period;
+    loop {
+        tokio::time::sleep(wait).await;
+        wait = match upload().await {
+            Ok(0) => period,
+            Ok(uploaded) => {
+                log::info!("Uploaded {uploaded} play events");
+                period
+            }
+            Err(e) => {
+                let backoff = (wait * 2).min(MAX_BACKOFF.max(period));
+                log::warn!("Couldn't upload play events, trying again in {backoff:?}: {e}");
+                backoff
+            }
+        };
+    }
+}
+
+/**
+ * Upload every queued play event, a batch at a time, getting how many were. Batches that were
+ * uploaded are dropped from the queue even if a later one fails.
+ *
+ * # Errors
+ * This function will return an error if a batch can't be uploaded, or the queue can't be written.
+ */
+pub async fn upload() -> Result<usize, Error> {
+    let mut uploaded = 0;
+    loop {
+        let batch: Vec<PlayEvent> = QUEUE
+            .lock()
+            .unwrap()
+            .iter()
+            .take(BATCH_SIZE)
+            .cloned()
+            .collect();
+        if batch.is_empty() {
+            return Ok(uploaded);
+        }
+        let result = api::upload_play_events(&batch).await;
+        if result.is_ok() {
+            uploaded += batch.len();
+        }
+        let queue = {
+            let mut queue = QUEUE.lock().unwrap();
+            if result.is_ok() {
+                queue.retain(|event| !batch.contains(event));
+            }
+            queue.clone()
+        };
+        atomic::write_async(queue_path(), serde_json::to_string(&queue)?).await?;
+        result?;
+    }
+}
+
+fn anonymize(session: &GameSession) -> PlayEvent {
+    PlayEvent {
+        game_id: session.game_id.clone(),
+        started_at: session.started_at - session.started_at % START_PRECISION_SECS,
+        duration_secs: session.duration_ms / 1000,
+        crashed: session.crashed(),
+        players: session.players.len() as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn play_events_leave_out_players() {
+        let session = GameSession {
+            game_id: String::from("game"),
+            started_at: 7 * 60 * 60 + 1234,
+            duration_ms: 90_500,
+            exit_code: Some(0),
+            players: vec![String::from("a"), String::from("b")],
+            ..GameSession::default()
+        };
+        assert_eq!(
+            anonymize(&session),
+            PlayEvent {
+                game_id: String::from("game"),
+                started_at: 7 * 60 * 60,
+                duration_secs: 90,
+                crashed: false,
+                players: 2,
+            }
+        );
+    }
+}
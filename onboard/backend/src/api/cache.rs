@@ -0,0 +1,137 @@
+use crate::env::metadata_cache_ttl;
+use crate::events;
+use devcade_onboard_types::{
+    schema::{Collection, DevcadeGame, GameRating, Tag, User},
+    Event,
+};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    pub(super) static ref GAMES: TtlCache<String, DevcadeGame> = TtlCache::new();
+    pub(super) static ref TAGS: TtlCache<String, Tag> = TtlCache::new();
+    pub(super) static ref COLLECTIONS: TtlCache<(), Vec<Collection>> = TtlCache::new();
+    pub(super) static ref RATINGS: TtlCache<(), HashMap<String, GameRating>> = TtlCache::new();
+    pub(super) static ref USERS: TtlCache<String, User> = TtlCache::new();
+}
+
+/**
+ * A small in-memory cache where every entry expires a while after it was inserted (by default
+ * `metadata_cache_ttl()`). Expired entries are removed when they're looked up, and swept whenever a
+ * new entry is inserted so the cache can't grow without bound.
+ */
+pub struct TtlCache<K, V> {
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+    ttl: fn() -> Duration,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    /**
+     * Create a new empty cache
+     */
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_ttl(metadata_cache_ttl)
+    }
+
+    /**
+     * Create a new empty cache whose entries expire after however long `ttl` returns. It's called
+     * on every lookup, so the TTL can come from the environment.
+     */
+    #[must_use]
+    pub fn with_ttl(ttl: fn() -> Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /**
+     * Get an entry from the cache, if it exists and hasn't expired
+     */
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((inserted, value)) if inserted.elapsed() < (self.ttl)() => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /**
+     * Insert an entry into the cache, replacing any existing entry and resetting its expiry. Any
+     * expired entries are evicted at the same time.
+     */
+    pub fn insert(&self, key: K, value: V) {
+        let ttl = (self.ttl)();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (inserted, _)| inserted.elapsed() < ttl);
+        entries.insert(key, (Instant::now(), value));
+    }
+
+    /**
+     * Remove an entry from the cache
+     */
+    pub fn remove(&self, key: &K) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    /**
+     * Remove every entry from the cache
+     */
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl<K: Eq + Hash, V: Clone> Default for TtlCache<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * Drop any cached metadata for a game, so the next lookup goes to the API.
+ */
+pub fn invalidate(game_id: &str) {
+    log::debug!("Invalidating cached metadata for game {game_id}");
+    GAMES.remove(&game_id.to_string());
+}
+
+/**
+ * Drop all cached metadata. Used when switching between the production and development API.
+ */
+pub fn invalidate_all() {
+    log::debug!("Invalidating all cached metadata");
+    GAMES.clear();
+    TAGS.clear();
+    COLLECTIONS.clear();
+    RATINGS.clear();
+    USERS.clear();
+}
+
+/**
+ * Listen for game install, update, and removal events and invalidate the cached metadata for
+ * those games. This never returns, and should be spawned as a task at startup.
+ */
+pub async fn watch_events() -> ! {
+    let mut events = events::subscribe("metadata cache");
+    loop {
+        match events.recv().await {
+            Event::GameUpdated(game_id)
+            | Event::GameInstalled(game_id)
+            | Event::GameRemoved(game_id) => invalidate(game_id.as_str()),
+            Event::Gap(_) => {
+                log::warn!("Metadata cache missed events, clearing it");
+                invalidate_all();
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,20 +1,67 @@
-use crate::env::{api_url, devcade_path};
-use crate::nfc::NFC_CLIENT;
+use crate::analytics;
+use crate::atomic;
+use crate::attract;
+use crate::audio;
+use crate::controllers;
+use crate::display;
+use crate::env::{self, api_url};
+use crate::executables;
+use crate::game_logs;
+use crate::gpu;
+use crate::guests;
+use crate::history;
+use crate::i18n::tr;
+use crate::install_history;
+use crate::install_queue;
+use crate::install_report;
+use crate::install_state::{self, InstallStage, InstallState};
+use crate::metrics::{self, Counter, Histogram};
+use crate::nfc;
+use crate::play_stats;
+use crate::profile;
+use crate::readiness;
+use crate::recording;
+use crate::save_crypto;
+use crate::save_watch;
+use crate::self_update;
+use crate::session_stats;
+use crate::signing;
+use crate::storage::{
+    self, BANNER, BUNDLE, CONTROLLER_MAPPINGS, ENV_OVERRIDES, GAME_JSON, ICON, INSTALL_STATE,
+    PERMISSION_OVERRIDES, SIGNATURE,
+};
+use crate::ticker;
+use crate::versions;
+use crate::watchdog;
 use anyhow::{anyhow, Error};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use devcade_onboard_types::{
-    schema::{DevcadeGame, MinimalGame, Tag, User},
-    Map, Player, Value,
+    schema::{
+        AchievementUnlock, BundleCheck, BundleValidation, CabinetIdentity, Collection, CorruptGame,
+        DevcadeGame, GameChannel, GamePermission, GameRating, GameSession, GameTrustInfo,
+        InstalledGames, LeaderboardEntry, MinimalGame, NfcRealm, PlayEvent, SaveCacheStats,
+        SavePage, SaveUsage, Tag, User,
+    },
+    Event, Map, Player, Value,
 };
-use log::{log, Level};
+use save_storage::Storage;
 
+use futures_util::StreamExt;
 use lazy_static::lazy_static;
-use libflatpak::{gio, prelude::*, Installation, Transaction};
-use std::collections::{HashMap, HashSet};
+use libflatpak::gio::glib::{KeyFile, KeyFileFlags};
+use libflatpak::{gio, prelude::*, BundleRef, Installation, RefKind, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
-use std::time::Duration;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::fs;
-use tokio::process::Command;
+use tokio::process::{Child, Command};
 use tokio::sync::oneshot;
 
 lazy_static! {
@@ -25,17 +72,118 @@ lazy_static! {
     static ref ON_MACHINE: bool = Path::new("/home/devcade").exists();
     static ref DB: tokio::sync::Mutex<HashMap<String, HashMap<String, String>>> = tokio::sync::Mutex::new(HashMap::new());
     static ref DB_MODIFIED: tokio::sync::Mutex<HashSet<String>> = tokio::sync::Mutex::new(HashSet::new());
+    /**
+     * Where saves are kept between runs, picked by `DEVCADE_SAVE_STORAGE`
+     */
+    static ref STORAGE: Mutex<Box<dyn Storage>> = Mutex::new(save_storage::open(save_root()));
+    /**
+     * How the save cache has been written since the backend started
+     */
+    static ref FLUSH_HISTORY: Mutex<FlushHistory> = Mutex::new(FlushHistory::default());
+    /**
+     * Roughly how many bytes of save data each game has. A game is counted the first time its
+     * quota is checked, then kept up to date as values are saved and deleted.
+     */
+    static ref SAVE_USAGE: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+    // How many times each game has crashed in a row, reset when it exits normally
+    static ref CRASH_COUNTS: Mutex<HashMap<String, u32>> = Mutex::new(HashMap::new());
 }
 
+/**
+ * Set when the current game is stopped or killed from the menu, so its exit isn't mistaken for a
+ * crash
+ */
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/**
+ * When the game list was last fetched from the API, in seconds since the unix epoch, or 0 if it
+ * hasn't been since the backend started
+ */
+static LAST_API_SYNC: AtomicU64 = AtomicU64::new(0);
+
+/**
+ * How long to keep capturing a game's output after it exits
+ */
+const CAPTURE_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/**
+ * How long a game has to exit after being asked to at the session limit, before it's killed
+ */
+const SESSION_STOP_GRACE: Duration = Duration::from_secs(5);
+
+/**
+ * The maximum number of single-game requests that will be in flight at once when a batch request
+ * isn't available
+ */
+const MAX_PARALLEL_FETCHES: usize = 8;
+
+/**
+ * Staging games' local IDs start with this, so they can't collide with the production version of
+ * the same game
+ */
+pub const STAGING_PREFIX: &str = "staging~";
+
+/**
+ * The largest bundle `validate_game` accepts. Nothing stops a bigger one from being installed, but
+ * it would take most of a cabinet's disk.
+ */
+const MAX_BUNDLE_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+/**
+ * The most space `validate_game` accepts a game taking once it's installed
+ */
+const MAX_INSTALLED_BYTES: u64 = 16 * 1024 * 1024 * 1024;
+
+/**
+ * How many values a game gets back from each scan of its saves
+ */
+const SCAN_PAGE_SIZE: usize = 100;
+
+/**
+ * Blobs larger than this are kept in files of their own instead of in their group's save file
+ */
+const BLOB_FILE_THRESHOLD: usize = 64 * 1024;
+
+/**
+ * What the value of a blob starts with when it's kept in its group, base64 encoded
+ */
+const BLOB_INLINE: &str = "\u{1}blob:";
+
+/**
+ * What the value of a blob starts with when it's kept in a file of its own, followed by the
+ * file's name
+ */
+const BLOB_FILE: &str = "\u{1}blob-file:";
+
+/**
+ * The key each group keeps its values' expiry times under, as a JSON object of keys to Unix times
+ */
+const EXPIRY_KEY: &str = "\u{1}expires";
+
+/**
+ * Module for caching metadata requested from the API
+ */
+pub mod cache;
+
+/**
+ * Module for the engines saves are stored in between runs
+ */
+pub mod save_storage;
+
 /**
  * Internal module for network requests and JSON serialization
  */
 mod network {
+    use crate::cabinet;
+    use crate::metrics::{self, Counter, Histogram};
     use anyhow::Error;
     use lazy_static::lazy_static;
-    use log::{log, Level};
-    use serde::Deserialize;
+    use serde::{Deserialize, Serialize};
+    use std::fmt::Display;
     use std::ops::Deref;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+    use tokio::io::AsyncWriteExt;
 
     // Construct a static client to be used for all requests. Prevents opening a new connection for
     // every request.
@@ -43,16 +191,243 @@ mod network {
         static ref CLIENT: reqwest::Client = reqwest::Client::new();
     }
 
+    /**
+     * How much of an unexpected response body is kept in an `ApiError`
+     */
+    const EXCERPT_CHARS: usize = 200;
+
+    /**
+     * The header requests carry the cabinet's ID in
+     */
+    const CABINET_ID_HEADER: &str = "X-Devcade-Cabinet";
+
+    /**
+     * The header requests carry the cabinet's name in
+     */
+    const CABINET_NAME_HEADER: &str = "X-Devcade-Cabinet-Name";
+
+    /**
+     * The API responded with something other than what was asked for, such as an error status, an
+     * HTML error page, or JSON that doesn't match what's expected
+     */
+    #[derive(Debug)]
+    pub struct ApiError {
+        pub url: String,
+        pub status: reqwest::StatusCode,
+        pub content_type: Option<String>,
+        pub problem: String,
+        /**
+         * The start of the response body, with whitespace collapsed
+         */
+        pub excerpt: String,
+    }
+
+    impl ApiError {
+        /**
+         * Describe a bad response, reading what's left of its body into the excerpt. Every bad
+         * response is logged, so it shows up in the admin dashboard's logs.
+         */
+        async fn from_response(
+            url: &str,
+            response: reqwest::Response,
+            problem: impl Into<String>,
+        ) -> Error {
+            let status = response.status();
+            let content_type = content_type(&response);
+            let body = response.text().await.unwrap_or_default();
+            Self::with_body(url, status, content_type, problem, body.as_str())
+        }
+
+        fn with_body(
+            url: &str,
+            status: reqwest::StatusCode,
+            content_type: Option<String>,
+            problem: impl Into<String>,
+            body: &str,
+        ) -> Error {
+            let error = Self {
+                url: url.to_string(),
+                status,
+                content_type,
+                problem: problem.into(),
+                excerpt: excerpt(body),
+            };
+            log::error!("{error}");
+            error.into()
+        }
+    }
+
+    impl Display for ApiError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "Bad response from {} ({}, {}): {}",
+                self.url,
+                self.status,
+                self.content_type.as_deref().unwrap_or("no content type"),
+                self.problem
+            )?;
+            if !self.excerpt.is_empty() {
+                write!(f, ". Body: {}", self.excerpt)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl std::error::Error for ApiError {}
+
+    fn content_type(response: &reqwest::Response) -> Option<String> {
+        response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    }
+
+    fn excerpt(body: &str) -> String {
+        let collapsed = body.split_whitespace().collect::<Vec<_>>().join(" ");
+        match collapsed.char_indices().nth(EXCERPT_CHARS) {
+            Some((end, _)) => format!("{}...", &collapsed[..end]),
+            None => collapsed,
+        }
+    }
+
+    /**
+     * Check that a response succeeded, and (if `json` is set) that it's JSON rather than something
+     * like an HTML error page
+     */
+    async fn check(
+        url: &str,
+        response: reqwest::Response,
+        json: bool,
+    ) -> Result<reqwest::Response, Error> {
+        if !response.status().is_success() {
+            return Err(ApiError::from_response(url, response, "Unexpected status").await);
+        }
+        let content_type = content_type(&response).unwrap_or_default();
+        if content_type.starts_with("text/html") {
+            return Err(
+                ApiError::from_response(url, response, "Got an HTML page instead of data").await,
+            );
+        }
+        if json && !content_type.is_empty() && !content_type.contains("json") {
+            return Err(ApiError::from_response(url, response, "Expected JSON").await);
+        }
+        Ok(response)
+    }
+
+    /**
+     * Read a checked response's JSON body
+     */
+    async fn read_json<T: for<'de> Deserialize<'de>>(
+        url: &str,
+        response: reqwest::Response,
+    ) -> Result<T, Error> {
+        let status = response.status();
+        let content_type = content_type(&response);
+        let body = response.text().await?;
+        serde_json::from_str(body.as_str()).map_err(|e| {
+            ApiError::with_body(
+                url,
+                status,
+                content_type,
+                format!("JSON doesn't match what was expected: {e}"),
+                body.as_str(),
+            )
+        })
+    }
+
+    /**
+     * Send a request, timing how long the API takes to respond
+     */
+    async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, reqwest::Error> {
+        let started = Instant::now();
+        let response = identify(request).send().await;
+        metrics::observe(Histogram::ApiLatency, started.elapsed());
+        response
+    }
+
+    /**
+     * Say which cabinet a request is from, once the cabinet's identity has been loaded
+     */
+    fn identify(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let Some(identity) = cabinet::identity() else {
+            return request;
+        };
+        let request = request.header(CABINET_ID_HEADER, identity.id);
+        // Names aren't always valid header values, and the API can look them up by ID anyway
+        match reqwest::header::HeaderValue::from_str(identity.name.as_str()) {
+            Ok(name) => request.header(CABINET_NAME_HEADER, name),
+            Err(_) => request,
+        }
+    }
+
+    /**
+     * Start a GET request, asking for metadata in the selected locale
+     */
+    fn get(url: &str) -> reqwest::RequestBuilder {
+        CLIENT
+            .deref()
+            .get(url)
+            .header(reqwest::header::ACCEPT_LANGUAGE, crate::env::locale())
+    }
+
+    /**
+     * Start a POST request with a JSON body, asking for responses in the selected locale
+     */
+    fn post_body<B: Serialize + ?Sized>(url: &str, body: &B) -> reqwest::RequestBuilder {
+        CLIENT
+            .deref()
+            .post(url)
+            .header(reqwest::header::ACCEPT_LANGUAGE, crate::env::locale())
+            .json(body)
+    }
+
+    /**
+     * Check that a server answers at a URL, whatever it answers with
+     *
+     * # Errors
+     * This function will return an error if the server can't be reached in time.
+     */
+    pub async fn reachable(url: &str, timeout: Duration) -> Result<(), Error> {
+        send(CLIENT.deref().get(url).timeout(timeout)).await?;
+        Ok(())
+    }
+
     /**
      * Request JSON from a URL and serialize it into a struct
      *
      * # Errors
-     * This function will return an error if the request fails, or if the JSON cannot be deserialized
+     * This function will return an error if the request fails, if the server responds with an error
+     * status or something other than JSON, or if the JSON cannot be deserialized
      */
     pub async fn request_json<T: for<'de> Deserialize<'de>>(url: &str) -> Result<T, Error> {
-        log!(Level::Trace, "Requesting JSON from {}", url);
-        let response = CLIENT.deref().get(url).send().await?;
-        let json = response.json().await?;
+        tracing::trace!("Requesting JSON from {}", url);
+        let response = check(url, send(get(url)).await?, true).await?;
+        read_json(url, response).await
+    }
+
+    /**
+     * Request JSON from a URL like `request_json`, and check it makes sense with `invariant`, which
+     * describes what's wrong with it if it doesn't
+     *
+     * # Errors
+     * This function will return an error if `request_json` would, or if the invariant doesn't hold
+     */
+    pub async fn request_json_checked<T: for<'de> Deserialize<'de> + Serialize>(
+        url: &str,
+        invariant: impl FnOnce(&T) -> Result<(), String>,
+    ) -> Result<T, Error> {
+        let json: T = request_json(url).await?;
+        if let Err(problem) = invariant(&json) {
+            return Err(ApiError::with_body(
+                url,
+                reqwest::StatusCode::OK,
+                Some(String::from("application/json")),
+                problem,
+                serde_json::to_string(&json)?.as_str(),
+            ));
+        }
         Ok(json)
     }
 
@@ -60,14 +435,118 @@ mod network {
      * Request binary data from a URL
      *
      * # Errors
-     * This function will return an error if the request fails.
+     * This function will return an error if the request fails, or if the server responds with an
+     * error status or an HTML page.
      */
     pub async fn request_bytes(url: &str) -> Result<Vec<u8>, Error> {
-        log!(Level::Trace, "Requesting binary from {}", url);
-        let response = CLIENT.deref().get(url).send().await?;
+        tracing::trace!("Requesting binary from {}", url);
+        let response = check(url, send(get(url)).await?, false).await?;
         let bytes = response.bytes().await?;
+        metrics::count(Counter::DownloadBytes, bytes.len() as u64);
         Ok(bytes.to_vec())
     }
+
+    /**
+     * A download was stopped for being bigger than it's allowed to be. Holds how many bytes it was
+     * known to be when it was stopped, which is at least that many.
+     */
+    #[derive(Debug)]
+    pub struct TooLarge(pub u64);
+
+    impl Display for TooLarge {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "Download is at least {} bytes, which is too large",
+                self.0
+            )
+        }
+    }
+
+    impl std::error::Error for TooLarge {}
+
+    /**
+     * Download binary data from a URL into a file, without holding it in memory. The download is
+     * refused up front if the server says it's bigger than `max_bytes`, and stopped as soon as more
+     * than that has been received otherwise. `on_progress` is called with the number of bytes
+     * received so far (and the total size, if the server reported it) after every chunk. Returns
+     * how many bytes were written.
+     *
+     * # Errors
+     * This function will return a `TooLarge` error if the download is too big, or another error if
+     * the request fails, the server responds with an error status or an HTML page, or the file
+     * can't be written. The file may be left partly written.
+     */
+    pub async fn download_to_file(
+        url: &str,
+        path: &Path,
+        max_bytes: u64,
+        mut on_progress: impl FnMut(u64, Option<u64>),
+    ) -> Result<u64, Error> {
+        tracing::trace!("Downloading {} to {:?}", url, path);
+        let mut response = check(url, send(get(url)).await?, false).await?;
+        let total = response.content_length();
+        if let Some(total) = total.filter(|total| *total > max_bytes) {
+            return Err(TooLarge(total).into());
+        }
+        let mut file = tokio::fs::File::create(path).await?;
+        let mut written = 0u64;
+        while let Some(chunk) = response.chunk().await? {
+            metrics::count(Counter::DownloadBytes, chunk.len() as u64);
+            written += chunk.len() as u64;
+            if written > max_bytes {
+                return Err(TooLarge(written).into());
+            }
+            file.write_all(&chunk).await?;
+            on_progress(written, total);
+        }
+        file.flush().await?;
+        Ok(written)
+    }
+
+    /**
+     * POST a JSON body to a URL and serialize the JSON response into a struct
+     *
+     * # Errors
+     * This function will return an error if the request fails, if the server responds with an error
+     * status or something other than JSON, or if the JSON cannot be deserialized
+     */
+    pub async fn post_json<B: Serialize + ?Sized, T: for<'de> Deserialize<'de>>(
+        url: &str,
+        body: &B,
+    ) -> Result<T, Error> {
+        tracing::trace!("Posting JSON to {}", url);
+        let response = check(url, send(post_body(url, body)).await?, true).await?;
+        read_json(url, response).await
+    }
+
+    /**
+     * POST a JSON body to a URL, ignoring the response body
+     *
+     * # Errors
+     * This function will return an error if the request fails, or if the server responds with an
+     * error status
+     */
+    pub async fn post<B: Serialize + ?Sized>(url: &str, body: &B) -> Result<(), Error> {
+        tracing::trace!("Posting JSON to {}", url);
+        check(url, send(post_body(url, body)).await?, false).await?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn excerpts_are_collapsed_and_cut() {
+            assert_eq!(
+                excerpt("<html>\n  <body>Bad gateway</body>\n</html>"),
+                "<html> <body>Bad gateway</body> </html>"
+            );
+            let long = "a".repeat(EXCERPT_CHARS + 10);
+            assert_eq!(excerpt(long.as_str()).len(), EXCERPT_CHARS + 3);
+        }
+    }
 }
 
 /**
@@ -91,6 +570,13 @@ mod route {
         format!("games/{id}")
     }
 
+    /**
+     * Get several games at once, by a list of IDs sent in the request body
+     */
+    pub fn game_batch() -> String {
+        String::from("games/batch")
+    }
+
     /**
      * Get a specific game's icon by ID
      */
@@ -112,6 +598,41 @@ mod route {
         format!("games/{id}/game")
     }
 
+    /**
+     * Get the detached signature of a specific game's binary by ID
+     */
+    pub fn game_signature(id: &str) -> String {
+        format!("games/{id}/signature")
+    }
+
+    /**
+     * Get the SDL controller mappings a specific game should be run with by ID
+     */
+    pub fn game_controller_mappings(id: &str) -> String {
+        format!("games/{id}/controller-mappings")
+    }
+
+    /**
+     * Report problems with a specific game on this cabinet
+     */
+    pub fn game_reports(id: &str) -> String {
+        format!("games/{id}/reports")
+    }
+
+    /**
+     * Rate a specific game
+     */
+    pub fn game_ratings(id: &str) -> String {
+        format!("games/{id}/ratings")
+    }
+
+    /**
+     * Get every game's aggregate rating
+     */
+    pub fn ratings() -> String {
+        String::from("ratings/")
+    }
+
     /**
      * Get all tags
      */
@@ -133,556 +654,3062 @@ mod route {
         format!("tags/{name}/games")
     }
 
+    /**
+     * Get the curated collections of games
+     */
+    pub fn collections() -> String {
+        String::from("collections/")
+    }
+
     /**
      * Get a specific user
      */
     pub fn user(uid: &str) -> String {
         format!("users/{uid}")
     }
+
+    /**
+     * Start a login that's confirmed on a phone
+     */
+    pub fn login_qr() -> String {
+        String::from("auth/qr")
+    }
+
+    /**
+     * Check whether a login started with a QR code was confirmed
+     */
+    pub fn login_qr_status(code: &str) -> String {
+        format!("auth/qr/{code}")
+    }
+
+    /**
+     * Log in with a PIN
+     */
+    pub fn login_pin() -> String {
+        String::from("auth/pin")
+    }
+
+    /**
+     * Back up or restore a player's saves for a specific game, by game ID and badge handle
+     */
+    pub fn saves(game_id: &str, handle: &str) -> String {
+        format!("saves/{game_id}/{handle}")
+    }
+
+    /**
+     * Submit scores to one of a game's leaderboards
+     */
+    pub fn leaderboard(game_id: &str, board: &str) -> String {
+        format!("leaderboards/{game_id}/{board}")
+    }
+
+    /**
+     * Submit the achievements a player unlocked in a specific game, by game ID and badge handle
+     */
+    pub fn achievements(game_id: &str, handle: &str) -> String {
+        format!("achievements/{game_id}/{handle}")
+    }
+
+    /**
+     * Add or remove a badged user's favorite games, by badge handle
+     */
+    pub fn favorites(handle: &str) -> String {
+        format!("favorites/{handle}")
+    }
+
+    /**
+     * Register a cabinet, or update what the API knows about it
+     */
+    pub fn cabinets() -> String {
+        String::from("cabinets/")
+    }
+
+    /**
+     * Get the newest onboard release
+     */
+    pub fn latest_release() -> String {
+        String::from("releases/onboard/latest")
+    }
+
+    /**
+     * Download an onboard release's binary
+     */
+    pub fn release_binary(version: &str) -> String {
+        format!("releases/onboard/{version}/binary")
+    }
+
+    /**
+     * Submit anonymous play events for game authors
+     */
+    pub fn play_events() -> String {
+        String::from("analytics/plays")
+    }
 }
 
 /**
- * Get a list of games from the API. This is the preferred method of getting games.
+ * Tell the API whether a game is broken on this cabinet, so its author can see which cabinets
+ * report problems.
  *
  * # Errors
- * This function will return an error if the request fails, or if the JSON cannot be deserialized
+ * This function will return an error if the request fails.
  */
-pub async fn game_list() -> Result<Vec<DevcadeGame>, Error> {
-    let games: Vec<DevcadeGame> =
-        network::request_json(format!("{}/{}", api_url(), route::game_list()).as_str()).await?;
-    Ok(games
-        .into_iter()
-        .filter(|game| game.hash.is_some())
-        .collect::<Vec<DevcadeGame>>())
+pub async fn report_broken(game_id: &str, broken: bool, note: &str) -> Result<(), Error> {
+    network::post(
+        game_route(game_id, route::game_reports)?.as_str(),
+        &serde_json::json!({
+            "cabinet": env::cabinet_name(),
+            "broken": broken,
+            "note": note,
+        }),
+    )
+    .await
 }
 
 /**
- * Get a specific game from the API. This is the preferred method of getting games.
+ * Submit a badged user's rating of a game
  *
  * # Errors
- * This function will return an error if the request fails, or if the JSON cannot be deserialized
+ * This function will return an error if the API can't be reached, or refuses the rating.
  */
-pub async fn get_game(id: &str) -> Result<DevcadeGame, Error> {
-    let game = network::request_json(format!("{}/{}", api_url(), route::game(id)).as_str()).await?;
-    Ok(game)
+pub async fn submit_rating(
+    game_id: &str,
+    handle: &str,
+    stars: u8,
+    rated_at: u64,
+) -> Result<(), Error> {
+    network::post(
+        game_route(game_id, route::game_ratings)?.as_str(),
+        &serde_json::json!({
+            "cabinet": env::cabinet_name(),
+            "handle": handle,
+            "stars": stars,
+            "rated_at": rated_at,
+        }),
+    )
+    .await
 }
 
 /**
- * Get the list of games currently installed on the filesystem. This can be used if the API is down.
- * This is not the preferred method of getting games.
+ * Get a list of games from the API. This is the preferred method of getting games.
  *
  * # Errors
- * This function will return an error if the filesystem cannot be read at the DEVCADE_PATH location.
+ * This function will return an error if the request fails, or if the JSON cannot be deserialized
  */
-pub fn game_list_from_fs() -> Result<Vec<DevcadeGame>, Error> {
-    let mut games = Vec::new();
-    for entry in std::fs::read_dir(devcade_path())? {
-        let entry = entry?;
-        let path = entry.path();
-        if !path.is_dir() {
-            continue;
-        }
-
-        for entry_ in std::fs::read_dir(path)? {
-            let entry_ = entry_?;
-            let path_ = entry_.path();
-            if path_.is_dir() {
-                continue;
-            }
-
-            if let Ok(game) = game_from_path(&path_) {
-                games.push(game);
-            }
-        }
+pub async fn game_list() -> Result<Vec<DevcadeGame>, Error> {
+    let url = format!("{}/{}", api_url(), route::game_list());
+    let (games, staging, ratings) = futures_util::join!(
+        network::request_json_checked(url.as_str(), |games: &Vec<DevcadeGame>| {
+            check_game_list(games)
+        }),
+        staging_game_list(),
+        ratings()
+    );
+    let mut games = games?
+        .into_iter()
+        .chain(staging)
+        .filter(|game| game.hash.is_some())
+        .collect::<Vec<DevcadeGame>>();
+    add_ratings(&mut games, ratings);
+    for game in &games {
+        cache::GAMES.insert(game.id.clone(), game.clone());
     }
+    LAST_API_SYNC.store(unix_now(), Ordering::SeqCst);
     Ok(games)
 }
 
 /**
- * Download's a game's banner from the API.
- *
- * # Errors
- * This function will return an error if the request fails, or if the filesystem cannot be written to.
+ * Get when the game list was last fetched from the API, in seconds since the unix epoch, or `None`
+ * if it hasn't been since the backend started
  */
-pub async fn download_banner(game_id: String) -> Result<(), Error> {
-    let path = Path::new(devcade_path().as_str())
-        .join(game_id.clone())
-        .join("banner.png");
-    if path.exists() {
-        return Ok(());
-    }
-    if !path.parent().unwrap().exists() {
-        std::fs::create_dir_all(path.parent().unwrap())?;
+#[must_use]
+pub fn last_api_sync() -> Option<u64> {
+    match LAST_API_SYNC.load(Ordering::SeqCst) {
+        0 => None,
+        at => Some(at),
     }
-
-    let bytes = network::request_bytes(
-        format!("{}/{}", api_url(), route::game_banner(game_id.as_str())).as_str(),
-    )
-    .await?;
-    std::fs::write(path, bytes)?;
-    Ok(())
 }
 
 /**
- * Download's a game's icon from the API.
- *
- * # Errors
- * This function will return an error if the request fails, or if the filesystem cannot be written to.
+ * The API always has games, so an empty list means something went wrong on its end, and showing it
+ * would empty the menu
  */
-pub async fn download_icon(game_id: String) -> Result<(), Error> {
-    let api_url = api_url();
-    let file_path = devcade_path();
-
-    let path = Path::new(file_path.as_str())
-        .join(game_id.clone())
-        .join("icon.png");
-    if path.exists() {
-        return Ok(());
+fn check_game_list(games: &[DevcadeGame]) -> Result<(), String> {
+    if games.is_empty() {
+        return Err(String::from("The game list is empty"));
     }
-    if !path.parent().unwrap().exists() {
-        std::fs::create_dir_all(path.parent().unwrap())?;
+    if let Some(game) = games.iter().find(|game| game.id.is_empty()) {
+        return Err(format!("Game '{}' has no ID", game.name));
     }
-
-    let bytes = network::request_bytes(
-        format!("{}/{}", api_url, route::game_icon(game_id.as_str())).as_str(),
-    )
-    .await?;
-    std::fs::write(path, bytes)?;
     Ok(())
 }
 
-pub async fn nfc_tags(reader_id: Player) -> Result<Option<String>, Error> {
-    assert!(reader_id == Player::P1);
-    NFC_CLIENT
-        .submit()
-        .await
-        .map_err(|err| anyhow!("Couldn't get NFC tags: {:?}", err))
+/**
+ * Get the games uploaded to the staging API, if staff mode is on and a staging API is set. Failures
+ * are logged and treated as no games, so a broken staging API can't hide the production menu.
+ */
+async fn staging_game_list() -> Vec<DevcadeGame> {
+    let Some(staging_url) = env::staging_api_url().filter(|_| env::staff_mode()) else {
+        return Vec::new();
+    };
+    match network::request_json::<Vec<DevcadeGame>>(
+        format!("{staging_url}/{}", route::game_list()).as_str(),
+    )
+    .await
+    {
+        Ok(games) => games.into_iter().map(into_staging).collect(),
+        Err(e) => {
+            log::warn!("Couldn't get staging games: {e}");
+            Vec::new()
+        }
+    }
 }
 
-pub async fn nfc_user(association_id: String) -> Result<Map<String, Value>, Error> {
-    NFC_CLIENT
-        .get_user(association_id)
-        .await
-        .map_err(|err| anyhow!("Couldn't get NFC user: {:?}", err))
+/**
+ * Mark a game fetched from the staging API as such, giving it its local ID
+ */
+fn into_staging(mut game: DevcadeGame) -> DevcadeGame {
+    game.id = format!("{STAGING_PREFIX}{}", game.id);
+    game.channel = GameChannel::Staging;
+    game
 }
 
-async fn install_flatpak_bundle_async(bundle_path: PathBuf) -> Result<String, Error> {
-    let (tx, rx) = oneshot::channel();
-    std::thread::spawn(move || {
-        tx.send(install_flatpak_bundle(&bundle_path))
-            .expect("Server thread died before we could send flatpak install response?")
-    });
-    match rx.await {
-        Ok(result) => result,
-        Err(err) => Err(err.into()),
+/**
+ * Leave staging games out of a game list unless staff mode is on
+ */
+#[must_use]
+pub fn hide_staging(games: Vec<DevcadeGame>) -> Vec<DevcadeGame> {
+    if env::staff_mode() {
+        return games;
     }
+    games
+        .into_iter()
+        .filter(|game| game.channel != GameChannel::Staging)
+        .collect()
 }
 
-fn install_flatpak_bundle(bundle_path: &Path) -> Result<String, Error> {
-    let transaction = Transaction::for_installation(
-        &Installation::new_user(None::<&gio::Cancellable>)?,
-        None::<&gio::Cancellable>,
-    )?;
-    transaction.set_no_pull(false);
-    transaction.set_no_interaction(true);
-    transaction.add_default_dependency_sources();
-    transaction.add_install_bundle(&gio::File::for_path(bundle_path), None)?;
-    transaction.set_reinstall(true);
-    let (tx_app_id, rx_app_id) = std::sync::mpsc::channel::<String>();
-    transaction.connect_ready(move |transaction| {
-        // Return false to abort!
-        let mut app_name = None::<String>;
-        for op in transaction.operations() {
-            log::debug!(
-                "Processing operation for bundle {:?}",
-                op.bundle_path().map(|path| path.to_string())
-            );
-            if let Some(metadata) = op.metadata() {
-                log::debug!("Checking metadata: {}", metadata.to_data().as_str());
-                let name = metadata
-                    .string("Application", "name")
-                    .map(|name| name.to_string());
-                if let Ok(name) = &name {
-                    log::info!("Found an app name {name}");
-                    app_name = Some(name.clone());
-                }
-                log::debug!("Name of bundle is {name:?}");
-                match is_install_allowed(&metadata) {
-                    Ok(true) => {
-                        log::debug!("All permissions look OK on app {name:?}");
-                    }
-                    Ok(false) => {
-                        log::error!("Aborting installation of {name:?}");
-                        return false;
-                    }
-                    Err(err) => {
-                        log::error!("Aborting installation of {name:?} due to error {err}");
-                        return false;
-                    }
-                }
-            } else {
-                println!("no data for {:?}", op.bundle_path());
-            }
+/**
+ * Get the URL of one of a game's routes, on the API the game was published to
+ *
+ * # Errors
+ * This function will return an error if the game is from staging but no staging API is set.
+ */
+fn game_route(game_id: &str, route: fn(&str) -> String) -> Result<String, Error> {
+    match game_id.strip_prefix(STAGING_PREFIX) {
+        Some(api_id) => {
+            let staging_url = env::staging_api_url().ok_or_else(|| {
+                anyhow!("Game {game_id} is from staging, but DEVCADE_STAGING_API_DOMAIN isn't set")
+            })?;
+            Ok(format!("{staging_url}/{}", route(api_id)))
         }
-        tx_app_id.send(app_name.unwrap()).unwrap();
-        // looks like we're good!
-        true
-    });
-    transaction.run(None::<&gio::Cancellable>)?;
-    Ok(rx_app_id.recv().unwrap())
-    //Ok("todo".to_owned())
+        None => Ok(format!("{}/{}", api_url(), route(game_id))),
+    }
 }
 
-fn is_install_allowed(metadata: &gio::glib::KeyFile) -> Result<bool, Error> {
-    if !metadata.has_group("Context") {
-        return Ok(true);
+/**
+ * Get a specific game from the API. This is the preferred method of getting games. The result may
+ * come from the metadata cache, so use `fetch_game` when the latest version is needed.
+ *
+ * # Errors
+ * This function will return an error if the request fails, or if the JSON cannot be deserialized
+ */
+pub async fn get_game(id: &str) -> Result<DevcadeGame, Error> {
+    if let Some(game) = cache::GAMES.get(&id.to_string()) {
+        return Ok(game);
     }
-    let allowed_permissions = HashMap::from([
-        ("shared", HashSet::from(["network", "ipc"])),
-        ("sockets", HashSet::from(["x11", "pulseaudio"])),
-        ("devices", HashSet::from(["dri", "input"])),
-        (
-            "filesystems",
-            HashSet::from(["/tmp/devcade/persistence.sock", "/tmp/devcade/game.sock"]),
-        ),
-    ]);
+    fetch_game(id).await
+}
 
-    for (realm, allowed_capabilities) in allowed_permissions.iter() {
-        if !metadata.has_key("Context", realm)? {
-            continue;
-        }
-        for capability in metadata
-            .string_list("Context", realm)?
-            .iter()
-            .map(|entry| entry.to_str())
-        {
-            if !allowed_capabilities.contains(capability) {
-                // Disallowed/unknown cap!
-                log::error!("Unknown capability {realm}={capability} is not allowed!");
-                return Ok(false);
+/**
+ * Get a specific game from the API, skipping the metadata cache. The cache is refreshed with the
+ * result. This is used when checking for updates, where a stale hash would launch an old build.
+ *
+ * # Errors
+ * This function will return an error if the request fails, or if the JSON cannot be deserialized
+ */
+pub async fn fetch_game(id: &str) -> Result<DevcadeGame, Error> {
+    // Staging games are asked for by their ID on the staging API
+    let api_id = id.strip_prefix(STAGING_PREFIX).unwrap_or(id);
+    let mut game: DevcadeGame = network::request_json_checked(
+        game_route(id, route::game)?.as_str(),
+        |game: &DevcadeGame| {
+            if game.id == api_id {
+                Ok(())
+            } else {
+                Err(format!("Asked for game {api_id} but got {}", game.id))
             }
-        }
+        },
+    )
+    .await?;
+    if id.starts_with(STAGING_PREFIX) {
+        game = into_staging(game);
     }
+    add_ratings(std::slice::from_mut(&mut game), ratings().await);
+    cache::GAMES.insert(id.to_string(), game.clone());
+    Ok(game)
+}
 
-    for realm in metadata.keys("Context")?.iter().map(|entry| entry.to_str()) {
-        if !allowed_permissions.contains_key(realm) {
-            log::error!("Unknown realm {realm} is not allowed!");
-            return Ok(false);
-        }
+/**
+ * Get every game's aggregate rating, by game ID. The ratings are cached like the rest of the
+ * metadata, so fetching games one at a time doesn't fetch them again.
+ *
+ * # Errors
+ * This function will return an error if the request fails, or if the JSON cannot be deserialized
+ */
+async fn ratings() -> Result<HashMap<String, GameRating>, Error> {
+    if let Some(ratings) = cache::RATINGS.get(&()) {
+        return Ok(ratings);
     }
+    let ratings: HashMap<String, GameRating> =
+        network::request_json(format!("{}/{}", api_url(), route::ratings()).as_str()).await?;
+    cache::RATINGS.insert((), ratings.clone());
+    Ok(ratings)
+}
 
-    Ok(true)
+/**
+ * Fill in games' ratings. Ratings aren't needed to play anything, so if they couldn't be fetched
+ * the games are left without them.
+ */
+fn add_ratings(games: &mut [DevcadeGame], ratings: Result<HashMap<String, GameRating>, Error>) {
+    let ratings = match ratings {
+        Ok(ratings) => ratings,
+        Err(e) => {
+            log::debug!("Couldn't fetch game ratings: {e}");
+            return;
+        }
+    };
+    for game in games {
+        game.rating = ratings.get(&game.id).copied();
+    }
 }
 
 /**
- * Download's a game's zip file from the API and unzips it into the game's directory. If the game is
- * already downloaded, it will check if the hash is the same. If it is, it will not download the game
- * again.
+ * Get several games from the API in a single request. If the API doesn't support the batch route
+ * (it responds with 404 or 405), this falls back to fetching each game individually, with at most
+ * `MAX_PARALLEL_FETCHES` requests in flight at once. Games missing from a batch response are also
+ * fetched individually. Games that fail to fetch individually are logged and skipped.
+ *
+ * The returned games are in the same order as `ids`.
  *
  * # Errors
- * This function will return an error if the request fails, or if the filesystem cannot be written to.
+ * This function will return an error if the batch request fails for any reason other than the
+ * route being unavailable. Individual fetch failures are not errors.
  */
-pub async fn download_game(game_id: String) -> Result<DevcadeGame, Error> {
-    log::debug!("Downloading a game!");
-    let game_dir = Path::new(devcade_path().as_str()).join(game_id.clone());
-    let game_json_path = game_dir.join("game.json");
+pub async fn get_games(ids: &[String]) -> Result<Vec<DevcadeGame>, Error> {
+    let mut games: HashMap<String, DevcadeGame> = ids
+        .iter()
+        .filter_map(|id| cache::GAMES.get(id).map(|game| (id.clone(), game)))
+        .collect();
+    let uncached: Vec<String> = ids
+        .iter()
+        .filter(|id| !games.contains_key(*id))
+        .cloned()
+        .collect();
+    if uncached.is_empty() {
+        return Ok(ids.iter().filter_map(|id| games.remove(id)).collect());
+    }
 
-    let local_game = game_from_path(&game_json_path);
-    let mut game = match get_game(game_id.as_str()).await {
-        Ok(game) => {
-            log::debug!("Fetched game meta!");
-            game
+    // Staging games come from another API, so they're always fetched individually
+    let production: Vec<String> = uncached
+        .iter()
+        .filter(|id| !id.starts_with(STAGING_PREFIX))
+        .cloned()
+        .collect();
+    let mut batch_available = !production.is_empty();
+    let batch = match batch_available {
+        false => Ok(Vec::new()),
+        true => {
+            network::post_json::<[String], Vec<DevcadeGame>>(
+                format!("{}/{}", api_url(), route::game_batch()).as_str(),
+                production.as_slice(),
+            )
+            .await
         }
-        Err(err) => {
-            log::warn!("Couldn't request live info on game! Falling back to local file! {err:?}");
-            local_game
-                .as_ref()
-                .expect("Game not downloaded and we're offline!")
-                .clone()
+    };
+    let batch = match batch {
+        Ok(games) => games,
+        Err(err) if route_unavailable(&err) => {
+            tracing::debug!(
+                "Batch game route unavailable, falling back to single fetches: {}",
+                err
+            );
+            batch_available = false;
+            Vec::new()
         }
+        Err(err) => return Err(err),
     };
-    // Is the current hash == the remote hash?
-    if let Ok(local_game) = local_game {
-        if local_game.hash == game.hash {
-            return Ok(local_game);
+
+    // The API doesn't promise to return every game we asked for, or to return them in order
+    for game in batch {
+        if uncached.contains(&game.id) {
+            cache::GAMES.insert(game.id.clone(), game.clone());
+            games.insert(game.id.clone(), game);
+        } else {
+            tracing::warn!("Batch response included unrequested game {}", game.id);
         }
     }
 
-    log!(Level::Info, "Downloading game {}...", game.name);
-
-    let bytes = network::request_bytes(
-        format!("{}/{}", api_url(), route::game_download(game_id.as_str())).as_str(),
-    )
-    .await?;
-
-    log!(Level::Info, " game {}...", game.name);
-    log!(Level::Trace, "Flatpak bundle size: {} bytes", bytes.len());
-
-    // // install flatpak
-    tokio::fs::create_dir_all(&game_dir).await?;
-    let bundle_path = game_dir.join("bundle.flatpak").to_owned();
-    tokio::fs::write(&bundle_path, &bytes).await?;
-
-    game.flatpak_app_id = Some(install_flatpak_bundle_async(bundle_path).await?);
-    log::info!("Hi, flatpak app id {:?}", game.flatpak_app_id);
-
-    // Write the game's JSON file to the game's directory (this is used later to get the games from
-    // the filesystem)
-    log!(
-        Level::Debug,
-        "Writing game.json file for game {}...",
-        game.name
-    );
-    log!(
-        Level::Trace,
-        "Game json path: {}",
-        game_json_path.to_str().unwrap()
-    );
-    let json = serde_json::to_string(&game)?;
-    match tokio::fs::write(&game_json_path, json).await {
-        Ok(_) => {}
-        Err(e) => {
-            log!(Level::Warn, "Error writing game.json file: {}", e);
-            return Err(e.into());
+    let missing: Vec<String> = uncached
+        .into_iter()
+        .filter(|id| !games.contains_key(id))
+        .collect();
+    if batch_available && !missing.is_empty() {
+        tracing::debug!(
+            "Batch response was missing {} games, fetching them individually",
+            missing.len()
+        );
+    }
+    let fetched: Vec<(String, Result<DevcadeGame, Error>)> = futures_util::stream::iter(missing)
+        .map(|id| async move {
+            let game = get_game(id.as_str()).await;
+            (id, game)
+        })
+        .buffered(MAX_PARALLEL_FETCHES)
+        .collect()
+        .await;
+    for (id, game) in fetched {
+        match game {
+            Ok(game) => {
+                games.insert(id, game);
+            }
+            Err(err) => tracing::warn!("Failed to get game {id}: {err}"),
         }
-    };
-    log::debug!("Downloaded game {game:?}");
+    }
 
-    Ok(game)
+    Ok(ids.iter().filter_map(|id| games.remove(id)).collect())
 }
 
-fn generate_clean_env() -> HashMap<String, String> {
-    // needs to be outside command builder because std::env::vars() is not Send
-    // and even though this creates owned copies of everything, it still doesn't like it.
-    std::env::vars()
-        .filter(|(ref key, _value)| {
-            key == "DISPLAY"
-                || key == "XAUTHORITY"
-                || key.starts_with("XDG_")
-                || key.starts_with("DBUS_")
-                || key.starts_with("LC_")
-                || key == "LANG"
-                || key == "TERM"
-                || key == "DEVCADE_PATH"
+/**
+ * Whether a request failed because the route doesn't exist on the API (as opposed to the server
+ * failing, the request timing out, or the response being malformed).
+ */
+fn route_unavailable(err: &Error) -> bool {
+    err.downcast_ref::<network::ApiError>()
+        .map(|err| err.status)
+        .is_some_and(|status| {
+            status == reqwest::StatusCode::NOT_FOUND
+                || status == reqwest::StatusCode::METHOD_NOT_ALLOWED
         })
-        .map(|(k, v)| (k.clone(), v.clone()))
-        .collect::<HashMap<String, String>>()
 }
 
 /**
- * Launch a game by its ID. This will check if the game is downloaded, and if it is, it will launch
- * the game. This returns a `JoinHandle`, which should be used to check for game exit and notify the
- * backend.
+ * Record a game directory that couldn't be scanned, logging why it was skipped
+ */
+fn skip_corrupt(installed: &mut InstalledGames, path: &Path, error: impl Display) {
+    tracing::warn!("Skipping corrupt game at {:?}: {}", path, error);
+    installed.corrupt.push(CorruptGame {
+        path: path.to_string_lossy().to_string(),
+        error: error.to_string(),
+    });
+}
+
+/**
+ * Get the list of games currently installed on the filesystem. This can be used if the API is down.
+ * This is not the preferred method of getting games.
  *
- * # Errors
- * This function will return an error if the filesystem cannot be read from,
- * or if the game cannot be launched.
+ * Each game directory is read concurrently. Directories without a `game.json` (e.g. a game whose
+ * download never finished) are skipped, and directories that can't be read or have a malformed
+ * `game.json` are logged and returned separately so they can be reported.
  *
- * # Panics
- * This function will never panic, but contains an `unwrap` call that will never fail. This section
- * is here to make clippy happy.
+ * # Errors
+ * This function will return an error if the DEVCADE_PATH directory itself cannot be read.
  */
-pub async fn launch_game(game_id: String) -> Result<(), Error> {
-    let path = Path::new(devcade_path().as_str())
-        .join(game_id.clone())
-        .join("publish");
-
-    log!(Level::Info, "Launching game {}...", game_id);
-    log!(Level::Trace, "Game path: {}", path.to_str().unwrap());
+pub async fn game_list_from_fs() -> Result<InstalledGames, Error> {
+    let mut installed = InstalledGames::default();
+    let devcade_path = storage::root();
+    let mut game_json_paths = Vec::new();
+    let mut entries = fs::read_dir(&devcade_path).await?;
+    loop {
+        let entry = match entries.next_entry().await {
+            Ok(Some(entry)) => entry,
+            Ok(None) => break,
+            Err(err) => {
+                // The directory listing itself is broken, so there's nothing more to read
+                skip_corrupt(&mut installed, &devcade_path, err);
+                break;
+            }
+        };
+        match entry.file_type().await {
+            Ok(file_type) if file_type.is_dir() => {}
+            Ok(_) => continue,
+            Err(err) => {
+                skip_corrupt(&mut installed, &entry.path(), err);
+                continue;
+            }
+        }
+        let path = entry.path().join(GAME_JSON);
+        match fs::try_exists(&path).await {
+            Ok(true) => game_json_paths.push(path),
+            Ok(false) => tracing::debug!("Skipping {:?}, it has no game.json", entry.path()),
+            Err(err) => skip_corrupt(&mut installed, &path, err),
+        }
+    }
 
-    // Downloads game if we don't already have it
-    let game = download_game(game_id.clone()).await?;
+    let results = futures_util::future::join_all(game_json_paths.into_iter().map(|path| async {
+        let game = game_from_path(&path).await;
+        (path, game)
+    }))
+    .await;
 
-    // flush data every time a new game is opened (in case previous launched game forgor)
-    match persistence_flush().await {
-        Ok(_) => {}
-        Err(e) => log::warn!("Failed to flush save cache: {e}"),
+    for (path, game) in results {
+        match game {
+            Ok(game) => installed.games.push(game),
+            Err(err) => skip_corrupt(&mut installed, &path, err),
+        }
     }
-    *CURRENT_GAME.lock().unwrap() = Some(game.clone());
-
-    let envs = generate_clean_env();
-    log!(Level::Trace, "Game ENV: {:?}", envs);
+    Ok(installed)
+}
 
-    // Launch the game and silence stdout (allow the game to print to stderr)
-    let mut child = Command::new("flatpak")
+/**
+ * Download's a game's banner from the API.
+ *
+ * # Errors
+ * This function will return an error if the request fails, or if the filesystem cannot be written to.
+ */
+pub async fn download_banner(game_id: String) -> Result<(), Error> {
+    let path = storage::game_file(game_id.as_str(), BANNER);
+    if path.exists() {
+        return Ok(());
+    }
+    if !path.parent().unwrap().exists() {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+    }
+
+    let bytes =
+        network::request_bytes(game_route(game_id.as_str(), route::game_banner)?.as_str()).await?;
+    atomic::write_async(path, bytes).await?;
+    // Games that aren't installed yet have it inventoried along with the rest of their files
+    if !storage::is_inventoried(game_id.as_str())? {
+        return Ok(());
+    }
+    storage::record_file(game_id.as_str(), BANNER).await
+}
+
+/**
+ * Download's a game's icon from the API.
+ *
+ * # Errors
+ * This function will return an error if the request fails, or if the filesystem cannot be written to.
+ */
+pub async fn download_icon(game_id: String) -> Result<(), Error> {
+    let path = storage::game_file(game_id.as_str(), ICON);
+    if path.exists() {
+        return Ok(());
+    }
+    if !path.parent().unwrap().exists() {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+    }
+
+    let bytes =
+        network::request_bytes(game_route(game_id.as_str(), route::game_icon)?.as_str()).await?;
+    atomic::write_async(path, bytes).await?;
+    // Games that aren't installed yet have it inventoried along with the rest of their files
+    if !storage::is_inventoried(game_id.as_str())? {
+        return Ok(());
+    }
+    storage::record_file(game_id.as_str(), ICON).await
+}
+
+#[tracing::instrument(skip_all, fields(reader = ?reader_id, realm = ?realm))]
+pub async fn nfc_tags(reader_id: Player, realm: NfcRealm) -> Result<Option<String>, Error> {
+    if !env::nfc_allowed_realms().contains(&realm) {
+        return Err(anyhow!(tr(
+            "nfc_realm_not_allowed",
+            &[("realm", format!("{realm:?}").as_str())]
+        )));
+    }
+    let association_id = nfc::nfc_client(&reader_id)
+        .submit(realm)
+        .await
+        .map_err(|err| {
+            metrics::count(Counter::NfcFailures, 1);
+            anyhow!("Couldn't get NFC tags: {err}")
+        })?;
+    // Someone who played as a guest may want to keep what they did now that they've badged in
+    if let Some(association_id) = &association_id {
+        let guests = guests::offer_merge(association_id.as_str());
+        if !guests.is_empty() {
+            crate::events::emit(Event::GuestMergeOffered(association_id.clone(), guests));
+        }
+        ticker::badged_in(reader_id);
+    }
+    Ok(association_id)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn nfc_user(association_id: String) -> Result<Map<String, Value>, Error> {
+    if association_id.starts_with(guests::GUEST_PREFIX) {
+        return guests::get(association_id.as_str())
+            .map(|guest| guests::as_user(&guest))
+            .ok_or_else(|| anyhow!(tr("nfc_user_not_found", &[])));
+    }
+    nfc::get_user(association_id)
+        .await
+        .map_err(|err| anyhow!("Couldn't get NFC user: {err}"))
+}
+
+/**
+ * A login started with the API, to be confirmed on a phone
+ */
+#[derive(Deserialize)]
+pub struct QrLogin {
+    pub code: String,
+    /**
+     * Where the phone confirms the login, shown as a QR code
+     */
+    pub url: String,
+    pub expires_in_secs: u64,
+}
+
+/**
+ * Who a login belongs to, once it's confirmed
+ */
+#[derive(Deserialize)]
+struct LoginResult {
+    association_id: Option<String>,
+}
+
+/**
+ * A copy of a player's saves for a game, kept by the API
+ */
+#[derive(Default, Serialize, Deserialize)]
+pub struct SaveBackup {
+    /**
+     * The contents of each file in the player's save directory, base64 encoded, by path from the
+     * directory
+     */
+    pub files: BTreeMap<String, String>,
+}
+
+/**
+ * Upload a copy of a player's saves for a game, replacing the last one
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or doesn't keep backups.
+ */
+pub async fn upload_saves(game_id: &str, handle: &str, backup: &SaveBackup) -> Result<(), Error> {
+    network::post(
+        format!("{}/{}", api_url(), route::saves(game_id, handle)).as_str(),
+        backup,
+    )
+    .await
+}
+
+/**
+ * Upload scores players got on one of a game's leaderboards
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or refuses the scores.
+ */
+pub async fn upload_scores(
+    game_id: &str,
+    board: &str,
+    entries: &[LeaderboardEntry],
+) -> Result<(), Error> {
+    network::post(
+        format!("{}/{}", api_url(), route::leaderboard(game_id, board)).as_str(),
+        entries,
+    )
+    .await
+}
+
+/**
+ * Check that the API answers, without caring what it answers with
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached in time.
+ */
+pub async fn api_reachable(timeout: Duration) -> Result<(), Error> {
+    network::reachable(api_url().as_str(), timeout).await
+}
+
+/**
+ * Register the cabinet with the API, or update what it knows about it
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or refuses the cabinet.
+ */
+pub async fn register_cabinet(identity: &CabinetIdentity) -> Result<(), Error> {
+    network::post(
+        format!("{}/{}", api_url(), route::cabinets()).as_str(),
+        &serde_json::json!({
+            "id": identity.id,
+            "name": identity.name,
+            "location": identity.location,
+            "version": env!("CARGO_PKG_VERSION"),
+        }),
+    )
+    .await
+}
+
+/**
+ * Get the newest onboard release the API has
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or doesn't publish releases.
+ */
+pub async fn latest_release() -> Result<self_update::Release, Error> {
+    network::request_json(format!("{}/{}", api_url(), route::latest_release()).as_str()).await
+}
+
+/**
+ * Download an onboard release's binary
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or doesn't have the release.
+ */
+pub async fn download_release(version: &str) -> Result<Vec<u8>, Error> {
+    network::request_bytes(format!("{}/{}", api_url(), route::release_binary(version)).as_str())
+        .await
+}
+
+/**
+ * Upload play events to the API's analytics
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or refuses the events.
+ */
+pub async fn upload_play_events(events: &[PlayEvent]) -> Result<(), Error> {
+    network::post(
+        format!("{}/{}", api_url(), route::play_events()).as_str(),
+        events,
+    )
+    .await
+}
+
+/**
+ * Push a batch of log streams to a Loki server. This goes to the server's own URL, not the API.
+ *
+ * # Errors
+ * This function will return an error if the server can't be reached, or refuses the logs.
+ */
+pub async fn push_logs(url: &str, streams: &Value) -> Result<(), Error> {
+    network::post(url, streams).await
+}
+
+/**
+ * Upload achievements a player unlocked in a game
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or refuses the unlocks.
+ */
+pub async fn upload_achievements(
+    game_id: &str,
+    handle: &str,
+    unlocks: &[AchievementUnlock],
+) -> Result<(), Error> {
+    network::post(
+        format!("{}/{}", api_url(), route::achievements(game_id, handle)).as_str(),
+        unlocks,
+    )
+    .await
+}
+
+/**
+ * Tell the API a game was made one of a badged user's favorites, or taken out of them
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or refuses the change.
+ */
+pub async fn set_favorite(
+    handle: &str,
+    game_id: &str,
+    favorite: bool,
+    favorited_at: u64,
+) -> Result<(), Error> {
+    network::post(
+        format!("{}/{}", api_url(), route::favorites(handle)).as_str(),
+        &serde_json::json!({
+            "game_id": game_id,
+            "favorite": favorite,
+            "favorited_at": favorited_at,
+        }),
+    )
+    .await
+}
+
+/**
+ * Download the last copy of a player's saves for a game that was uploaded from any cabinet
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or has no copy.
+ */
+pub async fn download_saves(game_id: &str, handle: &str) -> Result<SaveBackup, Error> {
+    network::request_json(format!("{}/{}", api_url(), route::saves(game_id, handle)).as_str()).await
+}
+
+/**
+ * Start a login that's confirmed on a phone
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or doesn't support QR logins.
+ */
+pub async fn start_qr_login() -> Result<QrLogin, Error> {
+    network::post_json(
+        format!("{}/{}", api_url(), route::login_qr()).as_str(),
+        &serde_json::json!({ "cabinet": env::cabinet_name() }),
+    )
+    .await
+}
+
+/**
+ * Check whether a login started with a QR code was confirmed, getting who confirmed it
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or the login expired.
+ */
+pub async fn qr_login_status(code: &str) -> Result<Option<String>, Error> {
+    let result: LoginResult =
+        network::request_json(format!("{}/{}", api_url(), route::login_qr_status(code)).as_str())
+            .await?;
+    Ok(result.association_id)
+}
+
+/**
+ * Get who a login PIN belongs to, or `None` if it isn't anyone's
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or doesn't support PIN logins.
+ */
+pub async fn pin_login(pin: &str) -> Result<Option<String>, Error> {
+    let result: LoginResult = network::post_json(
+        format!("{}/{}", api_url(), route::login_pin()).as_str(),
+        &serde_json::json!({ "cabinet": env::cabinet_name(), "pin": pin }),
+    )
+    .await?;
+    Ok(result.association_id)
+}
+
+#[tracing::instrument(skip_all, fields(bundle = %bundle_path.display()))]
+async fn install_flatpak_bundle_async(
+    bundle_path: PathBuf,
+    report: install_report::Recorder,
+) -> Result<String, Error> {
+    let (tx, rx) = oneshot::channel();
+    std::thread::spawn(move || {
+        tx.send(install_flatpak_bundle(&bundle_path, &report))
+            .expect("Server thread died before we could send flatpak install response?")
+    });
+    match rx.await {
+        Ok(result) => result,
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn install_flatpak_bundle(
+    bundle_path: &Path,
+    report: &install_report::Recorder,
+) -> Result<String, Error> {
+    let transaction = Transaction::for_installation(
+        &Installation::new_user(None::<&gio::Cancellable>)?,
+        None::<&gio::Cancellable>,
+    )?;
+    transaction.set_no_pull(false);
+    transaction.set_no_interaction(true);
+    transaction.add_default_dependency_sources();
+    transaction.add_install_bundle(&gio::File::for_path(bundle_path), None)?;
+    transaction.set_reinstall(true);
+    let (tx_app_id, rx_app_id) = std::sync::mpsc::channel::<String>();
+    let operation_report = report.clone();
+    transaction.connect_new_operation(move |_, operation, progress| {
+        operation_report.line(format!(
+            "{} {}",
+            operation
+                .operation_type()
+                .to_str()
+                .map(|name| name.to_string())
+                .unwrap_or_default(),
+            operation
+                .get_ref()
+                .map(|name| name.to_string())
+                .unwrap_or_default()
+        ));
+        // Progress changes many times a second, so only new statuses are recorded
+        let progress_report = operation_report.clone();
+        let last_status = std::cell::RefCell::new(None::<String>);
+        progress.connect_changed(move |progress| {
+            let status = progress.status().map(|status| status.to_string());
+            if status.is_some() && *last_status.borrow() != status {
+                progress_report.line(format!(
+                    "{} ({}%)",
+                    status.as_deref().unwrap_or_default(),
+                    progress.progress()
+                ));
+                *last_status.borrow_mut() = status;
+            }
+        });
+    });
+    let ready_report = report.clone();
+    transaction.connect_ready(move |transaction| {
+        // Return false to abort!
+        let mut app_name = None::<String>;
+        for op in transaction.operations() {
+            log::debug!(
+                "Processing operation for bundle {:?}",
+                op.bundle_path().map(|path| path.to_string())
+            );
+            if let Some(metadata) = op.metadata() {
+                log::debug!("Checking metadata: {}", metadata.to_data().as_str());
+                let name = metadata
+                    .string("Application", "name")
+                    .map(|name| name.to_string());
+                if let Ok(name) = &name {
+                    log::info!("Found an app name {name}");
+                    app_name = Some(name.clone());
+                }
+                log::debug!("Name of bundle is {name:?}");
+                match is_install_allowed(&metadata) {
+                    Ok(true) => {
+                        log::debug!("All permissions look OK on app {name:?}");
+                    }
+                    Ok(false) => {
+                        log::error!("Aborting installation of {name:?}");
+                        ready_report.line("Aborted, the app asks for permissions games can't have");
+                        return false;
+                    }
+                    Err(err) => {
+                        log::error!("Aborting installation of {name:?} due to error {err}");
+                        ready_report.line(format!("Aborted, couldn't check permissions: {err}"));
+                        return false;
+                    }
+                }
+            } else {
+                println!("no data for {:?}", op.bundle_path());
+            }
+        }
+        tx_app_id.send(app_name.unwrap()).unwrap();
+        // looks like we're good!
+        true
+    });
+    transaction.run(None::<&gio::Cancellable>)?;
+    Ok(rx_app_id.recv().unwrap())
+    //Ok("todo".to_owned())
+}
+
+fn is_install_allowed(metadata: &gio::glib::KeyFile) -> Result<bool, Error> {
+    if !metadata.has_group("Context") {
+        return Ok(true);
+    }
+    let allowed_permissions = HashMap::from([
+        ("shared", HashSet::from(["network", "ipc"])),
+        (
+            "sockets",
+            HashSet::from(["x11", "wayland", "fallback-x11", "pulseaudio"]),
+        ),
+        ("devices", HashSet::from(["dri", "input"])),
+        (
+            "filesystems",
+            HashSet::from(["/tmp/devcade/persistence.sock", "/tmp/devcade/game.sock"]),
+        ),
+    ]);
+
+    for (realm, allowed_capabilities) in allowed_permissions.iter() {
+        if !metadata.has_key("Context", realm)? {
+            continue;
+        }
+        for capability in metadata
+            .string_list("Context", realm)?
+            .iter()
+            .map(|entry| entry.to_str())
+        {
+            if !allowed_capabilities.contains(capability) {
+                // Disallowed/unknown cap!
+                log::error!("Unknown capability {realm}={capability} is not allowed!");
+                return Ok(false);
+            }
+        }
+    }
+
+    for realm in metadata.keys("Context")?.iter().map(|entry| entry.to_str()) {
+        if !allowed_permissions.contains_key(realm) {
+            log::error!("Unknown realm {realm} is not allowed!");
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/**
+ * Get the latest metadata for a game, along with the installed copy of the game if its hash matches
+ * the latest version. If the API can't be reached, the installed copy is assumed to be the latest.
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached and the game isn't installed.
+ */
+pub(crate) async fn installed_version(
+    game_id: &str,
+) -> Result<(DevcadeGame, Option<DevcadeGame>), Error> {
+    let game_json_path = storage::game_file(game_id, GAME_JSON);
+
+    let local_game = game_from_path(&game_json_path).await;
+    let game = match fetch_game(game_id).await {
+        Ok(game) => {
+            log::debug!("Fetched game meta!");
+            game
+        }
+        Err(err) => {
+            log::warn!("Couldn't request live info on game! Falling back to local file! {err:?}");
+            local_game
+                .as_ref()
+                .map_err(|_| {
+                    anyhow!(tr(
+                        "game_offline",
+                        &[("game_id", game_id), ("error", err.to_string().as_str())]
+                    ))
+                })?
+                .clone()
+        }
+    };
+    // Is the current hash == the remote hash? A version that was rolled back from counts as the
+    // installed one, so the broken update isn't installed again
+    let rolled_back_from = versions::rolled_back_from(game_id).await;
+    let current = local_game.ok().filter(|local| {
+        local.hash == game.hash || (game.hash.is_some() && game.hash == rolled_back_from)
+    });
+    Ok((game, current))
+}
+
+/**
+ * Get what's known about whether a game can be trusted: the signature kept with the installed
+ * bundle, and whether the installed copy is the latest published version.
+ *
+ * # Errors
+ * This function will return an error if the game ID is invalid, or if the API can't be reached and
+ * the game isn't installed.
+ */
+pub async fn game_trust_info(game_id: String) -> Result<GameTrustInfo, Error> {
+    check_game_id(game_id.as_str())?;
+    let installed = fs::try_exists(storage::game_file(game_id.as_str(), GAME_JSON)).await?;
+    let installed_matches = match installed_version(game_id.as_str()).await {
+        Ok((_, current)) => current.is_some(),
+        Err(_) if !installed => false,
+        Err(err) => return Err(err),
+    };
+    Ok(GameTrustInfo {
+        signature: signing::info(game_id.as_str()).await,
+        game_id,
+        installed,
+        installed_matches,
+    })
+}
+
+/**
+ * Check a game's bundle the way installing it would, without installing it. The bundle is downloaded
+ * next to the devcade directory and removed afterwards, so an installed copy is left alone. Checks
+ * that fail are reported in the result rather than as errors, so authors can see everything that's
+ * wrong with an upload at once.
+ *
+ * # Errors
+ * This function will return an error if the game ID is invalid, or if the downloaded bundle can't
+ * be written.
+ */
+pub async fn validate_game(game_id: String) -> Result<BundleValidation, Error> {
+    check_game_id(game_id.as_str())?;
+    let mut validation = BundleValidation {
+        game_id: game_id.clone(),
+        checks: Vec::new(),
+    };
+    let game = match fetch_game(game_id.as_str()).await {
+        Ok(game) => {
+            validation.checks.push(bundle_check(
+                "metadata",
+                true,
+                format!("{} by {}", game.name, game.author),
+            ));
+            game
+        }
+        Err(e) => {
+            validation.checks.push(bundle_check(
+                "metadata",
+                false,
+                format!("Couldn't get the game from the API: {e}"),
+            ));
+            return Ok(validation);
+        }
+    };
+
+    let bundle_path = storage::validate_bundle_path(game_id.as_str());
+    if let Some(parent) = bundle_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let download = match game_route(game_id.as_str(), route::game_download) {
+        Ok(url) => {
+            network::download_to_file(url.as_str(), &bundle_path, MAX_BUNDLE_BYTES, |_, _| {}).await
+        }
+        Err(e) => Err(e),
+    };
+    let bundle_bytes = match download.map_err(|e| e.downcast::<network::TooLarge>()) {
+        Ok(bytes) => bytes,
+        Err(result) => {
+            validation.checks.push(match result {
+                Ok(network::TooLarge(bytes)) => bundle_check(
+                    "bundle_size",
+                    false,
+                    format!("At least {bytes} bytes, at most {MAX_BUNDLE_BYTES} allowed"),
+                ),
+                Err(e) => bundle_check(
+                    "download",
+                    false,
+                    format!("Couldn't download the bundle: {e}"),
+                ),
+            });
+            let _ = fs::remove_file(&bundle_path).await;
+            return Ok(validation);
+        }
+    };
+    validation.checks.push(bundle_check(
+        "bundle_size",
+        true,
+        format!("{bundle_bytes} bytes, at most {MAX_BUNDLE_BYTES} allowed"),
+    ));
+
+    let inspect_path = bundle_path.clone();
+    let entrypoint = game.entrypoint.clone();
+    let checks =
+        tokio::task::spawn_blocking(move || inspect_bundle(&inspect_path, entrypoint.as_deref()))
+            .await;
+    if let Err(e) = fs::remove_file(&bundle_path).await {
+        log::warn!("Couldn't remove validated bundle {:?}: {e}", bundle_path);
+    }
+    validation.checks.extend(checks?);
+    Ok(validation)
+}
+
+fn bundle_check(name: &str, passed: bool, detail: impl Into<String>) -> BundleCheck {
+    BundleCheck {
+        name: name.to_string(),
+        passed,
+        detail: detail.into(),
+    }
+}
+
+/**
+ * Read a downloaded bundle's ref and metadata, and check them against what the cabinet can run.
+ * Files inside the bundle can't be read without installing it, so an entrypoint set in game.json is
+ * only checked for when the game is installed.
+ */
+fn inspect_bundle(path: &Path, entrypoint: Option<&str>) -> Vec<BundleCheck> {
+    let mut checks = Vec::new();
+    let bundle = match BundleRef::new(&gio::File::for_path(path)) {
+        Ok(bundle) => bundle,
+        Err(e) => {
+            checks.push(bundle_check(
+                "bundle",
+                false,
+                format!("Not a flatpak bundle: {e}"),
+            ));
+            return checks;
+        }
+    };
+    checks.push(bundle_check(
+        "bundle",
+        bundle.kind() == RefKind::App,
+        format!(
+            "Contains {}",
+            bundle
+                .format_ref()
+                .map(|r| r.to_string())
+                .unwrap_or_default()
+        ),
+    ));
+
+    let arch = bundle
+        .arch()
+        .map(|arch| arch.to_string())
+        .unwrap_or_default();
+    let supported: Vec<String> = libflatpak::supported_arches()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    checks.push(bundle_check(
+        "arch",
+        supported.contains(&arch),
+        format!(
+            "Built for {arch}, this cabinet runs {}",
+            supported.join(", ")
+        ),
+    ));
+
+    let installed_bytes = bundle.installed_size();
+    checks.push(bundle_check(
+        "installed_size",
+        installed_bytes <= MAX_INSTALLED_BYTES,
+        format!("{installed_bytes} bytes once installed, at most {MAX_INSTALLED_BYTES} allowed"),
+    ));
+
+    let metadata = bundle.metadata().and_then(|bytes| {
+        let metadata = KeyFile::new();
+        metadata
+            .load_from_bytes(&bytes, KeyFileFlags::NONE)
+            .ok()
+            .map(|()| metadata)
+    });
+    let Some(metadata) = metadata else {
+        checks.push(bundle_check(
+            "metadata",
+            false,
+            "The bundle has no readable metadata",
+        ));
+        return checks;
+    };
+
+    let context = metadata
+        .keys("Context")
+        .map(|keys| {
+            keys.iter()
+                .map(|key| key.to_str())
+                .filter_map(|key| {
+                    let value = metadata.value("Context", key).ok()?;
+                    Some(format!("{key}={value}"))
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_default();
+    checks.push(match is_install_allowed(&metadata) {
+        Ok(true) => bundle_check(
+            "permissions",
+            true,
+            "Only asks for permissions games can have",
+        ),
+        Ok(false) => bundle_check(
+            "permissions",
+            false,
+            format!("Asks for permissions games can't have: {context}"),
+        ),
+        Err(e) => bundle_check(
+            "permissions",
+            false,
+            format!("Couldn't read permissions: {e}"),
+        ),
+    });
+
+    let command = metadata
+        .string("Application", "command")
+        .map(|command| command.to_string())
+        .ok();
+    checks.push(match (entrypoint, command) {
+        (Some(entrypoint), _) => bundle_check(
+            "entrypoint",
+            true,
+            format!("game.json runs {entrypoint}, which is checked for when the game is installed"),
+        ),
+        (None, Some(command)) => bundle_check("entrypoint", true, format!("Runs {command}")),
+        (None, None) => bundle_check(
+            "entrypoint",
+            false,
+            "Neither game.json nor the bundle's metadata say what to run",
+        ),
+    });
+    checks
+}
+
+/**
+ * Make sure the latest version of a game is installed. If the installed copy is already up to date
+ * it is returned immediately, otherwise the game is added to the install queue and this waits for
+ * it to be installed.
+ *
+ * # Errors
+ * This function will return an error if the request fails, if the install fails or is cancelled,
+ * or if the filesystem cannot be written to.
+ */
+pub async fn download_game(game_id: String) -> Result<DevcadeGame, Error> {
+    if let (_, Some(local_game)) = installed_version(game_id.as_str()).await? {
+        return Ok(local_game);
+    }
+    if let Some(problem) = readiness::install_problem() {
+        return Err(anyhow!(tr(
+            "install_not_ready",
+            &[("problem", problem.as_str())]
+        )));
+    }
+    install_queue::install(game_id).await
+}
+
+/**
+ * Download's a game's flatpak bundle from the API and installs it. If the game is already
+ * downloaded, it will check if the hash is the same. If it is, it will not download the game
+ * again. This is run by the install queue, everything else should use `download_game`.
+ *
+ * Each stage of the install is saved as it starts. An install that was interrupted is picked up
+ * from its last stage, and one that fails before its flatpak is installed is rolled back.
+ *
+ * # Errors
+ * This function will return an error if the request fails, or if the filesystem cannot be written to.
+ */
+#[tracing::instrument(skip_all, fields(game_id = %game_id))]
+pub async fn install_game(game_id: String) -> Result<DevcadeGame, Error> {
+    log::debug!("Downloading a game!");
+    let mut state = match install_state::resume(game_id.as_str()).await {
+        Some(state) => {
+            log::info!("Resuming install of {game_id} from {:?}", state.stage);
+            state
+        }
+        None => match installed_version(game_id.as_str()).await? {
+            (_, Some(local_game)) => return Ok(local_game),
+            (game, None) => InstallState::begin(game).await?,
+        },
+    };
+
+    match run_install(&mut state).await {
+        Ok(()) => {
+            let game = state.game.clone();
+            if let Err(e) = state.finish().await {
+                log::warn!("Couldn't clear install state for {game_id}: {e}");
+            }
+            Ok(game)
+        }
+        Err(e) => {
+            state.roll_back().await;
+            Err(e)
+        }
+    }
+}
+
+/**
+ * A staging build and the production version of the same game share a flatpak app ID, so
+ * installing one replaces the other's flatpak. The other game is marked as not installed, so it's
+ * installed again the next time it's launched instead of running the wrong build.
+ */
+async fn release_shared_flatpak(game: &DevcadeGame) {
+    let installed = match game_list_from_fs().await {
+        Ok(installed) => installed.games,
+        Err(e) => {
+            log::warn!(
+                "Couldn't check for games sharing {}'s flatpak: {e}",
+                game.id
+            );
+            return;
+        }
+    };
+    for other in installed {
+        if other.id == game.id || other.flatpak_app_id != game.flatpak_app_id {
+            continue;
+        }
+        log::warn!(
+            "Installing {} replaced the flatpak {} was using, it'll be reinstalled when launched",
+            game.id,
+            other.id
+        );
+        if let Err(e) = fs::remove_file(storage::game_file(other.id.as_str(), GAME_JSON)).await {
+            log::warn!("Couldn't mark {} as not installed: {e}", other.id);
+        }
+    }
+}
+
+/**
+ * Fetch the SDL controller mappings the API has for a game and keep them with it. Most games don't
+ * need any, and a game with old mappings is still playable, so failures are only logged.
+ */
+async fn fetch_controller_mappings(game_id: &str) {
+    let path = storage::game_file(game_id, CONTROLLER_MAPPINGS);
+    let fetched = match game_route(game_id, route::game_controller_mappings) {
+        Ok(url) => network::request_json::<Vec<String>>(url.as_str()).await,
+        Err(e) => Err(e),
+    };
+    let saved = match fetched {
+        Ok(mappings) => match serde_json::to_string(&mappings) {
+            Ok(json) => atomic::write_async(&path, json).await,
+            Err(e) => Err(e.into()),
+        },
+        // The game doesn't have mappings (any more)
+        Err(e) if route_unavailable(&e) => match fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        },
+        Err(e) => {
+            log::warn!("Couldn't fetch controller mappings for {game_id}, keeping any it had: {e}");
+            return;
+        }
+    };
+    if let Err(e) = saved {
+        log::warn!("Couldn't save controller mappings for {game_id}: {e}");
+    }
+}
+
+/**
+ * Run an install from whatever stage it's at until the game is installed
+ */
+async fn run_install(state: &mut InstallState) -> Result<(), Error> {
+    let game_id = state.game.id.clone();
+    let game_dir = storage::game_dir(game_id.as_str());
+    let bundle_path = game_dir.join(BUNDLE);
+    loop {
+        match state.stage {
+            InstallStage::Downloading => {
+                tracing::info!("Downloading game {}...", state.game.name);
+                // Fetched first, so a game that can't be installed isn't downloaded
+                let signature = if signing::required() {
+                    Some(
+                        network::request_json::<signing::DetachedSignature>(
+                            game_route(game_id.as_str(), route::game_signature)?.as_str(),
+                        )
+                        .await
+                        .map_err(|e| {
+                            anyhow!(
+                                "{}: {e}",
+                                tr("game_unsigned", &[("game_id", game_id.as_str())])
+                            )
+                        })?,
+                    )
+                } else {
+                    None
+                };
+                // Streamed to a temp file, since bundles can be too big to hold in memory
+                let download_path = atomic::temp_path(&bundle_path)?;
+                let downloaded = network::download_to_file(
+                    game_route(game_id.as_str(), route::game_download)?.as_str(),
+                    &download_path,
+                    MAX_BUNDLE_BYTES,
+                    |received, total| {
+                        install_queue::set_download_progress(game_id.as_str(), received, total)
+                    },
+                )
+                .await;
+                let bundle_bytes = match downloaded {
+                    Ok(bundle_bytes) => bundle_bytes,
+                    Err(e) => {
+                        let _ = fs::remove_file(&download_path).await;
+                        return Err(e);
+                    }
+                };
+                tracing::trace!("Flatpak bundle size: {} bytes", bundle_bytes);
+                if let Err(e) = versions::archive(game_id.as_str()).await {
+                    log::warn!("Couldn't keep the installed version of {game_id}: {e}");
+                }
+                atomic::persist_async(download_path, bundle_path.clone()).await?;
+                let signature_path = game_dir.join(SIGNATURE);
+                match signature {
+                    Some(signature) => {
+                        atomic::write_async(&signature_path, serde_json::to_string(&signature)?)
+                            .await?
+                    }
+                    // A signature left from an earlier version wouldn't match this bundle
+                    None => match fs::remove_file(&signature_path).await {
+                        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                        _ => {}
+                    },
+                }
+                fetch_controller_mappings(game_id.as_str()).await;
+                state.bundle_bytes = bundle_bytes;
+                state.advance(InstallStage::Installing).await?;
+            }
+            InstallStage::Installing => {
+                // Checked from disk, since an install resumed after a reboot didn't download it
+                signing::check(game_id.as_str()).await?;
+                install_queue::set_installing(game_id.as_str(), state.bundle_bytes);
+                let install_started = Instant::now();
+                let report = install_report::Recorder::new(game_id.as_str());
+                report.line(format!("Installing {} byte bundle", state.bundle_bytes));
+                let installed =
+                    install_flatpak_bundle_async(bundle_path.clone(), report.clone()).await;
+                match &installed {
+                    Ok(app_id) => report.line(format!("Installed {app_id}")),
+                    Err(e) => report.line(format!("Install failed: {e}")),
+                }
+                if let Err(e) = report.finish(installed.as_ref().err()).await {
+                    log::warn!("Couldn't save install report for {game_id}: {e}");
+                }
+                state.game.flatpak_app_id = Some(installed?);
+                metrics::observe(Histogram::InstallDuration, install_started.elapsed());
+                if let Err(e) =
+                    install_history::record(state.bundle_bytes, install_started.elapsed()).await
+                {
+                    log::warn!("Couldn't save install history: {e}");
+                }
+                log::info!("Hi, flatpak app id {:?}", state.game.flatpak_app_id);
+                state.advance(InstallStage::Installed).await?;
+            }
+            InstallStage::Installed => {
+                // Write the game's JSON file to the game's directory (this is used later to get
+                // the games from the filesystem)
+                let game = &state.game;
+                release_shared_flatpak(game).await;
+                let game_json_path = game_dir.join(GAME_JSON);
+                tracing::debug!("Writing game.json file for game {}...", game.name);
+                tracing::trace!("Game json path: {}", game_json_path.to_str().unwrap());
+                let json = serde_json::to_string(game)?;
+                if let Err(e) = atomic::write_async(&game_json_path, json).await {
+                    tracing::warn!("Error writing game.json file: {}", e);
+                    return Err(e);
+                }
+                log::debug!("Downloaded game {game:?}");
+                if let Err(e) = storage::record_game(game.id.as_str()).await {
+                    log::warn!("Couldn't add {} to the manifest: {e}", game.id);
+                }
+                if let Err(e) = versions::clear_rolled_back(game.id.as_str()).await {
+                    log::warn!("Couldn't clear rollback of {}: {e}", game.id);
+                }
+                crate::events::emit(Event::GameUpdated(game.id.clone()));
+                return Ok(());
+            }
+        }
+    }
+}
+
+/**
+ * Uninstall a game: its flatpak is removed and its directory is deleted. The installed game watcher
+ * notices the directory going away and announces the removal with a `GameRemoved` event.
+ *
+ * # Errors
+ * This function will return an error if the game is running or isn't installed, or if the flatpak
+ * or the game's directory can't be removed.
+ */
+pub async fn uninstall_game(game_id: String) -> Result<(), Error> {
+    check_game_id(game_id.as_str())?;
+    if current_game().is_some_and(|game| game.id == game_id) {
+        return Err(anyhow!(tr(
+            "uninstall_running",
+            &[("game_id", game_id.as_str())]
+        )));
+    }
+    let game_dir = storage::game_dir(game_id.as_str());
+    let game = game_from_path(&game_dir.join(GAME_JSON))
+        .await
+        .map_err(|e| {
+            anyhow!(tr(
+                "game_not_installed",
+                &[
+                    ("game_id", game_id.as_str()),
+                    ("error", e.to_string().as_str())
+                ]
+            ))
+        })?;
+
+    log::info!("Uninstalling game {game_id}");
+    if let Some(app_id) = game.flatpak_app_id {
+        tokio::task::spawn_blocking(move || uninstall_flatpak(app_id.as_str())).await??;
+    }
+    fs::remove_dir_all(&game_dir).await?;
+    if let Err(e) = storage::forget_game(game_id.as_str()).await {
+        log::warn!("Couldn't remove {game_id} from the manifest: {e}");
+    }
+    Ok(())
+}
+
+/**
+ * Go back to the most recent kept version of a game, such as after a broken update. The version it
+ * was rolled back from is kept too, and isn't installed again until a newer version is published.
+ *
+ * # Errors
+ * This function will return an error if the game is running or being installed, if it isn't
+ * installed or has no earlier version kept, or if the earlier version can't be installed.
+ */
+pub async fn rollback_game(game_id: String) -> Result<DevcadeGame, Error> {
+    check_game_id(game_id.as_str())?;
+    if current_game().is_some_and(|game| game.id == game_id) {
+        return Err(anyhow!(tr(
+            "rollback_running",
+            &[("game_id", game_id.as_str())]
+        )));
+    }
+    if storage::game_file(game_id.as_str(), INSTALL_STATE).exists() {
+        return Err(anyhow!(tr(
+            "rollback_installing",
+            &[("game_id", game_id.as_str())]
+        )));
+    }
+    let game_dir = storage::game_dir(game_id.as_str());
+    let current = game_from_path(&game_dir.join(GAME_JSON))
+        .await
+        .map_err(|e| {
+            anyhow!(tr(
+                "game_not_installed",
+                &[
+                    ("game_id", game_id.as_str()),
+                    ("error", e.to_string().as_str())
+                ]
+            ))
+        })?;
+    let (Some(mut previous), Some(current_hash)) = (
+        versions::list(game_id.as_str())
+            .await
+            .into_iter()
+            .find(|version| version.hash != current.hash),
+        current.hash.clone(),
+    ) else {
+        return Err(anyhow!(tr(
+            "rollback_unavailable",
+            &[("game_id", game_id.as_str())]
+        )));
+    };
+    let previous_hash = previous.hash.clone().unwrap_or_default();
+
+    log::info!("Rolling back {game_id} from {current_hash} to {previous_hash}");
+    // Staged before the current version is kept, since keeping it may prune the one being restored
+    let staged = versions::stage_bundle(game_id.as_str(), previous_hash.as_str()).await?;
+    versions::archive(game_id.as_str()).await?;
+    let bundle_path = game_dir.join(BUNDLE);
+    fs::rename(&staged, &bundle_path).await?;
+    versions::restore_signature(game_id.as_str(), previous_hash.as_str()).await?;
+
+    let report = install_report::Recorder::new(game_id.as_str());
+    report.line(format!(
+        "Rolling back from {current_hash} to {previous_hash}"
+    ));
+    let installed = match signing::check(game_id.as_str()).await {
+        Ok(()) => install_flatpak_bundle_async(bundle_path.clone(), report.clone()).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = report.finish(installed.as_ref().err()).await {
+        log::warn!("Couldn't save install report for {game_id}: {e}");
+    }
+    let app_id = match installed {
+        Ok(app_id) => app_id,
+        Err(e) => {
+            // Put the current version's bundle back, since its flatpak is still the installed one
+            match versions::stage_bundle(game_id.as_str(), current_hash.as_str()).await {
+                Ok(staged) => {
+                    fs::rename(&staged, &bundle_path).await?;
+                    versions::restore_signature(game_id.as_str(), current_hash.as_str()).await?;
+                }
+                Err(e) => log::warn!("Couldn't restore the bundle of {game_id}: {e}"),
+            }
+            return Err(e);
+        }
+    };
+
+    previous.flatpak_app_id = Some(app_id);
+    atomic::write_async(game_dir.join(GAME_JSON), serde_json::to_string(&previous)?).await?;
+    versions::set_rolled_back_from(game_id.as_str(), current_hash.as_str()).await?;
+    if let Err(e) = storage::record_game(game_id.as_str()).await {
+        log::warn!("Couldn't update {game_id} in the manifest: {e}");
+    }
+    crate::events::emit(Event::GameUpdated(game_id));
+    Ok(previous)
+}
+
+/**
+ * Export an installed game's flatpak as a bundle, so it can be copied onto another cabinet or a dev
+ * machine (such as on a USB stick) and installed there without downloading it again. If `dest` is a
+ * directory, the bundle is written into it as `<game_id>.flatpak`. Bundles are only written under
+ * `DEVCADE_EXPORT_DIR`, and never over an existing file. The bundle is written next to its
+ * destination first, so a failed export doesn't leave half a bundle behind. Returns where the
+ * bundle was written.
+ *
+ * # Errors
+ * This function will return an error if the game isn't installed, if `dest` isn't an absolute path
+ * in a directory under the export directory, if something's already there, or if flatpak can't
+ * build the bundle.
+ */
+pub async fn export_bundle(game_id: String, dest: String) -> Result<PathBuf, Error> {
+    check_game_id(game_id.as_str())?;
+    let game = game_from_path(&storage::game_file(game_id.as_str(), GAME_JSON))
+        .await
+        .map_err(|e| {
+            anyhow!(tr(
+                "game_not_installed",
+                &[
+                    ("game_id", game_id.as_str()),
+                    ("error", e.to_string().as_str())
+                ]
+            ))
+        })?;
+    let app_id = game.flatpak_app_id.ok_or_else(|| {
+        anyhow!(tr(
+            "game_not_installed",
+            &[
+                ("game_id", game_id.as_str()),
+                ("error", "game.json has no flatpak app ID")
+            ]
+        ))
+    })?;
+
+    let dest_path = export_path(game_id.as_str(), dest.as_str()).await?;
+
+    let lookup_app_id = app_id.clone();
+    let (repo, branch, arch) =
+        tokio::task::spawn_blocking(move || installed_flatpak_ref(lookup_app_id.as_str()))
+            .await??;
+    let partial = dest_path.with_extension("flatpak.part");
+    log::info!("Exporting {app_id} for game {game_id} to {:?}", dest_path);
+    let output = Command::new("flatpak")
+        .arg("build-bundle")
+        .arg(format!("--arch={arch}"))
+        .arg(&repo)
+        .arg(&partial)
+        .arg(app_id.as_str())
+        .arg(branch)
+        .output()
+        .await?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&partial).await;
+        return Err(anyhow!(
+            "flatpak build-bundle failed for {app_id}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    // Something could have been put there while the bundle was built
+    if fs::symlink_metadata(&dest_path).await.is_ok() {
+        let _ = fs::remove_file(&partial).await;
+        return Err(export_exists(&dest_path));
+    }
+    fs::rename(&partial, &dest_path).await?;
+    Ok(dest_path)
+}
+
+/**
+ * Work out where an exported bundle goes, making sure it's in a directory under the export
+ * directory (after following symlinks) and that nothing's there yet
+ */
+async fn export_path(game_id: &str, dest: &str) -> Result<PathBuf, Error> {
+    let export_dir = env::export_dir();
+    let invalid_dest = || {
+        anyhow!(tr(
+            "export_dest_invalid",
+            &[
+                ("dest", dest),
+                ("export_dir", export_dir.display().to_string().as_str())
+            ]
+        ))
+    };
+    let mut dest_path = PathBuf::from(dest);
+    if !dest_path.is_absolute() {
+        return Err(invalid_dest());
+    }
+    if fs::metadata(&dest_path)
+        .await
+        .is_ok_and(|meta| meta.is_dir())
+    {
+        dest_path = dest_path.join(format!("{game_id}.flatpak"));
+    }
+    let (Some(parent), Some(file_name)) = (dest_path.parent(), dest_path.file_name()) else {
+        return Err(invalid_dest());
+    };
+    let (Ok(parent), Ok(export_dir)) = (
+        fs::canonicalize(parent).await,
+        fs::canonicalize(&export_dir).await,
+    ) else {
+        return Err(invalid_dest());
+    };
+    if !parent.starts_with(&export_dir) {
+        return Err(invalid_dest());
+    }
+    let dest_path = parent.join(file_name);
+    if fs::symlink_metadata(&dest_path).await.is_ok() {
+        return Err(export_exists(&dest_path));
+    }
+    Ok(dest_path)
+}
+
+fn export_exists(dest: &Path) -> Error {
+    anyhow!(tr(
+        "export_dest_exists",
+        &[("dest", dest.display().to_string().as_str())]
+    ))
+}
+
+/**
+ * Get the repo an installed flatpak app is kept in, and its branch and arch
+ */
+fn installed_flatpak_ref(app_id: &str) -> Result<(PathBuf, String, String), Error> {
+    let installation = Installation::new_user(None::<&gio::Cancellable>)?;
+    let installed = installation
+        .installed_ref(RefKind::App, app_id, None, None, None::<&gio::Cancellable>)
+        .map_err(|e| anyhow!("flatpak app {app_id} isn't installed: {e}"))?;
+    let path = installation
+        .path()
+        .and_then(|file| gio::prelude::FileExt::path(&file))
+        .ok_or_else(|| anyhow!("The user flatpak installation has no path"))?;
+    Ok((
+        path.join("repo"),
+        installed
+            .branch()
+            .map(|branch| branch.to_string())
+            .unwrap_or_else(|| String::from("master")),
+        installed
+            .arch()
+            .map(|arch| arch.to_string())
+            .ok_or_else(|| anyhow!("flatpak app {app_id} has no arch"))?,
+    ))
+}
+
+/**
+ * Throw away a game's install and download it again, such as after its files were modified.
+ *
+ * # Errors
+ * This function will return an error if the game is running, or if it can't be downloaded again.
+ */
+pub async fn reinstall_game(game_id: String) -> Result<DevcadeGame, Error> {
+    check_game_id(game_id.as_str())?;
+    if current_game().is_some_and(|game| game.id == game_id) {
+        return Err(anyhow!(tr(
+            "reinstall_running",
+            &[("game_id", game_id.as_str())]
+        )));
+    }
+    log::info!("Reinstalling game {game_id}");
+    // Without its game.json the game no longer counts as installed, so it's downloaded again
+    match fs::remove_file(storage::game_file(game_id.as_str(), GAME_JSON)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    download_game(game_id).await
+}
+
+fn uninstall_flatpak(app_id: &str) -> Result<(), Error> {
+    let installation = Installation::new_user(None::<&gio::Cancellable>)?;
+    let installed = match installation.installed_ref(
+        RefKind::App,
+        app_id,
+        None,
+        None,
+        None::<&gio::Cancellable>,
+    ) {
+        Ok(installed) => installed,
+        Err(e) => {
+            // Nothing to uninstall, but the game's directory should still go
+            log::warn!("Flatpak app {app_id} isn't installed: {e}");
+            return Ok(());
+        }
+    };
+    let full_ref = installed
+        .format_ref()
+        .ok_or_else(|| anyhow!("Couldn't format the ref of flatpak app {app_id}"))?;
+    let transaction = Transaction::for_installation(&installation, None::<&gio::Cancellable>)?;
+    transaction.set_no_interaction(true);
+    transaction.add_uninstall(full_ref.as_str())?;
+    transaction.run(None::<&gio::Cancellable>)?;
+    Ok(())
+}
+
+/**
+ * Get the environment variables a game is run with inside its sandbox: the `env` from its metadata,
+ * overridden by the game directory's `env.json` if there is one. Variables with invalid names are
+ * logged and skipped.
+ */
+async fn game_env(game: &DevcadeGame) -> BTreeMap<String, String> {
+    let mut env = game.env.clone();
+    let path = storage::game_file(game.id.as_str(), ENV_OVERRIDES);
+    match fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str::<BTreeMap<String, String>>(json.as_str()) {
+            Ok(overrides) => env.extend(overrides),
+            Err(e) => log::warn!("Ignoring invalid env overrides {:?}: {e}", path),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Couldn't read env overrides {:?}: {e}", path),
+    }
+    env.retain(|key, _| {
+        let valid = !key.is_empty()
+            && !key.starts_with(|c: char| c.is_ascii_digit())
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            log::warn!(
+                "Ignoring invalid environment variable '{key}' for game {}",
+                game.id
+            );
+        }
+        valid
+    });
+    env
+}
+
+fn generate_clean_env() -> HashMap<String, String> {
+    // needs to be outside command builder because std::env::vars() is not Send
+    // and even though this creates owned copies of everything, it still doesn't like it.
+    std::env::vars()
+        .filter(|(ref key, _value)| {
+            key == "DISPLAY"
+                || key == "WAYLAND_DISPLAY"
+                || key == "XAUTHORITY"
+                || key.starts_with("XDG_")
+                || key.starts_with("DBUS_")
+                || key.starts_with("LC_")
+                || key == "LANG"
+                || key == "TERM"
+                || key == "DEVCADE_PATH"
+        })
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect::<HashMap<String, String>>()
+}
+
+/**
+ * Launch a game by its ID, passing `args` to the game's command. This will check if the game is
+ * downloaded, and if it is, it will launch the game and wait for it to exit. When the game exits, a `GameExited` event describing the session
+ * is emitted, and the session is returned.
+ *
+ * If the game crashes, it's relaunched once when `DEVCADE_RELAUNCH_ON_CRASH` is set. A game that
+ * keeps crashing is reported as broken with a `GameCrashed` event.
+ *
+ * # Errors
+ * This function will return an error if the filesystem cannot be read from,
+ * or if the game cannot be launched.
+ *
+ * # Panics
+ * This function will never panic, but contains an `unwrap` call that will never fail. This section
+ * is here to make clippy happy.
+ */
+#[tracing::instrument(skip_all, fields(game_id = %game_id))]
+pub async fn launch_game(game_id: String, args: Vec<String>) -> Result<GameSession, Error> {
+    let path = storage::game_dir(game_id.as_str()).join("publish");
+
+    tracing::info!("Launching game {}...", game_id);
+    tracing::trace!("Game path: {}", path.to_str().unwrap());
+
+    // Downloads game if we don't already have it
+    let mut game = download_game(game_id.clone()).await?;
+    let launch = match validate_install(&game).await {
+        Ok(launch) => launch,
+        Err(err) => {
+            let Some(corrupt) = err.downcast_ref::<InstallCorrupt>() else {
+                return Err(err);
+            };
+            log::warn!("{corrupt}, downloading it again");
+            // Without its game.json the game no longer counts as installed, so it's reinstalled
+            match fs::remove_file(storage::game_file(game.id.as_str(), GAME_JSON)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            game = download_game(game_id.clone()).await?;
+            validate_install(&game).await?
+        }
+    };
+    signing::check(game_id.as_str()).await?;
+
+    // flush data every time a new game is opened (in case previous launched game forgor)
+    match persistence_flush().await {
+        Ok(_) => {}
+        Err(e) => log::warn!("Failed to flush save cache: {e}"),
+    }
+
+    loop {
+        let session = run_game(&game, path.as_path(), &launch, args.as_slice()).await?;
+        // Nobody played a demo, so it isn't counted as a play
+        if !attract::is_demo(game.id.as_str()) {
+            if let Err(e) = play_stats::record(&session).await {
+                log::warn!("Couldn't save play stats for {}: {e}", game.id);
+            }
+            if let Err(e) = analytics::record(&session).await {
+                log::warn!("Couldn't queue play event for {}: {e}", game.id);
+            }
+            if let Err(e) = history::record(&session).await {
+                log::warn!("Couldn't save history for {}: {e}", game.id);
+            }
+        }
+        if !session.crashed() {
+            CRASH_COUNTS.lock().unwrap().remove(&game.id);
+            guests::end_session().await;
+            return Ok(session);
+        }
+
+        metrics::count(Counter::GameCrashes, 1);
+        let crashes = {
+            let mut counts = CRASH_COUNTS.lock().unwrap();
+            let count = counts.entry(game.id.clone()).or_default();
+            *count += 1;
+            *count
+        };
+        if crashes == 1 && env::relaunch_on_crash() {
+            log::warn!("Game {} crashed, relaunching it", game.id);
+            continue;
+        }
+        log::warn!("Game {} crashed {crashes} times in a row", game.id);
+        crate::events::emit(Event::GameCrashed {
+            game_id: game.id.clone(),
+            code: session.exit_code,
+            signal: session.signal,
+        });
+        guests::end_session().await;
+        return Ok(session);
+    }
+}
+
+/**
+ * A game's install is broken in a way that re-downloading it should fix
+ */
+#[derive(Debug)]
+pub struct InstallCorrupt {
+    pub game_id: String,
+    pub reason: String,
+}
+
+impl Display for InstallCorrupt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = tr(
+            "install_corrupt",
+            &[
+                ("game_id", self.game_id.as_str()),
+                ("reason", self.reason.as_str()),
+            ],
+        );
+        write!(f, "{message}")
+    }
+}
+
+impl std::error::Error for InstallCorrupt {}
+
+/**
+ * How an installed game is started inside its sandbox
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LaunchCommand {
+    /**
+     * The file to run, or `None` to use the flatpak's own command
+     */
+    pub command: Option<String>,
+    /**
+     * The directory the game is run in
+     */
+    pub working_dir: String,
+    /**
+     * Arguments passed before any the frontend asked for
+     */
+    pub args: Vec<String>,
+}
+
+impl LaunchCommand {
+    fn new(command: Option<String>) -> Self {
+        Self {
+            command,
+            working_dir: String::from("/app/publish"),
+            args: Vec::new(),
+        }
+    }
+}
+
+/**
+ * Check that an installed game can actually be launched: its game.json parses, its flatpak is
+ * installed, and it has an executable file to start. This catches broken installs before
+ * `flatpak run` fails with an opaque error.
+ *
+ * The game is started with its `entrypoint` if it has one, then with the command in a
+ * `devcade.toml` shipped in its files, then with the flatpak's command. If none of those exist, the
+ * best executable in the game's files is used instead.
+ *
+ * # Errors
+ * This function will return an `InstallCorrupt` error describing what's wrong with the install, or
+ * another error if the flatpak installation can't be opened.
+ */
+pub async fn validate_install(game: &DevcadeGame) -> Result<LaunchCommand, Error> {
+    let corrupt = |reason: String| {
+        Error::new(InstallCorrupt {
+            game_id: game.id.clone(),
+            reason,
+        })
+    };
+
+    game_from_path(&storage::game_file(game.id.as_str(), GAME_JSON))
+        .await
+        .map_err(|e| corrupt(format!("game.json can't be read: {e}")))?;
+    let app_id = game
+        .flatpak_app_id
+        .clone()
+        .ok_or_else(|| corrupt(String::from("game.json has no flatpak app ID")))?;
+
+    let (files, command) =
+        tokio::task::spawn_blocking(move || flatpak_command_path(app_id.as_str()))
+            .await?
+            .map_err(|e| corrupt(e.to_string()))?;
+
+    if let Some(entrypoint) = &game.entrypoint {
+        let relative = entrypoint.strip_prefix("/app/").unwrap_or(entrypoint);
+        if !executables::stays_inside(relative) {
+            return Err(corrupt(format!(
+                "entrypoint '{entrypoint}' must stay inside the game's files"
+            )));
+        }
+        check_executable(&files.join(relative))
+            .await
+            .map_err(|e| corrupt(format!("entrypoint {e}")))?;
+        return Ok(LaunchCommand::new(Some(format!("/app/{relative}"))));
+    }
+    let manifest_files = files.clone();
+    let manifest =
+        tokio::task::spawn_blocking(move || executables::read_launch_manifest(&manifest_files))
+            .await?
+            .map_err(|e| corrupt(e.to_string()))?;
+    if let Some(manifest) = manifest {
+        check_executable(&files.join(manifest.command.as_str()))
+            .await
+            .map_err(|e| corrupt(format!("{} command {e}", executables::LAUNCH_MANIFEST)))?;
+        let mut launch = LaunchCommand::new(Some(format!("/app/{}", manifest.command)));
+        if let Some(working_dir) = manifest.working_dir {
+            launch.working_dir = format!("/app/{working_dir}");
+        }
+        launch.args = manifest.args;
+        return Ok(launch);
+    }
+    let command_error = match check_executable(&command).await {
+        Ok(()) => return Ok(LaunchCommand::new(None)),
+        Err(e) => e,
+    };
+
+    let names = [
+        game.name.clone(),
+        game.id.clone(),
+        // The last part of the app ID is usually the game's name
+        game.flatpak_app_id
+            .as_deref()
+            .and_then(|app_id| app_id.rsplit('.').next())
+            .map(String::from)
+            .unwrap_or_default(),
+    ];
+    let located = tokio::task::spawn_blocking(move || {
+        executables::locate_executable(&files, &names.each_ref().map(String::as_str))
+    })
+    .await?;
+    match located {
+        Ok(relative) => {
+            log::warn!(
+                "Flatpak command {command_error} for game {}, running {} instead",
+                game.id,
+                relative.display()
+            );
+            Ok(LaunchCommand::new(Some(format!(
+                "/app/{}",
+                relative.display()
+            ))))
+        }
+        Err(e) => Err(corrupt(format!("command {command_error}, and {e}"))),
+    }
+}
+
+/**
+ * Check that a path is an executable file, describing the problem if it isn't
+ */
+async fn check_executable(path: &Path) -> Result<(), Error> {
+    let metadata = fs::metadata(path)
+        .await
+        .map_err(|e| anyhow!("{} is missing: {e}", path.display()))?;
+    if !metadata.is_file() || metadata.permissions().mode() & 0o111 == 0 {
+        return Err(anyhow!("{} isn't an executable file", path.display()));
+    }
+    Ok(())
+}
+
+/**
+ * Find the directory an installed flatpak app's files are in (`/app` inside the sandbox), and the
+ * file it runs from the `command` in its metadata
+ */
+fn flatpak_command_path(app_id: &str) -> Result<(PathBuf, PathBuf), Error> {
+    let installation = Installation::new_user(None::<&gio::Cancellable>)?;
+    let installed = installation
+        .installed_ref(RefKind::App, app_id, None, None, None::<&gio::Cancellable>)
+        .map_err(|e| anyhow!("flatpak app {app_id} isn't installed: {e}"))?;
+    let deploy_dir = installed
+        .deploy_dir()
+        .ok_or_else(|| anyhow!("flatpak app {app_id} has no deploy directory"))?;
+
+    let metadata = gio::glib::KeyFile::new();
+    metadata.load_from_bytes(
+        &installed.load_metadata(None::<&gio::Cancellable>)?,
+        gio::glib::KeyFileFlags::NONE,
+    )?;
+    let command = metadata
+        .string("Application", "command")
+        .map_err(|e| anyhow!("flatpak app {app_id} has no command: {e}"))?;
+
+    // /app inside the sandbox is the deploy directory's files, and bare commands are run from
+    // /app/bin
+    let files = Path::new(deploy_dir.as_str()).join("files");
+    let command = match command.strip_prefix("/app/") {
+        Some(relative) => files.join(relative),
+        None => files.join("bin").join(command.as_str()),
+    };
+    Ok((files, command))
+}
+
+/**
+ * Run an installed game once and wait for it to exit, capturing its output. The game gets a fresh
+ * temporary directory in `TMPDIR` for the session, which is wiped when it exits. A `GameExited`
+ * event describing the session is emitted when it exits.
+ */
+async fn run_game(
+    game: &DevcadeGame,
+    path: &Path,
+    launch: &LaunchCommand,
+    args: &[String],
+) -> Result<GameSession, Error> {
+    let tmp_dir = create_session_tmp(game.id.as_str()).await?;
+    *CURRENT_GAME.lock().unwrap() = Some(game.clone());
+    nfc::clear_associations();
+    STOP_REQUESTED.store(false, Ordering::SeqCst);
+    if let Err(e) = audio::apply().await {
+        log::warn!("Couldn't set the volume for game {}: {e}", game.id);
+    }
+
+    let envs = generate_clean_env();
+    tracing::trace!("Game ENV: {:?}", envs);
+    let mut game_env = game_env(game).await;
+    // The directory is mounted at the same path inside the sandbox
+    let tmp_path = tmp_dir.to_string_lossy().into_owned();
+    let permission_args = permission_args(game, &game_permissions(game).await);
+    gpu::apply(game.id.as_str(), &mut game_env).await;
+    controllers::apply(game.id.as_str(), &mut game_env).await;
+    let screen = display::prepare(game, &mut game_env).await;
+    let display_args: &[&str] = match env::display_server() {
+        env::DisplayServer::X11 => &["--socket=x11"],
+        env::DisplayServer::Wayland => {
+            // Games can still pick another driver in their metadata or env.json
+            game_env
+                .entry(String::from("SDL_VIDEODRIVER"))
+                .or_insert_with(|| String::from("wayland"));
+            &["--socket=wayland", "--socket=fallback-x11"]
+        }
+    };
+    game_env.insert(String::from("TMPDIR"), tmp_path.clone());
+    game_env.insert(String::from("DEVCADE_SESSION_TMP"), tmp_path.clone());
+    tracing::debug!("Game {} sandbox ENV: {:?}", game.id, game_env);
+    tracing::debug!("Game {} launch: {:?}, args: {:?}", game.id, launch, args);
+
+    // Launch the game, capturing its output so it can be retrieved later
+    let mut child = Command::new("flatpak")
         .arg("run")
         .arg("--user")
-        .arg("--device=dri")
-        .arg("--cwd=/app/publish")
+        .args(
+            profile::current()
+                .devices
+                .iter()
+                .map(|device| format!("--device={device}")),
+        )
+        .arg(format!("--cwd={}", launch.working_dir))
+        .arg(format!("--filesystem={tmp_path}"))
+        .args(display_args)
+        .args(permission_args)
+        .args(
+            launch
+                .command
+                .as_ref()
+                .map(|command| format!("--command={command}")),
+        )
+        .args(
+            game_env
+                .iter()
+                .map(|(key, value)| format!("--env={key}={value}")),
+        )
+        .arg(game.flatpak_app_id.clone().unwrap())
+        .args(&launch.args)
+        .args(args)
+        // This unwrap is safe because it is guaranteed to have a parent
+        .current_dir(path.parent().unwrap())
+        // Oops, there's kind of secrets in there
+        .env_clear()
+        .envs(envs)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to launch game");
+
+    let started = Instant::now();
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let capture = game_logs::start_session(game.id.as_str()).await;
+    let log_session = capture.session.clone();
+    let recording = recording::start(game.id.as_str(), log_session.as_str()).await;
+    // Stopped once the game exits, so it can't clear the stats of the next game's session
+    let mut stats_task = None;
+    if let Some(pid) = child.id() {
+        stats_task = Some(tokio::spawn(session_stats::monitor(game.id.clone(), pid)));
+        // This stops by itself once the game's gone
+        tokio::spawn(watchdog::watch(game.id.clone(), pid));
+    }
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let mut capture_task = tokio::spawn(async move {
+        // These unwraps are safe because both streams were piped above
+        futures_util::join!(
+            capture.capture(stdout.unwrap(), game_logs::Stream::Stdout),
+            capture.capture(stderr.unwrap(), game_logs::Stream::Stderr)
+        );
+    });
+
+    let (wait_result, time_limited) = match env::max_session_length() {
+        Some(limit) => wait_with_limit(&mut child, game, limit).await,
+        None => (child.wait().await, false),
+    };
+    *CURRENT_GAME.lock().unwrap() = None;
+    if let Err(e) = audio::apply().await {
+        log::warn!("Couldn't set the volume for the menu: {e}");
+    }
+    recording::stop(recording).await;
+    if let Some(stats_task) = stats_task {
+        stats_task.abort();
+    }
+    session_stats::clear();
+    let players = nfc::session_handles();
+    nfc::clear_associations();
+    let status = wait_result.expect("Failed to launch game");
+
+    let session = GameSession {
+        game_id: game.id.clone(),
+        log_session,
+        started_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        exit_code: status.code(),
+        signal: status.signal(),
+        time_limited,
+        stopped: STOP_REQUESTED.load(Ordering::SeqCst),
+        players,
+    };
+    log::info!("Game finished! {session}");
+    if time_limited {
+        // Same as stopping the game from the menu, the frontend is about to show the menu again
+        if let Err(e) = persistence_flush().await {
+            log::warn!("Failed to flush save cache after session limit: {e}");
+        }
+    }
+    crate::events::emit(Event::GameExited(session.clone()));
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Kill leftover processes before waiting on the capture, since they may hold the pipes open
+    let killed = kill_game(game.clone()).await;
+    display::restore(game, screen).await;
+
+    // Give the capture a moment to drain what's left in the pipes, but don't wait on it forever
+    match tokio::time::timeout(CAPTURE_DRAIN_TIMEOUT, &mut capture_task).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log::warn!("Game log capture failed: {e}"),
+        Err(_) => {
+            log::warn!("Game output was still open after exit, stopping log capture");
+            capture_task.abort();
+        }
+    }
+
+    if let Err(e) = fs::remove_dir_all(&tmp_dir).await {
+        log::warn!("Couldn't remove session temp dir {:?}: {e}", tmp_dir);
+    }
+
+    killed?;
+    Ok(session)
+}
+
+/**
+ * Get the permissions a game is given: the ones in its metadata, plus any the operator granted in
+ * its `permissions.json`
+ */
+async fn game_permissions(game: &DevcadeGame) -> Vec<GamePermission> {
+    let mut permissions = game.permissions.clone();
+    let path = storage::game_file(game.id.as_str(), PERMISSION_OVERRIDES);
+    match fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str::<Vec<GamePermission>>(json.as_str()) {
+            Ok(overrides) => {
+                log::info!("Game {} is trusted with {:?}", game.id, overrides);
+                permissions.extend(overrides);
+            }
+            Err(e) => log::warn!("Ignoring invalid permission overrides {:?}: {e}", path),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Couldn't read permission overrides {:?}: {e}", path),
+    }
+    permissions
+}
+
+/**
+ * Get the `flatpak run` arguments that take away the permissions a game wasn't given. Bundles can
+ * grant themselves anything in the install allow list, so this keeps every game to the least it
+ * needs; in particular, games can't reach the network unless they ask to or are trusted with it.
+ */
+fn permission_args(game: &DevcadeGame, permissions: &[GamePermission]) -> Vec<&'static str> {
+    if permissions.contains(&GamePermission::Unknown) {
+        log::warn!(
+            "Game {} asked for permissions this backend doesn't know",
+            game.id
+        );
+    }
+    [
+        (GamePermission::Network, "--unshare=network"),
+        (GamePermission::Audio, "--nosocket=pulseaudio"),
+        (GamePermission::Ipc, "--unshare=ipc"),
+    ]
+    .into_iter()
+    .filter(|(permission, _)| !permissions.contains(permission))
+    .map(|(_, arg)| arg)
+    .collect()
+}
+
+/**
+ * Create an empty temporary directory for a game session, wiping anything a previous session left
+ * behind if the backend went down before cleaning it up
+ */
+async fn create_session_tmp(game_id: &str) -> Result<PathBuf, Error> {
+    let dir = storage::session_tmp_dir(game_id);
+    match fs::remove_dir_all(&dir).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    fs::create_dir_all(&dir).await?;
+    fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).await?;
+    Ok(dir)
+}
+
+/**
+ * Wait for a game to exit, stopping it if it runs past the session limit. The frontend is warned
+ * with a `SessionEnding` event before the limit, then the game is asked to exit and killed if it
+ * hasn't after a grace period. Returns the game's exit status, and whether it hit the limit.
+ */
+async fn wait_with_limit(
+    child: &mut Child,
+    game: &DevcadeGame,
+    limit: Duration,
+) -> (std::io::Result<ExitStatus>, bool) {
+    let warn_after = limit.saturating_sub(env::session_warning());
+    tokio::select! {
+        status = child.wait() => return (status, false),
+        _ = tokio::time::sleep(warn_after) => {}
+    }
+    let remaining = limit - warn_after;
+    if !remaining.is_zero() {
+        crate::events::emit(Event::SessionEnding(game.id.clone(), remaining.as_secs()));
+        tokio::select! {
+            status = child.wait() => return (status, false),
+            _ = tokio::time::sleep(remaining) => {}
+        }
+    }
+
+    log::info!("Game {} reached the session limit, stopping it", game.id);
+    if let Some(pid) = child.id() {
+        // SAFETY: kill has no memory safety requirements, at worst the pid is already gone
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+    }
+    if let Ok(status) = tokio::time::timeout(SESSION_STOP_GRACE, child.wait()).await {
+        return (status, true);
+    }
+    log::warn!("Game {} didn't exit in time, killing it", game.id);
+    if let Err(e) = kill_game(game.clone()).await {
+        log::warn!("Couldn't kill game {}: {e}", game.id);
+    }
+    (child.wait().await, true)
+}
+
+/**
+ * Returns a list of all tags in the database
+ *
+ * # Errors
+ * This function will return an error if the server cannot be reached, or if the server returns an
+ * error.
+ */
+pub async fn tag_list() -> Result<Vec<Tag>, Error> {
+    network::request_json(format!("{}/{}", api_url(), route::tag_list()).as_str()).await
+}
+
+/**
+ * Returns a tag by its name
+ *
+ * # Errors
+ * This function will return an error if the server cannot be reached, or if the server returns an
+ * error.
+ */
+pub async fn tag(name: String) -> Result<Tag, Error> {
+    if let Some(tag) = cache::TAGS.get(&name) {
+        return Ok(tag);
+    }
+    let tag: Tag =
+        network::request_json(format!("{}/{}", api_url(), route::tag(name.as_str())).as_str())
+            .await?;
+    cache::TAGS.insert(name, tag.clone());
+    Ok(tag)
+}
+
+/**
+ * Returns a list of all games with the given tag
+ *
+ * # Errors
+ * This function will return an error if the server cannot be reached, or if the server returns an
+ * error.
+ */
+pub async fn tag_games(name: String) -> Result<Vec<DevcadeGame>, Error> {
+    let games: Vec<MinimalGame> = network::request_json(
+        format!("{}/{}", api_url(), route::tag_games(name.as_str())).as_str(),
+    )
+    .await?;
+    let ids: Vec<String> = games.into_iter().map(|game| game.id).collect();
+    get_games(&ids).await
+}
+
+/**
+ * Returns the curated collections of games
+ *
+ * # Errors
+ * This function will return an error if the server cannot be reached, or if the server returns an
+ * error.
+ */
+pub async fn collection_list() -> Result<Vec<Collection>, Error> {
+    if let Some(collections) = cache::COLLECTIONS.get(&()) {
+        return Ok(collections);
+    }
+    let collections: Vec<Collection> =
+        network::request_json(format!("{}/{}", api_url(), route::collections()).as_str()).await?;
+    cache::COLLECTIONS.insert((), collections.clone());
+    Ok(collections)
+}
+
+/**
+ * Gets a user's information by their user ID
+ *
+ * # Errors
+ * This function will return an error if the server cannot be reached, or if the server returns an
+ * error.
+ */
+pub async fn user(uid: String) -> Result<User, Error> {
+    if let Some(user) = cache::USERS.get(&uid) {
+        return Ok(user);
+    }
+    let user: User =
+        network::request_json(format!("{}/{}", api_url(), route::user(uid.as_str())).as_str())
+            .await?;
+    cache::USERS.insert(uid, user.clone());
+    Ok(user)
+}
+
+/**
+ * Returns a devcade game if the file at the path is a JSON file containing a devcade game
+ *
+ * # Errors
+ * This function will return an error if the file does not exist, is a directory, or if the file
+ * cannot be read.
+ */
+async fn game_from_path(path: &Path) -> Result<DevcadeGame, Error> {
+    tracing::trace!("Reading game from path {:?}", path);
+    if !fs::try_exists(path).await? {
+        return Err(anyhow!("Path does not exist"));
+    }
+    if fs::metadata(path).await?.is_dir() {
+        return Err(anyhow!("Path is a directory"));
+    }
+    let str = fs::read_to_string(path).await?;
+
+    let game: DevcadeGame = serde_json::from_str(&str)?;
+
+    Ok(game)
+}
+
+/**
+ * Get an installed game's metadata, as it was when it was installed
+ *
+ * # Errors
+ * This function will return an error if the game ID isn't allowed, or the game isn't installed.
+ */
+pub async fn installed_game(game_id: &str) -> Result<DevcadeGame, Error> {
+    check_game_id(game_id)?;
+    game_from_path(&storage::game_file(game_id, GAME_JSON)).await
+}
+
+/**
+ * Check that a game ID is safe to use as part of a path. IDs come from IPC clients, so an ID like
+ * `../..` must not be able to reach outside the game's directory.
+ *
+ * # Errors
+ * This function will return an error if the ID is empty or contains a path separator or `..`.
+ */
+pub fn check_game_id(game_id: &str) -> Result<(), Error> {
+    if game_id.is_empty() || game_id.contains('/') || game_id.contains("..") {
+        return Err(anyhow!("Invalid game ID '{game_id}'"));
+    }
+    Ok(())
+}
+
+pub fn current_game() -> Option<DevcadeGame> {
+    CURRENT_GAME.lock().unwrap().clone()
+}
+
+/**
+ * Get how many times each game has crashed in a row. Games that haven't crashed since they last
+ * exited normally aren't included.
+ */
+pub fn crash_counts() -> HashMap<String, u32> {
+    CRASH_COUNTS.lock().unwrap().clone()
+}
+
+async fn kill_game(game: DevcadeGame) -> Result<(), anyhow::Error> {
+    Command::new("flatpak")
+        .arg("kill")
         .arg(game.flatpak_app_id.clone().unwrap())
-        // This unwrap is safe because it is guaranteed to have a parent
-        .current_dir(path.parent().unwrap())
-        // Oops, there's kind of secrets in there
-        .env_clear()
-        .envs(envs)
-        .spawn()
-        .expect("Failed to launch game");
+        .spawn()?
+        .wait()
+        .await?;
+    Ok(())
+}
+
+pub async fn kill_current_game() -> Result<(), anyhow::Error> {
+    if let Some(current_game) = current_game() {
+        STOP_REQUESTED.store(true, Ordering::SeqCst);
+        kill_game(current_game).await?;
+        Ok(())
+    } else {
+        Err(anyhow!(tr("no_game_to_kill", &[])))
+    }
+}
+
+/**
+ * Stop the currently running game so the cabinet can return to the menu. The game's flatpak is
+ * killed, any data it saved is flushed to disk, and it's no longer reported as the current game.
+ *
+ * # Errors
+ * This function will return an error if no game is running, or if the game couldn't be killed. A
+ * failed flush is logged but doesn't fail the stop, since the game is already gone by then.
+ */
+pub async fn stop_current_game() -> Result<(), Error> {
+    let game = current_game().ok_or_else(|| anyhow!(tr("no_game_to_stop", &[])))?;
+    log::info!("Stopping game {}", game.id);
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+    kill_game(game).await?;
 
-    let wait_result = child.wait().await;
+    if let Err(e) = persistence_flush().await {
+        log::warn!("Failed to flush save cache after stopping game: {e}");
+    }
     *CURRENT_GAME.lock().unwrap() = None;
-    wait_result.expect("Failed to launch game");
+    Ok(())
+}
+
+/**
+ * Whether the save cache has been written since the backend started, and since when it's had
+ * changes that haven't been
+ */
+#[derive(Default)]
+struct FlushHistory {
+    flushes: u64,
+    failed_flushes: u64,
+    last_flush: Option<u64>,
+    dirty_since: Option<Instant>,
+}
 
-    log::info!("Game finished!");
+/**
+ * Whether a flush was started because too many groups changed, and hasn't finished yet
+ */
+static FLUSH_QUEUED: AtomicBool = AtomicBool::new(false);
 
-    tokio::time::sleep(Duration::from_millis(200)).await;
+/**
+ * A game tried to save more than its quota allows. Nothing was written.
+ */
+#[derive(Debug)]
+pub struct QuotaExceeded(pub SaveUsage);
 
-    kill_game(game).await?;
+impl Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let quota = self.0.quota_bytes.to_string();
+        let message = tr(
+            "save_quota_exceeded",
+            &[("game", self.0.game_id.as_str()), ("quota", quota.as_str())],
+        );
+        write!(f, "{message}")
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+// currently saves to the devcade machine (or local machine if running locally) in the future,
+// should ideally use a remote database / something else.
+pub async fn persistence_save(group: &str, key: &str, value: &str) -> Result<(), anyhow::Error> {
+    log::trace!("saving data to {}/{} ({})", group, key, value);
+    save_value(group, key, value, None).await
+}
+
+/**
+ * Save a value using a group and key that's forgotten once its time to live is up, for data that
+ * only matters for a while, like matchmaking tickets. Expired values are cleared out when saves
+ * are flushed.
+ *
+ * # Errors
+ * This function will return an error if the game is out of save space, or if the group's save file
+ * can't be read.
+ */
+pub async fn persistence_save_with_ttl(
+    group: &str,
+    key: &str,
+    value: &str,
+    ttl: Duration,
+) -> Result<(), Error> {
+    log::trace!("saving data to {}/{} for {:?} ({})", group, key, ttl, value);
+    save_value(group, key, value, Some(unix_now() + ttl.as_secs())).await
+}
+
+async fn save_value(
+    group: &str,
+    key: &str,
+    value: &str,
+    expires_at: Option<u64>,
+) -> Result<(), Error> {
+    if key == EXPIRY_KEY {
+        return Err(anyhow!("Key {} is reserved", key));
+    }
+    // Blobs are only saved through persistence_save_blob, which is what picks their file names
+    if is_blob(value) {
+        return Err(anyhow!(tr("save_value_reserved", &[("key", key)])));
+    }
+    let game_id = group_game_id(group);
+    let (path, file_name) = from_group(group)?;
+    let full_key = format!("{}/{}", path, file_name);
+
+    let mut data = DB.lock().await;
+    let mut mod_list = DB_MODIFIED.lock().await;
+
+    let inner = get_submap_or_load(&mut data, full_key.clone()).await?;
+    let old = inner.get(key).cloned();
+    let old_bytes = stored_bytes(full_key.as_str(), key, old.as_deref()).await;
+    let new_bytes = entry_bytes(key, value);
+    check_quota(&data, game_id, old_bytes, new_bytes).await?;
+
+    let inner = get_submap_or_load(&mut data, full_key.clone()).await?;
+    inner.insert(key.to_string(), value.to_string());
+    set_expiry(inner, key, expires_at);
+    charge_usage(game_id, old_bytes, new_bytes);
+    mark_dirty(&mut mod_list, full_key.clone());
+    remove_blob_file(full_key.as_str(), key, old.as_deref()).await;
+    save_watch::notify(group, key, Some(value), false);
+
+    Ok(())
+}
+
+/**
+ * Save binary data using a group and key. Small blobs are kept with the group's other values, and
+ * large ones in files of their own.
+ *
+ * # Errors
+ * This function will return an error if the game is out of save space, or if the blob's file
+ * can't be written.
+ */
+pub async fn persistence_save_blob(group: &str, key: &str, blob: Vec<u8>) -> Result<(), Error> {
+    log::trace!("saving {} byte blob to {}/{}", blob.len(), group, key);
+    if key == EXPIRY_KEY {
+        return Err(anyhow!("Key {} is reserved", key));
+    }
+    let game_id = group_game_id(group);
+    let (path, file_name) = from_group(group)?;
+    let full_key = format!("{}/{}", path, file_name);
+
+    let mut data = DB.lock().await;
+    let mut mod_list = DB_MODIFIED.lock().await;
+
+    let inner = get_submap_or_load(&mut data, full_key.clone()).await?;
+    let old = inner.get(key).cloned();
+    let old_bytes = stored_bytes(full_key.as_str(), key, old.as_deref()).await;
+
+    // Charge for what's stored, the sealed file or the base64 kept with the group's values
+    let (value, sealed) = if blob.len() > BLOB_FILE_THRESHOLD {
+        let name = sha256::digest(key);
+        (
+            format!("{BLOB_FILE}{name}"),
+            Some((name, save_crypto::seal(blob)?)),
+        )
+    } else {
+        (format!("{BLOB_INLINE}{}", BASE64.encode(blob)), None)
+    };
+    let file_bytes = sealed.as_ref().map_or(0, |(_, sealed)| sealed.len() as u64);
+    let new_bytes = entry_bytes(key, &value) + file_bytes;
+    check_quota(&data, game_id, old_bytes, new_bytes).await?;
+
+    match sealed {
+        Some((name, sealed)) => {
+            atomic::write_async(blob_path(full_key.as_str(), name.as_str())?, sealed).await?;
+        }
+        None => remove_blob_file(full_key.as_str(), key, old.as_deref()).await,
+    }
+
+    let inner = get_submap_or_load(&mut data, full_key.clone()).await?;
+    inner.insert(key.to_string(), value);
+    set_expiry(inner, key, None);
+    charge_usage(game_id, old_bytes, new_bytes);
+    mark_dirty(&mut mod_list, full_key);
+    save_watch::notify(group, key, None, true);
+
+    Ok(())
+}
+
+/**
+ * Open binary data saved using a group and key, getting its length and something to stream it
+ * from. Large blobs are read straight from their files rather than all at once.
+ *
+ * # Errors
+ * This function will return an error if there's no blob with that key, or if its file can't be
+ * opened.
+ */
+pub async fn persistence_load_blob(
+    group: &str,
+    key: &str,
+) -> Result<(u64, Box<dyn tokio::io::AsyncRead + Send + Unpin>), Error> {
+    log::trace!("loading blob from {}/{}", group, key);
+    let (path, file_name) = from_group(group)?;
+    let full_key = format!("{}/{}", path, file_name);
+
+    let mut data = DB.lock().await;
+    let inner = get_submap_or_load(&mut data, full_key.clone()).await?;
+    let value = inner
+        .get(key)
+        .filter(|_| !is_expired(inner, key, unix_now()))
+        .ok_or_else(|| anyhow!("Could not find key {} in group {}", key, full_key))?;
+
+    if let Some(name) = value.strip_prefix(BLOB_FILE) {
+        let path = blob_path(full_key.as_str(), name)?;
+        // Encrypted blobs have to be read whole to be checked before any of them is used
+        if save_crypto::enabled() {
+            let blob = save_crypto::open(fs::read(path).await?)?;
+            return Ok((blob.len() as u64, Box::new(std::io::Cursor::new(blob))));
+        }
+        let file = fs::File::open(path).await?;
+        let length = file.metadata().await?.len();
+        Ok((length, Box::new(file)))
+    } else if let Some(encoded) = value.strip_prefix(BLOB_INLINE) {
+        let blob = BASE64.decode(encoded)?;
+        Ok((blob.len() as u64, Box::new(std::io::Cursor::new(blob))))
+    } else {
+        Err(anyhow!("Key {} in group {} isn't a blob", key, full_key))
+    }
+}
+
+/**
+ * Get how much save data a game has, and how much it's allowed
+ */
+pub async fn game_save_usage(game_id: &str) -> SaveUsage {
+    let data = DB.lock().await;
+    SaveUsage {
+        game_id: game_id.to_string(),
+        used_bytes: used_bytes(&data, game_id).await,
+        quota_bytes: env::save_quota(game_id),
+    }
+}
 
+/**
+ * Check a game has room to replace a value taking `old_bytes` with one taking `new_bytes`. Saves
+ * that don't grow the data are always allowed, so games can free space up.
+ */
+async fn check_quota(
+    data: &HashMap<String, HashMap<String, String>>,
+    game_id: &str,
+    old_bytes: u64,
+    new_bytes: u64,
+) -> Result<(), Error> {
+    if new_bytes <= old_bytes {
+        return Ok(());
+    }
+    let quota_bytes = env::save_quota(game_id);
+    let used_bytes = used_bytes(data, game_id).await;
+    if used_bytes.saturating_sub(old_bytes) + new_bytes > quota_bytes {
+        log::warn!("Game {game_id} is out of save space ({used_bytes} of {quota_bytes} bytes)");
+        return Err(QuotaExceeded(SaveUsage {
+            game_id: game_id.to_string(),
+            used_bytes,
+            quota_bytes,
+        })
+        .into());
+    }
     Ok(())
 }
 
 /**
- * Returns a list of all tags in the database
- *
- * # Errors
- * This function will return an error if the server cannot be reached, or if the server returns an
- * error.
+ * Get roughly how many bytes of save data a game has from its running count, counting its saves
+ * if it hasn't been yet
+ */
+async fn used_bytes(data: &HashMap<String, HashMap<String, String>>, game_id: &str) -> u64 {
+    if let Some(used) = SAVE_USAGE.lock().unwrap().get(game_id) {
+        return *used;
+    }
+    let used = game_save_bytes(data, game_id).await;
+    SAVE_USAGE.lock().unwrap().insert(game_id.to_string(), used);
+    used
+}
+
+/**
+ * Update a game's running count after a value taking `old_bytes` was replaced with one taking
+ * `new_bytes`. Games that haven't been counted yet are left for when they are.
+ */
+fn charge_usage(game_id: &str, old_bytes: u64, new_bytes: u64) {
+    if let Some(used) = SAVE_USAGE.lock().unwrap().get_mut(game_id) {
+        *used = used.saturating_sub(old_bytes) + new_bytes;
+    }
+}
+
+/**
+ * How much space a saved value takes, including its blob file if it has one
  */
-pub async fn tag_list() -> Result<Vec<Tag>, Error> {
-    network::request_json(format!("{}/{}", api_url(), route::tag_list()).as_str()).await
+async fn stored_bytes(full_key: &str, key: &str, value: Option<&str>) -> u64 {
+    let Some(value) = value else {
+        return 0;
+    };
+    let file_bytes = match value
+        .strip_prefix(BLOB_FILE)
+        .and_then(|name| blob_path(full_key, name).ok())
+    {
+        Some(path) => fs::metadata(path).await.map_or(0, |meta| meta.len()),
+        None => 0,
+    };
+    entry_bytes(key, value) + file_bytes
 }
 
 /**
- * Returns a tag by its name
- *
- * # Errors
- * This function will return an error if the server cannot be reached, or if the server returns an
- * error.
+ * Delete the file a replaced or deleted value kept its blob in, if it had one
  */
-pub async fn tag(name: String) -> Result<Tag, Error> {
-    network::request_json(format!("{}/{}", api_url(), route::tag(name.as_str())).as_str()).await
+async fn remove_blob_file(full_key: &str, key: &str, value: Option<&str>) {
+    let Some(name) = value.and_then(|value| value.strip_prefix(BLOB_FILE)) else {
+        return;
+    };
+    let removed = match blob_path(full_key, name) {
+        Ok(path) => fs::remove_file(path).await.map_err(Error::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = removed {
+        log::warn!("Couldn't delete blob file for {full_key}/{key}: {e}");
+    }
 }
 
 /**
- * Returns a list of all games with the given tag
+ * Get where a group keeps a blob that's in a file of its own. Blob files are named after the
+ * sha256 of their key, so any other name is refused rather than let out of the group's directory.
  *
  * # Errors
- * This function will return an error if the server cannot be reached, or if the server returns an
- * error.
+ * This function will return an error if the name isn't a sha256 digest.
  */
-pub async fn tag_games(name: String) -> Result<Vec<DevcadeGame>, Error> {
-    let games: Vec<MinimalGame> = network::request_json(
-        format!("{}/{}", api_url(), route::tag_games(name.as_str())).as_str(),
-    )
-    .await?;
-    let games: Vec<_> = games.into_iter().map(game_from_minimal).collect();
-    // await all the games and return them
-    let games: Vec<Result<DevcadeGame, Error>> = futures_util::future::join_all(games).await;
-    Ok(games
-        .into_iter()
-        .filter_map(|g| {
-            if let Ok(g) = g {
-                Some(g)
-            } else {
-                log!(
-                    Level::Warn,
-                    "Failed to get game by tag {name}: {}",
-                    g.unwrap_err()
-                );
-                None
-            }
-        })
-        .collect())
+fn blob_path(full_key: &str, name: &str) -> Result<PathBuf, Error> {
+    if name.len() != 64 || !name.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(anyhow!("'{name}' isn't a blob file name"));
+    }
+    Ok(PathBuf::from(format!("{full_key}.blobs")).join(name))
 }
 
 /**
- * Gets a user's information by their user ID
- *
- * # Errors
- * This function will return an error if the server cannot be reached, or if the server returns an
- * error.
- */
-pub async fn user(uid: String) -> Result<User, Error> {
-    network::request_json(format!("{}/{}", api_url(), route::user(uid.as_str())).as_str()).await
+ * Load a value from using a group and key
+ * group will start with a game_id, but can be further subdivided by the game to
+ * */
+pub async fn persistence_load(group: &str, key: &str) -> Result<String, anyhow::Error> {
+    log::trace!("loading data from {}/{}", group, key);
+    let (path, file_name) = from_group(group)?;
+    let full_key = format!("{}/{}", path, file_name);
+
+    let mut data = DB.lock().await;
+
+    let inner = get_submap_or_load(&mut data, full_key.clone()).await?;
+
+    let value = inner
+        .get(&key.to_string())
+        .filter(|_| key != EXPIRY_KEY && !is_expired(inner, key, unix_now()))
+        .ok_or_else(|| anyhow!("Could not find key {} in group {}", key, full_key))?;
+    if is_blob(value) {
+        return Err(anyhow!("Key {} in group {} is a blob", key, full_key));
+    }
+    Ok(value.clone())
 }
 
 /**
- * Returns a devcade game if the file at the path is a JSON file containing a devcade game
+ * List the keys in a group that start with a prefix, in order
  *
  * # Errors
- * This function will return an error if the file does not exist, is a directory, or if the file
- * cannot be read.
+ * This function will return an error if the group's save file can't be read.
  */
-fn game_from_path(path: &Path) -> Result<DevcadeGame, Error> {
-    log!(Level::Trace, "Reading game from path {:?}", path);
-    if !path.exists() {
-        return Err(anyhow!("Path does not exist"));
-    }
-    if path.is_dir() {
-        return Err(anyhow!("Path is a directory"));
-    }
-    let str = std::fs::read_to_string(path)?;
+pub async fn persistence_keys(group: &str, prefix: &str) -> Result<Vec<String>, Error> {
+    log::trace!("listing keys in {} starting '{}'", group, prefix);
+    let (path, file_name) = from_group(group)?;
+    let full_key = format!("{}/{}", path, file_name);
 
-    let game: DevcadeGame = serde_json::from_str(&str)?;
+    let mut data = DB.lock().await;
+    let inner = get_submap_or_load(&mut data, full_key).await?;
 
-    Ok(game)
+    let now = unix_now();
+    let mut keys: Vec<String> = inner
+        .keys()
+        .filter(|key| key.starts_with(prefix) && is_live(inner, key, now))
+        .cloned()
+        .collect();
+    keys.sort();
+    Ok(keys)
 }
 
-async fn game_from_minimal(game: MinimalGame) -> Result<DevcadeGame, Error> {
-    network::request_json::<DevcadeGame>(
-        format!("{}/{}", api_url(), route::game(game.id.as_str())).as_str(),
-    )
-    .await
-}
+/**
+ * Get a page of the values in a group whose keys start with a prefix, in key order. Pass the
+ * cursor from each page to get the next one, and `None` to start from the beginning. Blobs are
+ * left out, since they're only loaded with `persistence_load_blob`.
+ *
+ * # Errors
+ * This function will return an error if the group's save file can't be read.
+ */
+pub async fn persistence_scan(
+    group: &str,
+    prefix: &str,
+    cursor: Option<String>,
+) -> Result<SavePage, Error> {
+    log::trace!("scanning {} starting '{}' from {:?}", group, prefix, cursor);
+    let (path, file_name) = from_group(group)?;
+    let full_key = format!("{}/{}", path, file_name);
 
-pub fn current_game() -> Option<DevcadeGame> {
-    CURRENT_GAME.lock().unwrap().clone()
-}
+    let mut data = DB.lock().await;
+    let inner = get_submap_or_load(&mut data, full_key).await?;
 
-async fn kill_game(game: DevcadeGame) -> Result<(), anyhow::Error> {
-    Command::new("flatpak")
-        .arg("kill")
-        .arg(game.flatpak_app_id.clone().unwrap())
-        .spawn()?
-        .wait()
-        .await?;
-    Ok(())
+    Ok(scan_page(inner, prefix, cursor.as_deref(), unix_now()))
 }
 
-pub async fn kill_current_game() -> Result<(), anyhow::Error> {
-    if let Some(current_game) = current_game() {
-        kill_game(current_game).await?;
-        Ok(())
-    } else {
-        Err(anyhow!("Tried to kill game, but there wasn't one running!"))
+/**
+ * Get the page of a group's values after a cursor. The cursor is the last key of the previous
+ * page, so values saved while scanning don't shift later pages.
+ */
+fn scan_page(
+    inner: &HashMap<String, String>,
+    prefix: &str,
+    cursor: Option<&str>,
+    now: u64,
+) -> SavePage {
+    let mut keys: Vec<&String> = inner
+        .keys()
+        .filter(|key| key.starts_with(prefix) && is_live(inner, key, now))
+        .filter(|key| !is_blob(&inner[*key]))
+        .filter(|key| cursor.is_none_or(|cursor| key.as_str() > cursor))
+        .collect();
+    keys.sort();
+    let more = keys.len() > SCAN_PAGE_SIZE;
+    keys.truncate(SCAN_PAGE_SIZE);
+    SavePage {
+        cursor: keys.last().filter(|_| more).map(|key| (*key).clone()),
+        entries: keys
+            .into_iter()
+            .map(|key| (key.clone(), inner[key].clone()))
+            .collect(),
     }
 }
 
-// currently saves to the devcade machine (or local machine if running locally) in the future,
-// should ideally use a remote database / something else.
-pub async fn persistence_save(group: &str, key: &str, value: &str) -> Result<(), anyhow::Error> {
-    log::trace!("saving data to {}/{} ({})", group, key, value);
-    let (path, group) = from_group(group);
-    let full_key = format!("{}/{}", path, group);
+/**
+ * Delete a value using a group and key. Deleting a value that isn't there does nothing.
+ *
+ * # Errors
+ * This function will return an error if the group's save file can't be read.
+ */
+pub async fn persistence_delete(group: &str, key: &str) -> Result<(), Error> {
+    log::trace!("deleting data from {}/{}", group, key);
+    let (path, file_name) = from_group(group)?;
+    let full_key = format!("{}/{}", path, file_name);
 
     let mut data = DB.lock().await;
     let mut mod_list = DB_MODIFIED.lock().await;
 
     let inner = get_submap_or_load(&mut data, full_key.clone()).await?;
-
-    inner.insert(key.to_string(), value.to_string());
-    mod_list.insert(full_key);
-
+    if let Some(old) = inner.remove(key) {
+        set_expiry(inner, key, None);
+        mark_dirty(&mut mod_list, full_key.clone());
+        let old_bytes = stored_bytes(full_key.as_str(), key, Some(old.as_str())).await;
+        charge_usage(group_game_id(group), old_bytes, 0);
+        remove_blob_file(full_key.as_str(), key, Some(old.as_str())).await;
+        save_watch::notify(group, key, None, false);
+    }
     Ok(())
 }
 
 /**
- * Load a value from using a group and key
- * group will start with a game_id, but can be further subdivided by the game to
- * */
-pub async fn persistence_load(group: &str, key: &str) -> Result<String, anyhow::Error> {
-    log::trace!("loading data from {}/{}", group, key);
-    let (path, file_name) = from_group(group);
-    let full_key = format!("{}/{}", path, file_name);
+ * Delete every group under a namespace, like a game's ID or one of its players' directories,
+ * cached or on disk.
+ *
+ * # Errors
+ * This function will return an error if the saves on disk can't be deleted.
+ */
+pub async fn persistence_clear(namespace: &str) -> Result<(), Error> {
+    log::info!("Clearing save data in {}", namespace);
+    let dir = save_root().join(namespace);
+    let prefix = format!("{}/", dir.to_str().unwrap_or(""));
 
     let mut data = DB.lock().await;
+    let mut mod_list = DB_MODIFIED.lock().await;
 
-    let inner = get_submap_or_load(&mut data, full_key.clone()).await?;
-
-    inner
-        .get(&key.to_string())
-        .ok_or_else(|| anyhow!("Could not find key {} in group {}", key, full_key))
-        .cloned()
+    data.retain(|group, _| !group.starts_with(&prefix));
+    mod_list.retain(|group| !group.starts_with(&prefix));
+    let groups = dir.clone();
+    let removed = with_storage(move |storage| storage.remove_all(&groups)).await;
+    // Some groups may be gone even if removing the rest failed, so the game is counted again
+    SAVE_USAGE.lock().unwrap().remove(group_game_id(namespace));
+    save_watch::reset(namespace);
+    removed?;
+    // Blob files are kept beside the groups, whichever storage they're in
+    match fs::remove_dir_all(&dir).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
 }
 
 /**
@@ -691,34 +3718,114 @@ pub async fn persistence_load(group: &str, key: &str) -> Result<String, anyhow::
 pub async fn persistence_flush() -> Result<(), anyhow::Error> {
     let mut data = DB.lock().await;
     let mut mod_list = DB_MODIFIED.lock().await;
+    flush_locked(&mut data, &mut mod_list).await
+}
+
+async fn flush_locked(
+    data: &mut HashMap<String, HashMap<String, String>>,
+    mod_list: &mut HashSet<String>,
+) -> Result<(), anyhow::Error> {
+    let now = unix_now();
+    for (key, inner) in data.iter_mut() {
+        let expired = sweep(inner, now);
+        if expired.is_empty() {
+            continue;
+        }
+        log::debug!("Cleared {} expired values from {}", expired.len(), key);
+        let group = to_group(key);
+        for (name, value) in expired {
+            let old_bytes = stored_bytes(key.as_str(), name.as_str(), Some(value.as_str())).await;
+            remove_blob_file(key.as_str(), name.as_str(), Some(value.as_str())).await;
+            if let Some(group) = &group {
+                charge_usage(group_game_id(group), old_bytes, 0);
+                save_watch::notify(group, name.as_str(), None, false);
+            }
+        }
+        mod_list.insert(key.clone());
+    }
 
+    if mod_list.is_empty() {
+        return Ok(());
+    }
     log::debug!(
         "Flushing data in db to file ({} modified groups)",
         mod_list.len()
     );
 
+    let mut groups = vec![];
     for key in mod_list.iter() {
-        let inner = get_submap_or_load(&mut data, key.clone()).await?;
-        let file_name = format!("{}.save", key);
-        log::debug!("Flushing to {}", file_name);
-        let path = Path::new(&file_name);
-        let dir = path.parent().expect("path failed to have parents");
-        if !dir.exists() {
-            fs::create_dir_all(dir).await?;
-        }
-        fs::write(path, serde_json::to_string(inner)?.as_bytes()).await?;
+        let inner = get_submap_or_load(data, key.clone()).await?;
+        groups.push((key.clone(), inner.clone()));
     }
+    let started = Instant::now();
+    let written = with_storage(move |storage| storage.write(&groups)).await;
+    metrics::observe(Histogram::SaveFlushDuration, started.elapsed());
 
+    let mut history = FLUSH_HISTORY.lock().unwrap();
+    if written.is_err() {
+        history.failed_flushes += 1;
+        return written;
+    }
+    history.flushes += 1;
+    history.last_flush = Some(unix_now());
+    history.dirty_since = None;
     mod_list.clear();
 
     Ok(())
 }
 
+/**
+ * Note a group has changed since it was last written. If enough groups have, they're written in
+ * the background without waiting for the next flush.
+ */
+fn mark_dirty(mod_list: &mut HashSet<String>, group: String) {
+    mod_list.insert(group);
+    FLUSH_HISTORY
+        .lock()
+        .unwrap()
+        .dirty_since
+        .get_or_insert_with(Instant::now);
+    let over_limit = env::save_flush_dirty_groups().is_some_and(|limit| mod_list.len() >= limit);
+    if over_limit && !FLUSH_QUEUED.swap(true, Ordering::SeqCst) {
+        log::debug!("{} groups changed, flushing early", mod_list.len());
+        tokio::spawn(async {
+            if let Err(e) = persistence_flush().await {
+                log::warn!("Couldn't flush changed saves: {e}");
+            }
+            FLUSH_QUEUED.store(false, Ordering::SeqCst);
+        });
+    }
+}
+
+/**
+ * Get how much of the save cache hasn't been written to storage yet, and how writing it has gone
+ */
+pub async fn save_cache_stats() -> SaveCacheStats {
+    let data = DB.lock().await;
+    let mod_list = DB_MODIFIED.lock().await;
+    let history = FLUSH_HISTORY.lock().unwrap();
+    SaveCacheStats {
+        cached_groups: data.len() as u64,
+        cached_values: data.values().map(|inner| inner.len() as u64).sum(),
+        dirty_groups: mod_list.len() as u64,
+        dirty_bytes: mod_list
+            .iter()
+            .filter_map(|group| data.get(group))
+            .flat_map(|inner| inner.iter())
+            .map(|(key, value)| entry_bytes(key, value))
+            .sum(),
+        oldest_dirty_secs: history.dirty_since.map(|since| since.elapsed().as_secs()),
+        flushes: history.flushes,
+        failed_flushes: history.failed_flushes,
+        last_flush: history.last_flush,
+    }
+}
+
 /**
  * Flushes all DB changes, and clears the in-memory cache. This shouldn't need to be done often but
  * can be done if some games are storing too much data and we need to save memory. I don't see this
  * actually needing use unless someone is maliciously (or stupidly) trying to store GBs of data at
- * a time.
+ * a time. Watchers only hear about values that expired while flushing, since nothing else changes.
  * */
 pub async fn clear_db() -> Result<(), anyhow::Error> {
     log::info!("Flushing and clearing DB cache");
@@ -730,37 +3837,280 @@ pub async fn clear_db() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-fn from_group(group: &str) -> (String, String) {
-    let save_path = Path::new(if *ON_MACHINE {
+/**
+ * Run a change to the stored saves, given the storage and the directory blob files are in. Cached
+ * saves are flushed first and forgotten afterwards, and no saves or loads happen while the change
+ * runs.
+ *
+ * # Errors
+ * This function will return an error if the cache can't be flushed, or if the change fails.
+ */
+pub async fn persistence_rewrite<T: Send + 'static>(
+    rewrite: impl FnOnce(&mut dyn Storage, &Path) -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    let mut data = DB.lock().await;
+    let mut mod_list = DB_MODIFIED.lock().await;
+    flush_locked(&mut data, &mut mod_list).await?;
+    data.clear();
+    let result = with_storage(move |storage| rewrite(storage, save_root())).await;
+    // Any group could have changed, even if the change failed partway
+    SAVE_USAGE.lock().unwrap().clear();
+    save_watch::reset("");
+    result
+}
+
+/**
+ * Run something against the save storage on a blocking thread
+ */
+async fn with_storage<T: Send + 'static>(
+    f: impl FnOnce(&mut dyn Storage) -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    tokio::task::spawn_blocking(move || {
+        let mut storage = STORAGE.lock().unwrap_or_else(PoisonError::into_inner);
+        f(storage.as_mut())
+    })
+    .await?
+}
+
+/**
+ * Get how much save data every game with some has, and how much each is allowed. Every game is
+ * counted again, correcting any drift in the running counts.
+ *
+ * # Errors
+ * This function will return an error if the save directory can't be read.
+ */
+pub async fn save_usage() -> Result<Vec<SaveUsage>, Error> {
+    let data = DB.lock().await;
+    let mut game_ids: HashSet<String> = with_storage(|storage| storage.groups(save_root()))
+        .await?
+        .into_iter()
+        .filter_map(|(group, _)| {
+            let game_id = Path::new(&group)
+                .strip_prefix(save_root())
+                .ok()?
+                .iter()
+                .next()?;
+            Some(game_id.to_string_lossy().to_string())
+        })
+        .collect();
+    match fs::read_dir(save_root()).await {
+        Ok(mut entries) => {
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_dir() {
+                    game_ids.insert(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let mut usage = vec![];
+    for game_id in game_ids {
+        let used_bytes = game_save_bytes(&data, game_id.as_str()).await;
+        SAVE_USAGE
+            .lock()
+            .unwrap()
+            .insert(game_id.clone(), used_bytes);
+        usage.push(SaveUsage {
+            used_bytes,
+            quota_bytes: env::save_quota(game_id.as_str()),
+            game_id,
+        });
+    }
+    usage.sort_by_key(|game| std::cmp::Reverse(game.used_bytes));
+    Ok(usage)
+}
+
+/**
+ * Roughly how many bytes of save data a game has. Cached groups count the size of their keys and
+ * values, since they may not be written yet, and the rest count the size they're stored at. Blob
+ * files always count.
+ */
+async fn game_save_bytes(data: &HashMap<String, HashMap<String, String>>, game_id: &str) -> u64 {
+    let dir = save_root().join(game_id);
+    let prefix = format!("{}/", dir.to_str().unwrap_or(""));
+    let cached: u64 = data
+        .iter()
+        .filter(|(group, _)| group.starts_with(&prefix))
+        .flat_map(|(_, inner)| inner.iter())
+        .map(|(key, value)| entry_bytes(key, value))
+        .sum();
+    let groups = dir.clone();
+    let stored = with_storage(move |storage| storage.groups(&groups))
+        .await
+        .unwrap_or_default();
+    let stored: u64 = stored
+        .into_iter()
+        .filter(|(group, _)| !data.contains_key(group))
+        .map(|(_, size)| size)
+        .sum();
+    let blob_files = tokio::task::spawn_blocking(move || {
+        let mut files = vec![];
+        blob_files(&dir, &mut files);
+        files
+    })
+    .await
+    .unwrap_or_default();
+    cached + stored + blob_files.into_iter().sum::<u64>()
+}
+
+/**
+ * Find the size of every blob file under a directory
+ */
+fn blob_files(dir: &Path, files: &mut Vec<u64>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if meta.is_dir() {
+            blob_files(&path, files);
+        } else if path.extension().is_none_or(|ext| ext != "save") {
+            // Save files are counted by the storage they belong to
+            files.push(meta.len());
+        }
+    }
+}
+
+/**
+ * Get when each value in a group with a time to live expires
+ */
+fn expiries(inner: &HashMap<String, String>) -> HashMap<String, u64> {
+    inner
+        .get(EXPIRY_KEY)
+        .and_then(|expiries| serde_json::from_str(expiries).ok())
+        .unwrap_or_default()
+}
+
+/**
+ * Set when a value expires, or that it never does
+ */
+fn set_expiry(inner: &mut HashMap<String, String>, key: &str, expires_at: Option<u64>) {
+    let mut expiries = expiries(inner);
+    let changed = match expires_at {
+        Some(expires_at) => expiries.insert(key.to_string(), expires_at) != Some(expires_at),
+        None => expiries.remove(key).is_some(),
+    };
+    if !changed {
+        return;
+    }
+    if expiries.is_empty() {
+        inner.remove(EXPIRY_KEY);
+    } else if let Ok(expiries) = serde_json::to_string(&expiries) {
+        inner.insert(EXPIRY_KEY.to_string(), expiries);
+    }
+}
+
+fn is_expired(inner: &HashMap<String, String>, key: &str, now: u64) -> bool {
+    inner.contains_key(EXPIRY_KEY) && expiries(inner).get(key).is_some_and(|at| *at <= now)
+}
+
+/**
+ * Whether a key holds one of the game's values, rather than bookkeeping or an expired value
+ */
+fn is_live(inner: &HashMap<String, String>, key: &str, now: u64) -> bool {
+    key != EXPIRY_KEY && !is_expired(inner, key, now)
+}
+
+/**
+ * Whether a stored value is a blob's marker, rather than a value saved as it is
+ */
+fn is_blob(value: &str) -> bool {
+    value.starts_with(BLOB_INLINE) || value.starts_with(BLOB_FILE)
+}
+
+/**
+ * Remove the values in a group that have expired, getting what was removed
+ */
+fn sweep(inner: &mut HashMap<String, String>, now: u64) -> Vec<(String, String)> {
+    let expiries = expiries(inner);
+    let mut expired = vec![];
+    for (key, _) in expiries.iter().filter(|(_, at)| **at <= now) {
+        if let Some(value) = inner.remove(key) {
+            expired.push((key.clone(), value));
+        }
+        set_expiry(inner, key, None);
+    }
+    expired
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn entry_bytes(key: &str, value: &str) -> u64 {
+    (key.len() + value.len()) as u64
+}
+
+fn save_root() -> &'static Path {
+    Path::new(if *ON_MACHINE {
         "/home/devcade/.save"
     } else {
         "./.save"
-    });
+    })
+}
+
+/**
+ * Get the ID of the game a group belongs to, which its quota is charged to
+ */
+fn group_game_id(group: &str) -> &str {
+    group.split('/').next().unwrap_or_default()
+}
+
+/**
+ * Get the group a cached or stored group's full key was made from, the opposite of `from_group`
+ */
+fn to_group(full_key: &str) -> Option<String> {
+    Path::new(full_key)
+        .strip_prefix(save_root())
+        .ok()?
+        .to_str()
+        .map(str::to_string)
+}
+
+/**
+ * Split a group into the directory its saves are kept in and the name of its save file. Groups
+ * start with a game's ID, so empty, `.` and `..` segments are refused to keep one game's saves
+ * (and its quota) away from another's.
+ *
+ * # Errors
+ * This function will return an error if the group has an empty, `.` or `..` segment.
+ */
+fn from_group(group: &str) -> Result<(String, String), Error> {
+    if group
+        .split('/')
+        .any(|part| part.is_empty() || part == "." || part == "..")
+    {
+        return Err(anyhow!(tr("save_group_invalid", &[("group", group)])));
+    }
+    let save_path = save_root();
 
     let mut parts: Vec<String> = group.split('/').map(|a| a.to_string()).collect();
     let group = parts.pop().unwrap_or_default();
     let save_path = save_path.join(parts.join("/"));
-    (save_path.to_str().unwrap_or("").to_string(), group)
+    Ok((save_path.to_str().unwrap_or("").to_string(), group))
 }
 
 /**
- * Gets the sub-map at a specified path, and returns the cached version, the version on the
- * filesystem, or a new empty HashMap, in order of preference.
+ * Gets the sub-map at a specified path, and returns the cached version, the stored version, or a
+ * new empty HashMap, in order of preference.
  * */
 async fn get_submap_or_load(
     db: &mut HashMap<String, HashMap<String, String>>,
     group: String,
 ) -> Result<&mut HashMap<String, String>, anyhow::Error> {
-    let file_name = format!("{}.save", group);
     if !db.contains_key(&group) {
-        if Path::new(&file_name).exists() {
-            let map = serde_json::from_str::<HashMap<String, String>>(
-                fs::read_to_string(file_name).await?.as_str(),
-            )?;
-            db.insert(group.clone(), map);
-        } else {
-            db.insert(group.clone(), HashMap::new());
-        }
+        let name = group.clone();
+        let map = with_storage(move |storage| storage.read(name.as_str()))
+            .await?
+            .unwrap_or_default();
+        db.insert(group.clone(), map);
     }
     Ok(db.get_mut(&group).unwrap())
 }
@@ -773,3 +4123,105 @@ pub async fn db_cache_size() -> usize {
     let data = DB.lock().await;
     data.values().map(|hm| hm.len()).sum()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scans_page_through_matching_keys() {
+        let inner: HashMap<String, String> = (0..SCAN_PAGE_SIZE + 5)
+            .map(|n| (format!("slot-{n:03}"), n.to_string()))
+            .chain([(String::from("settings"), String::new())])
+            .collect();
+        let first = scan_page(&inner, "slot-", None, 0);
+        assert_eq!(first.entries.len(), SCAN_PAGE_SIZE);
+        assert_eq!(first.entries.keys().next().unwrap(), "slot-000");
+        let second = scan_page(&inner, "slot-", first.cursor.as_deref(), 0);
+        assert_eq!(second.entries.len(), 5);
+        assert!(second.cursor.is_none());
+        assert!(!second.entries.contains_key("settings"));
+    }
+
+    #[test]
+    fn running_usage_counts_are_charged_for_changes() {
+        SAVE_USAGE
+            .lock()
+            .unwrap()
+            .insert(String::from("usage-test"), 100);
+        charge_usage("usage-test", 10, 30);
+        charge_usage("usage-test", 200, 0);
+        charge_usage("usage-uncounted", 0, 30);
+        let usage = SAVE_USAGE.lock().unwrap();
+        assert_eq!(usage.get("usage-test"), Some(&0));
+        assert!(!usage.contains_key("usage-uncounted"));
+        assert_eq!(group_game_id("pong/users/abc/progress"), "pong");
+    }
+
+    #[test]
+    fn scans_leave_blobs_out() {
+        let inner: HashMap<String, String> = [
+            ("slot-1", String::from("plain")),
+            ("slot-2", format!("{BLOB_INLINE}AAAA")),
+            ("slot-3", format!("{BLOB_FILE}abc")),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+        let page = scan_page(&inner, "slot-", None, 0);
+        assert_eq!(page.entries.keys().collect::<Vec<_>>(), ["slot-1"]);
+    }
+
+    #[test]
+    fn full_keys_map_back_to_their_groups() {
+        let (path, file_name) = from_group("pong/players/abc/scores").unwrap();
+        let full_key = format!("{path}/{file_name}");
+        assert_eq!(
+            to_group(&full_key).as_deref(),
+            Some("pong/players/abc/scores")
+        );
+        assert!(to_group("/elsewhere/scores").is_none());
+    }
+
+    #[test]
+    fn expired_values_are_hidden_then_swept() {
+        let mut inner = HashMap::new();
+        inner.insert(String::from("ticket"), String::from("abc"));
+        inner.insert(String::from("name"), String::from("alice"));
+        set_expiry(&mut inner, "ticket", Some(100));
+        assert!(is_live(&inner, "ticket", 99));
+        assert!(!is_live(&inner, "ticket", 100));
+        assert!(!is_live(&inner, EXPIRY_KEY, 0));
+        assert!(sweep(&mut inner, 99).is_empty());
+        let expired = sweep(&mut inner, 100);
+        assert_eq!(expired, vec![(String::from("ticket"), String::from("abc"))]);
+        assert_eq!(inner.keys().collect::<Vec<_>>(), vec!["name"]);
+    }
+
+    #[test]
+    fn blob_files_must_be_named_by_digest() {
+        let name = sha256::digest("level-1");
+        assert_eq!(
+            blob_path("saves/pong/default", name.as_str()).unwrap(),
+            PathBuf::from(format!("saves/pong/default.blobs/{name}"))
+        );
+        assert!(blob_path("saves/pong/default", "../../../../etc/shadow").is_err());
+        assert!(blob_path("saves/pong/default", "").is_err());
+    }
+
+    #[test]
+    fn groups_stay_inside_their_game() {
+        let (path, group) = from_group("pong/users/abc/progress").unwrap();
+        assert!(path.ends_with("pong/users/abc"));
+        assert_eq!(group, "progress");
+        for group in [
+            "pong/../tetris",
+            "pong/./default",
+            "pong//default",
+            "pong/",
+            "..",
+        ] {
+            assert!(from_group(group).is_err(), "{group} was allowed");
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use crate::atomic;
+use crate::env::{self, SaveStorage};
+use crate::save_crypto;
+use anyhow::{anyhow, Error};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/**
+ * The file, in the save directory, that the SQLite engine keeps saves in
+ */
+const DATABASE_FILE: &str = "saves.db";
+
+/**
+ * Where save groups are kept between runs. Groups are named by their path under the save
+ * directory, like `./.save/<game id>/progress`, and hold the group's keys and values. Groups are
+ * encrypted with `save_crypto` before they're stored, if it's turned on.
+ */
+pub trait Storage: Send {
+    /**
+     * Read a group's values, or `None` if it was never saved
+     *
+     * # Errors
+     * This function will return an error if the group can't be read or decrypted.
+     */
+    fn read(&self, group: &str) -> Result<Option<HashMap<String, String>>, Error>;
+
+    /**
+     * Write groups, replacing their values. Engines that can write them all or none of them do.
+     *
+     * # Errors
+     * This function will return an error if any group can't be written.
+     */
+    fn write(&mut self, groups: &[(String, HashMap<String, String>)]) -> Result<(), Error>;
+
+    /**
+     * Delete a group. Deleting a group that was never saved does nothing.
+     *
+     * # Errors
+     * This function will return an error if the group can't be deleted.
+     */
+    fn remove(&mut self, group: &str) -> Result<(), Error>;
+
+    /**
+     * List every group under a directory, with roughly how many bytes each takes to store
+     *
+     * # Errors
+     * This function will return an error if the groups can't be listed.
+     */
+    fn groups(&self, dir: &Path) -> Result<Vec<(String, u64)>, Error>;
+
+    /**
+     * Delete every group under a directory
+     *
+     * # Errors
+     * This function will return an error if the groups can't be deleted.
+     */
+    fn remove_all(&mut self, dir: &Path) -> Result<(), Error>;
+}
+
+/**
+ * Open the engine `DEVCADE_SAVE_STORAGE` picks for the saves in a directory. If SQLite can't be
+ * opened, saves fall back to files so games can still save.
+ */
+#[must_use]
+pub fn open(root: &Path) -> Box<dyn Storage> {
+    match env::save_storage() {
+        SaveStorage::Files => Box::new(FileStorage),
+        SaveStorage::Sqlite => match SqliteStorage::open(root) {
+            Ok(storage) => Box::new(storage),
+            Err(e) => {
+                log::error!("Couldn't open the save database, saving to files instead: {e}");
+                Box::new(FileStorage)
+            }
+        },
+    }
+}
+
+/**
+ * Keeps each group in a JSON file of its own, at the group's path with a `.save` extension
+ */
+pub struct FileStorage;
+
+impl Storage for FileStorage {
+    fn read(&self, group: &str) -> Result<Option<HashMap<String, String>>, Error> {
+        let data = match std::fs::read(format!("{group}.save")) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(serde_json::from_slice(&save_crypto::open(data)?)?))
+    }
+
+    fn write(&mut self, groups: &[(String, HashMap<String, String>)]) -> Result<(), Error> {
+        for (group, values) in groups {
+            let path = PathBuf::from(format!("{group}.save"));
+            log::debug!("Flushing to {}", path.display());
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            atomic::write(&path, save_crypto::seal(serde_json::to_vec(values)?)?)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, group: &str) -> Result<(), Error> {
+        match std::fs::remove_file(format!("{group}.save")) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn groups(&self, dir: &Path) -> Result<Vec<(String, u64)>, Error> {
+        let mut groups = vec![];
+        find_groups(dir, &mut groups)?;
+        Ok(groups)
+    }
+
+    fn remove_all(&mut self, dir: &Path) -> Result<(), Error> {
+        for (group, _) in self.groups(dir)? {
+            self.remove(group.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Keeps every group in a SQLite database in the save directory, written in transactions to a
+ * write-ahead log so a crash never leaves half a flush behind
+ */
+pub struct SqliteStorage {
+    connection: Connection,
+    root: PathBuf,
+}
+
+impl SqliteStorage {
+    /**
+     * Open the save database in a directory, creating it if it doesn't exist. Any save files in
+     * the directory, like ones from before SQLite was turned on, are moved into the database.
+     *
+     * # Errors
+     * This function will return an error if the database can't be opened or the save files can't
+     * be moved into it.
+     */
+    pub fn open(root: &Path) -> Result<Self, Error> {
+        std::fs::create_dir_all(root)?;
+        let connection = Connection::open(root.join(DATABASE_FILE))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS groups (name TEXT PRIMARY KEY, data BLOB NOT NULL)",
+            [],
+        )?;
+        let mut storage = SqliteStorage {
+            connection,
+            root: root.to_path_buf(),
+        };
+        storage.import_files()?;
+        storage
+            .connection
+            .pragma_update(None, "synchronous", "NORMAL")?;
+        Ok(storage)
+    }
+
+    /**
+     * Move save files into the database. The files are only deleted once the database has them
+     * safely on disk.
+     */
+    fn import_files(&mut self) -> Result<(), Error> {
+        let files = FileStorage.groups(&self.root)?;
+        if files.is_empty() {
+            return Ok(());
+        }
+        log::info!("Moving {} save files into the save database", files.len());
+        let transaction = self.connection.transaction()?;
+        for (group, _) in &files {
+            // Groups are stored in the same format as their files, so files are copied as they are
+            let data = std::fs::read(format!("{group}.save"))?;
+            transaction.execute(
+                "INSERT OR REPLACE INTO groups (name, data) VALUES (?1, ?2)",
+                params![name(&self.root, group)?, data],
+            )?;
+        }
+        transaction.commit()?;
+        self.connection
+            .pragma_update(None, "wal_checkpoint", "FULL")?;
+        FileStorage.remove_all(&self.root)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn read(&self, group: &str) -> Result<Option<HashMap<String, String>>, Error> {
+        let data: Option<Vec<u8>> = self
+            .connection
+            .query_row(
+                "SELECT data FROM groups WHERE name = ?1",
+                params![name(&self.root, group)?],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match data {
+            Some(data) => Ok(Some(serde_json::from_slice(&save_crypto::open(data)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn write(&mut self, groups: &[(String, HashMap<String, String>)]) -> Result<(), Error> {
+        let transaction = self.connection.transaction()?;
+        for (group, values) in groups {
+            let data = save_crypto::seal(serde_json::to_vec(values)?)?;
+            transaction.execute(
+                "INSERT OR REPLACE INTO groups (name, data) VALUES (?1, ?2)",
+                params![name(&self.root, group)?, data],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn remove(&mut self, group: &str) -> Result<(), Error> {
+        self.connection.execute(
+            "DELETE FROM groups WHERE name = ?1",
+            params![name(&self.root, group)?],
+        )?;
+        Ok(())
+    }
+
+    fn groups(&self, dir: &Path) -> Result<Vec<(String, u64)>, Error> {
+        let (from, to) = name_range(&self.root, dir)?;
+        let mut statement = self.connection.prepare(
+            "SELECT name, length(data) FROM groups WHERE name >= ?1 AND name < ?2 ORDER BY name",
+        )?;
+        let groups = statement
+            .query_map(params![from, to], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+            })?
+            .map(|group| {
+                let (name, size) = group?;
+                Ok((path_of(&self.root, name.as_str()), size))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(groups)
+    }
+
+    fn remove_all(&mut self, dir: &Path) -> Result<(), Error> {
+        let (from, to) = name_range(&self.root, dir)?;
+        self.connection.execute(
+            "DELETE FROM groups WHERE name >= ?1 AND name < ?2",
+            params![from, to],
+        )?;
+        Ok(())
+    }
+}
+
+/**
+ * Get what the database calls a group: its path under the save directory
+ */
+fn name(root: &Path, group: &str) -> Result<String, Error> {
+    Path::new(group)
+        .strip_prefix(root)
+        .ok()
+        .and_then(Path::to_str)
+        .map(String::from)
+        .ok_or_else(|| anyhow!("Save group {group} isn't in the save directory"))
+}
+
+fn path_of(root: &Path, name: &str) -> String {
+    root.join(name).to_string_lossy().to_string()
+}
+
+/**
+ * Get the range of names the groups under a directory have. `0` comes right after `/`, so every
+ * name starting with the directory and a `/` sorts between the two.
+ */
+fn name_range(root: &Path, dir: &Path) -> Result<(String, String), Error> {
+    if dir == root {
+        return Ok((String::new(), String::from("\u{10ffff}")));
+    }
+    let dir = name(root, dir.to_string_lossy().as_ref())?;
+    Ok((format!("{dir}/"), format!("{dir}0")))
+}
+
+fn find_groups(dir: &Path, groups: &mut Vec<(String, u64)>) -> Result<(), Error> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            find_groups(&path, groups)?;
+        } else if let Some(group) = path.to_str().and_then(|path| path.strip_suffix(".save")) {
+            groups.push((group.to_string(), meta.len()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_files_move_into_the_database() {
+        let root = std::env::temp_dir().join(format!("devcade-saves-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let group = |name: &str| root.join(name).to_string_lossy().to_string();
+        let values = HashMap::from([(String::from("level"), String::from("3"))]);
+        FileStorage
+            .write(&[(group("game/progress"), values.clone())])
+            .unwrap();
+
+        let mut storage = SqliteStorage::open(&root).unwrap();
+        assert!(!root.join("game/progress.save").exists());
+        assert_eq!(storage.read(&group("game/progress")).unwrap(), Some(values));
+        assert_eq!(storage.read(&group("game/other")).unwrap(), None);
+
+        let values = HashMap::from([(String::from("high"), String::from("900"))]);
+        storage
+            .write(&[(group("game/users/a/scores"), values.clone())])
+            .unwrap();
+        storage.write(&[(group("gamer/scores"), values)]).unwrap();
+        let names: Vec<String> = storage
+            .groups(&root.join("game"))
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            vec![group("game/progress"), group("game/users/a/scores")]
+        );
+
+        storage.remove_all(&root.join("game")).unwrap();
+        assert_eq!(storage.groups(&root).unwrap().len(), 1);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Error};
+use std::fs::{File, Permissions};
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/**
+ * Used to give concurrent writes to the same file their own temp files
+ */
+static NEXT_TEMP: AtomicU64 = AtomicU64::new(0);
+
+/**
+ * The points in a write where a crash is simulated by the tests
+ */
+#[cfg(test)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Created,
+    Written,
+    Synced,
+}
+
+#[cfg(test)]
+thread_local! {
+    static CRASH_AT: std::cell::Cell<Option<Step>> = const { std::cell::Cell::new(None) };
+}
+
+/**
+ * Fail the write at this step if the test asked for a crash there
+ */
+#[cfg(test)]
+fn crash_point(step: Step) -> Result<(), Error> {
+    match CRASH_AT.with(std::cell::Cell::get) {
+        Some(crash_at) if crash_at == step => Err(anyhow!("Simulated crash at {step:?}")),
+        _ => Ok(()),
+    }
+}
+
+/**
+ * Replace the contents of a file so that a crash or power loss at any point leaves either the old
+ * contents or the new ones, never a mix. The contents are written to a temp file next to the
+ * destination, synced to disk, and renamed over it, then the directory is synced so the rename
+ * sticks.
+ *
+ * # Errors
+ * This function will return an error if the file or its directory can't be written to. The
+ * destination is untouched if the write fails.
+ */
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), Error> {
+    replace(path, contents.as_ref(), None)
+}
+
+/**
+ * Like `write`, but the new file is executable (mode 755). This is safe to use on a program that's
+ * running, which keeps running the old contents.
+ *
+ * # Errors
+ * This function will return an error if the file or its directory can't be written to.
+ */
+pub fn write_executable(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), Error> {
+    replace(path, contents.as_ref(), Some(0o755))
+}
+
+fn replace(path: &Path, contents: &[u8], mode: Option<u32>) -> Result<(), Error> {
+    let tmp = temp_path(path)?;
+    let result = write_temp(&tmp, contents, mode).and_then(|()| {
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result?;
+    sync_dir(path)
+}
+
+/**
+ * Like `write`, but runs on a blocking thread so it can be awaited
+ *
+ * # Errors
+ * This function will return an error if the file or its directory can't be written to.
+ */
+pub async fn write_async(
+    path: impl Into<PathBuf>,
+    contents: impl Into<Vec<u8>>,
+) -> Result<(), Error> {
+    let path = path.into();
+    let contents = contents.into();
+    tokio::task::spawn_blocking(move || write(&path, contents)).await?
+}
+
+fn write_temp(tmp: &Path, contents: &[u8], mode: Option<u32>) -> Result<(), Error> {
+    let mut file = File::create(tmp)?;
+    if let Some(mode) = mode {
+        file.set_permissions(Permissions::from_mode(mode))?;
+    }
+    #[cfg(test)]
+    crash_point(Step::Created)?;
+    file.write_all(contents)?;
+    #[cfg(test)]
+    crash_point(Step::Written)?;
+    file.sync_all()?;
+    #[cfg(test)]
+    crash_point(Step::Synced)?;
+    Ok(())
+}
+
+/**
+ * Replace a file with a temp file next to it (from `temp_path`) that was written some other way,
+ * like a download too big to hold in memory. The temp file is synced before it's renamed over the
+ * destination, so this is as safe as `write`.
+ *
+ * # Errors
+ * This function will return an error if the temp file can't be synced or renamed. The temp file is
+ * removed if the rename fails.
+ */
+pub async fn persist_async(tmp: PathBuf, path: PathBuf) -> Result<(), Error> {
+    tokio::task::spawn_blocking(move || {
+        let result = File::open(&tmp)
+            .and_then(|file| file.sync_all())
+            .and_then(|()| std::fs::rename(&tmp, &path));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        result?;
+        sync_dir(&path)
+    })
+    .await?
+}
+
+/**
+ * Get a temp file path in the same directory as the destination, so it can be renamed over it
+ *
+ * # Errors
+ * This function will return an error if the destination has no file name.
+ */
+pub fn temp_path(path: &Path) -> Result<PathBuf, Error> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Can't write to {}, it has no file name", path.display()))?;
+    let n = NEXT_TEMP.fetch_add(1, Ordering::Relaxed);
+    Ok(path.with_file_name(format!(
+        ".{}.{}.{n}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    )))
+}
+
+fn sync_dir(path: &Path) -> Result<(), Error> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("devcade-atomic-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn crash_at(step: Option<Step>) {
+        CRASH_AT.with(|crash_at| crash_at.set(step));
+    }
+
+    #[test]
+    fn replaces_contents() {
+        let dir = temp_dir("replace");
+        let path = dir.join("file.json");
+        write(&path, "old").unwrap();
+        write(&path, "new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn crash_keeps_old_contents() {
+        let dir = temp_dir("crash");
+        let path = dir.join("file.json");
+        write(&path, "old").unwrap();
+        for step in [Step::Created, Step::Written, Step::Synced] {
+            crash_at(Some(step));
+            assert!(
+                write(&path, "new").is_err(),
+                "write didn't crash at {step:?}"
+            );
+            crash_at(None);
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+            // A failed write removes its temp file
+            assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn crash_before_first_write_leaves_nothing() {
+        let dir = temp_dir("first");
+        let path = dir.join("file.json");
+        crash_at(Some(Step::Written));
+        assert!(write(&path, "new").is_err());
+        crash_at(None);
+        assert!(!path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+use crate::api;
+use crate::broken_games;
+use crate::env;
+use crate::events;
+use devcade_onboard_types::schema::DevcadeGame;
+use devcade_onboard_types::Event;
+use lazy_static::lazy_static;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/**
+ * The tag that marks a game as fine to play unattended in attract mode
+ */
+const DEMO_SAFE_TAG: &str = "demo-safe";
+
+/**
+ * Where input devices are read from, to notice a player while a demo is playing
+ */
+const INPUT_DIR: &str = "/dev/input";
+
+/**
+ * The size of a `struct input_event` on 64-bit Linux: a timestamp, then a u16 type, a u16 code and
+ * an i32 value
+ */
+const INPUT_EVENT_SIZE: usize = 24;
+
+/**
+ * The input event type for key and button presses
+ */
+const EV_KEY: u16 = 1;
+
+/**
+ * How often the cabinet is checked for being idle
+ */
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    static ref LAST_ACTIVITY: Mutex<Instant> = Mutex::new(Instant::now());
+    static ref DEMO: Mutex<Option<String>> = Mutex::new(None);
+    static ref INTERRUPT: Notify = Notify::new();
+}
+
+/**
+ * Tell attract mode someone is using the cabinet. This restarts the idle timer, and stops the demo
+ * game if one is playing so the frontend goes back to the menu.
+ */
+pub fn activity() {
+    *LAST_ACTIVITY.lock().unwrap() = Instant::now();
+    INTERRUPT.notify_waiters();
+}
+
+/**
+ * Get whether a game is playing as a demo, rather than because someone launched it
+ */
+#[must_use]
+pub fn is_demo(game_id: &str) -> bool {
+    DEMO.lock().unwrap().as_deref() == Some(game_id)
+}
+
+/**
+ * Play installed games tagged `demo-safe` in turn, each for `DEVCADE_ATTRACT_GAME_SECS`, once the
+ * cabinet has been idle for `DEVCADE_ATTRACT_MINUTES`. Menu navigation, badge taps and button
+ * presses on any input device count as activity. Returns immediately if attract mode is off, and
+ * should be spawned as a task at startup.
+ */
+pub async fn run() {
+    let Some(idle_after) = env::attract_after() else {
+        return;
+    };
+    watch_input();
+    let mut next = 0;
+    loop {
+        let idle = LAST_ACTIVITY.lock().unwrap().elapsed();
+        if idle < idle_after || api::current_game().is_some() {
+            tokio::time::sleep(
+                idle_after
+                    .saturating_sub(idle)
+                    .clamp(CHECK_INTERVAL, idle_after),
+            )
+            .await;
+            continue;
+        }
+        let games = demo_games().await;
+        if games.is_empty() {
+            log::debug!("Not starting attract mode, no installed games are tagged {DEMO_SAFE_TAG}");
+            // Look again after another idle stretch
+            *LAST_ACTIVITY.lock().unwrap() = Instant::now();
+            continue;
+        }
+        let game = &games[next % games.len()];
+        next += 1;
+        play(game.id.clone()).await;
+    }
+}
+
+/**
+ * Get the installed games that can be played as demos
+ */
+async fn demo_games() -> Vec<DevcadeGame> {
+    let installed = match api::game_list_from_fs().await {
+        Ok(installed) => installed.games,
+        Err(e) => {
+            log::warn!("Couldn't list games for attract mode: {e}");
+            return vec![];
+        }
+    };
+    let broken: Vec<String> = broken_games::flags()
+        .into_iter()
+        .map(|flag| flag.game_id)
+        .collect();
+    installed
+        .into_iter()
+        .filter(|game| game.tags.iter().any(|tag| tag.name == DEMO_SAFE_TAG))
+        .filter(|game| !broken.contains(&game.id))
+        .collect()
+}
+
+/**
+ * Play a game as a demo until its time is up, it exits, or someone uses the cabinet
+ */
+async fn play(game_id: String) {
+    log::info!("Attract mode is playing {game_id}");
+    *DEMO.lock().unwrap() = Some(game_id.clone());
+    events::emit(Event::AttractMode(Some(game_id.clone())));
+
+    let input = INTERRUPT.notified();
+    let mut session = tokio::spawn(api::launch_game(game_id.clone(), Vec::new()));
+    let interrupted = tokio::select! {
+        () = tokio::time::sleep(env::attract_game_length()) => false,
+        () = input => true,
+        _ = &mut session => false,
+    };
+    if api::current_game().is_some_and(|game| game.id == game_id) {
+        if let Err(e) = api::stop_current_game().await {
+            log::warn!("Couldn't stop demo of {game_id}: {e}");
+        }
+    }
+    match session.await {
+        Ok(Err(e)) => log::warn!("Couldn't play {game_id} in attract mode: {e}"),
+        Err(e) => log::warn!("Attract mode's session of {game_id} failed: {e}"),
+        Ok(Ok(_)) => {}
+    }
+    *DEMO.lock().unwrap() = None;
+    if interrupted {
+        log::info!("Attract mode ended");
+        events::emit(Event::AttractMode(None));
+    }
+}
+
+/**
+ * Read every input device on a thread of its own, counting button presses as activity. Games read
+ * the same devices, so this never takes input away from them. Devices that can't be opened (e.g.
+ * the backend isn't in the `input` group) are logged and skipped.
+ */
+fn watch_input() {
+    let entries = match std::fs::read_dir(INPUT_DIR) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("Couldn't list input devices, only the menu ends attract mode: {e}");
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !entry.file_name().to_string_lossy().starts_with("event") {
+            continue;
+        }
+        let mut device = match std::fs::File::open(&path) {
+            Ok(device) => device,
+            Err(e) => {
+                log::warn!("Couldn't read input device {}: {e}", path.display());
+                continue;
+            }
+        };
+        std::thread::spawn(move || {
+            let mut event = [0u8; INPUT_EVENT_SIZE];
+            while device.read_exact(&mut event).is_ok() {
+                if is_press(&event) {
+                    activity();
+                }
+            }
+            log::debug!("Stopped reading input device {}", path.display());
+        });
+    }
+}
+
+/**
+ * Whether an input event is a button being pressed. Stick and axis movement isn't counted, since
+ * a drifting stick would keep the cabinet from ever being idle.
+ */
+fn is_press(event: &[u8; INPUT_EVENT_SIZE]) -> bool {
+    let kind = u16::from_ne_bytes([event[16], event[17]]);
+    let value = i32::from_ne_bytes([event[20], event[21], event[22], event[23]]);
+    kind == EV_KEY && value == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_button_presses_count_as_activity() {
+        let event = |kind: u16, value: i32| {
+            let mut event = [0u8; INPUT_EVENT_SIZE];
+            event[16..18].copy_from_slice(&kind.to_ne_bytes());
+            event[20..24].copy_from_slice(&value.to_ne_bytes());
+            event
+        };
+        assert!(is_press(&event(EV_KEY, 1)));
+        // Releases and auto-repeats
+        assert!(!is_press(&event(EV_KEY, 0)));
+        assert!(!is_press(&event(EV_KEY, 2)));
+        // Stick movement
+        assert!(!is_press(&event(3, 1)));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::api::{self, check_game_id};
+use crate::atomic;
+use crate::env;
+use crate::storage;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::AudioSettings;
+use lazy_static::lazy_static;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::process::Command;
+
+/**
+ * The file (relative to the devcade path) that audio settings are stored in
+ */
+const AUDIO_FILE: &str = "audio.json";
+
+/**
+ * The most a game's volume can be offset from the master volume, either way
+ */
+const MAX_OFFSET: i8 = 50;
+
+lazy_static! {
+    static ref SETTINGS: Mutex<AudioSettings> = Mutex::new(AudioSettings::default());
+}
+
+fn settings_path() -> PathBuf {
+    storage::root().join(AUDIO_FILE)
+}
+
+/**
+ * Load audio settings from the devcade directory and apply them. Missing or unreadable settings are
+ * logged and replaced with the defaults.
+ */
+pub async fn load() {
+    let path = settings_path();
+    let settings = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::warn!("Ignoring invalid audio settings at {:?}: {e}", path);
+                AudioSettings::default()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => AudioSettings::default(),
+        Err(e) => {
+            log::warn!("Couldn't read audio settings at {:?}: {e}", path);
+            AudioSettings::default()
+        }
+    };
+    *SETTINGS.lock().unwrap() = settings;
+    if let Err(e) = apply().await {
+        log::warn!("Couldn't apply audio settings: {e}");
+    }
+}
+
+/**
+ * Get the current audio settings
+ */
+#[must_use]
+pub fn settings() -> AudioSettings {
+    SETTINGS.lock().unwrap().clone()
+}
+
+/**
+ * Set the volume everything plays at, from 0 to 100. Higher volumes are capped at 100.
+ *
+ * # Errors
+ * This function will return an error if the settings can't be saved or applied.
+ */
+pub async fn set_master_volume(volume: u8) -> Result<AudioSettings, Error> {
+    update(|settings| settings.master_volume = volume.min(100)).await
+}
+
+/**
+ * Turn the master volume up (or down, if the change is negative), such as for the frontend's
+ * volume buttons.
+ *
+ * # Errors
+ * This function will return an error if the settings can't be saved or applied.
+ */
+pub async fn change_master_volume(change: i8) -> Result<AudioSettings, Error> {
+    update(|settings| {
+        settings.master_volume =
+            (i16::from(settings.master_volume) + i16::from(change)).clamp(0, 100) as u8;
+    })
+    .await
+}
+
+/**
+ * Set whether sound is muted while the menu is showing.
+ *
+ * # Errors
+ * This function will return an error if the settings can't be saved or applied.
+ */
+pub async fn set_mute_in_menu(mute: bool) -> Result<AudioSettings, Error> {
+    update(|settings| settings.mute_in_menu = mute).await
+}
+
+/**
+ * Set how much louder (or quieter, if negative) a game plays than the master volume. Offsets are
+ * capped at `MAX_OFFSET` either way, and an offset of 0 removes it.
+ *
+ * # Errors
+ * This function will return an error if the game ID is invalid, or the settings can't be saved or
+ * applied.
+ */
+pub async fn set_game_offset(game_id: String, offset: i8) -> Result<AudioSettings, Error> {
+    check_game_id(game_id.as_str())?;
+    let offset = offset.clamp(-MAX_OFFSET, MAX_OFFSET);
+    update(move |settings| {
+        if offset == 0 {
+            settings.game_offsets.remove(&game_id);
+        } else {
+            settings.game_offsets.insert(game_id, offset);
+        }
+    })
+    .await
+}
+
+/**
+ * Change the settings, then persist and apply them
+ */
+async fn update(change: impl FnOnce(&mut AudioSettings)) -> Result<AudioSettings, Error> {
+    let settings = {
+        let mut settings = SETTINGS.lock().unwrap();
+        change(&mut settings);
+        settings.clone()
+    };
+    atomic::write_async(settings_path(), serde_json::to_string(&settings)?).await?;
+    apply().await?;
+    Ok(settings)
+}
+
+/**
+ * Set the sink's volume and mute for whatever is showing: the running game's volume while a game is
+ * running, and the menu's otherwise. Called whenever a game starts or exits.
+ *
+ * # Errors
+ * This function will return an error if `pactl` can't be run or fails.
+ */
+pub async fn apply() -> Result<(), Error> {
+    let game = api::current_game().map(|game| game.id);
+    let (volume, muted) = levels(&settings(), game.as_deref());
+    let sink = env::audio_sink();
+    pactl(&[
+        "set-sink-volume",
+        sink.as_str(),
+        format!("{volume}%").as_str(),
+    ])
+    .await?;
+    pactl(&[
+        "set-sink-mute",
+        sink.as_str(),
+        if muted { "1" } else { "0" },
+    ])
+    .await
+}
+
+/**
+ * Get the volume and whether sound is muted, for a game or the menu if there's no game running
+ */
+fn levels(settings: &AudioSettings, game_id: Option<&str>) -> (u8, bool) {
+    match game_id {
+        Some(game_id) => {
+            let offset = settings.game_offsets.get(game_id).copied().unwrap_or(0);
+            let volume = (i16::from(settings.master_volume) + i16::from(offset)).clamp(0, 100);
+            (volume as u8, false)
+        }
+        None => (settings.master_volume, settings.mute_in_menu),
+    }
+}
+
+/**
+ * Run `pactl`, which controls PipeWire through `pipewire-pulse` as well as PulseAudio
+ */
+async fn pactl(args: &[&str]) -> Result<(), Error> {
+    let output = Command::new("pactl").args(args).output().await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "pactl {} failed with {}: {}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_offsets_are_clamped_and_menu_can_be_muted() {
+        let mut settings = AudioSettings {
+            master_volume: 80,
+            mute_in_menu: true,
+            ..AudioSettings::default()
+        };
+        settings.game_offsets.insert(String::from("loud"), -30);
+        settings.game_offsets.insert(String::from("quiet"), 40);
+
+        assert_eq!(levels(&settings, None), (80, true));
+        assert_eq!(levels(&settings, Some("loud")), (50, false));
+        assert_eq!(levels(&settings, Some("quiet")), (100, false));
+        assert_eq!(levels(&settings, Some("other")), (80, false));
+    }
+}
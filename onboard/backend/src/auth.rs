@@ -0,0 +1,218 @@
+use crate::api;
+use crate::i18n::tr;
+use crate::nfc;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::{LoginChallenge, LoginMethod};
+use devcade_onboard_types::Player;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/**
+ * How long a player has to enter their PIN
+ */
+const PIN_LIFETIME: Duration = Duration::from_secs(120);
+
+/**
+ * How many wrong PINs can be entered for one login before it has to be started again
+ */
+const PIN_ATTEMPTS: u32 = 3;
+
+/**
+ * A way of logging players in without their badge. Whatever the method, a login ends with the
+ * player's association ID, which is turned into a handle just like a badge tap.
+ */
+pub(crate) trait AuthProvider {
+    /**
+     * Start logging a player in, getting what the frontend should show them
+     */
+    async fn start(&self, player: Player) -> Result<Login, Error>;
+
+    /**
+     * Check on a login, with whatever the player entered. Returns `None` while waiting for the
+     * player.
+     */
+    async fn finish(
+        &self,
+        login: &mut Login,
+        response: Option<String>,
+    ) -> Result<Option<String>, Error>;
+}
+
+/**
+ * A login in progress
+ */
+pub(crate) struct Login {
+    challenge: LoginChallenge,
+    /**
+     * What the provider needs to finish the login, like the API's code for it
+     */
+    secret: String,
+    attempts: u32,
+}
+
+/**
+ * Logs players in by having them scan a QR code and confirm on their phone
+ */
+struct QrProvider;
+
+/**
+ * Logs players in with a PIN they set up on their account
+ */
+struct PinProvider;
+
+lazy_static! {
+    /**
+     * Logins in progress, by ID
+     */
+    static ref LOGINS: Mutex<HashMap<String, Login>> = Mutex::new(HashMap::new());
+}
+
+/**
+ * Start logging a player in without their badge.
+ *
+ * # Errors
+ * This function will return an error if the login method isn't available, for example because the
+ * API can't be reached.
+ */
+pub async fn start(player: Player, method: LoginMethod) -> Result<LoginChallenge, Error> {
+    let login = match method {
+        LoginMethod::Qr => QrProvider.start(player).await?,
+        LoginMethod::Pin => PinProvider.start(player).await?,
+    };
+    let challenge = login.challenge.clone();
+    let now = now();
+    let mut logins = LOGINS.lock().unwrap();
+    logins.retain(|_, login| login.challenge.expires_at > now);
+    logins.insert(challenge.id.clone(), login);
+    Ok(challenge)
+}
+
+/**
+ * Check on a login, with the PIN the player entered for PIN logins. Once the player is logged in,
+ * they're given to the running game like a badge tap and their handle is returned. Returns `None`
+ * while waiting for the player.
+ *
+ * # Errors
+ * This function will return an error if the login doesn't exist or has expired, if the PIN was
+ * wrong, or if the API can't be reached.
+ */
+pub async fn finish(id: String, response: Option<String>) -> Result<Option<String>, Error> {
+    let Some(mut login) = LOGINS.lock().unwrap().remove(&id) else {
+        return Err(anyhow!(tr("login_not_found", &[])));
+    };
+    if login.challenge.expires_at <= now() {
+        return Err(anyhow!(tr("login_not_found", &[])));
+    }
+    let result = match login.challenge.method {
+        LoginMethod::Qr => QrProvider.finish(&mut login, response).await,
+        LoginMethod::Pin => PinProvider.finish(&mut login, response).await,
+    };
+    match result {
+        Ok(Some(association_id)) => {
+            log::info!(
+                "Player {} logged in with {:?}",
+                login.challenge.player,
+                login.challenge.method
+            );
+            Ok(Some(nfc::login(association_id, &login.challenge.player)))
+        }
+        Ok(None) => {
+            LOGINS.lock().unwrap().insert(id, login);
+            Ok(None)
+        }
+        Err(err) => {
+            // Wrong PINs can be retried a few times, other failures end the login
+            if login.challenge.method == LoginMethod::Pin && login.attempts < PIN_ATTEMPTS {
+                LOGINS.lock().unwrap().insert(id, login);
+            }
+            Err(err)
+        }
+    }
+}
+
+impl AuthProvider for QrProvider {
+    async fn start(&self, player: Player) -> Result<Login, Error> {
+        let qr = api::start_qr_login().await?;
+        Ok(Login {
+            challenge: LoginChallenge {
+                id: new_id()?,
+                player,
+                method: LoginMethod::Qr,
+                url: Some(qr.url),
+                expires_at: now() + qr.expires_in_secs,
+            },
+            secret: qr.code,
+            attempts: 0,
+        })
+    }
+
+    async fn finish(
+        &self,
+        login: &mut Login,
+        _response: Option<String>,
+    ) -> Result<Option<String>, Error> {
+        api::qr_login_status(login.secret.as_str()).await
+    }
+}
+
+impl AuthProvider for PinProvider {
+    async fn start(&self, player: Player) -> Result<Login, Error> {
+        Ok(Login {
+            challenge: LoginChallenge {
+                id: new_id()?,
+                player,
+                method: LoginMethod::Pin,
+                url: None,
+                expires_at: now() + PIN_LIFETIME.as_secs(),
+            },
+            secret: String::new(),
+            attempts: 0,
+        })
+    }
+
+    async fn finish(
+        &self,
+        login: &mut Login,
+        response: Option<String>,
+    ) -> Result<Option<String>, Error> {
+        let Some(pin) = response.filter(|pin| !pin.is_empty()) else {
+            return Ok(None);
+        };
+        login.attempts += 1;
+        match api::pin_login(pin.as_str()).await? {
+            Some(association_id) => Ok(Some(association_id)),
+            None => Err(anyhow!(tr("login_pin_wrong", &[]))),
+        }
+    }
+}
+
+fn new_id() -> Result<String, Error> {
+    let mut bytes = [0; 16];
+    openssl::rand::rand_bytes(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pin_logins_wait_for_a_pin() {
+        let challenge = start(Player::P2, LoginMethod::Pin).await.unwrap();
+        assert_eq!(challenge.id.len(), 32);
+        assert!(challenge.url.is_none());
+        assert_eq!(finish(challenge.id.clone(), None).await.unwrap(), None);
+        // Still waiting, so it can be checked on again
+        assert_eq!(finish(challenge.id, None).await.unwrap(), None);
+        assert!(finish(String::from("unknown"), None).await.is_err());
+    }
+}
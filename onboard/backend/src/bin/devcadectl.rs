@@ -0,0 +1,89 @@
+use backend::servers::path::control_pipe;
+use devcade_onboard_types::{Request, RequestBody, Response, ResponseBody};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: devcadectl <command>
+
+Commands:
+  list              List installed games
+  install <game>    Download and install a game
+  uninstall <game>  Uninstall a game
+  launch <game>     Launch a game, waiting until it exits
+  stop              Stop the running game
+  logs [level]      Stream backend logs at or above a level (default info)
+  flush             Write cached saves to storage";
+
+/**
+ * A small CLI for driving the backend from a shell on the cabinet, through the control socket
+ */
+fn main() -> ExitCode {
+    // The backend is usually started from its own directory, with the .env one level up
+    let _ = dotenvy::from_filename("../.env");
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let request = match args.as_slice() {
+        ["list"] => RequestBody::GetInstalledGames,
+        ["install", game_id] => RequestBody::DownloadGame(game_id.to_string()),
+        ["uninstall", game_id] => RequestBody::UninstallGame(game_id.to_string()),
+        ["launch", game_id] => RequestBody::LaunchGame(game_id.to_string()),
+        ["stop"] => RequestBody::StopGame,
+        ["logs"] => RequestBody::TailLogs(String::from("info")),
+        ["logs", level] => RequestBody::TailLogs(level.to_string()),
+        ["flush"] => RequestBody::Flush,
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match send(request) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("devcadectl: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/**
+ * Send a request and print every response to it, getting whether it succeeded
+ */
+fn send(body: RequestBody) -> Result<bool, anyhow::Error> {
+    let path = control_pipe();
+    let mut stream = UnixStream::connect(path.as_str())
+        .map_err(|e| anyhow::anyhow!("Couldn't connect to the backend at {path}: {e}"))?;
+    let mut request = serde_json::to_vec(&Request {
+        request_id: 1,
+        body,
+    })?;
+    request.push(b'\n');
+    stream.write_all(&request)?;
+
+    for line in BufReader::new(stream).lines() {
+        let response: Response = serde_json::from_str(line?.as_str())?;
+        match response.body {
+            ResponseBody::LogLine(line) => println!("{line}"),
+            ResponseBody::InstalledGames(installed) => {
+                for game in installed.games {
+                    println!("{}\t{}", game.id, game.name);
+                }
+                for corrupt in installed.corrupt {
+                    eprintln!("corrupt: {}: {}", corrupt.path, corrupt.error);
+                }
+                return Ok(true);
+            }
+            ResponseBody::Err(err) => {
+                eprintln!("{err}");
+                return Ok(false);
+            }
+            body => {
+                println!("{body}");
+                return Ok(true);
+            }
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,213 @@
+use crate::api;
+use crate::atomic;
+use crate::env;
+use crate::events;
+use crate::i18n::tr;
+use crate::storage;
+use anyhow::Error;
+use devcade_onboard_types::schema::{BrokenFlag, DevcadeGame};
+use devcade_onboard_types::Event;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * The file (relative to the devcade path) that broken flags are stored in
+ */
+const FLAGS_FILE: &str = "broken_games.json";
+
+lazy_static! {
+    static ref FLAGS: tokio::sync::Mutex<BTreeMap<String, StoredFlag>> =
+        tokio::sync::Mutex::new(BTreeMap::new());
+    // A copy of the flags that aren't cleared, for filtering game lists without waiting on the lock
+    static ref BROKEN: std::sync::Mutex<BTreeMap<String, BrokenFlag>> =
+        std::sync::Mutex::new(BTreeMap::new());
+    // Held while syncing, so a flag isn't reported twice by syncs spawned close together
+    static ref SYNCING: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+/**
+ * A flag as it's stored on disk. Cleared flags are kept until the API has been told about them.
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct StoredFlag {
+    flag: BrokenFlag,
+    cleared: bool,
+    synced: bool,
+}
+
+fn flags_path() -> PathBuf {
+    storage::root().join(FLAGS_FILE)
+}
+
+/**
+ * Load broken flags from the devcade directory and report any the API hasn't heard about yet.
+ * Missing or unreadable flags are logged and replaced with none.
+ */
+pub async fn load() {
+    let path = flags_path();
+    let flags = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(flags) => flags,
+            Err(e) => {
+                log::warn!("Ignoring invalid broken flags at {:?}: {e}", path);
+                BTreeMap::new()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => {
+            log::warn!("Couldn't read broken flags at {:?}: {e}", path);
+            BTreeMap::new()
+        }
+    };
+    let mut stored = FLAGS.lock().await;
+    *stored = flags;
+    update_broken(&stored);
+    drop(stored);
+    tokio::spawn(sync());
+}
+
+/**
+ * Flag a game as broken on this cabinet, replacing any earlier note. The flag is reported to the
+ * API in the background.
+ *
+ * # Errors
+ * This function will return an error if the game ID is invalid or the flags can't be saved.
+ */
+pub async fn flag(game_id: String, note: String) -> Result<(), Error> {
+    api::check_game_id(game_id.as_str())?;
+    let flagged_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    log::warn!("Game {game_id} was flagged as broken: {note}");
+    let flag = StoredFlag {
+        flag: BrokenFlag {
+            game_id: game_id.clone(),
+            note,
+            flagged_at,
+        },
+        cleared: false,
+        synced: false,
+    };
+    update(|flags| {
+        flags.insert(game_id.clone(), flag);
+    })
+    .await?;
+    events::emit(Event::GameUpdated(game_id));
+    tokio::spawn(sync());
+    Ok(())
+}
+
+/**
+ * Remove a game's broken flag. The API is told in the background.
+ *
+ * # Errors
+ * This function will return an error if the game isn't flagged or the flags can't be saved.
+ */
+pub async fn clear(game_id: String) -> Result<(), Error> {
+    let mut found = false;
+    update(|flags| {
+        if let Some(stored) = flags.get_mut(&game_id).filter(|stored| !stored.cleared) {
+            stored.cleared = true;
+            stored.synced = false;
+            found = true;
+        }
+    })
+    .await?;
+    if !found {
+        return Err(anyhow::anyhow!(tr(
+            "broken_flag_not_found",
+            &[("game_id", game_id.as_str())]
+        )));
+    }
+    log::info!("Game {game_id} is no longer flagged as broken");
+    events::emit(Event::GameUpdated(game_id));
+    tokio::spawn(sync());
+    Ok(())
+}
+
+/**
+ * Get every game flagged as broken on this cabinet
+ */
+#[must_use]
+pub fn flags() -> Vec<BrokenFlag> {
+    BROKEN.lock().unwrap().values().cloned().collect()
+}
+
+/**
+ * Leave games flagged as broken out of a game list if `DEVCADE_HIDE_BROKEN_GAMES` is set. Otherwise
+ * the list is returned as is, and the frontend warns about flagged games.
+ */
+#[must_use]
+pub fn visible(games: Vec<DevcadeGame>) -> Vec<DevcadeGame> {
+    if !env::hide_broken_games() {
+        return games;
+    }
+    let broken = BROKEN.lock().unwrap();
+    games
+        .into_iter()
+        .filter(|game| !broken.contains_key(&game.id))
+        .collect()
+}
+
+/**
+ * Report flags the API hasn't heard about yet. Failures are logged and retried on the next sync.
+ * Only one sync runs at a time, later ones wait for it and then report what's still unsynced.
+ */
+pub async fn sync() {
+    let _syncing = SYNCING.lock().await;
+    let pending: Vec<StoredFlag> = FLAGS
+        .lock()
+        .await
+        .values()
+        .filter(|stored| !stored.synced)
+        .cloned()
+        .collect();
+    for stored in pending {
+        let game_id = stored.flag.game_id.clone();
+        if let Err(e) =
+            api::report_broken(game_id.as_str(), !stored.cleared, stored.flag.note.as_str()).await
+        {
+            log::warn!("Couldn't report broken flag for {game_id}, will retry: {e}");
+            continue;
+        }
+        let result = update(|flags| {
+            // Skip flags that changed while the report was in flight
+            if flags.get(&game_id).map(|current| current.flag.flagged_at)
+                != Some(stored.flag.flagged_at)
+            {
+                return;
+            }
+            if stored.cleared {
+                flags.remove(&game_id);
+            } else if let Some(current) = flags.get_mut(&game_id) {
+                current.synced = current.cleared == stored.cleared;
+            }
+        })
+        .await;
+        if let Err(e) = result {
+            log::warn!("Couldn't save broken flags: {e}");
+        }
+    }
+}
+
+/**
+ * Change the stored flags and save them
+ */
+async fn update(change: impl FnOnce(&mut BTreeMap<String, StoredFlag>)) -> Result<(), Error> {
+    let mut flags = FLAGS.lock().await;
+    change(&mut flags);
+    update_broken(&flags);
+    atomic::write_async(flags_path(), serde_json::to_string(&*flags)?).await
+}
+
+fn update_broken(flags: &BTreeMap<String, StoredFlag>) {
+    *BROKEN.lock().unwrap() = flags
+        .iter()
+        .filter(|(_, stored)| !stored.cleared)
+        .map(|(game_id, stored)| (game_id.clone(), stored.flag.clone()))
+        .collect();
+}
@@ -0,0 +1,159 @@
+use crate::api;
+use crate::atomic;
+use crate::env;
+use crate::storage;
+use anyhow::Error;
+use devcade_onboard_types::schema::CabinetIdentity;
+use lazy_static::lazy_static;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/**
+ * The file (relative to the devcade path) that the cabinet's identity is stored in
+ */
+const IDENTITY_FILE: &str = "cabinet.json";
+
+/**
+ * How long to wait before trying to register again when the API can't be reached
+ */
+const REGISTER_RETRY: Duration = Duration::from_secs(60 * 60);
+
+lazy_static! {
+    static ref IDENTITY: Mutex<Option<CabinetIdentity>> = Mutex::new(None);
+}
+
+fn identity_path() -> PathBuf {
+    storage::root().join(IDENTITY_FILE)
+}
+
+/**
+ * Get the cabinet's identity, or `None` if it hasn't been loaded yet
+ */
+#[must_use]
+pub fn identity() -> Option<CabinetIdentity> {
+    IDENTITY.lock().unwrap().clone()
+}
+
+/**
+ * Load the cabinet's identity from the devcade directory, making one up the first time the
+ * backend starts. The name and location are taken from the environment when they're set there.
+ *
+ * # Errors
+ * This function will return an error if the identity can't be written, or an ID can't be made.
+ */
+pub async fn load() -> Result<CabinetIdentity, Error> {
+    let path = identity_path();
+    let stored: Option<CabinetIdentity> = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(identity) => Some(identity),
+            Err(e) => {
+                // Making a new ID turns this into a different cabinet, but there's nothing to keep
+                log::warn!("Ignoring invalid cabinet identity at {:?}: {e}", path);
+                None
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut identity = match stored.clone() {
+        Some(identity) => identity,
+        None => {
+            let identity = CabinetIdentity {
+                id: new_id()?,
+                ..CabinetIdentity::default()
+            };
+            log::info!("This cabinet is now {}", identity.id);
+            identity
+        }
+    };
+    identity.name = env::cabinet_name();
+    if let Some(location) = env::cabinet_location() {
+        identity.location = Some(location);
+    }
+    if stored.as_ref() != Some(&identity) {
+        save(&identity).await?;
+    }
+    *IDENTITY.lock().unwrap() = Some(identity.clone());
+    Ok(identity)
+}
+
+async fn save(identity: &CabinetIdentity) -> Result<(), Error> {
+    atomic::write_async(identity_path(), serde_json::to_string_pretty(identity)?).await
+}
+
+/**
+ * Register the cabinet with the API, trying again every hour until it works. This should be
+ * spawned as a task at startup, after `load`.
+ */
+pub async fn run() {
+    loop {
+        match register().await {
+            Ok(()) => return,
+            Err(e) => {
+                log::warn!(
+                    "Couldn't register the cabinet, trying again in {REGISTER_RETRY:?}: {e}"
+                );
+                tokio::time::sleep(REGISTER_RETRY).await;
+            }
+        }
+    }
+}
+
+async fn register() -> Result<(), Error> {
+    let Some(mut identity) = identity() else {
+        return Ok(());
+    };
+    if !env::api_configured() {
+        log::info!("Not registering the cabinet, the API domain isn't set");
+        return Ok(());
+    }
+    api::register_cabinet(&identity).await?;
+    identity.registered_at = Some(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    );
+    log::info!("Registered cabinet {} ({})", identity.name, identity.id);
+    save(&identity).await?;
+    *IDENTITY.lock().unwrap() = Some(identity);
+    Ok(())
+}
+
+/**
+ * Make a random (version 4) UUID
+ */
+fn new_id() -> Result<String, Error> {
+    let mut bytes = [0u8; 16];
+    openssl::rand::rand_bytes(&mut bytes)?;
+    Ok(format_uuid(bytes))
+}
+
+fn format_uuid(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_version_4_uuids() {
+        assert_eq!(
+            format_uuid([0xff; 16]),
+            "ffffffff-ffff-4fff-bfff-ffffffffffff"
+        );
+    }
+}
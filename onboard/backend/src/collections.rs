@@ -0,0 +1,131 @@
+use crate::api;
+use crate::atomic;
+use crate::i18n::tr;
+use crate::storage;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::{Collection, DevcadeGame};
+use lazy_static::lazy_static;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/**
+ * The file (relative to the devcade path) the last collections fetched from the API are kept in,
+ * so the menu's shelves survive the API being down
+ */
+const COLLECTIONS_FILE: &str = "collections.json";
+
+lazy_static! {
+    /**
+     * What was last written to `COLLECTIONS_FILE`, so unchanged collections aren't written again
+     */
+    static ref SAVED: Mutex<Option<Vec<Collection>>> = Mutex::new(None);
+}
+
+fn collections_path() -> PathBuf {
+    storage::root().join(COLLECTIONS_FILE)
+}
+
+/**
+ * Get the curated collections from the API, or the last ones fetched if it can't be reached
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached and no collections have ever been
+ * fetched.
+ */
+pub async fn list() -> Result<Vec<Collection>, Error> {
+    match api::collection_list().await {
+        Ok(collections) => {
+            if let Err(e) = save(&collections).await {
+                log::warn!("Couldn't keep collections for offline use: {e}");
+            }
+            Ok(collections)
+        }
+        Err(err) => match load().await {
+            Some(collections) => {
+                log::warn!("Couldn't fetch collections, using the last ones fetched: {err}");
+                Ok(collections)
+            }
+            None => Err(err),
+        },
+    }
+}
+
+/**
+ * Get the games in a collection, in the collection's order. If the API can't be reached, only the
+ * installed games in the collection are returned.
+ *
+ * # Errors
+ * This function will return an error if the collection doesn't exist, or neither the API nor the
+ * installed games can be read.
+ */
+pub async fn games(collection_id: &str) -> Result<Vec<DevcadeGame>, Error> {
+    let collection = list()
+        .await?
+        .into_iter()
+        .find(|collection| collection.id == collection_id)
+        .ok_or_else(|| anyhow!(tr("collection_not_found", &[("collection", collection_id)])))?;
+    match api::get_games(&collection.game_ids).await {
+        Ok(games) => Ok(games),
+        Err(err) => {
+            log::warn!(
+                "Couldn't fetch games in collection {collection_id}, showing installed ones: {err}"
+            );
+            let installed = api::game_list_from_fs().await?.games;
+            Ok(in_order(&collection.game_ids, installed))
+        }
+    }
+}
+
+/**
+ * Pick the games with the given IDs out of a list, in the order of the IDs
+ */
+fn in_order(ids: &[String], games: Vec<DevcadeGame>) -> Vec<DevcadeGame> {
+    let mut games: Vec<DevcadeGame> = games
+        .into_iter()
+        .filter(|game| ids.contains(&game.id))
+        .collect();
+    games.sort_by_key(|game| ids.iter().position(|id| *id == game.id));
+    games
+}
+
+async fn save(collections: &[Collection]) -> Result<(), Error> {
+    if SAVED.lock().unwrap().as_deref() == Some(collections) {
+        return Ok(());
+    }
+    atomic::write_async(
+        collections_path(),
+        serde_json::to_string_pretty(collections)?,
+    )
+    .await?;
+    *SAVED.lock().unwrap() = Some(collections.to_vec());
+    Ok(())
+}
+
+async fn load() -> Option<Vec<Collection>> {
+    let path = collections_path();
+    let json = tokio::fs::read_to_string(&path).await.ok()?;
+    match serde_json::from_str(json.as_str()) {
+        Ok(collections) => Some(collections),
+        Err(e) => {
+            log::warn!("Ignoring invalid collections at {:?}: {e}", path);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offline_collections_keep_their_order() {
+        let game = |id: &str| DevcadeGame {
+            id: id.to_string(),
+            ..DevcadeGame::default()
+        };
+        let ids = vec![String::from("b"), String::from("c"), String::from("a")];
+        let games = in_order(&ids, vec![game("a"), game("b"), game("d")]);
+        let games: Vec<&str> = games.iter().map(|game| game.id.as_str()).collect();
+        assert_eq!(games, ["b", "a"]);
+    }
+}
@@ -1,10 +1,40 @@
+use crate::achievements;
 use crate::api::{self, nfc_user};
+use crate::attract;
+use crate::audio;
+use crate::auth;
+use crate::broken_games;
+use crate::cabinet;
+use crate::collections;
+use crate::favorites;
+use crate::game_logs::game_logs;
+use crate::gpu;
+use crate::guests;
+use crate::history;
+use crate::i18n::{self, tr};
+use crate::install_queue;
+use crate::install_report;
+use crate::leaderboards;
+use crate::log_stream;
+use crate::nfc;
+use crate::play_stats;
+use crate::prefetch;
+use crate::ratings;
+use crate::readiness;
+use crate::removal;
+use crate::safe_mode;
+use crate::save_slots;
+use crate::save_sync;
+use crate::session_stats;
+use crate::storage;
+use crate::ticker;
 
 use crate::api::{
-    download_banner, download_game, download_icon, game_list, game_list_from_fs, kill_current_game,
-    launch_game, nfc_tags, persistence_flush, persistence_load, persistence_save, tag_games,
-    tag_list, user,
+    download_banner, download_game, download_icon, game_list, game_list_from_fs, game_trust_info,
+    kill_current_game, launch_game, nfc_tags, persistence_flush, persistence_load,
+    persistence_save, reinstall_game, stop_current_game, tag_games, tag_list, uninstall_game, user,
 };
+use devcade_onboard_types::schema::AchievementStatus;
 use devcade_onboard_types::{RequestBody, ResponseBody};
 
 /**
@@ -13,21 +43,43 @@ use devcade_onboard_types::{RequestBody, ResponseBody};
 pub async fn handle(req: RequestBody) -> ResponseBody {
     match req {
         RequestBody::Ping => ResponseBody::Pong,
+        RequestBody::GetDiagnostics => ResponseBody::Diagnostics(safe_mode::diagnostics()),
+        RequestBody::GetReadiness => ResponseBody::Readiness(readiness::latest()),
+        RequestBody::GetCabinet => match cabinet::identity() {
+            Some(identity) => ResponseBody::Cabinet(identity),
+            None => ResponseBody::Err(tr("cabinet_unknown", &[])),
+        },
+        RequestBody::GetBackendLogs => ResponseBody::BackendLogs(log_stream::recent()),
+        RequestBody::ResetCaches | RequestBody::ExitSafeMode => {
+            ResponseBody::Err(tr("safe_mode_only", &[]))
+        }
+        RequestBody::TailLogs(_) => ResponseBody::Err(tr("control_socket_only", &[])),
         RequestBody::GetGameList => match game_list().await {
-            Ok(games) => ResponseBody::GameList(games),
-            Err(_) => match game_list_from_fs() {
-                Ok(games) => ResponseBody::GameList(games),
+            Ok(games) => ResponseBody::GameList(broken_games::visible(games)),
+            Err(_) => match game_list_from_fs().await {
+                Ok(installed) => ResponseBody::GameList(broken_games::visible(api::hide_staging(
+                    installed.games,
+                ))),
                 Err(err) => err.into(),
             },
         },
-        RequestBody::GetGameListFromFs => match game_list_from_fs() {
-            Ok(games) => ResponseBody::GameList(games),
+        RequestBody::GetGameListFromFs => match game_list_from_fs().await {
+            Ok(installed) => {
+                ResponseBody::GameList(broken_games::visible(api::hide_staging(installed.games)))
+            }
+            Err(err) => err.into(),
+        },
+        RequestBody::GetInstalledGames => match game_list_from_fs().await {
+            Ok(mut installed) => {
+                installed.games = broken_games::visible(api::hide_staging(installed.games));
+                ResponseBody::InstalledGames(installed)
+            }
             Err(err) => err.into(),
         },
         RequestBody::GetGame(game_id) => match game_list().await {
             Ok(game) => match game.into_iter().find(|g| g.id == game_id) {
                 Some(game) => ResponseBody::Game(game),
-                None => ResponseBody::Err(format!("Game with ID {game_id} not found")),
+                None => ResponseBody::Err(tr("game_not_found", &[("game_id", game_id.as_str())])),
             },
             Err(err) => err.into(),
         },
@@ -43,7 +95,11 @@ pub async fn handle(req: RequestBody) -> ResponseBody {
             Ok(_) => ResponseBody::Ok,
             Err(err) => err.into(),
         },
-        RequestBody::LaunchGame(game_id) => match launch_game(game_id).await {
+        RequestBody::LaunchGame(game_id) => match launch_game(game_id, Vec::new()).await {
+            Ok(_) => ResponseBody::Ok,
+            Err(err) => err.into(),
+        },
+        RequestBody::LaunchGameWithArgs(game_id, args) => match launch_game(game_id, args).await {
             Ok(_) => ResponseBody::Ok,
             Err(err) => err.into(),
         },
@@ -51,10 +107,132 @@ pub async fn handle(req: RequestBody) -> ResponseBody {
             Ok(_) => ResponseBody::Ok,
             Err(err) => err.into(),
         },
+        RequestBody::StopGame => match stop_current_game().await {
+            Ok(_) => ResponseBody::Ok,
+            Err(err) => err.into(),
+        },
+        RequestBody::HoverGame(game_id) => {
+            attract::activity();
+            prefetch::hint(game_id);
+            ResponseBody::Ok
+        }
+        RequestBody::GetInstallReport(game_id) => {
+            match install_report::report(game_id.as_str()).await {
+                Ok(report) => ResponseBody::InstallReport(report),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::RollbackGame(game_id) => match api::rollback_game(game_id).await {
+            Ok(game) => ResponseBody::Game(game),
+            Err(err) => err.into(),
+        },
+        RequestBody::ExportBundle(game_id, dest) => match api::export_bundle(game_id, dest).await {
+            Ok(path) => ResponseBody::BundleExported(path.display().to_string()),
+            Err(err) => err.into(),
+        },
+        RequestBody::ValidateGame(game_id) => match api::validate_game(game_id).await {
+            Ok(validation) => ResponseBody::BundleValidation(validation),
+            Err(err) => err.into(),
+        },
+        RequestBody::GetGameLogs(game_id, session) => {
+            match game_logs(game_id.as_str(), session).await {
+                Ok(lines) => ResponseBody::GameLogs(lines),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::GetGameTrustInfo(game_id) => match game_trust_info(game_id).await {
+            Ok(info) => ResponseBody::GameTrustInfo(info),
+            Err(err) => err.into(),
+        },
+        RequestBody::GetRemovalCandidates => match removal::candidates().await {
+            Ok(candidates) => ResponseBody::RemovalCandidates(candidates),
+            Err(err) => err.into(),
+        },
+        RequestBody::UninstallGame(game_id) => match uninstall_game(game_id).await {
+            Ok(()) => ResponseBody::Ok,
+            Err(err) => err.into(),
+        },
+        RequestBody::CheckIntegrity => match storage::check_integrity().await {
+            Ok(drifted) => ResponseBody::Integrity(drifted),
+            Err(err) => err.into(),
+        },
+        RequestBody::ReinstallGame(game_id) => match reinstall_game(game_id).await {
+            Ok(_) => ResponseBody::Ok,
+            Err(err) => err.into(),
+        },
+        RequestBody::FlagGameBroken(game_id, note) => {
+            match broken_games::flag(game_id, note).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::ClearBrokenFlag(game_id) => match broken_games::clear(game_id).await {
+            Ok(()) => ResponseBody::Ok,
+            Err(err) => err.into(),
+        },
+        RequestBody::GetBrokenGames => ResponseBody::BrokenGames(broken_games::flags()),
+        RequestBody::GetGpuPreference(game_id) => {
+            ResponseBody::GpuPreference(gpu::preference(game_id.as_str()).await)
+        }
+        RequestBody::SetGpuPreference(game_id, preference) => {
+            match gpu::set_preference(game_id, preference).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::GetQueueStatus => ResponseBody::QueueStatus(install_queue::queue_status()),
+        RequestBody::GetTicker => ResponseBody::Ticker(ticker::items()),
+        RequestBody::MoveInstallJob(job_id, position) => {
+            match install_queue::move_job(job_id, position) {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::CancelInstallJob(job_id) => match install_queue::cancel_job(job_id) {
+            Ok(()) => ResponseBody::Ok,
+            Err(err) => err.into(),
+        },
         RequestBody::SetProduction(prod) => {
             crate::env::set_production(prod);
+            api::cache::invalidate_all();
+            ResponseBody::Ok
+        }
+        RequestBody::GetSessionStats => ResponseBody::SessionStats(session_stats::latest()),
+        RequestBody::GetAudioSettings => ResponseBody::AudioSettings(audio::settings()),
+        RequestBody::SetMasterVolume(volume) => match audio::set_master_volume(volume).await {
+            Ok(settings) => ResponseBody::AudioSettings(settings),
+            Err(err) => err.into(),
+        },
+        RequestBody::ChangeMasterVolume(change) => {
+            match audio::change_master_volume(change).await {
+                Ok(settings) => ResponseBody::AudioSettings(settings),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::SetMuteInMenu(mute) => match audio::set_mute_in_menu(mute).await {
+            Ok(settings) => ResponseBody::AudioSettings(settings),
+            Err(err) => err.into(),
+        },
+        RequestBody::SetGameVolumeOffset(game_id, offset) => {
+            match audio::set_game_offset(game_id, offset).await {
+                Ok(settings) => ResponseBody::AudioSettings(settings),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::SetStaffMode(staff) => {
+            crate::env::set_staff_mode(staff);
+            api::cache::invalidate_all();
             ResponseBody::Ok
         }
+        RequestBody::SetLocale(locale) => match crate::env::set_locale(locale) {
+            Ok(()) => {
+                i18n::reload();
+                // Cached metadata is in the old locale
+                api::cache::invalidate_all();
+                ResponseBody::Ok
+            }
+            Err(err) => err.into(),
+        },
         RequestBody::GetTagList => match tag_list().await {
             Ok(tags) => ResponseBody::TagList(tags),
             Err(err) => err.into(),
@@ -62,19 +240,35 @@ pub async fn handle(req: RequestBody) -> ResponseBody {
         RequestBody::GetTag(tag_name) => match tag_list().await {
             Ok(tags) => match tags.into_iter().find(|t| t.name == tag_name) {
                 Some(tag) => ResponseBody::Tag(tag),
-                None => ResponseBody::Err(format!("Tag with name {tag_name} not found")),
+                None => ResponseBody::Err(tr("tag_not_found", &[("tag", tag_name.as_str())])),
             },
             Err(err) => err.into(),
         },
         RequestBody::GetGameListFromTag(tag_name) => match tag_games(tag_name).await {
-            Ok(games) => ResponseBody::GameList(games),
+            Ok(games) => ResponseBody::GameList(broken_games::visible(games)),
             Err(err) => err.into(),
         },
+        RequestBody::GetCollections => match collections::list().await {
+            Ok(collections) => ResponseBody::Collections(collections),
+            Err(err) => err.into(),
+        },
+        RequestBody::GetCollectionGames(collection_id) => {
+            match collections::games(collection_id.as_str()).await {
+                Ok(games) => ResponseBody::GameList(broken_games::visible(games)),
+                Err(err) => err.into(),
+            }
+        }
         RequestBody::GetUser(uid) => match user(uid).await {
             Ok(user) => ResponseBody::User(user),
             Err(err) => err.into(),
         },
-        RequestBody::GetNfcTag(reader_id) => match nfc_tags(reader_id).await {
+        RequestBody::GetNfcTag(reader_id) => {
+            match nfc_tags(reader_id, crate::env::nfc_realm()).await {
+                Ok(association_id) => ResponseBody::NfcTag(association_id),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::GetNfcTagInRealm(reader_id, realm) => match nfc_tags(reader_id, realm).await {
             Ok(association_id) => ResponseBody::NfcTag(association_id),
             Err(err) => err.into(),
         },
@@ -82,11 +276,34 @@ pub async fn handle(req: RequestBody) -> ResponseBody {
             Ok(user) => ResponseBody::NfcUser(user),
             Err(err) => err.into(),
         },
+        RequestBody::GetReaderStatus(player) => {
+            ResponseBody::ReaderStatus(nfc::nfc_client(&player).status())
+        }
+        RequestBody::StartLogin(player, method) => match auth::start(player, method).await {
+            Ok(challenge) => ResponseBody::LoginChallenge(challenge),
+            Err(err) => err.into(),
+        },
+        RequestBody::FinishLogin(id, response) => match auth::finish(id, response).await {
+            Ok(handle) => ResponseBody::NfcTag(handle),
+            Err(err) => err.into(),
+        },
+        RequestBody::GetNfcStatus => ResponseBody::NfcStatus(nfc::nfc_status()),
+        RequestBody::CreateGuest(name) => match guests::create(name).await {
+            Ok(guest) => ResponseBody::Guest(guest),
+            Err(err) => err.into(),
+        },
+        RequestBody::GetGuests => ResponseBody::Guests(guests::active()),
+        RequestBody::MergeGuest(guest_id, association_id, conflict) => {
+            match guests::merge(guest_id, association_id, conflict).await {
+                Ok(merge) => ResponseBody::GuestMerge(merge),
+                Err(err) => err.into(),
+            }
+        }
         RequestBody::Save(group, key, value) => {
             let group = format!("{}/{}", api::current_game().unwrap().id, group);
             match persistence_save(group.as_str(), key.as_str(), value.as_str()).await {
                 Ok(()) => ResponseBody::Ok,
-                Err(err) => err.into(),
+                Err(err) => save_error(err),
             }
         }
         RequestBody::Load(group, key) => {
@@ -100,5 +317,257 @@ pub async fn handle(req: RequestBody) -> ResponseBody {
             Ok(()) => ResponseBody::Ok,
             Err(err) => err.into(),
         },
+        RequestBody::SaveWithTtl(group, key, value, ttl) => {
+            let group = format!("{}/{}", api::current_game().unwrap().id, group);
+            let ttl = std::time::Duration::from_secs(ttl);
+            match api::persistence_save_with_ttl(&group, &key, &value, ttl).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => save_error(err),
+            }
+        }
+        RequestBody::RestoreSaves(game_id, handle) => {
+            match save_sync::restore(game_id, handle).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::ListKeys(group, prefix) => {
+            let group = format!("{}/{}", api::current_game().unwrap().id, group);
+            match api::persistence_keys(group.as_str(), prefix.as_str()).await {
+                Ok(keys) => ResponseBody::Keys(keys),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::Scan(group, prefix, cursor) => {
+            let group = format!("{}/{}", api::current_game().unwrap().id, group);
+            match api::persistence_scan(group.as_str(), prefix.as_str(), cursor).await {
+                Ok(page) => ResponseBody::SavePage(page),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::Delete(group, key) => {
+            let group = format!("{}/{}", api::current_game().unwrap().id, group);
+            match api::persistence_delete(group.as_str(), key.as_str()).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::ClearNamespace => {
+            match api::persistence_clear(api::current_game().unwrap().id.as_str()).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::ClearGameSaves(game_id, handle) => {
+            match clear_game_saves(game_id, handle).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        // The raw bytes are sent on the socket around the request, so the game socket handles these
+        RequestBody::SaveBlob(_, _, _) | RequestBody::LoadBlob(_, _) => {
+            anyhow::anyhow!("Blobs can only be saved and loaded on the game socket").into()
+        }
+        // Watches belong to a connection, so the servers handle them before they get here
+        RequestBody::WatchKeys(_, _) | RequestBody::UnwatchKeys(_, _) => {
+            anyhow::anyhow!("Keys can only be watched on the game and frontend sockets").into()
+        }
+        RequestBody::GetSaveUsage => match api::save_usage().await {
+            Ok(usage) => ResponseBody::SaveUsage(usage),
+            Err(err) => err.into(),
+        },
+        RequestBody::ListSlots => match running_game() {
+            Ok(game_id) => match save_slots::list(game_id.as_str()).await {
+                Ok(slots) => ResponseBody::Slots(slots),
+                Err(err) => err.into(),
+            },
+            Err(err) => err.into(),
+        },
+        RequestBody::CreateSlot(label, thumbnail) => match running_game() {
+            Ok(game_id) => match save_slots::create(&game_id, &label, thumbnail).await {
+                Ok(slot) => ResponseBody::Slot(slot),
+                Err(err) => save_error(err),
+            },
+            Err(err) => err.into(),
+        },
+        RequestBody::UpdateSlot(slot_id, label, thumbnail) => match running_game() {
+            Ok(game_id) => match save_slots::update(&game_id, &slot_id, &label, thumbnail).await {
+                Ok(slot) => ResponseBody::Slot(slot),
+                Err(err) => save_error(err),
+            },
+            Err(err) => err.into(),
+        },
+        RequestBody::DeleteSlot(slot_id) => match running_game() {
+            Ok(game_id) => match save_slots::delete(&game_id, &slot_id).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            },
+            Err(err) => err.into(),
+        },
+        RequestBody::ListGameSlots(game_id) => match api::check_game_id(game_id.as_str()) {
+            Ok(()) => match save_slots::list(game_id.as_str()).await {
+                Ok(slots) => ResponseBody::Slots(slots),
+                Err(err) => err.into(),
+            },
+            Err(err) => err.into(),
+        },
+        RequestBody::DeleteGameSlot(game_id, slot_id) => match api::check_game_id(&game_id) {
+            Ok(()) => match save_slots::delete(&game_id, &slot_id).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            },
+            Err(err) => err.into(),
+        },
+        RequestBody::SubmitScore(board, handle, score) => {
+            match leaderboards::submit(board.as_str(), handle, score).await {
+                Ok(rank) => ResponseBody::ScoreRank(rank),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::GetLeaderboard(game_id, board, limit) => ResponseBody::Leaderboard(
+            leaderboards::top(game_id.as_str(), board.as_str(), limit as usize),
+        ),
+        RequestBody::ListLeaderboards(game_id) => {
+            ResponseBody::Leaderboards(leaderboards::boards(game_id.as_str()))
+        }
+        RequestBody::UnlockAchievement(achievement_id, handle) => {
+            match achievements::unlock(achievement_id.as_str(), handle).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::GetAchievements(handle) => match api::current_game() {
+            Some(_)
+                if handle
+                    .as_deref()
+                    .is_some_and(|h| !nfc::tapped_this_session(h)) =>
+            {
+                anyhow::anyhow!(tr("nfc_user_not_found", &[])).into()
+            }
+            Some(game) => {
+                ResponseBody::Achievements(achievements::statuses(&game, handle.as_deref()))
+            }
+            None => anyhow::anyhow!(tr("no_game_running", &[])).into(),
+        },
+        RequestBody::GetGameAchievements(game_id, handle) => {
+            match game_achievements(game_id, handle).await {
+                Ok(statuses) => ResponseBody::Achievements(statuses),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::GetPopularGames(days, limit) => {
+            ResponseBody::PopularGames(play_stats::popular(days, limit as usize))
+        }
+        RequestBody::RecentlyPlayed(handle, limit) => {
+            match history::recently_played(handle.as_deref(), limit as usize) {
+                Ok(games) => ResponseBody::RecentGames(games),
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::Favorite(game_id, handle) => {
+            match favorites::favorite(game_id, handle).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::Unfavorite(game_id, handle) => {
+            match favorites::unfavorite(game_id, handle).await {
+                Ok(()) => ResponseBody::Ok,
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::RateGame(game_id, stars) => match ratings::rate(game_id, stars).await {
+            Ok(()) => ResponseBody::Ok,
+            Err(err) => err.into(),
+        },
+        RequestBody::ListFavorites(handle) => match favorites::list(handle.as_str()).await {
+            Ok(favorites) => ResponseBody::Favorites(favorites),
+            Err(err) => err.into(),
+        },
+        RequestBody::GetSaveCacheStats => {
+            ResponseBody::SaveCacheStats(api::save_cache_stats().await)
+        }
+        RequestBody::SaveForUser(handle, group, key, value) => {
+            match user_group(handle.as_deref(), group.as_str()) {
+                Ok(group) => {
+                    match persistence_save(group.as_str(), key.as_str(), value.as_str()).await {
+                        Ok(()) => ResponseBody::Ok,
+                        Err(err) => save_error(err),
+                    }
+                }
+                Err(err) => err.into(),
+            }
+        }
+        RequestBody::LoadForUser(handle, group, key) => {
+            match user_group(handle.as_deref(), group.as_str()) {
+                Ok(group) => match persistence_load(group.as_str(), key.as_str()).await {
+                    Ok(s) => ResponseBody::Object(s),
+                    Err(err) => err.into(),
+                },
+                Err(err) => err.into(),
+            }
+        }
+    }
+}
+
+/**
+ * Get a game's achievements for a player, given the handle the menu was given for their badge
+ */
+async fn game_achievements(
+    game_id: String,
+    handle: Option<String>,
+) -> Result<Vec<AchievementStatus>, anyhow::Error> {
+    let game = api::installed_game(game_id.as_str()).await?;
+    let handle = match handle {
+        Some(handle) => Some(
+            nfc::handle_in_game(handle.as_str(), game_id.as_str())
+                .ok_or_else(|| anyhow::anyhow!(tr("nfc_user_not_found", &[])))?,
+        ),
+        None => None,
+    };
+    Ok(achievements::statuses(&game, handle.as_deref()))
+}
+
+/**
+ * Get the ID of the running game, for requests that act on its saves
+ */
+fn running_game() -> Result<String, anyhow::Error> {
+    api::current_game()
+        .map(|game| game.id)
+        .ok_or_else(|| anyhow::anyhow!(tr("no_game_running", &[])))
+}
+
+/**
+ * Get the group a game's save data for a player goes in, beside the game's shared save data
+ */
+fn user_group(handle: Option<&str>, group: &str) -> Result<String, anyhow::Error> {
+    let namespace = nfc::save_namespace(handle)?;
+    Ok(format!("{}/users/{namespace}/{group}", running_game()?))
+}
+
+/**
+ * Tell games when they're out of save space, instead of just that the save failed
+ */
+pub fn save_error(err: anyhow::Error) -> ResponseBody {
+    match err.downcast::<api::QuotaExceeded>() {
+        Ok(api::QuotaExceeded(usage)) => ResponseBody::QuotaExceeded(usage),
+        Err(err) => err.into(),
     }
 }
+
+/**
+ * Clear a game's save data for the player whose badge the frontend was given a handle for, or
+ * for every player
+ */
+async fn clear_game_saves(game_id: String, handle: Option<String>) -> Result<(), anyhow::Error> {
+    api::check_game_id(game_id.as_str())?;
+    let namespace = match handle {
+        Some(handle) => {
+            let handle = nfc::handle_in_game(handle.as_str(), game_id.as_str())
+                .ok_or_else(|| anyhow::anyhow!(tr("nfc_user_not_found", &[])))?;
+            format!("{game_id}/users/{handle}")
+        }
+        None => game_id,
+    };
+    api::persistence_clear(namespace.as_str()).await
+}
@@ -0,0 +1,126 @@
+use crate::env;
+use futures_util::StreamExt;
+use inotify::{Inotify, WatchMask};
+use std::path::Path;
+use tokio::signal::unix::{signal, SignalKind};
+
+/**
+ * Events watched on the configuration file's directory. Editors often replace a file instead of
+ * writing to it, so the directory is watched rather than the file.
+ */
+const CONFIG_MASK: WatchMask = WatchMask::CLOSE_WRITE
+    .union(WatchMask::MOVED_TO)
+    .union(WatchMask::DELETE)
+    .union(WatchMask::ONLYDIR);
+
+/**
+ * Read the configuration file again whenever the backend gets SIGHUP or the file changes, so
+ * settings can be changed on a running cabinet. If the file can't be watched (e.g. its directory
+ * doesn't exist), only SIGHUP reloads it. This only returns if it can listen for neither, and
+ * should be spawned as a task at startup.
+ */
+pub async fn run() {
+    let path = env::config_path();
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => Some(hangup),
+        Err(e) => {
+            log::warn!("Couldn't listen for SIGHUP, the config file won't be reloaded on it: {e}");
+            None
+        }
+    };
+    let mut changes = match watch(&path) {
+        Ok(changes) => Some(changes),
+        Err(e) => {
+            log::info!("Not watching {} for changes: {e}", path.display());
+            None
+        }
+    };
+    let file_name = path.file_name().map(ToOwned::to_owned);
+
+    loop {
+        tokio::select! {
+            Some(()) = async { hangup.as_mut()?.recv().await } => {
+                log::info!("Got SIGHUP, reloading {}", path.display());
+            }
+            Some(event) = async { changes.as_mut()?.next().await } => {
+                match event {
+                    Ok(event) if event.name.as_deref() == file_name.as_deref() => {}
+                    Ok(_) => continue,
+                    Err(e) => {
+                        log::warn!("Stopped watching {} for changes: {e}", path.display());
+                        changes = None;
+                        continue;
+                    }
+                }
+            }
+            else => return,
+        }
+        reload(&path);
+    }
+}
+
+fn watch(path: &Path) -> Result<inotify::EventStream<[u8; 1024]>, std::io::Error> {
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    let inotify = Inotify::init()?;
+    inotify.watches().add(dir, CONFIG_MASK)?;
+    inotify.into_event_stream([0; 1024])
+}
+
+fn reload(path: &Path) {
+    match env::reload_config(path) {
+        Ok(changed) if changed.is_empty() => {
+            log::debug!("Reloaded {}, nothing changed", path.display());
+        }
+        Ok(changed) => {
+            log::info!(
+                "Reloaded {}, changed {}",
+                path.display(),
+                changed.join(", ")
+            );
+        }
+        Err(e) => log::warn!("Not reloading the config file, keeping the old settings: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reloads_apply_valid_files_and_reject_invalid_ones() {
+        let dir = std::env::temp_dir().join(format!("devcade-config-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+
+        std::fs::write(&path, "cabinet_name = \"Lobby\"\n").unwrap();
+        assert!(env::reload_config(&path)
+            .unwrap()
+            .contains(&"DEVCADE_CABINET_NAME"));
+        assert_eq!(env::cabinet_name(), "Lobby");
+
+        // Nothing changes if any of the file is invalid
+        std::fs::write(
+            &path,
+            "cabinet_name = \"Basement\"\napi_domain = \"https://devcade.csh.rit.edu\"\n",
+        )
+        .unwrap();
+        assert!(env::reload_config(&path).is_err());
+        std::fs::write(&path, "cabinet_name = \"Basement\"\ncolour = \"red\"\n").unwrap();
+        assert!(env::reload_config(&path).is_err());
+        assert_eq!(env::cabinet_name(), "Lobby");
+
+        std::fs::write(&path, "cabinet_name = \"Lobby\"\n").unwrap();
+        assert!(!env::reload_config(&path)
+            .unwrap()
+            .contains(&"DEVCADE_CABINET_NAME"));
+
+        // Settings taken out of the file are unset
+        std::fs::write(&path, "").unwrap();
+        assert!(env::reload_config(&path)
+            .unwrap()
+            .contains(&"DEVCADE_CABINET_NAME"));
+        assert!(std::env::var_os("DEVCADE_CABINET_NAME").is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
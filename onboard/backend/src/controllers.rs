@@ -0,0 +1,123 @@
+use crate::env;
+use crate::storage::{self, CONTROLLER_MAPPINGS, CONTROLLER_OVERRIDES};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::fs;
+
+/**
+ * The variable SDL reads extra controller mappings from, one per line
+ */
+const SDL_MAPPINGS_VAR: &str = "SDL_GAMECONTROLLERCONFIG";
+
+/**
+ * Get the SDL controller mappings a game should be run with: the cabinet's, then the API's for the
+ * game, then the operator's for the game. When more than one has a mapping for the same controller,
+ * the later one wins.
+ */
+pub async fn mappings(game_id: &str) -> Vec<String> {
+    let mut lines = vec![];
+    if let Some(path) = env::controller_mappings() {
+        lines.extend(read_db(Path::new(path.as_str())).await);
+    }
+    let path = storage::game_file(game_id, CONTROLLER_MAPPINGS);
+    match fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str::<Vec<String>>(json.as_str()) {
+            Ok(mappings) => lines.extend(mappings),
+            Err(e) => log::warn!("Ignoring invalid controller mappings {:?}: {e}", path),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Couldn't read controller mappings {:?}: {e}", path),
+    }
+    lines.extend(read_db(&storage::game_file(game_id, CONTROLLER_OVERRIDES)).await);
+    merge(lines)
+}
+
+/**
+ * Give a game its controller mappings, unless its metadata or `env.json` already set them
+ */
+pub async fn apply(game_id: &str, game_env: &mut BTreeMap<String, String>) {
+    if game_env.contains_key(SDL_MAPPINGS_VAR) {
+        return;
+    }
+    let mappings = mappings(game_id).await;
+    if mappings.is_empty() {
+        return;
+    }
+    log::debug!(
+        "Game {game_id} is run with {} controller mappings",
+        mappings.len()
+    );
+    game_env.insert(String::from(SDL_MAPPINGS_VAR), mappings.join("\n"));
+}
+
+/**
+ * Read the lines of a file in `gamecontrollerdb.txt` format, or nothing if it doesn't exist
+ */
+async fn read_db(path: &Path) -> Vec<String> {
+    match fs::read_to_string(path).await {
+        Ok(db) => db.lines().map(String::from).collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(e) => {
+            log::warn!("Couldn't read controller mappings {:?}: {e}", path);
+            vec![]
+        }
+    }
+}
+
+/**
+ * Keep the last mapping for each controller, in the order controllers were first seen. Mappings
+ * look like `<32 hex digit GUID>,<name>,<buttons...>`; comments and blank lines are dropped, and
+ * anything else that doesn't look like a mapping is logged and skipped.
+ */
+fn merge(lines: Vec<String>) -> Vec<String> {
+    let mut guids: Vec<String> = vec![];
+    let mut by_guid: BTreeMap<String, String> = BTreeMap::new();
+    for line in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let guid = line.split(',').next().unwrap_or_default();
+        let valid = guid.len() == 32
+            && guid.chars().all(|c| c.is_ascii_hexdigit())
+            && line.split(',').count() > 2
+            && !line.contains('\n');
+        if !valid {
+            log::warn!("Ignoring invalid controller mapping '{line}'");
+            continue;
+        }
+        let guid = guid.to_ascii_lowercase();
+        if by_guid.insert(guid.clone(), String::from(line)).is_none() {
+            guids.push(guid);
+        }
+    }
+    guids
+        .into_iter()
+        .filter_map(|guid| by_guid.remove(&guid))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_mappings_win() {
+        let pad = "03000000de280000ff11000001000000";
+        let stick = "030000005e0400008e02000010010000";
+        let merged = merge(vec![
+            String::from("# Cabinet encoder"),
+            format!("{pad},Encoder,a:b0,b:b1,platform:Linux,"),
+            format!("{stick},Stick,a:b0,platform:Linux,"),
+            String::from("not a mapping"),
+            format!("{},Encoder,a:b1,b:b0,platform:Linux,", pad.to_uppercase()),
+        ]);
+        assert_eq!(
+            merged,
+            vec![
+                format!("{},Encoder,a:b1,b:b0,platform:Linux,", pad.to_uppercase()),
+                format!("{stick},Stick,a:b0,platform:Linux,"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use crate::env::{self, DisplayServer};
+use devcade_onboard_types::schema::{DevcadeGame, GameOrientation};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::process::Command;
+
+/**
+ * How long the display hook can take before it's given up on, so a stuck hook can't stop games
+ * from launching
+ */
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/**
+ * Which way an output is rotated, counter-clockwise
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Normal,
+    Left,
+    Inverted,
+    Right,
+}
+
+impl Rotation {
+    /**
+     * The rotation's name, as used by `xrandr --rotate`
+     */
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Rotation::Normal => "normal",
+            Rotation::Left => "left",
+            Rotation::Inverted => "inverted",
+            Rotation::Right => "right",
+        }
+    }
+
+    /**
+     * The rotation as a Wayland output transform, as used by `wlr-randr --transform`
+     */
+    #[must_use]
+    pub fn transform(self) -> &'static str {
+        match self {
+            Rotation::Normal => "normal",
+            Rotation::Left => "90",
+            Rotation::Inverted => "180",
+            Rotation::Right => "270",
+        }
+    }
+}
+
+/**
+ * The output games are shown on
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Screen {
+    pub output: String,
+    /**
+     * The width as games see it, after rotation
+     */
+    pub width: u32,
+    /**
+     * The height as games see it, after rotation
+     */
+    pub height: u32,
+    pub rotation: Rotation,
+}
+
+/**
+ * The part of the screen a game should draw in
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+/**
+ * Get the output games are shown on, using `xrandr` or `wlr-randr` depending on the display
+ * server. `DEVCADE_DISPLAY_OUTPUT` picks the output if there's more than one. Returns `None` (after
+ * logging why) if it can't be found.
+ */
+pub async fn screen() -> Option<Screen> {
+    let display_server = env::display_server();
+    let (program, args): (&str, &[&str]) = match display_server {
+        DisplayServer::X11 => ("xrandr", &["--current"]),
+        DisplayServer::Wayland => ("wlr-randr", &[]),
+    };
+    let output = match Command::new(program).args(args).output().await {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            log::warn!("{program} failed with {}", output.status);
+            return None;
+        }
+        Err(e) => {
+            log::warn!("Couldn't run {program}: {e}");
+            return None;
+        }
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let wanted = env::display_output();
+    let screen = match display_server {
+        DisplayServer::X11 => parse_xrandr(&stdout, wanted.as_deref()),
+        DisplayServer::Wayland => parse_wlr_randr(&stdout, wanted.as_deref()),
+    };
+    if screen.is_none() {
+        log::warn!("Couldn't find the output games are shown on in {program}'s output");
+    }
+    screen
+}
+
+/**
+ * Find the output in `xrandr --current` output: the wanted one, or else the primary one, or else
+ * the first connected one. Connected outputs look like
+ * `HDMI-1 connected primary 1080x1920+0+0 left (normal left inverted right) 527mm x 296mm`, where
+ * the rotation is left out if it's normal.
+ */
+fn parse_xrandr(output: &str, wanted: Option<&str>) -> Option<Screen> {
+    let mut screens = output.lines().filter_map(|line| {
+        let mut words = line.split_whitespace();
+        let name = words.next()?;
+        if words.next()? != "connected" {
+            return None;
+        }
+        let mut primary = false;
+        let mut geometry = words.next()?;
+        if geometry == "primary" {
+            primary = true;
+            geometry = words.next()?;
+        }
+        let (width, height) = geometry.split('+').next()?.split_once('x')?;
+        let rotation = match words.next() {
+            Some("left") => Rotation::Left,
+            Some("inverted") => Rotation::Inverted,
+            Some("right") => Rotation::Right,
+            _ => Rotation::Normal,
+        };
+        let screen = Screen {
+            output: String::from(name),
+            width: width.parse().ok()?,
+            height: height.parse().ok()?,
+            rotation,
+        };
+        Some((screen, primary))
+    });
+    match wanted {
+        Some(wanted) => screens
+            .find(|(screen, _)| screen.output == wanted)
+            .map(|(screen, _)| screen),
+        None => {
+            let screens: Vec<_> = screens.collect();
+            screens
+                .iter()
+                .find(|(_, primary)| *primary)
+                .or(screens.first())
+                .map(|(screen, _)| screen.clone())
+        }
+    }
+}
+
+/**
+ * Find the output in `wlr-randr` output: the wanted one, or else the first enabled one. Each output
+ * starts with an unindented line with its name, followed by indented properties, including its
+ * modes (`1920x1080 px, 60.000000 Hz (preferred, current)`) and `Transform: 90`. Modes are before
+ * the transform is applied.
+ */
+fn parse_wlr_randr(output: &str, wanted: Option<&str>) -> Option<Screen> {
+    let mut screens = vec![];
+    let mut current: Option<(Screen, bool)> = None;
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            screens.extend(current.take());
+            let Some(name) = line.split_whitespace().next() else {
+                continue;
+            };
+            let screen = Screen {
+                output: String::from(name),
+                width: 0,
+                height: 0,
+                rotation: Rotation::Normal,
+            };
+            current = Some((screen, true));
+            continue;
+        }
+        let Some((screen, enabled)) = current.as_mut() else {
+            continue;
+        };
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("Enabled:") {
+            *enabled = value.trim() == "yes";
+        } else if let Some(value) = line.strip_prefix("Transform:") {
+            screen.rotation = match value.trim() {
+                "90" => Rotation::Left,
+                "180" => Rotation::Inverted,
+                "270" => Rotation::Right,
+                _ => Rotation::Normal,
+            };
+        } else if line.contains("current") {
+            if let Some((width, height)) = line
+                .split_whitespace()
+                .next()
+                .and_then(|mode| mode.split_once('x'))
+            {
+                screen.width = width.parse().unwrap_or(0);
+                screen.height = height.parse().unwrap_or(0);
+            }
+        }
+    }
+    screens.extend(current);
+
+    let mut screens = screens
+        .into_iter()
+        .filter(|(screen, enabled)| *enabled && screen.width > 0 && screen.height > 0)
+        .map(|(mut screen, _)| {
+            if matches!(screen.rotation, Rotation::Left | Rotation::Right) {
+                std::mem::swap(&mut screen.width, &mut screen.height);
+            }
+            screen
+        });
+    match wanted {
+        Some(wanted) => screens.find(|screen| screen.output == wanted),
+        None => screens.next(),
+    }
+}
+
+/**
+ * Get the part of the screen a game should draw in. Games made for the other orientation are
+ * letterboxed (or pillarboxed) to 16:9 in the middle of the screen.
+ */
+#[must_use]
+pub fn viewport(screen: &Screen, orientation: GameOrientation) -> Viewport {
+    let portrait_screen = screen.height >= screen.width;
+    match (orientation, portrait_screen) {
+        (GameOrientation::Landscape, true) => {
+            let height = (screen.width * 9 / 16).min(screen.height);
+            Viewport {
+                width: screen.width,
+                height,
+                x: 0,
+                y: (screen.height - height) / 2,
+            }
+        }
+        (GameOrientation::Portrait, false) => {
+            let width = (screen.height * 9 / 16).min(screen.width);
+            Viewport {
+                width,
+                height: screen.height,
+                x: (screen.width - width) / 2,
+                y: 0,
+            }
+        }
+        _ => Viewport {
+            width: screen.width,
+            height: screen.height,
+            x: 0,
+            y: 0,
+        },
+    }
+}
+
+/**
+ * Get the variables that tell a game about the screen and where it should draw
+ */
+fn hints(game: &DevcadeGame, screen: Option<&Screen>) -> BTreeMap<String, String> {
+    let mut hints = BTreeMap::new();
+    let orientation = match game.orientation {
+        GameOrientation::Portrait => "portrait",
+        GameOrientation::Landscape => "landscape",
+    };
+    hints.insert("DEVCADE_ORIENTATION", String::from(orientation));
+    if let Some(screen) = screen {
+        let viewport = viewport(screen, game.orientation);
+        hints.insert("DEVCADE_SCREEN_WIDTH", screen.width.to_string());
+        hints.insert("DEVCADE_SCREEN_HEIGHT", screen.height.to_string());
+        hints.insert(
+            "DEVCADE_SCREEN_ROTATION",
+            String::from(screen.rotation.name()),
+        );
+        hints.insert(
+            "DEVCADE_VIEWPORT",
+            format!(
+                "{}x{}+{}+{}",
+                viewport.width, viewport.height, viewport.x, viewport.y
+            ),
+        );
+    }
+    hints
+        .into_iter()
+        .map(|(key, value)| (String::from(key), value))
+        .collect()
+}
+
+/**
+ * Get the display ready for a game, before it's launched. The screen and viewport hints are added
+ * to the game's environment (unless it already sets them), and the operator's display hook is run
+ * with `before`. Returns the screen as it was, to be restored once the game exits.
+ */
+pub async fn prepare(
+    game: &DevcadeGame,
+    game_env: &mut BTreeMap<String, String>,
+) -> Option<Screen> {
+    let screen = screen().await;
+    let hints = hints(game, screen.as_ref());
+    run_hook("before", game, &hints).await;
+    for (key, value) in hints {
+        game_env.entry(key).or_insert(value);
+    }
+    screen
+}
+
+/**
+ * Put the display back after a game exits. The operator's display hook is run with `after`, then
+ * the output's rotation is restored if the game (or the hook) changed it.
+ */
+pub async fn restore(game: &DevcadeGame, before: Option<Screen>) {
+    run_hook("after", game, &hints(game, before.as_ref())).await;
+    let Some(before) = before else {
+        return;
+    };
+    let Some(after) = screen().await else {
+        return;
+    };
+    if after.output != before.output || after.rotation == before.rotation {
+        return;
+    }
+    log::warn!(
+        "Game {} left {} rotated {}, restoring {}",
+        game.id,
+        before.output,
+        after.rotation.name(),
+        before.rotation.name()
+    );
+    let mut command = match env::display_server() {
+        DisplayServer::X11 => {
+            let mut command = Command::new("xrandr");
+            command.args(["--output", before.output.as_str()]);
+            command.args(["--rotate", before.rotation.name()]);
+            command
+        }
+        DisplayServer::Wayland => {
+            let mut command = Command::new("wlr-randr");
+            command.args(["--output", before.output.as_str()]);
+            command.args(["--transform", before.rotation.transform()]);
+            command
+        }
+    };
+    match command.status().await {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::warn!("Restoring the display rotation failed with {status}"),
+        Err(e) => log::warn!("Couldn't restore the display rotation: {e}"),
+    }
+}
+
+/**
+ * Run the operator's display hook, if there is one, with the stage (`before` or `after`) and game
+ * ID as arguments and the display hints in its environment. The hook is where anything cabinet
+ * specific belongs, such as switching modes with `xrandr` for a game. Failures are only logged.
+ */
+async fn run_hook(stage: &str, game: &DevcadeGame, hints: &BTreeMap<String, String>) {
+    let Some(hook) = env::display_hook() else {
+        return;
+    };
+    let status = Command::new(&hook)
+        .arg(stage)
+        .arg(game.id.as_str())
+        .envs(hints)
+        .kill_on_drop(true)
+        .status();
+    match tokio::time::timeout(HOOK_TIMEOUT, status).await {
+        Ok(Ok(status)) if status.success() => {}
+        Ok(Ok(status)) => log::warn!("Display hook {hook} {stage} failed with {status}"),
+        Ok(Err(e)) => log::warn!("Couldn't run display hook {hook}: {e}"),
+        Err(_) => log::warn!(
+            "Display hook {hook} {stage} took longer than {}s, skipping it",
+            HOOK_TIMEOUT.as_secs()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn landscape_games_are_letterboxed_on_rotated_screens() {
+        let xrandr = "Screen 0: minimum 320 x 200, current 1080 x 1920, maximum 16384 x 16384\n\
+            DP-1 disconnected (normal left inverted right x axis y axis)\n\
+            HDMI-1 connected primary 1080x1920+0+0 left (normal left inverted right x axis y axis) 527mm x 296mm\n   \
+            1920x1080     60.00*+";
+        let screen = parse_xrandr(xrandr, None).unwrap();
+        assert_eq!(screen.output, "HDMI-1");
+        assert_eq!(screen.rotation, Rotation::Left);
+
+        let wlr_randr = "HDMI-A-1 \"Some Monitor (HDMI-A-1)\"\n  Enabled: yes\n  Modes:\n    \
+            1920x1080 px, 60.000000 Hz (preferred, current)\n  Transform: 90\n";
+        let wayland_screen = parse_wlr_randr(wlr_randr, None).unwrap();
+        assert_eq!(wayland_screen.output, "HDMI-A-1");
+        assert_eq!(
+            (wayland_screen.width, wayland_screen.height),
+            (screen.width, screen.height)
+        );
+        assert_eq!(wayland_screen.rotation, Rotation::Left);
+
+        assert_eq!(
+            viewport(&screen, GameOrientation::Landscape),
+            Viewport {
+                width: 1080,
+                height: 607,
+                x: 0,
+                y: 656,
+            }
+        );
+        assert_eq!(viewport(&screen, GameOrientation::Portrait).height, 1920);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::env;
+use devcade_onboard_types::Event;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+lazy_static! {
+    /**
+     * Every subscriber gets its own view of this buffer, so a slow one only falls behind itself.
+     * Once it's `DEVCADE_EVENT_BUFFER` events behind, its oldest events are dropped.
+     */
+    static ref EVENTS: broadcast::Sender<Event> = broadcast::channel(env::event_buffer()).0;
+    /**
+     * How many events each subscriber has had dropped, by name. Subscribers are removed when
+     * they're dropped, so connections that come and go don't pile up here.
+     */
+    static ref DROPPED: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+}
+
+/**
+ * Broadcast an event to all current subscribers. Events emitted while nobody is subscribed are
+ * dropped.
+ */
+pub fn emit(event: Event) {
+    log::debug!("Emitting event: {event}");
+    // An error here only means there are no subscribers right now
+    let _ = EVENTS.send(event);
+}
+
+/**
+ * Subscribe to all events emitted after this call. The name identifies the subscriber in the
+ * counts of dropped events.
+ */
+#[must_use]
+pub fn subscribe(name: impl Into<String>) -> Subscriber {
+    Subscriber {
+        name: name.into(),
+        events: EVENTS.subscribe(),
+    }
+}
+
+/**
+ * Get how many events each current subscriber has had dropped for falling behind
+ */
+#[must_use]
+pub fn dropped() -> BTreeMap<String, u64> {
+    DROPPED.lock().unwrap().clone()
+}
+
+/**
+ * A subscription to the backend's events, with a bounded buffer of events it hasn't received yet
+ */
+pub struct Subscriber {
+    name: String,
+    events: broadcast::Receiver<Event>,
+}
+
+impl Subscriber {
+    /**
+     * Wait for the next event. If the subscriber fell too far behind, the events it missed are
+     * dropped (oldest first) and an `Event::Gap` saying how many is returned in their place.
+     */
+    pub async fn recv(&mut self) -> Event {
+        match self.events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                log::warn!("{} fell behind and missed {missed} events", self.name);
+                *DROPPED
+                    .lock()
+                    .unwrap()
+                    .entry(self.name.clone())
+                    .or_default() += missed;
+                Event::Gap(missed)
+            }
+            Err(RecvError::Closed) => unreachable!("The event sender is never dropped"),
+        }
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        DROPPED.lock().unwrap().remove(&self.name);
+    }
+}
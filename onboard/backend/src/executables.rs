@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Error};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/**
+ * How many directories deep into a game's files executables are looked for
+ */
+const MAX_DEPTH: usize = 4;
+
+/**
+ * The file (relative to a game's files) a bundle can use to say how it's started
+ */
+pub const LAUNCH_MANIFEST: &str = "devcade.toml";
+
+/**
+ * How a bundle says it should be started, for bundles that ship a launcher alongside helper
+ * binaries. Paths are relative to the game's files (`/app` in the sandbox).
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LaunchManifest {
+    /**
+     * The file to run
+     */
+    pub command: String,
+    /**
+     * The directory to run it in
+     */
+    pub working_dir: Option<String>,
+    /**
+     * Arguments it's always run with
+     */
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/**
+ * Read a bundle's `devcade.toml`, if it has one
+ *
+ * # Errors
+ * This function will return an error if the file can't be read or parsed, or if it names a path
+ * outside the game's files.
+ */
+pub fn read_launch_manifest(dir: &Path) -> Result<Option<LaunchManifest>, Error> {
+    let path = dir.join(LAUNCH_MANIFEST);
+    let toml = match std::fs::read_to_string(&path) {
+        Ok(toml) => toml,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let manifest: LaunchManifest =
+        toml::from_str(toml.as_str()).map_err(|e| anyhow!("Invalid {LAUNCH_MANIFEST}: {e}"))?;
+    for relative in std::iter::once(&manifest.command).chain(&manifest.working_dir) {
+        if !stays_inside(relative) {
+            return Err(anyhow!(
+                "{LAUNCH_MANIFEST} path '{relative}' must stay inside the game's files"
+            ));
+        }
+    }
+    Ok(Some(manifest))
+}
+
+/**
+ * Get whether a path relative to a game's files stays inside them, that is it isn't absolute and
+ * has no `..` parts
+ */
+#[must_use]
+pub fn stays_inside(relative: &str) -> bool {
+    !relative.starts_with('/') && !relative.split('/').any(|part| part == "..")
+}
+
+/**
+ * What kind of file an executable candidate is
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutableKind {
+    /**
+     * A native binary, such as a Godot or C++ export
+     */
+    Elf,
+    /**
+     * A script with a `#!` line, such as a Love2D launcher
+     */
+    Script,
+}
+
+/**
+ * A file that could be a game's entry point, and how likely it is to be the right one
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    /**
+     * The path of the file, relative to the directory that was searched
+     */
+    pub path: PathBuf,
+    pub kind: ExecutableKind,
+    pub score: u32,
+}
+
+/**
+ * Find every executable in a game's files, best candidate first. Native binaries rank above
+ * scripts, files in `bin` rank above others, and files named like one of the `names` (such as the
+ * game's name) rank highest. Shared libraries are never candidates.
+ *
+ * # Errors
+ * This function will return an error if the directory can't be read.
+ */
+pub fn candidates(dir: &Path, names: &[&str]) -> Result<Vec<Candidate>, Error> {
+    let names: Vec<String> = names.iter().map(|name| normalize(name)).collect();
+    let mut candidates = Vec::new();
+    walk(dir, Path::new(""), 0, &names, &mut candidates)?;
+    candidates.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
+    Ok(candidates)
+}
+
+/**
+ * Pick a game's entry point from its files
+ *
+ * # Errors
+ * This function will return an error if there are no executables, or if the best candidates are
+ * tied so the right one can't be told apart. Both errors suggest setting `entrypoint` in the
+ * game's metadata.
+ */
+pub fn locate_executable(dir: &Path, names: &[&str]) -> Result<PathBuf, Error> {
+    let candidates = candidates(dir, names)?;
+    match candidates.as_slice() {
+        [] => Err(anyhow!(
+            "No executables found in {}, set 'entrypoint' in game.json",
+            dir.display()
+        )),
+        [best, next, ..] if best.score == next.score => {
+            let tied: Vec<String> = candidates
+                .iter()
+                .take_while(|candidate| candidate.score == best.score)
+                .map(|candidate| candidate.path.display().to_string())
+                .collect();
+            Err(anyhow!(
+                "Can't tell which executable to run ({}), set 'entrypoint' in game.json",
+                tied.join(", ")
+            ))
+        }
+        [best, ..] => Ok(best.path.clone()),
+    }
+}
+
+fn walk(
+    root: &Path,
+    relative: &Path,
+    depth: usize,
+    names: &[String],
+    candidates: &mut Vec<Candidate>,
+) -> Result<(), Error> {
+    for entry in std::fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        // Symlinked directories aren't followed, so links can't loop
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if depth < MAX_DEPTH {
+                walk(root, &path, depth + 1, names, candidates)?;
+            }
+            continue;
+        }
+        if let Some(candidate) = check(root, &path, names) {
+            candidates.push(candidate);
+        }
+    }
+    Ok(())
+}
+
+fn check(root: &Path, path: &Path, names: &[String]) -> Option<Candidate> {
+    let file_name = path.file_name()?.to_string_lossy().to_string();
+    if file_name.ends_with(".so") || file_name.contains(".so.") {
+        return None;
+    }
+    let full_path = root.join(path);
+    let metadata = std::fs::metadata(&full_path).ok()?;
+    if !metadata.is_file() || metadata.permissions().mode() & 0o111 == 0 {
+        return None;
+    }
+
+    let mut header = [0u8; 4];
+    let read = File::open(&full_path).ok()?.read(&mut header).ok()?;
+    let kind = match &header[..read] {
+        [0x7f, b'E', b'L', b'F'] => ExecutableKind::Elf,
+        [b'#', b'!', ..] => ExecutableKind::Script,
+        _ => return None,
+    };
+
+    let mut score = match kind {
+        ExecutableKind::Elf => 3,
+        ExecutableKind::Script => 2,
+    };
+    if path.parent().and_then(Path::file_name) == Some("bin".as_ref()) {
+        score += 1;
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| normalize(&stem.to_string_lossy()));
+    if stem.is_some_and(|stem| names.contains(&stem)) {
+        score += 4;
+    }
+    Some(Candidate {
+        path: path.to_path_buf(),
+        kind,
+        score,
+    })
+}
+
+/**
+ * Lowercase a name and drop anything that isn't a letter or number, so "Space Game" matches
+ * `space_game`
+ */
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("devcade-exec-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("bin")).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, path: &str, contents: &[u8], mode: u32) {
+        let path = dir.join(path);
+        std::fs::write(&path, contents).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn ranks_native_binaries_named_like_the_game() {
+        let dir = temp_dir("rank");
+        write(&dir, "bin/launch.sh", b"#!/bin/sh\n", 0o755);
+        write(&dir, "bin/space_game", b"\x7fELF\x02\x01", 0o755);
+        write(&dir, "bin/helper", b"\x7fELF\x02\x01", 0o755);
+        write(&dir, "libgame.so", b"\x7fELF\x02\x01", 0o755);
+        write(&dir, "data.pck", b"\x7fELF\x02\x01", 0o644);
+
+        let found = candidates(&dir, &["Space Game"]).unwrap();
+        let paths: Vec<_> = found.iter().map(|c| c.path.to_str().unwrap()).collect();
+        assert_eq!(paths, vec!["bin/space_game", "bin/helper", "bin/launch.sh"]);
+        assert_eq!(
+            locate_executable(&dir, &["Space Game"]).unwrap(),
+            PathBuf::from("bin/space_game")
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reads_launch_manifest() {
+        let dir = temp_dir("manifest");
+        assert_eq!(read_launch_manifest(&dir).unwrap(), None);
+        std::fs::write(
+            dir.join(LAUNCH_MANIFEST),
+            "command = \"bin/launcher\"\nworking_dir = \"data\"\nargs = [\"--fullscreen\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_launch_manifest(&dir).unwrap(),
+            Some(LaunchManifest {
+                command: String::from("bin/launcher"),
+                working_dir: Some(String::from("data")),
+                args: vec![String::from("--fullscreen")],
+            })
+        );
+        std::fs::write(dir.join(LAUNCH_MANIFEST), "command = \"../escape\"\n").unwrap();
+        assert!(read_launch_manifest(&dir).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn paths_must_stay_inside_the_game() {
+        assert!(stays_inside("bin/game"));
+        assert!(stays_inside("game..x86_64"));
+        assert!(!stays_inside("/usr/bin/sh"));
+        assert!(!stays_inside("bin/../../usr/bin/sh"));
+    }
+
+    #[test]
+    fn refuses_to_guess_between_ties() {
+        let dir = temp_dir("tie");
+        write(&dir, "bin/one", b"\x7fELF", 0o755);
+        write(&dir, "bin/two", b"\x7fELF", 0o755);
+        let err = locate_executable(&dir, &["game"]).unwrap_err().to_string();
+        assert!(err.contains("bin/one, bin/two"), "{err}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
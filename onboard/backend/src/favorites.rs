@@ -0,0 +1,245 @@
+use crate::api;
+use crate::atomic;
+use crate::i18n::tr;
+use crate::nfc;
+use crate::storage;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::Favorite;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * The file (relative to the devcade path) that favorites are stored in
+ */
+const FAVORITES_FILE: &str = "favorites.json";
+
+lazy_static! {
+    /**
+     * Every badge's favorites, by badge handle and game ID
+     */
+    static ref FAVORITES: tokio::sync::Mutex<BTreeMap<String, BTreeMap<String, StoredFavorite>>> =
+        tokio::sync::Mutex::new(BTreeMap::new());
+}
+
+/**
+ * A favorite as it's stored on disk. Removed favorites are kept until the API has been told about
+ * them.
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct StoredFavorite {
+    favorited_at: u64,
+    removed: bool,
+    synced: bool,
+}
+
+fn favorites_path() -> PathBuf {
+    storage::root().join(FAVORITES_FILE)
+}
+
+/**
+ * Load favorites from the devcade directory and send any the API hasn't heard about yet. Missing
+ * or unreadable favorites are logged and replaced with none.
+ */
+pub async fn load() {
+    let path = favorites_path();
+    let favorites = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(favorites) => favorites,
+            Err(e) => {
+                log::warn!("Ignoring invalid favorites at {:?}: {e}", path);
+                BTreeMap::new()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => {
+            log::warn!("Couldn't read favorites at {:?}: {e}", path);
+            BTreeMap::new()
+        }
+    };
+    *FAVORITES.lock().await = favorites;
+    tokio::spawn(sync());
+}
+
+/**
+ * Make a game one of a badged user's favorites. The API is told in the background.
+ *
+ * # Errors
+ * This function will return an error if the game ID is invalid, the handle wasn't tapped in this
+ * session, or the favorites can't be saved.
+ */
+pub async fn favorite(game_id: String, handle: String) -> Result<(), Error> {
+    api::check_game_id(game_id.as_str())?;
+    if !nfc::tapped_this_session(handle.as_str()) {
+        return Err(anyhow!(tr("nfc_user_not_found", &[])));
+    }
+    let favorited_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    update(|favorites| {
+        favorites.entry(handle).or_default().insert(
+            game_id,
+            StoredFavorite {
+                favorited_at,
+                removed: false,
+                synced: false,
+            },
+        );
+    })
+    .await?;
+    tokio::spawn(sync());
+    Ok(())
+}
+
+/**
+ * Take a game out of a badged user's favorites. The API is told in the background.
+ *
+ * # Errors
+ * This function will return an error if the handle wasn't tapped in this session, the game isn't
+ * one of their favorites, or the favorites can't be saved.
+ */
+pub async fn unfavorite(game_id: String, handle: String) -> Result<(), Error> {
+    if !nfc::tapped_this_session(handle.as_str()) {
+        return Err(anyhow!(tr("nfc_user_not_found", &[])));
+    }
+    let mut found = false;
+    update(|favorites| {
+        if let Some(stored) = favorites
+            .get_mut(&handle)
+            .and_then(|games| games.get_mut(&game_id))
+            .filter(|stored| !stored.removed)
+        {
+            stored.removed = true;
+            stored.synced = false;
+            found = true;
+        }
+    })
+    .await?;
+    if !found {
+        return Err(anyhow!(tr(
+            "favorite_not_found",
+            &[("game_id", game_id.as_str())]
+        )));
+    }
+    tokio::spawn(sync());
+    Ok(())
+}
+
+/**
+ * Get a badged user's favorite games, most recently favorited first
+ *
+ * # Errors
+ * This function will return an error if the handle couldn't have come from a tap.
+ */
+pub async fn list(handle: &str) -> Result<Vec<Favorite>, Error> {
+    nfc::save_namespace(Some(handle))?;
+    let favorites = FAVORITES.lock().await;
+    Ok(favorites.get(handle).map(listed).unwrap_or_default())
+}
+
+fn listed(games: &BTreeMap<String, StoredFavorite>) -> Vec<Favorite> {
+    let mut favorites: Vec<Favorite> = games
+        .iter()
+        .filter(|(_, stored)| !stored.removed)
+        .map(|(game_id, stored)| Favorite {
+            game_id: game_id.clone(),
+            favorited_at: stored.favorited_at,
+        })
+        .collect();
+    favorites.sort_by_key(|favorite| std::cmp::Reverse(favorite.favorited_at));
+    favorites
+}
+
+/**
+ * Send favorites the API hasn't heard about yet. Failures are logged and retried on the next sync.
+ */
+pub async fn sync() {
+    let pending: Vec<(String, String, StoredFavorite)> = FAVORITES
+        .lock()
+        .await
+        .iter()
+        .flat_map(|(handle, games)| {
+            games
+                .iter()
+                .filter(|(_, stored)| !stored.synced)
+                .map(|(game_id, stored)| (handle.clone(), game_id.clone(), stored.clone()))
+        })
+        .collect();
+    for (handle, game_id, stored) in pending {
+        if let Err(e) = api::set_favorite(
+            handle.as_str(),
+            game_id.as_str(),
+            !stored.removed,
+            stored.favorited_at,
+        )
+        .await
+        {
+            log::warn!("Couldn't sync favorite {game_id}, will retry: {e}");
+            continue;
+        }
+        let result = update(|favorites| {
+            let Some(games) = favorites.get_mut(&handle) else {
+                return;
+            };
+            // Skip favorites that changed while the request was in flight
+            match games.get_mut(&game_id) {
+                Some(current)
+                    if current.favorited_at == stored.favorited_at
+                        && current.removed == stored.removed =>
+                {
+                    if stored.removed {
+                        games.remove(&game_id);
+                    } else {
+                        current.synced = true;
+                    }
+                }
+                _ => {}
+            }
+            if games.is_empty() {
+                favorites.remove(&handle);
+            }
+        })
+        .await;
+        if let Err(e) = result {
+            log::warn!("Couldn't save favorites: {e}");
+        }
+    }
+}
+
+/**
+ * Change the stored favorites and save them
+ */
+async fn update(
+    change: impl FnOnce(&mut BTreeMap<String, BTreeMap<String, StoredFavorite>>),
+) -> Result<(), Error> {
+    let mut favorites = FAVORITES.lock().await;
+    change(&mut favorites);
+    atomic::write_async(favorites_path(), serde_json::to_string(&*favorites)?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_favorites_are_left_out_and_newest_come_first() {
+        let stored = |favorited_at: u64, removed: bool| StoredFavorite {
+            favorited_at,
+            removed,
+            synced: false,
+        };
+        let games = BTreeMap::from([
+            (String::from("a"), stored(100, false)),
+            (String::from("b"), stored(300, true)),
+            (String::from("c"), stored(200, false)),
+        ]);
+        let favorites: Vec<String> = listed(&games)
+            .into_iter()
+            .map(|favorite| favorite.game_id)
+            .collect();
+        assert_eq!(favorites, ["c", "a"]);
+    }
+}
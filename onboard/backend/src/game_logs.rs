@@ -0,0 +1,317 @@
+use crate::api::check_game_id;
+use crate::log_files;
+use crate::log_stream;
+use crate::storage;
+use anyhow::{anyhow, Error};
+use lazy_static::lazy_static;
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+
+/**
+ * How many lines of output are kept in memory for the most recent session of each game
+ */
+const MEMORY_LINES: usize = 1024;
+
+/**
+ * How many sessions are kept on disk for each game. Older sessions are deleted when a new session
+ * starts.
+ */
+const MAX_SESSION_FILES: usize = 5;
+
+/**
+ * How large a session's log file can get before it is rotated
+ */
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/**
+ * How many rotated files are kept for a single session, on top of the file being written to
+ */
+const MAX_ROTATED_FILES: usize = 2;
+
+lazy_static! {
+    /**
+     * The most recent capture for each game, keyed by game ID
+     */
+    static ref LATEST: Mutex<HashMap<String, Arc<LogCapture>>> = Mutex::new(HashMap::new());
+}
+
+/**
+ * Which of a game's output streams a line came from
+ */
+#[derive(Clone, Copy, Debug)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn tag(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+
+    /**
+     * The level lines from this stream are given when streamed alongside the backend's own logs
+     */
+    fn level(self) -> log::Level {
+        match self {
+            Stream::Stdout => log::Level::Info,
+            Stream::Stderr => log::Level::Warn,
+        }
+    }
+}
+
+/**
+ * The log file a session is currently writing to
+ */
+struct SessionFile {
+    path: PathBuf,
+    file: fs::File,
+    written: u64,
+}
+
+/**
+ * Output captured from a single run of a game. Lines are tagged with the stream they came from,
+ * kept in a ring buffer in memory, and appended to a size-rotated per-session log file.
+ */
+pub struct LogCapture {
+    /**
+     * The session this capture belongs to
+     */
+    pub session: String,
+    game_id: String,
+    lines: Mutex<AllocRingBuffer<String>>,
+    file: tokio::sync::Mutex<Option<SessionFile>>,
+}
+
+impl LogCapture {
+    /**
+     * Read lines from one of a child process's output streams until it closes, recording each one.
+     */
+    pub async fn capture<R: AsyncRead + Unpin>(&self, stream: R, source: Stream) {
+        let mut lines = BufReader::new(stream).lines();
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) => {
+                    log_stream::publish(
+                        source.level(),
+                        format!("game:{}", self.game_id).as_str(),
+                        line.clone(),
+                    );
+                    self.push(format!("[{}] {line}", source.tag())).await
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    log::warn!("Error reading game {}: {e}", source.tag());
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn push(&self, line: String) {
+        log::trace!("[game] {line}");
+        log_files::write_line(format!("[game:{}] {line}", self.game_id).as_str());
+        let mut file = self.file.lock().await;
+        if let Some(session_file) = file.as_mut() {
+            if let Err(e) = write_line(session_file, line.as_str()).await {
+                log::warn!("Couldn't write game log, disabling log file for this session: {e}");
+                *file = None;
+            }
+        }
+        self.lines.lock().unwrap().push(line);
+    }
+}
+
+/**
+ * Append a line to a session's log file, rotating it first if it has reached `MAX_FILE_BYTES`
+ */
+async fn write_line(session_file: &mut SessionFile, line: &str) -> Result<(), Error> {
+    if session_file.written >= MAX_FILE_BYTES {
+        session_file.file.flush().await?;
+        rotate(&session_file.path).await?;
+        session_file.file = fs::File::create(&session_file.path).await?;
+        session_file.written = 0;
+    }
+    let line = format!("{line}\n");
+    session_file.file.write_all(line.as_bytes()).await?;
+    session_file.written += line.len() as u64;
+    Ok(())
+}
+
+/**
+ * Get the path of a session log file's `n`th rotation, where 0 is the file being written to
+ */
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    match n {
+        0 => path.to_path_buf(),
+        n => PathBuf::from(format!("{}.{n}", path.display())),
+    }
+}
+
+/**
+ * Shift a session's log files down by one rotation, dropping the oldest
+ */
+async fn rotate(path: &Path) -> Result<(), Error> {
+    for n in (0..MAX_ROTATED_FILES).rev() {
+        let from = rotated_path(path, n);
+        if fs::try_exists(&from).await? {
+            fs::rename(&from, rotated_path(path, n + 1)).await?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Get the directory a game's session logs are stored in
+ */
+fn log_dir(game_id: &str) -> PathBuf {
+    storage::game_dir(game_id).join(storage::LOGS_DIR)
+}
+
+/**
+ * Get the current log file of each of a game's sessions, oldest first
+ */
+async fn session_files(game_id: &str) -> Result<Vec<PathBuf>, Error> {
+    let dir = log_dir(game_id);
+    if !fs::try_exists(&dir).await? {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "log") {
+            files.push(path);
+        }
+    }
+    // Session names are timestamps, so sorting by name sorts by age
+    files.sort();
+    Ok(files)
+}
+
+/**
+ * Start capturing output for a new session of a game. This removes old sessions and replaces the
+ * in-memory buffer for the game.
+ */
+pub async fn start_session(game_id: &str) -> Arc<LogCapture> {
+    let session = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .to_string();
+
+    let file = match open_session_file(game_id, session.as_str()).await {
+        Ok(file) => Some(file),
+        Err(e) => {
+            log::warn!("Couldn't open log file for {game_id}, only keeping logs in memory: {e}");
+            None
+        }
+    };
+
+    let capture = Arc::new(LogCapture {
+        session,
+        game_id: game_id.to_string(),
+        lines: Mutex::new(AllocRingBuffer::new(MEMORY_LINES)),
+        file: tokio::sync::Mutex::new(file),
+    });
+    LATEST
+        .lock()
+        .unwrap()
+        .insert(game_id.to_string(), capture.clone());
+    capture
+}
+
+/**
+ * Add a line from the backend (rather than the game) to a game's current session, such as why it
+ * was stopped. Does nothing if the game hasn't been run since the backend started.
+ */
+pub async fn note(game_id: &str, line: &str) {
+    let capture = LATEST.lock().unwrap().get(game_id).cloned();
+    if let Some(capture) = capture {
+        capture.push(format!("[devcade] {line}")).await;
+    }
+}
+
+async fn open_session_file(game_id: &str, session: &str) -> Result<SessionFile, Error> {
+    let dir = log_dir(game_id);
+    fs::create_dir_all(&dir).await?;
+
+    let files = session_files(game_id).await?;
+    // Leave room for the session we're about to create
+    let excess = (files.len() + 1).saturating_sub(MAX_SESSION_FILES);
+    for old in files.iter().take(excess) {
+        log::debug!("Removing old game log {:?}", old);
+        for n in 0..=MAX_ROTATED_FILES {
+            let path = rotated_path(old, n);
+            if fs::try_exists(&path).await? {
+                fs::remove_file(path).await?;
+            }
+        }
+    }
+
+    let path = dir.join(format!("{session}.log"));
+    Ok(SessionFile {
+        file: fs::File::create(&path).await?,
+        path,
+        written: 0,
+    })
+}
+
+/**
+ * Read every rotation of a session's log file, oldest first
+ */
+async fn read_session(path: &Path) -> Result<Vec<String>, Error> {
+    let mut lines = Vec::new();
+    for n in (0..=MAX_ROTATED_FILES).rev() {
+        let path = rotated_path(path, n);
+        if fs::try_exists(&path).await? {
+            lines.extend(fs::read_to_string(path).await?.lines().map(String::from));
+        }
+    }
+    Ok(lines)
+}
+
+/**
+ * Get the captured output of a game. If no session is given, the most recent session is returned,
+ * from memory if it was captured since the backend started and from disk otherwise.
+ *
+ * # Errors
+ * This function will return an error if the game ID or session is invalid, if there are no logs
+ * for the game or session, or if the log file can't be read.
+ */
+pub async fn game_logs(game_id: &str, session: Option<String>) -> Result<Vec<String>, Error> {
+    check_game_id(game_id)?;
+    if let Some(session) = &session {
+        // Sessions are millisecond timestamps, anything else could be used to escape the log dir
+        if session.is_empty() || !session.chars().all(|c| c.is_ascii_digit()) {
+            return Err(anyhow!("Invalid session '{session}'"));
+        }
+    }
+
+    let latest = LATEST.lock().unwrap().get(game_id).cloned();
+    if let Some(capture) = latest {
+        if session.as_ref().is_none_or(|s| s == &capture.session) {
+            return Ok(capture.lines.lock().unwrap().to_vec());
+        }
+    }
+
+    let path = match session {
+        Some(session) => log_dir(game_id).join(format!("{session}.log")),
+        None => session_files(game_id)
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("No logs found for game {game_id}"))?,
+    };
+    if !fs::try_exists(&path).await? {
+        return Err(anyhow!("No logs found at {:?}", path));
+    }
+    read_session(&path).await
+}
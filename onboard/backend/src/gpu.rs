@@ -0,0 +1,109 @@
+use crate::api::check_game_id;
+use crate::atomic;
+use crate::env;
+use crate::storage::{self, GPU_PREFERENCE};
+use anyhow::Error;
+use devcade_onboard_types::schema::GpuPreference;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::fs;
+
+/**
+ * Only exists when the proprietary NVIDIA driver is loaded, which uses its own offload variables
+ * instead of Mesa's `DRI_PRIME`
+ */
+const NVIDIA_DRIVER: &str = "/proc/driver/nvidia/version";
+
+/**
+ * Get the GPU a game was set to render on, or `GpuPreference::Default` if it hasn't been set.
+ */
+pub async fn preference(game_id: &str) -> GpuPreference {
+    let path = storage::game_file(game_id, GPU_PREFERENCE);
+    match fs::read_to_string(&path).await {
+        Ok(json) => serde_json::from_str(json.as_str()).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid GPU preference {:?}: {e}", path);
+            GpuPreference::Default
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => GpuPreference::Default,
+        Err(e) => {
+            log::warn!("Couldn't read GPU preference {:?}: {e}", path);
+            GpuPreference::Default
+        }
+    }
+}
+
+/**
+ * Set the GPU a game renders on from its next launch. Setting it back to
+ * `GpuPreference::Default` removes the preference.
+ *
+ * # Errors
+ * This function will return an error if the game ID is invalid or the preference can't be written.
+ */
+pub async fn set_preference(game_id: String, gpu: GpuPreference) -> Result<(), Error> {
+    check_game_id(game_id.as_str())?;
+    let path = storage::game_file(game_id.as_str(), GPU_PREFERENCE);
+    log::info!("Game {game_id} will render on the {gpu:?} GPU");
+    if gpu == GpuPreference::Default {
+        return match fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+    fs::create_dir_all(storage::game_dir(game_id.as_str())).await?;
+    atomic::write_async(&path, serde_json::to_vec(&gpu)?).await
+}
+
+/**
+ * Get the environment variables that make a game render on the GPU it was set to, falling back to
+ * the cabinet's default. Variables the game already sets are left alone, so metadata and
+ * `env.json` can still override them.
+ */
+pub async fn apply(game_id: &str, game_env: &mut BTreeMap<String, String>) {
+    let gpu = match preference(game_id).await {
+        GpuPreference::Default => env::gpu(),
+        gpu => gpu,
+    };
+    for (key, value) in offload_env(gpu, Path::new(NVIDIA_DRIVER).exists()) {
+        game_env
+            .entry(String::from(key))
+            .or_insert_with(|| String::from(value));
+    }
+}
+
+/**
+ * The PRIME offload variables for a GPU. Mesa picks the GPU with `DRI_PRIME`; the NVIDIA driver
+ * only renders on the discrete GPU when offloading is asked for, for both OpenGL and Vulkan.
+ */
+fn offload_env(gpu: GpuPreference, nvidia: bool) -> Vec<(&'static str, &'static str)> {
+    match (gpu, nvidia) {
+        (GpuPreference::Default, _) => vec![],
+        (GpuPreference::Integrated, false) => vec![("DRI_PRIME", "0")],
+        (GpuPreference::Integrated, true) => vec![
+            ("DRI_PRIME", "0"),
+            ("__VK_LAYER_NV_optimus", "non_NVIDIA_only"),
+        ],
+        (GpuPreference::Discrete, false) => vec![("DRI_PRIME", "1")],
+        (GpuPreference::Discrete, true) => vec![
+            ("__NV_PRIME_RENDER_OFFLOAD", "1"),
+            ("__GLX_VENDOR_LIBRARY_NAME", "nvidia"),
+            ("__VK_LAYER_NV_optimus", "NVIDIA_only"),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offload_env_matches_driver() {
+        assert!(offload_env(GpuPreference::Default, true).is_empty());
+        assert_eq!(
+            offload_env(GpuPreference::Discrete, false),
+            vec![("DRI_PRIME", "1")]
+        );
+        let nvidia = offload_env(GpuPreference::Discrete, true);
+        assert!(nvidia.contains(&("__NV_PRIME_RENDER_OFFLOAD", "1")));
+        assert!(!nvidia.iter().any(|(key, _)| *key == "DRI_PRIME"));
+    }
+}
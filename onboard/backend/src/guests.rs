@@ -0,0 +1,410 @@
+use crate::api::{self, save_storage::Storage};
+use crate::atomic;
+use crate::env;
+use crate::i18n::tr;
+use crate::storage;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::{GuestMerge, GuestProfile, MergeConflict};
+use devcade_onboard_types::{Map, Value};
+use lazy_static::lazy_static;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+
+/**
+ * The file (relative to the devcade path) that guest profiles are stored in
+ */
+const GUESTS_FILE: &str = "guests.json";
+
+/**
+ * The file (relative to the devcade path) every guest merge is appended to, one JSON object per line
+ */
+const MERGE_LOG_FILE: &str = "guest_merges.log";
+
+/**
+ * Every guest ID starts with this, so guests can't be mistaken for badged users
+ */
+pub const GUEST_PREFIX: &str = "guest-";
+
+/**
+ * The longest name a guest can enter, in characters
+ */
+const MAX_NAME_LEN: usize = 16;
+
+/**
+ * Used to keep guest IDs unique when two guests are created in the same instant
+ */
+static NEXT_GUEST: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref GUESTS: Mutex<BTreeMap<String, GuestProfile>> = Mutex::new(BTreeMap::new());
+    // Held while saving, so saves can't land on disk out of order
+    static ref SAVING: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+    // Association IDs and the guests they've been offered a merge with, so a player is only asked
+    // once per session
+    static ref OFFERED: Mutex<HashSet<(String, String)>> = Mutex::new(HashSet::new());
+}
+
+fn guests_path() -> PathBuf {
+    storage::root().join(GUESTS_FILE)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/**
+ * Load guest profiles from the devcade directory, dropping expired ones and ones that only lasted
+ * for a session (which ended when the backend stopped). Missing or unreadable profiles are logged
+ * and replaced with none.
+ */
+pub async fn load() {
+    let path = guests_path();
+    let guests: BTreeMap<String, GuestProfile> = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(guests) => guests,
+            Err(e) => {
+                log::warn!("Ignoring invalid guest profiles at {:?}: {e}", path);
+                BTreeMap::new()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => {
+            log::warn!("Couldn't read guest profiles at {:?}: {e}", path);
+            BTreeMap::new()
+        }
+    };
+    let now = now();
+    *GUESTS.lock().unwrap() = guests
+        .into_iter()
+        .filter(|(_, guest)| guest.expires_at.is_some_and(|expires_at| expires_at > now))
+        .collect();
+}
+
+/**
+ * Create a guest profile with the name a player entered. The profile lasts for
+ * `DEVCADE_GUEST_MINUTES`, or until the current game exits if that's 0.
+ *
+ * # Errors
+ * This function will return an error if the name is empty, too long or has control characters in
+ * it, or if the profiles can't be saved.
+ */
+pub async fn create(name: String) -> Result<GuestProfile, Error> {
+    let name = check_name(name.as_str())?;
+    let created_at = now();
+    let n = NEXT_GUEST.fetch_add(1, Ordering::Relaxed);
+    let digest = sha256::digest(format!("{name}:{created_at}:{}:{n}", std::process::id()));
+    let guest = GuestProfile {
+        id: format!("{GUEST_PREFIX}{}", &digest[..16]),
+        name,
+        created_at,
+        expires_at: env::guest_lifetime().map(|lifetime| created_at + lifetime.as_secs()),
+    };
+    log::info!("Created guest profile {} ({})", guest.id, guest.name);
+    update(|guests| {
+        guests.insert(guest.id.clone(), guest.clone());
+    })
+    .await?;
+    Ok(guest)
+}
+
+/**
+ * Get every guest profile that hasn't expired
+ */
+#[must_use]
+pub fn active() -> Vec<GuestProfile> {
+    let now = now();
+    GUESTS
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|guest| guest.expires_at.is_none_or(|expires_at| expires_at > now))
+        .cloned()
+        .collect()
+}
+
+/**
+ * Get a guest profile that hasn't expired by its ID
+ */
+#[must_use]
+pub fn get(guest_id: &str) -> Option<GuestProfile> {
+    active().into_iter().find(|guest| guest.id == guest_id)
+}
+
+/**
+ * Describe a guest the same way a badged user is described, marked with `"guest": true`
+ */
+#[must_use]
+pub fn as_user(guest: &GuestProfile) -> Map<String, Value> {
+    let mut user = Map::new();
+    user.insert(String::from("uid"), Value::from(guest.id.clone()));
+    user.insert(String::from("cn"), Value::from(guest.name.clone()));
+    user.insert(String::from("guest"), Value::from(true));
+    user.insert(String::from("expires_at"), Value::from(guest.expires_at));
+    user
+}
+
+/**
+ * Forget guests that only lasted for the game session that just ended, along with any that have
+ * expired
+ */
+pub async fn end_session() {
+    OFFERED.lock().unwrap().clear();
+    let now = now();
+    let result = update(|guests| {
+        guests.retain(|_, guest| guest.expires_at.is_some_and(|expires_at| expires_at > now));
+    })
+    .await;
+    if let Err(e) = result {
+        log::warn!("Couldn't save guest profiles: {e}");
+    }
+}
+
+/**
+ * Get the guests a player who just badged in hasn't been offered a merge with yet
+ */
+#[must_use]
+pub fn offer_merge(association_id: &str) -> Vec<GuestProfile> {
+    if association_id.starts_with(GUEST_PREFIX) {
+        return Vec::new();
+    }
+    let mut offered = OFFERED.lock().unwrap();
+    active()
+        .into_iter()
+        .filter(|guest| offered.insert((association_id.to_string(), guest.id.clone())))
+        .collect()
+}
+
+/**
+ * Move everything saved under a guest's ID to a badged user's association ID, then forget the
+ * guest. Saved data belongs to a guest if a directory or group in its path is named after the
+ * guest's ID, or if its key is the guest's ID. The merge is appended to the merge log.
+ *
+ * # Errors
+ * This function will return an error if either ID is invalid, or if the saves can't be rewritten.
+ */
+pub async fn merge(
+    guest_id: String,
+    association_id: String,
+    conflict: MergeConflict,
+) -> Result<GuestMerge, Error> {
+    if !guest_id.starts_with(GUEST_PREFIX)
+        || association_id.starts_with(GUEST_PREFIX)
+        || !is_path_safe(guest_id.as_str())
+        || !is_path_safe(association_id.as_str())
+    {
+        return Err(anyhow!(tr(
+            "guest_merge_invalid",
+            &[
+                ("guest_id", guest_id.as_str()),
+                ("association_id", association_id.as_str())
+            ]
+        )));
+    }
+
+    let (from, to) = (guest_id.clone(), association_id.clone());
+    let (moved, conflicts) = api::persistence_rewrite(move |storage, root| {
+        merge_saves(storage, root, from.as_str(), to.as_str(), conflict)
+    })
+    .await?;
+    let merge = GuestMerge {
+        guest_id,
+        association_id,
+        conflict,
+        merged_at: now(),
+        moved,
+        conflicts,
+    };
+    log::info!(
+        "Merged {} values from guest {} into {} ({} conflicts, {:?})",
+        merge.moved,
+        merge.guest_id,
+        merge.association_id,
+        merge.conflicts.len(),
+        merge.conflict
+    );
+    append_merge_log(&merge).await?;
+    update(|guests| {
+        guests.remove(&merge.guest_id);
+    })
+    .await?;
+    Ok(merge)
+}
+
+/**
+ * Whether an ID can be used as a single path segment
+ */
+fn is_path_safe(id: &str) -> bool {
+    !id.is_empty() && id != "." && id != ".." && !id.contains(['/', '\\', '\0'])
+}
+
+async fn append_merge_log(merge: &GuestMerge) -> Result<(), Error> {
+    let mut line = serde_json::to_vec(merge)?;
+    line.push(b'\n');
+    let mut log = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(storage::root().join(MERGE_LOG_FILE))
+        .await?;
+    log.write_all(&line).await?;
+    log.sync_all().await?;
+    Ok(())
+}
+
+/**
+ * Move saved data from one ID to another in the save files under `root`. Returns how many values
+ * were moved, and the `group/key` of every value both IDs had.
+ */
+fn merge_saves(
+    storage: &mut dyn Storage,
+    root: &Path,
+    from: &str,
+    to: &str,
+    conflict: MergeConflict,
+) -> Result<(u64, Vec<String>), Error> {
+    let mut moved = 0;
+    let mut conflicts = Vec::new();
+    for (name, _) in storage.groups(root)? {
+        // The part of the name after the save directory is the group
+        let group = Path::new(&name).strip_prefix(root)?.to_path_buf();
+        let owned_by_guest = group.iter().any(|part| part == from);
+        let Some(mut source) = storage.read(name.as_str())? else {
+            continue;
+        };
+
+        if owned_by_guest {
+            let target_group: PathBuf = group
+                .iter()
+                .map(|part| if part == from { OsStr::new(to) } else { part })
+                .collect();
+            let target = root.join(&target_group).to_string_lossy().to_string();
+            let mut values = storage.read(target.as_str())?.unwrap_or_default();
+            let label = target_group.display().to_string();
+            for (key, value) in source {
+                moved += merge_value(&mut values, key, value, conflict, &label, &mut conflicts);
+            }
+            storage.write(&[(target, values)])?;
+            storage.remove(name.as_str())?;
+        } else if let Some(value) = source.remove(from) {
+            let label = group.display().to_string();
+            moved += merge_value(
+                &mut source,
+                to.to_string(),
+                value,
+                conflict,
+                &label,
+                &mut conflicts,
+            );
+            storage.write(&[(name, source)])?;
+        }
+    }
+    Ok((moved, conflicts))
+}
+
+/**
+ * Put a guest's value into the account's values, following the conflict rule if the account
+ * already has one. Returns 1 if the guest's value was kept, 0 if it was dropped.
+ */
+fn merge_value(
+    values: &mut HashMap<String, String>,
+    key: String,
+    value: String,
+    conflict: MergeConflict,
+    group: &str,
+    conflicts: &mut Vec<String>,
+) -> u64 {
+    if values.contains_key(&key) {
+        conflicts.push(format!("{group}/{key}"));
+        if conflict == MergeConflict::KeepAccount {
+            return 0;
+        }
+    }
+    values.insert(key, value);
+    1
+}
+
+fn check_name(name: &str) -> Result<String, Error> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN || name.chars().any(char::is_control)
+    {
+        return Err(anyhow!(tr(
+            "guest_name_invalid",
+            &[("max", MAX_NAME_LEN.to_string().as_str())]
+        )));
+    }
+    Ok(name.to_string())
+}
+
+/**
+ * Change the stored guests and save them
+ */
+async fn update(change: impl FnOnce(&mut BTreeMap<String, GuestProfile>)) -> Result<(), Error> {
+    let _saving = SAVING.lock().await;
+    let json = {
+        let mut guests = GUESTS.lock().unwrap();
+        change(&mut guests);
+        serde_json::to_string(&*guests)?
+    };
+    atomic::write_async(guests_path(), json).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::save_storage::FileStorage;
+
+    #[test]
+    fn checks_names() {
+        assert_eq!(check_name("  ABC ").unwrap(), "ABC");
+        assert!(check_name("").is_err());
+        assert!(check_name("   ").is_err());
+        assert!(check_name("a\nb").is_err());
+        assert!(check_name("this name is far too long").is_err());
+    }
+
+    #[test]
+    fn merges_saves_keeping_account_data() {
+        let root = std::env::temp_dir().join(format!("devcade-guests-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let write = |path: &str, json: &str| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, json).unwrap();
+        };
+        write(
+            "game/guest-a/progress.save",
+            r#"{"level":"3","coins":"10"}"#,
+        );
+        write("game/user/progress.save", r#"{"level":"5"}"#);
+        write("game/scores.save", r#"{"guest-a":"900","other":"100"}"#);
+
+        let mut storage = FileStorage;
+        let (moved, mut conflicts) = merge_saves(
+            &mut storage,
+            &root,
+            "guest-a",
+            "user",
+            MergeConflict::KeepAccount,
+        )
+        .unwrap();
+        conflicts.sort();
+        assert_eq!(moved, 2);
+        assert_eq!(conflicts, vec!["game/user/progress/level"]);
+        assert!(!root.join("game/guest-a/progress.save").exists());
+        let group = |name: &str| root.join(name).to_string_lossy().to_string();
+        let progress = storage.read(&group("game/user/progress")).unwrap().unwrap();
+        assert_eq!(progress["level"], "5");
+        assert_eq!(progress["coins"], "10");
+        let scores = storage.read(&group("game/scores")).unwrap().unwrap();
+        assert_eq!(scores.get("guest-a"), None);
+        assert_eq!(scores["user"], "900");
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+use crate::atomic;
+use crate::nfc;
+use crate::storage;
+use anyhow::Error;
+use devcade_onboard_types::schema::{GameSession, RecentGame};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/**
+ * The file (relative to the devcade path) that recently played games are stored in
+ */
+const HISTORY_FILE: &str = "history.json";
+
+/**
+ * How many games each history keeps
+ */
+const MAX_GAMES: usize = 20;
+
+/**
+ * How many badges' histories are kept. The badges that played least recently are forgotten first.
+ */
+const MAX_BADGES: usize = 500;
+
+lazy_static! {
+    static ref HISTORY: Mutex<History> = Mutex::new(History::default());
+}
+
+/**
+ * The games played recently on this cabinet, by anyone and by each badge, most recent first
+ */
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct History {
+    cabinet: Vec<RecentGame>,
+    /**
+     * Each badge's recent games, by the handle the menu knows it by
+     */
+    badges: BTreeMap<String, Vec<RecentGame>>,
+}
+
+fn history_path() -> PathBuf {
+    storage::root().join(HISTORY_FILE)
+}
+
+/**
+ * Load the history from the devcade directory. A missing or unreadable history is logged and
+ * replaced with an empty one.
+ */
+pub async fn load() {
+    let path = history_path();
+    let history = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(history) => history,
+            Err(e) => {
+                log::warn!("Ignoring invalid history at {:?}: {e}", path);
+                History::default()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => History::default(),
+        Err(e) => {
+            log::warn!("Couldn't read history at {:?}: {e}", path);
+            History::default()
+        }
+    };
+    *HISTORY.lock().unwrap() = history;
+}
+
+/**
+ * Add a finished session to the cabinet's history and the history of every badge tapped while it
+ * ran, and persist them
+ *
+ * # Errors
+ * This function will return an error if the history can't be written.
+ */
+pub async fn record(session: &GameSession) -> Result<(), Error> {
+    let history = {
+        let mut history = HISTORY.lock().unwrap();
+        add(&mut history, session);
+        history.clone()
+    };
+    atomic::write_async(history_path(), serde_json::to_string(&history)?).await
+}
+
+/**
+ * Get the games played most recently by a badge, or by anyone for `None`, most recent first
+ *
+ * # Errors
+ * This function will return an error if the handle couldn't have come from a tap.
+ */
+pub fn recently_played(handle: Option<&str>, limit: usize) -> Result<Vec<RecentGame>, Error> {
+    let history = HISTORY.lock().unwrap();
+    let games = match handle {
+        Some(handle) => {
+            nfc::save_namespace(Some(handle))?;
+            history.badges.get(handle).cloned().unwrap_or_default()
+        }
+        None => history.cabinet.clone(),
+    };
+    Ok(games.into_iter().take(limit).collect())
+}
+
+fn add(history: &mut History, session: &GameSession) {
+    let game = RecentGame {
+        game_id: session.game_id.clone(),
+        played_at: session.started_at,
+    };
+    push(&mut history.cabinet, game.clone());
+    for handle in &session.players {
+        push(
+            history.badges.entry(handle.clone()).or_default(),
+            game.clone(),
+        );
+    }
+    while history.badges.len() > MAX_BADGES {
+        let oldest = history
+            .badges
+            .iter()
+            .min_by_key(|(_, games)| games.first().map_or(0, |game| game.played_at))
+            .map(|(handle, _)| handle.clone());
+        match oldest {
+            Some(handle) => history.badges.remove(&handle),
+            None => break,
+        };
+    }
+}
+
+/**
+ * Put a game at the front of a history, moving it there if it's already in it
+ */
+fn push(games: &mut Vec<RecentGame>, game: RecentGame) {
+    games.retain(|played| played.game_id != game.game_id);
+    games.insert(0, game);
+    games.truncate(MAX_GAMES);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn games_move_to_the_front_when_played_again() {
+        let session = |game_id: &str, started_at: u64, players: &[&str]| GameSession {
+            game_id: game_id.to_string(),
+            started_at,
+            players: players.iter().map(ToString::to_string).collect(),
+            ..GameSession::default()
+        };
+        let mut history = History::default();
+        add(&mut history, &session("a", 1, &["alice"]));
+        add(&mut history, &session("b", 2, &[]));
+        add(&mut history, &session("a", 3, &["bob"]));
+
+        let ids = |games: &[RecentGame]| -> Vec<String> {
+            games.iter().map(|game| game.game_id.clone()).collect()
+        };
+        assert_eq!(ids(&history.cabinet), ["a", "b"]);
+        assert_eq!(history.cabinet[0].played_at, 3);
+        assert_eq!(ids(&history.badges["alice"]), ["a"]);
+        assert_eq!(ids(&history.badges["bob"]), ["a"]);
+    }
+}
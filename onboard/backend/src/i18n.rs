@@ -0,0 +1,163 @@
+use crate::env;
+use crate::storage;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/**
+ * The language every message has a translation in, used when a message is missing from the
+ * selected language
+ */
+const FALLBACK_LOCALE: &str = "en";
+
+/**
+ * Catalogs built into the backend, keyed by language
+ */
+const BUILTIN_CATALOGS: &[(&str, &str)] = &[("en", include_str!("locales/en.json"))];
+
+/**
+ * The longest locale that's accepted, enough for any language tag a catalog would be named after
+ */
+const MAX_LOCALE_LEN: usize = 35;
+
+/**
+ * The directory (relative to the devcade path) operators can drop `<locale>.json` catalogs in. A
+ * catalog there adds to or overrides the built in one for its language.
+ */
+const LOCALES_DIR: &str = "locales";
+
+type Catalog = Arc<HashMap<String, String>>;
+
+lazy_static! {
+    static ref CATALOGS: Mutex<HashMap<String, Catalog>> = Mutex::new(HashMap::new());
+}
+
+/**
+ * Get a user-facing message in the selected locale, with `{name}` placeholders filled in from
+ * `args`. Messages missing from the selected locale fall back to its base language (`de` for
+ * `de-AT`) and then to English. A message missing from every catalog is returned as its key.
+ */
+#[must_use]
+pub fn tr(key: &str, args: &[(&str, &str)]) -> String {
+    let locale = env::locale();
+    let template = candidates(locale.as_str())
+        .into_iter()
+        .find_map(|locale| catalog(locale.as_str()).get(key).cloned())
+        .unwrap_or_else(|| {
+            log::warn!("No message '{key}' in any catalog");
+            key.to_string()
+        });
+    fill(template.as_str(), args)
+}
+
+/**
+ * Get whether a locale is a tag like `en` or `de-AT`, made only of letters, digits, dashes and
+ * underscores. Locales are used in catalog file names and the `Accept-Language` header, so anything
+ * else is refused.
+ */
+#[must_use]
+pub fn is_valid_locale(locale: &str) -> bool {
+    (1..=MAX_LOCALE_LEN).contains(&locale.len())
+        && locale
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/**
+ * Forget loaded catalogs, so catalogs in the devcade directory are read again the next time
+ * they're used
+ */
+pub fn reload() {
+    CATALOGS.lock().unwrap().clear();
+}
+
+/**
+ * The locales to look a message up in, most specific first
+ */
+fn candidates(locale: &str) -> Vec<String> {
+    let mut candidates = vec![locale.to_string()];
+    if let Some((language, _)) = locale.split_once(['-', '_']) {
+        candidates.push(language.to_string());
+    }
+    candidates.push(FALLBACK_LOCALE.to_string());
+    candidates
+}
+
+fn catalog(locale: &str) -> Catalog {
+    if let Some(catalog) = CATALOGS.lock().unwrap().get(locale) {
+        return catalog.clone();
+    }
+
+    let mut messages = HashMap::new();
+    if let Some((_, json)) = BUILTIN_CATALOGS.iter().find(|(name, _)| *name == locale) {
+        // The built in catalogs are checked by the tests, so this can't fail
+        messages = serde_json::from_str(json).expect("Built in message catalog is invalid");
+    }
+    let path = storage::root()
+        .join(LOCALES_DIR)
+        .join(format!("{locale}.json"));
+    match std::fs::read_to_string(&path) {
+        Ok(json) => match serde_json::from_str::<HashMap<String, String>>(json.as_str()) {
+            Ok(overrides) => messages.extend(overrides),
+            Err(e) => log::warn!("Ignoring invalid message catalog {:?}: {e}", path),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Couldn't read message catalog {:?}: {e}", path),
+    }
+
+    let catalog = Arc::new(messages);
+    CATALOGS
+        .lock()
+        .unwrap()
+        .insert(locale.to_string(), catalog.clone());
+    catalog
+}
+
+fn fill(template: &str, args: &[(&str, &str)]) -> String {
+    args.iter()
+        .fold(template.to_string(), |message, (name, value)| {
+            message.replace(format!("{{{name}}}").as_str(), value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_catalogs_parse() {
+        for (locale, json) in BUILTIN_CATALOGS {
+            assert!(
+                serde_json::from_str::<HashMap<String, String>>(json).is_ok(),
+                "{locale} catalog is invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn fills_placeholders() {
+        assert_eq!(
+            fill(
+                "Game {game_id} isn't installed: {error}",
+                &[("game_id", "a"), ("error", "gone")]
+            ),
+            "Game a isn't installed: gone"
+        );
+    }
+
+    #[test]
+    fn locales_are_plain_tags() {
+        assert!(is_valid_locale("en"));
+        assert!(is_valid_locale("de-AT"));
+        assert!(is_valid_locale("zh_Hant_TW"));
+        assert!(!is_valid_locale(""));
+        assert!(!is_valid_locale("../../etc/passwd"));
+        assert!(!is_valid_locale("en\r\nX-Injected: 1"));
+        assert!(!is_valid_locale(&"a".repeat(MAX_LOCALE_LEN + 1)));
+    }
+
+    #[test]
+    fn falls_back_to_base_language() {
+        assert_eq!(candidates("de-AT"), vec!["de-AT", "de", "en"]);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::atomic;
+use crate::storage;
+use anyhow::Error;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/**
+ * The file (relative to the devcade path) that past install times are stored in
+ */
+pub const HISTORY_FILE: &str = "install_history.json";
+
+/**
+ * How many past installs are kept. Older installs are dropped so the model follows changes to the
+ * machine's performance.
+ */
+const MAX_SAMPLES: usize = 50;
+
+lazy_static! {
+    static ref HISTORY: Mutex<InstallHistory> = Mutex::new(InstallHistory::default());
+}
+
+/**
+ * How long it took flatpak to install a single bundle
+ */
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct InstallSample {
+    bundle_bytes: u64,
+    install_ms: u64,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+struct InstallHistory {
+    samples: VecDeque<InstallSample>,
+}
+
+fn history_path() -> PathBuf {
+    storage::root().join(HISTORY_FILE)
+}
+
+/**
+ * Load the install history from the devcade directory. A missing or unreadable history is logged
+ * and replaced with an empty one, since it only affects estimates.
+ */
+pub async fn load() {
+    let path = history_path();
+    let history = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(history) => history,
+            Err(e) => {
+                log::warn!("Ignoring invalid install history at {:?}: {e}", path);
+                InstallHistory::default()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => InstallHistory::default(),
+        Err(e) => {
+            log::warn!("Couldn't read install history at {:?}: {e}", path);
+            InstallHistory::default()
+        }
+    };
+    *HISTORY.lock().unwrap() = history;
+}
+
+/**
+ * Record how long a bundle took to install and persist the updated history.
+ *
+ * # Errors
+ * This function will return an error if the history can't be written.
+ */
+pub async fn record(bundle_bytes: u64, took: Duration) -> Result<(), Error> {
+    let history = {
+        let mut history = HISTORY.lock().unwrap();
+        history.samples.push_back(InstallSample {
+            bundle_bytes,
+            install_ms: took.as_millis() as u64,
+        });
+        while history.samples.len() > MAX_SAMPLES {
+            history.samples.pop_front();
+        }
+        history.clone()
+    };
+    atomic::write_async(history_path(), serde_json::to_string(&history)?).await?;
+    Ok(())
+}
+
+/**
+ * Estimate how long a bundle of the given size will take to install, based on past installs.
+ * Returns `None` if nothing has been installed yet.
+ */
+#[must_use]
+pub fn estimate(bundle_bytes: u64) -> Option<Duration> {
+    let mut history = HISTORY.lock().unwrap();
+    fit(history.samples.make_contiguous())
+        .map(|(base, per_byte)| base + per_byte * bundle_bytes as f64)
+        .map(|ms| Duration::from_millis(ms.max(0.0) as u64))
+}
+
+/**
+ * Fit install time against bundle size with least squares, returning the fixed overhead and the
+ * time per byte in milliseconds. When every sample is the same size, there's no slope to fit, so
+ * their average time is used as the overhead instead.
+ */
+fn fit(samples: &[InstallSample]) -> Option<(f64, f64)> {
+    if samples.is_empty() {
+        return None;
+    }
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|s| s.bundle_bytes as f64).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|s| s.install_ms as f64).sum::<f64>() / n;
+    let (mut cov, mut var) = (0.0, 0.0);
+    for sample in samples {
+        let dx = sample.bundle_bytes as f64 - mean_x;
+        cov += dx * (sample.install_ms as f64 - mean_y);
+        var += dx * dx;
+    }
+    if var == 0.0 {
+        return Some((mean_y, 0.0));
+    }
+    let per_byte = (cov / var).max(0.0);
+    Some((mean_y - per_byte * mean_x, per_byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(bundle_bytes: u64, install_ms: u64) -> InstallSample {
+        InstallSample {
+            bundle_bytes,
+            install_ms,
+        }
+    }
+
+    #[test]
+    fn no_history_has_no_estimate() {
+        assert!(fit(&[]).is_none());
+    }
+
+    #[test]
+    fn same_size_uses_average() {
+        let (base, per_byte) = fit(&[sample(100, 1000), sample(100, 3000)]).unwrap();
+        assert_eq!(base, 2000.0);
+        assert_eq!(per_byte, 0.0);
+    }
+
+    #[test]
+    fn fits_time_per_byte() {
+        let (base, per_byte) =
+            fit(&[sample(0, 500), sample(1000, 1500), sample(2000, 2500)]).unwrap();
+        assert!((base - 500.0).abs() < 1e-6);
+        assert!((per_byte - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,459 @@
+use crate::{api, events, install_history};
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::{DevcadeGame, InstallJob, InstallPhase};
+use devcade_onboard_types::Event;
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Notify};
+use tokio::task::AbortHandle;
+
+/**
+ * The minimum time between progress events for a download, so a fast download doesn't flood the
+ * event channel
+ */
+const PROGRESS_EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+type InstallCallback = oneshot::Sender<Result<DevcadeGame, String>>;
+
+lazy_static! {
+    static ref QUEUE: Mutex<InstallQueue> = Mutex::new(InstallQueue::default());
+    static ref JOB_ADDED: Notify = Notify::new();
+}
+
+/**
+ * A job in the queue, along with everyone waiting for it to finish
+ */
+struct QueuedJob {
+    info: InstallJob,
+    waiters: Vec<InstallCallback>,
+}
+
+/**
+ * The job currently being run by the worker
+ */
+struct RunningJob {
+    job: QueuedJob,
+    /**
+     * Handle used to stop the job's task. This is `None` for the short time between the job being
+     * taken off the queue and its task being spawned.
+     */
+    abort: Option<AbortHandle>,
+    /**
+     * Whether the job was cancelled before its task was spawned
+     */
+    cancelled: bool,
+    /**
+     * When the first chunk of the download arrived, used to estimate throughput
+     */
+    download_started: Option<Instant>,
+    /**
+     * When the bundle started installing, and how long it's expected to take from past installs
+     */
+    install_started: Option<(Instant, Option<Duration>)>,
+    /**
+     * When a progress event was last emitted for this job
+     */
+    last_progress_event: Option<Instant>,
+}
+
+impl RunningJob {
+    /**
+     * Get the job's info with an up to date ETA. The ETA while installing counts down from the
+     * estimate made when the install started.
+     */
+    fn info(&self) -> InstallJob {
+        let mut info = self.job.info.clone();
+        if let Some((started, Some(estimate))) = self.install_started {
+            info.eta_secs = Some(estimate.saturating_sub(started.elapsed()).as_secs());
+        }
+        info
+    }
+
+    /**
+     * Emit a progress event for the job, unless `throttle` is set and one was emitted recently
+     */
+    fn emit_progress(&mut self, throttle: bool) {
+        if throttle
+            && self
+                .last_progress_event
+                .is_some_and(|last| last.elapsed() < PROGRESS_EVENT_INTERVAL)
+        {
+            return;
+        }
+        self.last_progress_event = Some(Instant::now());
+        events::emit(Event::InstallProgress(self.info()));
+    }
+}
+
+#[derive(Default)]
+struct InstallQueue {
+    next_id: u32,
+    pending: VecDeque<QueuedJob>,
+    running: Option<RunningJob>,
+}
+
+/**
+ * Add a game to the install queue and wait for it to be installed. If the game is already queued or
+ * being installed, this waits for the existing job instead of queueing it again.
+ *
+ * # Errors
+ * This function will return an error if the install fails or is cancelled.
+ */
+pub async fn install(game_id: String) -> Result<DevcadeGame, Error> {
+    let (tx, rx) = oneshot::channel();
+    enqueue_locked(&mut QUEUE.lock().unwrap(), game_id, Some(tx));
+    rx.await?.map_err(|err| anyhow!(err))
+}
+
+/**
+ * Add a game to the install queue without waiting for it, returning the ID of its job. If the game
+ * is already queued or being installed, the existing job's ID is returned. This is used for
+ * speculative installs, which can later be dropped with `cancel_if_unwaited`.
+ */
+pub fn enqueue(game_id: String) -> u32 {
+    enqueue_locked(&mut QUEUE.lock().unwrap(), game_id, None)
+}
+
+fn enqueue_locked(
+    queue: &mut InstallQueue,
+    game_id: String,
+    waiter: Option<InstallCallback>,
+) -> u32 {
+    let existing = queue
+        .running
+        .as_mut()
+        .map(|running| &mut running.job)
+        .into_iter()
+        .chain(queue.pending.iter_mut())
+        .find(|job| job.info.game_id == game_id);
+    if let Some(job) = existing {
+        job.waiters.extend(waiter);
+        return job.info.id;
+    }
+    let id = queue.next_id;
+    queue.next_id = queue.next_id.wrapping_add(1);
+    log::info!("Queueing install of game {game_id} as job {id}");
+    queue.pending.push_back(QueuedJob {
+        info: InstallJob {
+            id,
+            game_id,
+            ..Default::default()
+        },
+        waiters: waiter.into_iter().collect(),
+    });
+    JOB_ADDED.notify_one();
+    id
+}
+
+/**
+ * Mark the running job for a game as installing a downloaded bundle of the given size, estimating
+ * how long the install will take from past installs. Does nothing if the game isn't being
+ * installed.
+ */
+pub fn set_installing(game_id: &str, bundle_bytes: u64) {
+    let mut queue = QUEUE.lock().unwrap();
+    let Some(running) = queue.running.as_mut() else {
+        return;
+    };
+    if running.job.info.game_id != game_id {
+        return;
+    }
+    running.job.info.phase = InstallPhase::Installing;
+    running.job.info.eta_secs = None;
+    running.install_started = Some((Instant::now(), install_history::estimate(bundle_bytes)));
+    running.emit_progress(false);
+}
+
+/**
+ * Record download progress for the running job for a game, and update its ETA. The ETA is the time
+ * left to download at the throughput so far, plus how long past installs suggest the bundle will
+ * take to install. Does nothing if the game isn't being installed.
+ */
+pub fn set_download_progress(game_id: &str, received: u64, total: Option<u64>) {
+    let mut queue = QUEUE.lock().unwrap();
+    let Some(running) = queue.running.as_mut() else {
+        return;
+    };
+    if running.job.info.game_id != game_id {
+        return;
+    }
+    let started = *running.download_started.get_or_insert_with(Instant::now);
+    running.job.info.eta_secs = total.and_then(|total| {
+        let elapsed = started.elapsed().as_secs_f64();
+        if received == 0 || elapsed <= 0.0 {
+            return None;
+        }
+        let rate = received as f64 / elapsed;
+        let download = Duration::from_secs_f64(total.saturating_sub(received) as f64 / rate);
+        let install = install_history::estimate(total).unwrap_or_default();
+        Some((download + install).as_secs())
+    });
+    running.emit_progress(true);
+}
+
+/**
+ * Get every job in the queue, starting with the running job (if any) followed by pending jobs in
+ * the order they'll be run.
+ */
+#[must_use]
+pub fn queue_status() -> Vec<InstallJob> {
+    status_locked(&QUEUE.lock().unwrap())
+}
+
+fn status_locked(queue: &InstallQueue) -> Vec<InstallJob> {
+    queue
+        .running
+        .iter()
+        .map(RunningJob::info)
+        .chain(queue.pending.iter().map(|job| job.info.clone()))
+        .collect()
+}
+
+/**
+ * Move a pending job to a new position among the pending jobs, where 0 is the next job to run.
+ * Positions past the end of the queue move the job to the back.
+ *
+ * # Errors
+ * This function will return an error if no pending job has the given ID.
+ */
+pub fn move_job(job_id: u32, position: usize) -> Result<(), Error> {
+    move_locked(&mut QUEUE.lock().unwrap(), job_id, position)
+}
+
+fn move_locked(queue: &mut InstallQueue, job_id: u32, position: usize) -> Result<(), Error> {
+    let index = queue
+        .pending
+        .iter()
+        .position(|job| job.info.id == job_id)
+        .ok_or_else(|| anyhow!("No pending install job with ID {job_id}"))?;
+    // Unwrap rationale: the index was just found in the queue
+    let job = queue.pending.remove(index).unwrap();
+    let position = position.min(queue.pending.len());
+    log::info!("Moving install job {job_id} to position {position}");
+    queue.pending.insert(position, job);
+    Ok(())
+}
+
+/**
+ * Cancel a job. Pending jobs are removed from the queue, and a running job is stopped if it's
+ * still downloading. Everyone waiting on the job is told it was cancelled.
+ *
+ * # Errors
+ * This function will return an error if no job has the given ID, or if the job is already being
+ * installed by flatpak and can no longer be stopped safely.
+ */
+pub fn cancel_job(job_id: u32) -> Result<(), Error> {
+    cancel_locked(&mut QUEUE.lock().unwrap(), job_id)
+}
+
+/**
+ * Cancel a job if nobody is waiting for it to finish, meaning it was only queued speculatively.
+ * Jobs that someone has since asked for, or that can no longer be cancelled, are left alone.
+ */
+pub fn cancel_if_unwaited(job_id: u32) {
+    let mut queue = QUEUE.lock().unwrap();
+    let unwaited = queue
+        .running
+        .as_ref()
+        .map(|running| &running.job)
+        .into_iter()
+        .chain(queue.pending.iter())
+        .find(|job| job.info.id == job_id)
+        .is_some_and(|job| job.waiters.iter().all(|waiter| waiter.is_closed()));
+    if unwaited {
+        if let Err(e) = cancel_locked(&mut queue, job_id) {
+            log::debug!("Leaving speculative install job {job_id} running: {e}");
+        }
+    }
+}
+
+fn cancel_locked(queue: &mut InstallQueue, job_id: u32) -> Result<(), Error> {
+    if let Some(index) = queue.pending.iter().position(|job| job.info.id == job_id) {
+        log::info!("Cancelling pending install job {job_id}");
+        // Unwrap rationale: the index was just found in the queue
+        let job = queue.pending.remove(index).unwrap();
+        for waiter in job.waiters {
+            let _ = waiter.send(Err(String::from("Install was cancelled")));
+        }
+        return Ok(());
+    }
+    match queue.running.as_mut() {
+        Some(running) if running.job.info.id == job_id => {
+            if running.job.info.phase == InstallPhase::Installing {
+                return Err(anyhow!(
+                    "Install job {job_id} is already being installed and can't be cancelled"
+                ));
+            }
+            log::info!("Cancelling running install job {job_id}");
+            match &running.abort {
+                Some(abort) => abort.abort(),
+                None => running.cancelled = true,
+            }
+            Ok(())
+        }
+        _ => Err(anyhow!("No install job with ID {job_id}")),
+    }
+}
+
+/**
+ * Run jobs from the install queue one at a time, forever. This should be spawned as a task at
+ * startup.
+ */
+pub async fn run() -> ! {
+    loop {
+        // The job is moved from pending to running under one lock, so it's always visible to
+        // `install`, `cancel_job`, and `set_phase`
+        let game_id = {
+            let mut queue = QUEUE.lock().unwrap();
+            match queue.pending.pop_front() {
+                Some(mut job) => {
+                    job.info.phase = InstallPhase::Downloading;
+                    let game_id = job.info.game_id.clone();
+                    let mut running = RunningJob {
+                        job,
+                        abort: None,
+                        cancelled: false,
+                        download_started: None,
+                        install_started: None,
+                        last_progress_event: None,
+                    };
+                    running.emit_progress(false);
+                    queue.running = Some(running);
+                    Some(game_id)
+                }
+                None => None,
+            }
+        };
+        let Some(game_id) = game_id else {
+            JOB_ADDED.notified().await;
+            continue;
+        };
+
+        let task = tokio::spawn(async move {
+            api::install_game(game_id)
+                .await
+                .map_err(|err| err.to_string())
+        });
+        if let Some(running) = QUEUE.lock().unwrap().running.as_mut() {
+            if running.cancelled {
+                task.abort();
+            }
+            running.abort = Some(task.abort_handle());
+        }
+
+        let result = match task.await {
+            Ok(result) => result,
+            Err(err) if err.is_cancelled() => Err(String::from("Install was cancelled")),
+            Err(err) => Err(format!("Install failed unexpectedly: {err}")),
+        };
+
+        let running = QUEUE.lock().unwrap().running.take();
+        if let Some(running) = running {
+            match &result {
+                Ok(_) => log::info!("Install job {} finished", running.job.info.id),
+                Err(err) => log::warn!("Install job {} failed: {err}", running.job.info.id),
+            }
+            for waiter in running.job.waiters {
+                let _ = waiter.send(result.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue_of(game_ids: &[&str]) -> InstallQueue {
+        let mut queue = InstallQueue::default();
+        for game_id in game_ids {
+            enqueue_locked(&mut queue, String::from(*game_id), None);
+        }
+        queue
+    }
+
+    fn start_next(queue: &mut InstallQueue) {
+        let mut job = queue.pending.pop_front().unwrap();
+        job.info.phase = InstallPhase::Downloading;
+        queue.running = Some(RunningJob {
+            job,
+            abort: None,
+            cancelled: false,
+            download_started: None,
+            install_started: None,
+            last_progress_event: None,
+        });
+    }
+
+    fn game_ids(queue: &InstallQueue) -> Vec<String> {
+        status_locked(queue)
+            .into_iter()
+            .map(|job| job.game_id)
+            .collect()
+    }
+
+    #[test]
+    fn status_lists_the_running_job_then_pending_jobs_in_order() {
+        let mut queue = queue_of(&["pong", "tetris", "snake"]);
+        assert_eq!(game_ids(&queue), ["pong", "tetris", "snake"]);
+        start_next(&mut queue);
+        let status = status_locked(&queue);
+        assert_eq!(status[0].game_id, "pong");
+        assert_eq!(status[0].phase, InstallPhase::Downloading);
+        assert_eq!(game_ids(&queue), ["pong", "tetris", "snake"]);
+    }
+
+    #[test]
+    fn queueing_a_queued_game_reuses_its_job() {
+        let mut queue = queue_of(&["pong", "tetris"]);
+        start_next(&mut queue);
+        let (tx, _rx) = oneshot::channel();
+        assert_eq!(
+            enqueue_locked(&mut queue, String::from("pong"), Some(tx)),
+            0
+        );
+        assert_eq!(enqueue_locked(&mut queue, String::from("tetris"), None), 1);
+        assert_eq!(queue.running.as_ref().unwrap().job.waiters.len(), 1);
+        assert_eq!(game_ids(&queue), ["pong", "tetris"]);
+    }
+
+    #[test]
+    fn jobs_move_among_pending_jobs() {
+        let mut queue = queue_of(&["pong", "tetris", "snake"]);
+        start_next(&mut queue);
+        move_locked(&mut queue, 2, 0).unwrap();
+        assert_eq!(game_ids(&queue), ["pong", "snake", "tetris"]);
+        move_locked(&mut queue, 2, 100).unwrap();
+        assert_eq!(game_ids(&queue), ["pong", "tetris", "snake"]);
+        // The running job isn't pending, so it can't be moved
+        assert!(move_locked(&mut queue, 0, 1).is_err());
+        assert!(move_locked(&mut queue, 7, 0).is_err());
+    }
+
+    #[test]
+    fn cancelling_a_pending_job_tells_its_waiters() {
+        let mut queue = queue_of(&["pong"]);
+        let (tx, mut rx) = oneshot::channel();
+        enqueue_locked(&mut queue, String::from("tetris"), Some(tx));
+        cancel_locked(&mut queue, 1).unwrap();
+        assert_eq!(game_ids(&queue), ["pong"]);
+        assert!(rx.try_recv().unwrap().is_err());
+        assert!(cancel_locked(&mut queue, 1).is_err());
+    }
+
+    #[test]
+    fn running_jobs_can_only_be_cancelled_while_downloading() {
+        let mut queue = queue_of(&["pong"]);
+        start_next(&mut queue);
+        cancel_locked(&mut queue, 0).unwrap();
+        assert!(queue.running.as_ref().unwrap().cancelled);
+
+        let mut queue = queue_of(&["pong"]);
+        start_next(&mut queue);
+        queue.running.as_mut().unwrap().job.info.phase = InstallPhase::Installing;
+        assert!(cancel_locked(&mut queue, 0).is_err());
+        assert!(!queue.running.as_ref().unwrap().cancelled);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::api::check_game_id;
+use crate::atomic;
+use crate::events;
+use crate::storage::{self, INSTALL_REPORT};
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::InstallReport;
+use devcade_onboard_types::Event;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * How many lines are kept in a report. Later lines are dropped, since the first problem is usually
+ * the interesting one.
+ */
+const MAX_LINES: usize = 1000;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/**
+ * Collects what happens while a game is installed. Every line is streamed to the frontend as an
+ * `InstallLog` event as it's recorded. Clones record into the same report, so one can be handed to
+ * each flatpak callback.
+ */
+#[derive(Clone)]
+pub struct Recorder {
+    report: Arc<Mutex<InstallReport>>,
+}
+
+impl Recorder {
+    #[must_use]
+    pub fn new(game_id: &str) -> Self {
+        Self {
+            report: Arc::new(Mutex::new(InstallReport {
+                game_id: game_id.to_string(),
+                started_at: now(),
+                ..InstallReport::default()
+            })),
+        }
+    }
+
+    /**
+     * Record a line of install output
+     */
+    pub fn line(&self, line: impl Into<String>) {
+        let line = line.into();
+        let mut report = self.report.lock().unwrap();
+        log::info!("Installing {}: {line}", report.game_id);
+        events::emit(Event::InstallLog(report.game_id.clone(), line.clone()));
+        if report.lines.len() < MAX_LINES {
+            report.lines.push(line);
+        }
+    }
+
+    /**
+     * Finish the report and save it next to the game's game.json, replacing the last one
+     *
+     * # Errors
+     * This function will return an error if the report can't be written.
+     */
+    pub async fn finish(&self, error: Option<&Error>) -> Result<(), Error> {
+        let report = {
+            let mut report = self.report.lock().unwrap();
+            report.finished_at = now();
+            report.error = error.map(|e| format!("{e:#}"));
+            report.clone()
+        };
+        let path = storage::game_file(report.game_id.as_str(), INSTALL_REPORT);
+        atomic::write_async(path, serde_json::to_string(&report)?).await
+    }
+}
+
+/**
+ * Get the report from the last time a game was installed
+ *
+ * # Errors
+ * This function will return an error if the game ID is invalid or the game has never been installed
+ * on this cabinet.
+ */
+pub async fn report(game_id: &str) -> Result<InstallReport, Error> {
+    check_game_id(game_id)?;
+    let path = storage::game_file(game_id, INSTALL_REPORT);
+    let json = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| anyhow!("No install report for game {game_id}: {e}"))?;
+    Ok(serde_json::from_str(json.as_str())?)
+}
@@ -0,0 +1,282 @@
+use crate::atomic;
+use crate::storage::{self, BUNDLE, INSTALL_STATE, SIGNATURE};
+use anyhow::Error;
+use devcade_onboard_types::schema::DevcadeGame;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/**
+ * How far an install has got. Each stage is saved before its work starts, so an install that was
+ * interrupted (such as by power loss) can pick up where it stopped on the next boot.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InstallStage {
+    /**
+     * The bundle is being downloaded. Nothing durable has happened yet, so an interrupted download
+     * is rolled back.
+     */
+    Downloading,
+    /**
+     * The bundle is on disk and is being installed with flatpak. Flatpak transactions are atomic,
+     * so an interrupted install is resumed by installing the bundle again.
+     */
+    Installing,
+    /**
+     * The flatpak is installed and the game's metadata is being written.
+     */
+    Installed,
+}
+
+/**
+ * An install in progress, saved next to the game's game.json
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InstallState {
+    /**
+     * The game being installed, with its flatpak app ID once the flatpak is installed
+     */
+    pub game: DevcadeGame,
+    pub stage: InstallStage,
+    /**
+     * The size of the downloaded bundle, once it's been downloaded
+     */
+    pub bundle_bytes: u64,
+}
+
+impl InstallState {
+    /**
+     * Start installing a game, saving that its download has started
+     *
+     * # Errors
+     * This function will return an error if the game's directory or the state can't be written.
+     */
+    pub async fn begin(game: DevcadeGame) -> Result<Self, Error> {
+        tokio::fs::create_dir_all(storage::game_dir(game.id.as_str())).await?;
+        let state = Self {
+            game,
+            stage: InstallStage::Downloading,
+            bundle_bytes: 0,
+        };
+        state.save().await?;
+        Ok(state)
+    }
+
+    /**
+     * Move the install on to its next stage and save it
+     *
+     * # Errors
+     * This function will return an error if the state can't be written.
+     */
+    pub async fn advance(&mut self, stage: InstallStage) -> Result<(), Error> {
+        log::debug!("Install of {} is now {:?}", self.game.id, stage);
+        self.stage = stage;
+        self.save().await
+    }
+
+    /**
+     * Forget the saved state of a finished install
+     *
+     * # Errors
+     * This function will return an error if the state can't be removed.
+     */
+    pub async fn finish(self) -> Result<(), Error> {
+        remove_if_exists(&storage::game_file(self.game.id.as_str(), INSTALL_STATE)).await
+    }
+
+    /**
+     * Undo an install that can't be finished: the downloaded bundle (if the download finished) and
+     * the saved state are removed, and so is the game's directory if nothing else is in it. A
+     * previously installed version's game.json is left alone. Installs that got as far as
+     * installing the flatpak are kept to be resumed instead, since only their metadata is missing.
+     */
+    pub async fn roll_back(self) {
+        if self.stage == InstallStage::Installed {
+            return;
+        }
+        log::warn!(
+            "Rolling back install of {} from {:?}",
+            self.game.id,
+            self.stage
+        );
+        let game_id = self.game.id.as_str();
+        // Until the download finishes, the bundle on disk is the previous version's
+        let files: &[&str] = match self.stage {
+            InstallStage::Downloading => &[INSTALL_STATE],
+            _ => &[BUNDLE, SIGNATURE, INSTALL_STATE],
+        };
+        for file in files {
+            if let Err(e) = remove_if_exists(&storage::game_file(game_id, file)).await {
+                log::warn!("Couldn't remove {file} while rolling back {game_id}: {e}");
+            }
+        }
+        remove_partial_downloads(game_id).await;
+        // Fails if the directory still has something in it, like an earlier version or env.json
+        let _ = tokio::fs::remove_dir(storage::game_dir(game_id)).await;
+    }
+
+    async fn save(&self) -> Result<(), Error> {
+        atomic::write_async(
+            storage::game_file(self.game.id.as_str(), INSTALL_STATE),
+            serde_json::to_string(self)?,
+        )
+        .await
+    }
+}
+
+/**
+ * Get a game's interrupted install, if it has one that can be resumed. Interrupted downloads are
+ * rolled back, as are installs whose bundle has gone missing.
+ */
+pub async fn resume(game_id: &str) -> Option<InstallState> {
+    let path = storage::game_file(game_id, INSTALL_STATE);
+    let json = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            log::warn!("Couldn't read install state at {:?}: {e}", path);
+            return None;
+        }
+    };
+    let state = match serde_json::from_str::<InstallState>(json.as_str()) {
+        Ok(state) if state.game.id == game_id => state,
+        Ok(_) | Err(_) => {
+            log::warn!("Ignoring invalid install state at {:?}", path);
+            let _ = remove_if_exists(&path).await;
+            return None;
+        }
+    };
+    let bundle_missing = !storage::game_file(game_id, BUNDLE).exists();
+    if state.stage == InstallStage::Downloading
+        || (state.stage == InstallStage::Installing && bundle_missing)
+    {
+        state.roll_back().await;
+        return None;
+    }
+    Some(state)
+}
+
+/**
+ * Find installs that were interrupted while the backend wasn't running. Ones that can't be resumed
+ * are rolled back, and the IDs of games whose installs can be resumed are returned.
+ *
+ * # Errors
+ * This function will return an error if the devcade directory can't be read.
+ */
+pub async fn recover() -> Result<Vec<String>, Error> {
+    let mut resumable = Vec::new();
+    let mut entries = tokio::fs::read_dir(storage::root()).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let Some(game_id) = entry.file_name().to_str().map(String::from) else {
+            continue;
+        };
+        if !entry.path().join(INSTALL_STATE).exists() {
+            continue;
+        }
+        if let Some(state) = resume(game_id.as_str()).await {
+            log::info!(
+                "Install of {game_id} was interrupted while {:?}, resuming it",
+                state.stage
+            );
+            resumable.push(game_id);
+        }
+    }
+    Ok(resumable)
+}
+
+/**
+ * Remove the temp files of bundle downloads that were cut off, which are named like `atomic`'s temp
+ * files for the bundle
+ */
+async fn remove_partial_downloads(game_id: &str) {
+    let Ok(mut entries) = tokio::fs::read_dir(storage::game_dir(game_id)).await else {
+        return;
+    };
+    let prefix = format!(".{BUNDLE}.");
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(&prefix) && name.ends_with(".tmp") {
+            if let Err(e) = tokio::fs::remove_file(entry.path()).await {
+                log::warn!("Couldn't remove partial download {name} of {game_id}: {e}");
+            }
+        }
+    }
+}
+
+async fn remove_if_exists(path: &Path) -> Result<(), Error> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn interrupted(game_id: &str, stage: InstallStage, bundle: bool) {
+        let game = DevcadeGame {
+            id: String::from(game_id),
+            ..DevcadeGame::default()
+        };
+        let mut state = InstallState::begin(game).await.unwrap();
+        state.advance(stage).await.unwrap();
+        if bundle {
+            std::fs::write(storage::game_file(game_id, BUNDLE), b"bundle").unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn recover_resumes_or_rolls_back_each_stage() {
+        storage::test_root();
+        interrupted("recover-downloading", InstallStage::Downloading, false).await;
+        let partial = storage::game_dir("recover-downloading").join(format!(".{BUNDLE}.1.tmp"));
+        std::fs::write(&partial, b"part").unwrap();
+        interrupted("recover-installing", InstallStage::Installing, true).await;
+        interrupted("recover-no-bundle", InstallStage::Installing, false).await;
+        interrupted("recover-installed", InstallStage::Installed, true).await;
+        let invalid = storage::game_dir("recover-invalid");
+        std::fs::create_dir_all(&invalid).unwrap();
+        std::fs::write(invalid.join(INSTALL_STATE), b"not json").unwrap();
+
+        let mut resumable: Vec<String> = recover()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|game_id| game_id.starts_with("recover-"))
+            .collect();
+        resumable.sort();
+        assert_eq!(resumable, ["recover-installed", "recover-installing"]);
+
+        // Rolled back installs leave nothing behind
+        assert!(!storage::game_dir("recover-downloading").exists());
+        assert!(!storage::game_dir("recover-no-bundle").exists());
+        assert!(!invalid.join(INSTALL_STATE).exists());
+        // Resumable installs keep their bundle and state
+        for game_id in ["recover-installing", "recover-installed"] {
+            assert!(storage::game_file(game_id, BUNDLE).exists());
+            assert_eq!(resume(game_id).await.unwrap().game.id, game_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn rolling_back_a_download_keeps_the_previous_version() {
+        storage::test_root();
+        let game_id = "roll-back-update";
+        std::fs::create_dir_all(storage::game_dir(game_id)).unwrap();
+        std::fs::write(storage::game_file(game_id, BUNDLE), b"previous").unwrap();
+        InstallState::begin(DevcadeGame {
+            id: String::from(game_id),
+            ..DevcadeGame::default()
+        })
+        .await
+        .unwrap()
+        .roll_back()
+        .await;
+        assert_eq!(
+            std::fs::read(storage::game_file(game_id, BUNDLE)).unwrap(),
+            b"previous"
+        );
+        assert!(!storage::game_file(game_id, INSTALL_STATE).exists());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::events;
+use crate::storage::{self, GAME_JSON};
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::{schema::DevcadeGame, Event};
+use futures_util::StreamExt;
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/**
+ * Events watched on the devcade directory itself, to notice game directories coming and going
+ */
+const ROOT_MASK: WatchMask = WatchMask::CREATE
+    .union(WatchMask::MOVED_TO)
+    .union(WatchMask::DELETE)
+    .union(WatchMask::MOVED_FROM)
+    .union(WatchMask::ONLYDIR);
+
+/**
+ * Events watched on each game directory, to notice a `game.json` being written
+ */
+const GAME_MASK: WatchMask = WatchMask::CLOSE_WRITE
+    .union(WatchMask::MOVED_TO)
+    .union(WatchMask::ONLYDIR);
+
+/**
+ * Tracks which game directories are being watched and which hold a valid game
+ */
+struct WatchState {
+    watches: Watches,
+    root: PathBuf,
+    root_watch: WatchDescriptor,
+    /**
+     * Game directory watches, mapped to the directory name
+     */
+    game_dirs: HashMap<WatchDescriptor, String>,
+    /**
+     * Directory names that hold a valid game
+     */
+    installed: HashSet<String>,
+}
+
+impl WatchState {
+    /**
+     * Start watching a game directory, and announce it if it already holds a valid game
+     */
+    async fn add_game_dir(&mut self, name: &str, announce: bool) {
+        let dir = self.root.join(name);
+        match self.watches.add(&dir, GAME_MASK) {
+            Ok(wd) => {
+                self.game_dirs.insert(wd, name.to_string());
+            }
+            Err(e) => {
+                log::warn!("Couldn't watch game directory {:?}: {}", dir, e);
+                return;
+            }
+        }
+        if tokio::fs::try_exists(dir.join(GAME_JSON))
+            .await
+            .unwrap_or(false)
+        {
+            self.check_game_json(name, announce).await;
+        }
+    }
+
+    /**
+     * Stop watching a game directory, and announce its removal if it held a valid game
+     */
+    async fn remove_game_dir(&mut self, name: &str) {
+        let wd = self
+            .game_dirs
+            .iter()
+            .find(|(_, dir)| dir.as_str() == name)
+            .map(|(wd, _)| wd.clone());
+        if let Some(wd) = wd {
+            self.game_dirs.remove(&wd);
+            // The watch is already gone if the directory was deleted, so errors are expected
+            let _ = self.watches.remove(wd);
+        }
+        if self.installed.remove(name) {
+            log::info!("Game directory {name} was removed");
+            if let Err(e) = storage::forget_game(name).await {
+                log::warn!("Couldn't remove {name} from the manifest: {e}");
+            }
+            events::emit(Event::GameRemoved(name.to_string()));
+        }
+    }
+
+    /**
+     * Validate a game directory's `game.json`, announcing the game if it's newly valid
+     */
+    async fn check_game_json(&mut self, name: &str, announce: bool) {
+        match validate_game_json(&self.root.join(name), name).await {
+            Ok(game) => {
+                if self.installed.insert(name.to_string()) && announce {
+                    log::info!("Found new game {} ({}) in {name}", game.name, game.id);
+                    if let Err(e) = storage::record_game(name).await {
+                        log::warn!("Couldn't add {name} to the manifest: {e}");
+                    }
+                    events::emit(Event::GameInstalled(game.id));
+                }
+            }
+            Err(e) => log::warn!("Ignoring game directory {name}: {e}"),
+        }
+    }
+}
+
+/**
+ * Check that a directory's `game.json` parses and belongs to the directory it's in
+ */
+async fn validate_game_json(dir: &Path, name: &str) -> Result<DevcadeGame, Error> {
+    let json = tokio::fs::read_to_string(dir.join(GAME_JSON)).await?;
+    let game: DevcadeGame = serde_json::from_str(&json)?;
+    if game.id != name {
+        return Err(anyhow!(
+            "game.json has id '{}' but is in directory '{name}'",
+            game.id
+        ));
+    }
+    Ok(game)
+}
+
+/**
+ * Watch the devcade directory for game directories being added or removed by hand (e.g. copying
+ * an unreleased game onto the machine for a demo). New directories are announced with a
+ * `GameInstalled` event once they contain a valid `game.json`, and removed directories with a
+ * `GameRemoved` event. This only returns if the watch can't be set up or inotify fails, and should
+ * be spawned as a task at startup.
+ *
+ * # Errors
+ * This function will return an error if inotify can't be initialized or the devcade directory
+ * can't be watched or read.
+ */
+pub async fn run() -> Result<(), Error> {
+    let root = storage::root();
+    let inotify = Inotify::init()?;
+    let mut stream = inotify.into_event_stream([0; 4096])?;
+    let mut watches = stream.watches();
+    let root_watch = watches.add(&root, ROOT_MASK)?;
+
+    let mut state = WatchState {
+        watches,
+        root: root.clone(),
+        root_watch,
+        game_dirs: HashMap::new(),
+        installed: HashSet::new(),
+    };
+
+    // Pick up what's already there without announcing it
+    let mut entries = tokio::fs::read_dir(&root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            if let Some(name) = entry.file_name().to_str() {
+                state.add_game_dir(name, false).await;
+            }
+        }
+    }
+    log::info!(
+        "Watching {:?} for sideloaded games ({} installed)",
+        root,
+        state.installed.len()
+    );
+
+    while let Some(event) = stream.next().await {
+        let event = event?;
+        let Some(name) = event.name.as_deref().and_then(OsStr::to_str) else {
+            continue;
+        };
+
+        if event.wd == state.root_watch {
+            if !event.mask.contains(EventMask::ISDIR) {
+                continue;
+            }
+            if event
+                .mask
+                .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+            {
+                state.add_game_dir(name, true).await;
+            } else if event
+                .mask
+                .intersects(EventMask::DELETE | EventMask::MOVED_FROM)
+            {
+                state.remove_game_dir(name).await;
+            }
+        } else if name == GAME_JSON {
+            if let Some(dir) = state.game_dirs.get(&event.wd).cloned() {
+                state.check_game_json(dir.as_str(), true).await;
+            }
+        }
+    }
+
+    Err(anyhow!("inotify event stream ended"))
+}
@@ -0,0 +1,291 @@
+use crate::api;
+use crate::atomic;
+use crate::env;
+use crate::i18n::tr;
+use crate::nfc;
+use crate::storage;
+use crate::ticker;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::LeaderboardEntry;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * The file (relative to the devcade path) that leaderboards are stored in
+ */
+const LEADERBOARDS_FILE: &str = "leaderboards.json";
+
+/**
+ * How many of the best scores each board keeps
+ */
+const MAX_ENTRIES: usize = 100;
+
+/**
+ * How many scores can wait to be uploaded before the oldest are dropped
+ */
+const MAX_PENDING: usize = 1000;
+
+/**
+ * The longest a board's name can be, in characters
+ */
+const MAX_BOARD_LEN: usize = 32;
+
+lazy_static! {
+    static ref LEADERBOARDS: Mutex<Leaderboards> = Mutex::new(Leaderboards::default());
+}
+
+/**
+ * Every board's best scores on this cabinet, and the scores that haven't been uploaded yet
+ */
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Leaderboards {
+    /**
+     * Each board's best scores, best first, by game ID and board name
+     */
+    boards: BTreeMap<String, BTreeMap<String, Vec<LeaderboardEntry>>>,
+    pending: Vec<LeaderboardEntry>,
+}
+
+fn leaderboards_path() -> PathBuf {
+    storage::root().join(LEADERBOARDS_FILE)
+}
+
+/**
+ * Load leaderboards from the devcade directory. Missing or unreadable leaderboards are logged and
+ * replaced with empty ones.
+ */
+pub async fn load() {
+    let path = leaderboards_path();
+    let leaderboards = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(leaderboards) => leaderboards,
+            Err(e) => {
+                log::warn!("Ignoring invalid leaderboards at {:?}: {e}", path);
+                Leaderboards::default()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Leaderboards::default(),
+        Err(e) => {
+            log::warn!("Couldn't read leaderboards at {:?}: {e}", path);
+            Leaderboards::default()
+        }
+    };
+    *LEADERBOARDS.lock().unwrap() = leaderboards;
+}
+
+/**
+ * Submit a score the running game's player got on one of its boards, getting where it ranks on
+ * this cabinet. Higher scores rank first. Scores can only be submitted for badges tapped in this
+ * session, or for guests, whose scores aren't uploaded.
+ *
+ * # Errors
+ * This function will return an error if no game is running, if the board's name isn't allowed, if
+ * the handle wasn't tapped in this session, or if the leaderboards can't be written.
+ */
+pub async fn submit(board: &str, handle: Option<String>, score: i64) -> Result<u32, Error> {
+    let game_id = api::current_game()
+        .map(|game| game.id)
+        .ok_or_else(|| anyhow!(tr("no_game_running", &[])))?;
+    check_board(board)?;
+    let name = match &handle {
+        Some(handle) if !nfc::tapped_this_session(handle) => {
+            return Err(anyhow!(tr("nfc_user_not_found", &[])));
+        }
+        Some(handle) => player_name(handle.clone()).await,
+        None => String::new(),
+    };
+    let entry = LeaderboardEntry {
+        game_id: game_id.clone(),
+        board: board.to_string(),
+        handle,
+        name,
+        score,
+        submitted_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+    log::info!("Score {score} submitted to {game_id}/{board}");
+
+    let (rank, leaderboards) = {
+        let mut leaderboards = LEADERBOARDS.lock().unwrap();
+        if entry.handle.is_some() && env::leaderboard_sync_interval().is_some() {
+            leaderboards.pending.push(entry.clone());
+            let overflow = leaderboards.pending.len().saturating_sub(MAX_PENDING);
+            leaderboards.pending.drain(..overflow);
+        }
+        let entries = leaderboards
+            .boards
+            .entry(game_id)
+            .or_default()
+            .entry(board.to_string())
+            .or_default();
+        let rank = insert(entries, entry.clone());
+        (rank, leaderboards.clone())
+    };
+    atomic::write_async(leaderboards_path(), serde_json::to_string(&leaderboards)?).await?;
+    if rank == 1 {
+        ticker::high_score(&entry);
+    }
+    Ok(rank)
+}
+
+/**
+ * Get the best scores on one of a game's boards, best first
+ */
+#[must_use]
+pub fn top(game_id: &str, board: &str, limit: usize) -> Vec<LeaderboardEntry> {
+    LEADERBOARDS
+        .lock()
+        .unwrap()
+        .boards
+        .get(game_id)
+        .and_then(|boards| boards.get(board))
+        .map(|entries| entries.iter().take(limit).cloned().collect())
+        .unwrap_or_default()
+}
+
+/**
+ * Get the names of a game's boards that have scores
+ */
+#[must_use]
+pub fn boards(game_id: &str) -> Vec<String> {
+    LEADERBOARDS
+        .lock()
+        .unwrap()
+        .boards
+        .get(game_id)
+        .map(|boards| boards.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/**
+ * Upload scores to the API every `DEVCADE_LEADERBOARD_SYNC_MINUTES`. Returns immediately if scores
+ * aren't uploaded.
+ */
+pub async fn run() {
+    let Some(period) = env::leaderboard_sync_interval() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        match sync().await {
+            Ok(0) => {}
+            Ok(uploaded) => log::info!("Uploaded {uploaded} scores"),
+            Err(e) => log::warn!("Couldn't upload scores: {e}"),
+        }
+    }
+}
+
+/**
+ * Upload the scores that haven't been yet, a board at a time, getting how many were. Boards that
+ * can't be uploaded are tried again next time.
+ *
+ * # Errors
+ * This function will return an error if the leaderboards can't be written.
+ */
+pub async fn sync() -> Result<usize, Error> {
+    let pending = LEADERBOARDS.lock().unwrap().pending.clone();
+    let mut by_board: BTreeMap<(String, String), Vec<LeaderboardEntry>> = BTreeMap::new();
+    for entry in pending {
+        by_board
+            .entry((entry.game_id.clone(), entry.board.clone()))
+            .or_default()
+            .push(entry);
+    }
+
+    let mut uploaded = vec![];
+    for ((game_id, board), entries) in by_board {
+        match api::upload_scores(game_id.as_str(), board.as_str(), &entries).await {
+            Ok(()) => uploaded.extend(entries),
+            Err(e) => log::warn!("Couldn't upload scores of {game_id}/{board}: {e}"),
+        }
+    }
+    if uploaded.is_empty() {
+        return Ok(0);
+    }
+    let leaderboards = {
+        let mut leaderboards = LEADERBOARDS.lock().unwrap();
+        leaderboards
+            .pending
+            .retain(|entry| !uploaded.contains(entry));
+        leaderboards.clone()
+    };
+    atomic::write_async(leaderboards_path(), serde_json::to_string(&leaderboards)?).await?;
+    Ok(uploaded.len())
+}
+
+/**
+ * Put a score in its place on a board, keeping only the best, and get its rank. Ties go to whoever
+ * got the score first. Scores that don't make the board rank just past its end.
+ */
+fn insert(entries: &mut Vec<LeaderboardEntry>, entry: LeaderboardEntry) -> u32 {
+    let index = entries.partition_point(|other| other.score >= entry.score);
+    entries.insert(index, entry);
+    entries.truncate(MAX_ENTRIES);
+    index as u32 + 1
+}
+
+fn check_board(board: &str) -> Result<(), Error> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if board.is_empty() || board.len() > MAX_BOARD_LEN || !board.chars().all(allowed) {
+        return Err(anyhow!(tr(
+            "leaderboard_name_invalid",
+            &[("max", MAX_BOARD_LEN.to_string().as_str())]
+        )));
+    }
+    Ok(())
+}
+
+/**
+ * Get the name a badge's player is shown by on leaderboards, or nothing if they can't be looked up
+ */
+async fn player_name(handle: String) -> String {
+    match nfc::get_user(handle).await {
+        Ok(user) => ["cn", "uid"]
+            .iter()
+            .find_map(|field| user.get(*field).and_then(|name| name.as_str()))
+            .unwrap_or_default()
+            .to_string(),
+        Err(e) => {
+            log::debug!("Couldn't look up the player submitting a score: {e}");
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(score: i64) -> LeaderboardEntry {
+        LeaderboardEntry {
+            score,
+            ..LeaderboardEntry::default()
+        }
+    }
+
+    #[test]
+    fn ranks_scores_best_first() {
+        let mut entries = vec![];
+        assert_eq!(insert(&mut entries, entry(50)), 1);
+        assert_eq!(insert(&mut entries, entry(80)), 1);
+        assert_eq!(insert(&mut entries, entry(50)), 3);
+        assert_eq!(insert(&mut entries, entry(10)), 4);
+        let scores: Vec<i64> = entries.iter().map(|entry| entry.score).collect();
+        assert_eq!(scores, vec![80, 50, 50, 10]);
+
+        for _ in 0..MAX_ENTRIES {
+            insert(&mut entries, entry(100));
+        }
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(insert(&mut entries, entry(0)), MAX_ENTRIES as u32 + 1);
+        assert!(check_board("high-scores_2").is_ok());
+        assert!(check_board("../other").is_err());
+    }
+}
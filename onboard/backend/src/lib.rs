@@ -18,19 +18,617 @@ pub mod command;
  */
 pub mod nfc;
 
+/**
+ * Module for a pretend badge reader, for machines without a real one
+ */
+pub mod nfc_mock;
+
+/**
+ * Module for logging players in without their badge, with a QR code or a PIN
+ */
+pub mod auth;
+
+/**
+ * Module for writing files so a crash or power loss can't leave them half written
+ */
+pub mod atomic;
+
+/**
+ * Module for broadcasting events that happen in the backend to anything that's interested
+ */
+pub mod events;
+
+/**
+ * Module for logging to the console and streaming logs to remote subscribers
+ */
+pub mod log_stream;
+
+/**
+ * Module for writing backend logs and game output to size-rotated files
+ */
+pub mod log_files;
+
+/**
+ * Module for shipping warnings and errors to a central syslog or Loki server
+ */
+pub mod log_shipping;
+
+/**
+ * Module for capturing and retrieving the output of games
+ */
+pub mod game_logs;
+
+/**
+ * Module for queueing game installs so they run one at a time
+ */
+pub mod install_queue;
+
+/**
+ * Module for keeping a history of install times, used to estimate how long installs will take
+ */
+pub mod install_history;
+
+/**
+ * Module for watching the devcade directory for games added or removed by hand
+ */
+pub mod installed_watcher;
+
+/**
+ * Module for speculatively fetching the game the user is hovering in the menu
+ */
+pub mod prefetch;
+
+/**
+ * Module for the layout of the devcade directory and the manifest describing it
+ */
+pub mod storage;
+
+/**
+ * Module for keeping track of how much each game is played
+ */
+pub mod play_stats;
+
+/**
+ * Module for counting and timing what the backend does, for the metrics server
+ */
+pub mod metrics;
+
+/**
+ * Module for uploading anonymous play events to the API, if the cabinet opts in
+ */
+pub mod analytics;
+
+/**
+ * Module for suggesting large games that aren't played for removal, and optionally removing them
+ */
+pub mod removal;
+
+/**
+ * Module for translating user-facing messages generated by the backend
+ */
+pub mod i18n;
+
+/**
+ * Module for flagging games as broken on this cabinet
+ */
+pub mod broken_games;
+
+/**
+ * Module for keeping what flatpak reported while installing a game
+ */
+pub mod install_report;
+
+/**
+ * Module for saving how far installs have got, so interrupted ones can be resumed or rolled back
+ */
+pub mod install_state;
+
+/**
+ * Module for checking game bundles are signed by a trusted publisher
+ */
+pub mod signing;
+
+/**
+ * Module for keeping earlier versions of games, so a broken update can be rolled back
+ */
+pub mod versions;
+
+/**
+ * Module for giving games SDL controller mappings, so games expecting other layouts stay playable
+ */
+pub mod controllers;
+
+/**
+ * Module for sampling how much CPU, memory and GPU the running game uses
+ */
+pub mod session_stats;
+
+/**
+ * Module for noticing a running game has hung, and stopping it
+ */
+pub mod watchdog;
+
+/**
+ * Module for recording gameplay, for attract-mode reels and debugging visual glitches
+ */
+pub mod recording;
+
+/**
+ * Module for setting the cabinet's volume, for the menu and each game
+ */
+pub mod audio;
+
+/**
+ * Module for describing the screen to games and setting up the display around launches
+ */
+pub mod display;
+
+/**
+ * Module for picking which GPU games render on, for cabinets with hybrid graphics
+ */
+pub mod gpu;
+
+/**
+ * Module for the hardware profiles the backend can run on
+ */
+pub mod profile;
+
+/**
+ * Module for temporary profiles for players without badges
+ */
+pub mod guests;
+
+/**
+ * Module for finding the file a game should be started with
+ */
+pub mod executables;
+
+/**
+ * Module for migrating the on-disk layout of the devcade directory between versions
+ */
+pub mod migrations;
+
+/**
+ * Module for the feed of recent cabinet activity shown on the marquee ticker
+ */
+pub mod ticker;
+
+/**
+ * Module for checking everything the cabinet needs at startup, and reporting what's missing
+ */
+pub mod readiness;
+
+/**
+ * Module for noticing the backend keeps crashing on startup, and starting in a minimal safe mode
+ */
+pub mod safe_mode;
+
+/**
+ * Module for backing players' saves up to the API and restoring them
+ */
+pub mod save_sync;
+
+/**
+ * Module for encrypting save data on disk
+ */
+pub mod save_crypto;
+
+/**
+ * Module for pushing changes to saved values to the connections watching their keys
+ */
+pub mod save_watch;
+
+/**
+ * Module for save slots games keep separate saves in, with a label and thumbnail for each
+ */
+pub mod save_slots;
+
+/**
+ * Module for games' leaderboards, kept on the cabinet and uploaded to the API
+ */
+pub mod leaderboards;
+
+/**
+ * Module for achievements games declare and players unlock, kept on the cabinet and uploaded to
+ * the API
+ */
+pub mod achievements;
+
+/**
+ * Module for writing cached saves to storage on a schedule, and before the backend is stopped
+ */
+pub mod save_flush;
+
+/**
+ * Module for reloading the configuration file while the backend is running
+ */
+pub mod config_reload;
+
+/**
+ * Module for the cabinet's identity, and registering it with the API
+ */
+pub mod cabinet;
+
+/**
+ * Module for updating the backend to new onboard releases published by the API
+ */
+pub mod self_update;
+
+/**
+ * Module for updating installed games during off-hours, so players don't wait for downloads
+ */
+pub mod update_window;
+
+/**
+ * Module for attract mode, which plays demo games while the cabinet sits idle
+ */
+pub mod attract;
+
+/**
+ * Module for curated collections of games, kept on the cabinet for when the API is down
+ */
+pub mod collections;
+
+/**
+ * Module for badged users' favorite games, kept on the cabinet and sent to the API
+ */
+pub mod favorites;
+
+/**
+ * Module for the games played recently on the cabinet, by anyone and by each badge
+ */
+pub mod history;
+
+/**
+ * Module for players' ratings of games, queued on the cabinet until the API can be reached
+ */
+pub mod ratings;
+
 /**
  * Module for safely getting environment variables, logging any errors that occur and providing
  * default values.
  */
 pub mod env {
     // TODO Cache env vars? Probably not necessary
-    use log::{log, Level};
+    use anyhow::{anyhow, Error};
+    use devcade_onboard_types::schema::NfcRealm;
+    use serde::Deserialize;
     use std::env;
+    use std::fmt::Display;
+    use std::net::SocketAddr;
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
     use std::sync::Mutex;
+    use std::time::Duration;
+
+    /**
+     * The configuration file read at startup, unless `DEVCADE_CONFIG` names another one
+     */
+    pub const CONFIG_FILE: &str = "/etc/devcade/config.toml";
+
+    /**
+     * Settings read from the configuration file. Each one is the lowercase name of its environment
+     * variable without `DEVCADE_`, and an environment variable that's set takes precedence over
+     * it. Anything left out of both gets its default.
+     */
+    #[derive(Clone, Debug, Default, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        pub api_domain: Option<String>,
+        pub dev_api_domain: Option<String>,
+        pub staging_api_domain: Option<String>,
+        pub path: Option<PathBuf>,
+        pub log_dir: Option<PathBuf>,
+        pub profile: Option<String>,
+        pub cabinet_name: Option<String>,
+        pub cabinet_location: Option<String>,
+        pub locale: Option<String>,
+        pub nfc_device: Option<String>,
+        pub nfc_device_p2: Option<String>,
+        pub nfc_poll_timeout_ms: Option<u64>,
+        pub metadata_cache_ttl: Option<u64>,
+        pub frontend_timeout_secs: Option<u64>,
+        pub game_timeout_secs: Option<u64>,
+        pub hang_timeout_secs: Option<u64>,
+        pub max_session_minutes: Option<u64>,
+        pub staff_mode: Option<bool>,
+        pub nfc_subscribe: Option<bool>,
+        pub prefetch_installs: Option<bool>,
+        pub relaunch_on_crash: Option<bool>,
+        pub record_gameplay: Option<bool>,
+        pub hide_broken_games: Option<bool>,
+        pub auto_remove_unplayed: Option<bool>,
+    }
+
+    impl Config {
+        /**
+         * Get every setting in the file, as the environment variable it stands in for and its
+         * value
+         */
+        #[must_use]
+        pub fn vars(&self) -> Vec<(&'static str, String)> {
+            fn var<T: ToString>(
+                name: &'static str,
+                value: &Option<T>,
+            ) -> Option<(&'static str, String)> {
+                value.as_ref().map(|value| (name, value.to_string()))
+            }
+            fn path(name: &'static str, value: &Option<PathBuf>) -> Option<(&'static str, String)> {
+                value
+                    .as_ref()
+                    .map(|value| (name, value.display().to_string()))
+            }
+            [
+                var("DEVCADE_API_DOMAIN", &self.api_domain),
+                var("DEVCADE_DEV_API_DOMAIN", &self.dev_api_domain),
+                var("DEVCADE_STAGING_API_DOMAIN", &self.staging_api_domain),
+                path("DEVCADE_PATH", &self.path),
+                path("DEVCADE_LOG_DIR", &self.log_dir),
+                var("DEVCADE_PROFILE", &self.profile),
+                var("DEVCADE_CABINET_NAME", &self.cabinet_name),
+                var("DEVCADE_CABINET_LOCATION", &self.cabinet_location),
+                var("DEVCADE_LOCALE", &self.locale),
+                var("DEVCADE_NFC_DEVICE", &self.nfc_device),
+                var("DEVCADE_NFC_DEVICE_P2", &self.nfc_device_p2),
+                var("DEVCADE_NFC_POLL_TIMEOUT_MS", &self.nfc_poll_timeout_ms),
+                var("DEVCADE_METADATA_CACHE_TTL", &self.metadata_cache_ttl),
+                var("DEVCADE_FRONTEND_TIMEOUT_SECS", &self.frontend_timeout_secs),
+                var("DEVCADE_GAME_TIMEOUT_SECS", &self.game_timeout_secs),
+                var("DEVCADE_HANG_TIMEOUT_SECS", &self.hang_timeout_secs),
+                var("DEVCADE_MAX_SESSION_MINUTES", &self.max_session_minutes),
+                var("DEVCADE_STAFF_MODE", &self.staff_mode),
+                var("DEVCADE_NFC_SUBSCRIBE", &self.nfc_subscribe),
+                var("DEVCADE_PREFETCH_INSTALLS", &self.prefetch_installs),
+                var("DEVCADE_RELAUNCH_ON_CRASH", &self.relaunch_on_crash),
+                var("DEVCADE_RECORD_GAMEPLAY", &self.record_gameplay),
+                var("DEVCADE_HIDE_BROKEN_GAMES", &self.hide_broken_games),
+                var("DEVCADE_AUTO_REMOVE_UNPLAYED", &self.auto_remove_unplayed),
+            ]
+            .into_iter()
+            .flatten()
+            .collect()
+        }
+
+        /**
+         * Check the settings make sense together
+         *
+         * # Errors
+         * This function will return an error naming the first setting that doesn't.
+         */
+        pub fn validate(&self) -> Result<(), Error> {
+            for (name, domain) in [
+                ("api_domain", &self.api_domain),
+                ("dev_api_domain", &self.dev_api_domain),
+                ("staging_api_domain", &self.staging_api_domain),
+            ] {
+                if domain.as_ref().is_some_and(|domain| domain.contains("://")) {
+                    return Err(anyhow!("{name} should be a domain, without http(s)://"));
+                }
+            }
+            for (name, path) in [("path", &self.path), ("log_dir", &self.log_dir)] {
+                if path.as_ref().is_some_and(|path| !path.is_absolute()) {
+                    return Err(anyhow!("{name} should be an absolute path"));
+                }
+            }
+            if let Some(profile) = &self.profile {
+                if !crate::profile::PROFILES
+                    .iter()
+                    .any(|known| known.name == profile.as_str())
+                {
+                    return Err(anyhow!("Unknown profile '{profile}'"));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /**
+     * Get the path of the configuration file. If `DEVCADE_CONFIG` is not set in the environment, it
+     * will default to `/etc/devcade/config.toml`.
+     */
+    #[must_use]
+    pub fn config_path() -> PathBuf {
+        env::var("DEVCADE_CONFIG")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map_or_else(|| PathBuf::from(CONFIG_FILE), PathBuf::from)
+    }
+
+    /**
+     * Which deployment the backend is running as, bundling the defaults that differ between them
+     * so a local build doesn't end up talking to the production API by accident. Defaults are
+     * below the configuration file and the environment, so either can still override them.
+     */
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Environment {
+        /**
+         * A developer's machine: the dev API, verbose logs, a pretend badge reader, and games
+         * allowed to run outside flatpak
+         */
+        Dev,
+        /**
+         * A cabinet testing upcoming changes: the dev API with staging games shown, and info logs
+         */
+        Staging,
+        /**
+         * A cabinet on the floor: the production API, and only errors logged
+         */
+        Prod,
+    }
+
+    impl Environment {
+        #[must_use]
+        pub fn name(self) -> &'static str {
+            match self {
+                Environment::Dev => "dev",
+                Environment::Staging => "staging",
+                Environment::Prod => "prod",
+            }
+        }
+
+        /**
+         * Whether the production API is used, unless the frontend picks otherwise
+         */
+        #[must_use]
+        pub fn production(self) -> bool {
+            self == Environment::Prod
+        }
+
+        /**
+         * The environment variables this environment sets when they aren't set any other way
+         */
+        #[must_use]
+        pub fn defaults(self) -> &'static [(&'static str, &'static str)] {
+            match self {
+                Environment::Dev => &[
+                    ("RUST_LOG", "debug"),
+                    ("DEVCADE_PROFILE", "dev-laptop"),
+                    ("DEVCADE_NFC_DEVICE", crate::nfc::MOCK_DEVICE),
+                    ("DEVCADE_TRUST_UNSANDBOXED_GAMES", "true"),
+                ],
+                Environment::Staging => &[("RUST_LOG", "info"), ("DEVCADE_STAFF_MODE", "true")],
+                Environment::Prod => &[("RUST_LOG", "error")],
+            }
+        }
+    }
+
+    /**
+     * Get the environment the backend is running as, from `DEVCADE_ENV`: `dev`, `staging` or
+     * `prod`. If the value is not set in the environment, debug builds default to dev and release
+     * builds to prod.
+     */
+    #[must_use]
+    pub fn environment() -> Environment {
+        let default = if cfg!(debug_assertions) {
+            Environment::Dev
+        } else {
+            Environment::Prod
+        };
+        let value = env::var("DEVCADE_ENV").unwrap_or_default();
+        match value.to_ascii_lowercase().as_str() {
+            "dev" | "development" => Environment::Dev,
+            "staging" => Environment::Staging,
+            "prod" | "production" => Environment::Prod,
+            "" => default,
+            other => {
+                tracing::warn!("Unknown DEVCADE_ENV '{}', using {}", other, default.name());
+                default
+            }
+        }
+    }
+
+    /**
+     * Get the settings in the configuration file, followed by the environment's defaults for
+     * anything the file doesn't set
+     */
+    fn layered_vars(config: &Config) -> Vec<(&'static str, String)> {
+        let mut vars = config.vars();
+        for (name, value) in environment().defaults() {
+            if !vars.iter().any(|(set, _)| set == name) {
+                vars.push((name, value.to_string()));
+            }
+        }
+        vars
+    }
+
+    /**
+     * Settings that are only read at startup, so the configuration file changing them doesn't take
+     * effect until the backend is restarted
+     */
+    const RESTART_ONLY_VARS: &[&str] = &["DEVCADE_PATH", "DEVCADE_LOG_DIR", "RUST_LOG"];
+
+    // The environment variables that were set from the configuration file, and their values
+    static FROM_CONFIG: Mutex<Vec<(&'static str, String)>> = Mutex::new(Vec::new());
+
+    /**
+     * Read the configuration file, and set every environment variable it (or failing that, the
+     * environment picked by `DEVCADE_ENV`) has a setting for that isn't already set. This picks
+     * the production or dev API for the environment too. This has to be called at startup before
+     * anything reads the environment, and before logging is set up, so nothing is logged: the
+     * caller should log what was loaded. A missing file is the same as an empty one.
+     *
+     * # Errors
+     * This function will return an error if the file can't be read, isn't valid TOML, has settings
+     * that aren't known or have the wrong type, or doesn't pass `Config::validate`.
+     */
+    pub fn load_config(path: &Path) -> Result<Config, Error> {
+        let config = read_config(path)?;
+        *PRODUCTION.lock().unwrap() = environment().production();
+        let mut from_config = FROM_CONFIG.lock().unwrap();
+        for (name, value) in layered_vars(&config) {
+            if env::var_os(name).is_none() {
+                env::set_var(name, value.as_str());
+                from_config.push((name, value));
+            }
+        }
+        Ok(config)
+    }
+
+    /**
+     * Read the configuration file again, replacing the settings it set before. Settings set in the
+     * environment still take precedence, and settings only read at startup are left alone.
+     * Returns the names of the settings that changed. If the file is invalid, nothing is changed.
+     *
+     * # Errors
+     * This function will return an error if the file can't be read, isn't valid TOML, has settings
+     * that aren't known or have the wrong type, or doesn't pass `Config::validate`.
+     */
+    pub fn reload_config(path: &Path) -> Result<Vec<&'static str>, Error> {
+        let config = read_config(path)?;
+        let mut from_config = FROM_CONFIG.lock().unwrap();
+        let old = std::mem::take(&mut *from_config);
+        let new = layered_vars(&config);
+        let mut changed = Vec::new();
+        for (name, value) in &old {
+            if RESTART_ONLY_VARS.contains(name) {
+                from_config.push((*name, value.clone()));
+            } else if !new.iter().any(|(new_name, _)| new_name == name) {
+                env::remove_var(name);
+                changed.push(*name);
+            }
+        }
+        for (name, value) in new {
+            let previous = old.iter().find(|(old_name, _)| *old_name == name);
+            if RESTART_ONLY_VARS.contains(&name) {
+                if previous.is_some_and(|(_, old_value)| *old_value != value) {
+                    tracing::warn!("{} changed in the config file, restart to use it", name);
+                }
+                continue;
+            }
+            // Anything that was set before and didn't come from the file is from the environment
+            if previous.is_none() && env::var_os(name).is_some() {
+                continue;
+            }
+            if previous.is_none_or(|(_, old_value)| *old_value != value) {
+                changed.push(name);
+            }
+            env::set_var(name, value.as_str());
+            from_config.push((name, value));
+        }
+        Ok(changed)
+    }
+
+    fn read_config(path: &Path) -> Result<Config, Error> {
+        let toml = match std::fs::read_to_string(path) {
+            Ok(toml) => toml,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(anyhow!("Couldn't read {}: {e}", path.display())),
+        };
+        let config: Config = toml::from_str(toml.as_str())
+            .map_err(|e| anyhow!("Invalid {}: {e}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
 
     // TODO should be Mutex? Lmao
     static PRODUCTION: Mutex<bool> = Mutex::new(true);
 
+    // Set by the frontend at runtime, overrides DEVCADE_LOCALE
+    static LOCALE: Mutex<Option<String>> = Mutex::new(None);
+
+    // Set by the frontend at runtime, overrides DEVCADE_STAFF_MODE
+    static STAFF_MODE: Mutex<Option<bool>> = Mutex::new(None);
+
     /**
      * Get the path to the devcade directory. This is where games are installed.
      * If the value is not set in the environment, it will default to /tmp/devcade.
@@ -42,8 +640,7 @@ pub mod env {
         match path {
             Ok(path) => path,
             Err(e) => {
-                log!(
-                    Level::Warn,
+                tracing::warn!(
                     "Error getting DEVCADE_PATH falling back to '$HOME/.devcade': {}",
                     e
                 );
@@ -70,9 +667,9 @@ pub mod env {
             Ok(url) => format!("https://{url}"),
             Err(e) => {
                 if *PRODUCTION.lock().unwrap() {
-                    log!(Level::Error, "Error getting DEVCADE_API_DOMAIN: {}", e);
+                    tracing::error!("Error getting DEVCADE_API_DOMAIN: {}", e);
                 } else {
-                    log!(Level::Error, "Error getting DEVCADE_DEV_API_DOMAIN: {}", e);
+                    tracing::error!("Error getting DEVCADE_DEV_API_DOMAIN: {}", e);
                 }
                 panic!();
             }
@@ -80,12 +677,1126 @@ pub mod env {
     }
 
     /**
-     * Sets whether the API will interact with the production or development API.
+     * Get how long game, tag, and user metadata from the API is cached before being re-requested.
+     * If the value is not set in the environment, it will default to 5 minutes.
      */
-    // This is thread safe because this is the only place that PRODUCTION can be modified
-    // so there is no way for a race condition to occur.
-    pub fn set_production(prod: bool) {
-        log!(Level::Info, "Setting production to {}", prod);
-        *PRODUCTION.lock().unwrap() = prod;
+    #[must_use]
+    pub fn metadata_cache_ttl() -> Duration {
+        Duration::from_secs(parse_var("DEVCADE_METADATA_CACHE_TTL", 300))
+    }
+
+    /**
+     * Get whether startup migrations should only log what they would do instead of running.
+     * If the value is not set in the environment, it will default to false.
+     */
+    #[must_use]
+    pub fn migrations_dry_run() -> bool {
+        parse_var("DEVCADE_MIGRATIONS_DRY_RUN", false)
+    }
+
+    /**
+     * Get whether hovering a game for long enough should speculatively install it.
+     * If the value is not set in the environment, it will default to false.
+     */
+    #[must_use]
+    pub fn prefetch_installs() -> bool {
+        parse_var("DEVCADE_PREFETCH_INSTALLS", false)
+    }
+
+    /**
+     * Get how much disk space must be free for a game to be speculatively installed, in bytes.
+     * If the value is not set in the environment, it will default to 4096 MiB.
+     */
+    #[must_use]
+    pub fn prefetch_min_free_bytes() -> u64 {
+        parse_var("DEVCADE_PREFETCH_MIN_FREE_MB", 4096u64) * 1024 * 1024
+    }
+
+    /**
+     * Get the longest a single game session may run before the game is stopped, or `None` if
+     * sessions are unlimited. If the value is not set in the environment, sessions are unlimited.
+     */
+    #[must_use]
+    pub fn max_session_length() -> Option<Duration> {
+        match parse_var("DEVCADE_MAX_SESSION_MINUTES", 0u64) {
+            0 => None,
+            minutes => Some(Duration::from_secs(minutes * 60)),
+        }
+    }
+
+    /**
+     * Get how long before the session limit the frontend is warned that the game will be stopped.
+     * If the value is not set in the environment, it will default to 2 minutes.
+     */
+    #[must_use]
+    pub fn session_warning() -> Duration {
+        Duration::from_secs(parse_var("DEVCADE_SESSION_WARNING_MINUTES", 2u64) * 60)
+    }
+
+    /**
+     * Get whether a game that crashes is relaunched once before it's reported as broken.
+     * If the value is not set in the environment, it will default to false.
+     */
+    #[must_use]
+    pub fn relaunch_on_crash() -> bool {
+        parse_var("DEVCADE_RELAUNCH_ON_CRASH", false)
+    }
+
+    /**
+     * Get the address the admin dashboard is served on, or `None` if it's disabled. If the value
+     * is not set in the environment, the dashboard is disabled.
+     */
+    #[must_use]
+    pub fn admin_address() -> Option<SocketAddr> {
+        let address: String = parse_var("DEVCADE_ADMIN_ADDR", String::new());
+        if address.is_empty() {
+            return None;
+        }
+        match address.parse() {
+            Ok(address) => Some(address),
+            Err(e) => {
+                tracing::warn!(
+                    "Error parsing DEVCADE_ADMIN_ADDR, disabling the dashboard: {}",
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /**
+     * Get the address metrics are served on for Prometheus, or `None` if they're disabled. If the
+     * value is not set in the environment, metrics aren't served.
+     */
+    #[must_use]
+    pub fn metrics_address() -> Option<SocketAddr> {
+        let address: String = parse_var("DEVCADE_METRICS_ADDR", String::new());
+        if address.is_empty() {
+            return None;
+        }
+        match address.parse() {
+            Ok(address) => Some(address),
+            Err(e) => {
+                tracing::warn!(
+                    "Error parsing DEVCADE_METRICS_ADDR, not serving metrics: {}",
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /**
+     * Get the token operators must send to view the cabinet's status and logs or perform actions
+     * from the admin dashboard, or `None` if all of those are disabled. If the value is not set in
+     * the environment, they are disabled.
+     */
+    #[must_use]
+    pub fn admin_token() -> Option<String> {
+        env::var("DEVCADE_ADMIN_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+    }
+
+    /**
+     * Get the token monitoring can send to read the cabinet's status from `/status` without the
+     * admin token, or `None` if only the admin token is accepted. If the value is not set in the
+     * environment, only the admin token is accepted.
+     */
+    #[must_use]
+    pub fn status_token() -> Option<String> {
+        env::var("DEVCADE_STATUS_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+    }
+
+    /**
+     * Get the size at which an installed game that hasn't been played recently is suggested for
+     * removal, in bytes. If the value is not set in the environment, it will default to 500 MiB.
+     */
+    #[must_use]
+    pub fn removal_min_bytes() -> u64 {
+        parse_var("DEVCADE_REMOVAL_MIN_MB", 500u64) * 1024 * 1024
+    }
+
+    /**
+     * Get how long a game has to go unplayed before it's suggested for removal.
+     * If the value is not set in the environment, it will default to 90 days.
+     */
+    #[must_use]
+    pub fn removal_unplayed_after() -> Duration {
+        Duration::from_secs(parse_var("DEVCADE_REMOVAL_UNPLAYED_DAYS", 90u64) * 24 * 60 * 60)
+    }
+
+    /**
+     * Get whether games suggested for removal are uninstalled automatically.
+     * If the value is not set in the environment, it will default to false.
+     */
+    #[must_use]
+    pub fn auto_remove_unplayed() -> bool {
+        parse_var("DEVCADE_AUTO_REMOVE_UNPLAYED", false)
+    }
+
+    /**
+     * Sets whether the API will interact with the production or development API.
+     */
+    // This is thread safe because this and `load_config` (which runs before anything else) are the
+    // only places that PRODUCTION can be modified, so there is no way for a race condition to occur.
+    pub fn set_production(prod: bool) {
+        tracing::info!("Setting production to {}", prod);
+        *PRODUCTION.lock().unwrap() = prod;
+    }
+
+    /**
+     * Get the name of the hardware profile the backend is running with, such as `cabinet-v2`. If
+     * the value is not set in the environment, it will default to `cabinet-v2`.
+     */
+    #[must_use]
+    pub fn profile_name() -> String {
+        env::var("DEVCADE_PROFILE")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| String::from(crate::profile::PROFILES[0].name))
+    }
+
+    /**
+     * Get the libnfc connection string of a player's badge reader, or `None` if there isn't one.
+     * Player 1's reader is `DEVCADE_NFC_DEVICE`, and if that's not set in the environment, the
+     * hardware profile's reader is used. Player 2's reader is `DEVCADE_NFC_DEVICE_P2`, and there
+     * isn't one if that's not set. Set either to `none` to disable the reader.
+     */
+    #[must_use]
+    pub fn nfc_device(player: &devcade_onboard_types::Player) -> Option<String> {
+        use devcade_onboard_types::Player;
+        let (name, default) = match player {
+            Player::P1 => ("DEVCADE_NFC_DEVICE", crate::profile::current().nfc_device),
+            Player::P2 => ("DEVCADE_NFC_DEVICE_P2", None),
+        };
+        match env::var(name) {
+            Ok(device) if device.eq_ignore_ascii_case("none") => None,
+            Ok(device) if !device.is_empty() => Some(device),
+            _ => default.map(String::from),
+        }
+    }
+
+    /**
+     * Get the Gatekeeper realm badges are read in unless a game asks for another. Cabinets should
+     * normally stay in the member projects realm, but can be moved to another one if their keys are
+     * only provisioned for it. If the value is not set in the environment, it will default to
+     * member projects.
+     */
+    #[must_use]
+    pub fn nfc_realm() -> NfcRealm {
+        let value = env::var("DEVCADE_NFC_REALM").unwrap_or_default();
+        if value.is_empty() {
+            return NfcRealm::MemberProjects;
+        }
+        parse_realm(value.as_str()).unwrap_or_else(|| {
+            tracing::warn!(
+                "Unknown DEVCADE_NFC_REALM '{}', using member projects",
+                value
+            );
+            NfcRealm::MemberProjects
+        })
+    }
+
+    /**
+     * Get the realms games can ask for badges to be read in, as a comma separated list. The
+     * default realm is always allowed. Every realm needs its `GK_REALM_<REALM>_*` keys set. If the
+     * value is not set in the environment, only the default realm is allowed.
+     */
+    #[must_use]
+    pub fn nfc_allowed_realms() -> Vec<NfcRealm> {
+        let mut realms = vec![nfc_realm()];
+        let value = env::var("DEVCADE_NFC_ALLOWED_REALMS").unwrap_or_default();
+        for name in value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            match parse_realm(name) {
+                Some(realm) if !realms.contains(&realm) => realms.push(realm),
+                Some(_) => {}
+                None => tracing::warn!(
+                    "Ignoring unknown realm '{}' in DEVCADE_NFC_ALLOWED_REALMS",
+                    name
+                ),
+            }
+        }
+        realms
+    }
+
+    fn parse_realm(name: &str) -> Option<NfcRealm> {
+        match name.to_ascii_lowercase().replace('-', "_").as_str() {
+            "member_projects" | "projects" => Some(NfcRealm::MemberProjects),
+            "door" | "doors" => Some(NfcRealm::Door),
+            "drink" => Some(NfcRealm::Drink),
+            _ => None,
+        }
+    }
+
+    /**
+     * Get how long a badge reader keeps polling for a badge when a game asks for one. Slow readers
+     * can miss a badge on a single poll. If the value is not set in the environment, it will
+     * default to 0, polling once.
+     */
+    #[must_use]
+    pub fn nfc_poll_timeout() -> Duration {
+        Duration::from_millis(parse_var("DEVCADE_NFC_POLL_TIMEOUT_MS", 0u64))
+    }
+
+    /**
+     * Get how long a badge handle given to a game can be used to look its user up after the badge
+     * was last tapped. Handles are also forgotten when the game exits. If the value is not set in
+     * the environment, it will default to 15 minutes.
+     */
+    #[must_use]
+    pub fn nfc_handle_ttl() -> Duration {
+        Duration::from_secs(parse_var("DEVCADE_NFC_HANDLE_TTL_SECS", 900))
+    }
+
+    /**
+     * Get how long users fetched from Gatekeeper for a badge are cached, so games looking the same
+     * player up again don't wait on the network. If the value is not set in the environment, it
+     * will default to 10 minutes.
+     */
+    #[must_use]
+    pub fn nfc_user_cache_ttl() -> Duration {
+        Duration::from_secs(parse_var("DEVCADE_NFC_USER_CACHE_SECS", 600))
+    }
+
+    /**
+     * Get whether badge readers are polled continuously, with taps pushed to the frontend and kept
+     * for the running game, instead of only being polled when a game asks for a tag. If the value
+     * is not set in the environment, it will default to false.
+     */
+    #[must_use]
+    pub fn nfc_subscribe() -> bool {
+        parse_var("DEVCADE_NFC_SUBSCRIBE", false)
+    }
+
+    /**
+     * Get how long a badge reader is kept open after its last request, before it's released for
+     * other programs. If the value is not set in the environment, it will default to 30 seconds.
+     */
+    #[must_use]
+    pub fn nfc_idle_timeout() -> Duration {
+        Duration::from_secs(parse_var("DEVCADE_NFC_IDLE_SECS", 30u64))
+    }
+
+    /**
+     * Get the URL of the staging API, where authors upload games to test on the cabinet, or `None`
+     * if `DEVCADE_STAGING_API_DOMAIN` isn't set.
+     */
+    #[must_use]
+    pub fn staging_api_url() -> Option<String> {
+        env::var("DEVCADE_STAGING_API_DOMAIN")
+            .ok()
+            .filter(|domain| !domain.is_empty())
+            .map(|domain| format!("https://{domain}"))
+    }
+
+    /**
+     * Get whether staging games are shown alongside production ones. If it hasn't been set by the
+     * frontend or in the environment, it will default to false.
+     */
+    #[must_use]
+    pub fn staff_mode() -> bool {
+        match *STAFF_MODE.lock().unwrap() {
+            Some(staff) => staff,
+            None => parse_var("DEVCADE_STAFF_MODE", false),
+        }
+    }
+
+    /**
+     * Sets whether staging games are shown alongside production ones.
+     */
+    pub fn set_staff_mode(staff: bool) {
+        tracing::info!("Setting staff mode to {}", staff);
+        *STAFF_MODE.lock().unwrap() = Some(staff);
+    }
+
+    /**
+     * The display server games are run under
+     */
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum DisplayServer {
+        X11,
+        /**
+         * Games use Wayland, falling back to XWayland if they don't support it
+         */
+        Wayland,
+    }
+
+    /**
+     * Get the display server games are run under. If the value is not set in the environment or
+     * is `auto`, the hardware profile's display server is used. If the profile doesn't have one,
+     * Wayland is used when `WAYLAND_DISPLAY` is set and X11 otherwise.
+     */
+    #[must_use]
+    pub fn display_server() -> DisplayServer {
+        let detected = crate::profile::current().display_server.unwrap_or_else(|| {
+            if env::var_os("WAYLAND_DISPLAY").is_some() {
+                DisplayServer::Wayland
+            } else {
+                DisplayServer::X11
+            }
+        });
+        let value = env::var("DEVCADE_DISPLAY_SERVER").unwrap_or_default();
+        match value.to_ascii_lowercase().as_str() {
+            "x11" => DisplayServer::X11,
+            "wayland" => DisplayServer::Wayland,
+            "" | "auto" => detected,
+            other => {
+                tracing::warn!(
+                    "Unknown DEVCADE_DISPLAY_SERVER '{}', detected {:?}",
+                    other,
+                    detected
+                );
+                detected
+            }
+        }
+    }
+
+    /**
+     * Get the name of the output games are shown on, as `xrandr` or `wlr-randr` calls it. If the
+     * value is not set in the environment, the primary (or first) connected output is used.
+     */
+    #[must_use]
+    pub fn display_output() -> Option<String> {
+        env::var("DEVCADE_DISPLAY_OUTPUT")
+            .ok()
+            .filter(|output| !output.is_empty())
+    }
+
+    /**
+     * Get the program run before each game launches and after it exits, to set up the display for
+     * it. If the value is not set in the environment, no hook is run.
+     */
+    #[must_use]
+    pub fn display_hook() -> Option<String> {
+        env::var("DEVCADE_DISPLAY_HOOK")
+            .ok()
+            .filter(|hook| !hook.is_empty())
+    }
+
+    /**
+     * Get how often the running game's resource usage is sampled and sent to the frontend, or
+     * `None` to never sample it. If the value is not set in the environment, it will default to 2
+     * seconds.
+     */
+    #[must_use]
+    pub fn session_stats_interval() -> Option<Duration> {
+        match parse_var("DEVCADE_SESSION_STATS_SECS", 2u64) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /**
+     * Get how much memory a game can use before it's stopped, in bytes, or `None` for no limit. The
+     * limit is only checked when resource usage is sampled. If the value is not set in the
+     * environment, there is no limit.
+     */
+    #[must_use]
+    pub fn game_memory_limit() -> Option<u64> {
+        match parse_var("DEVCADE_GAME_MEMORY_LIMIT_MIB", 0u64) {
+            0 => None,
+            mib => Some(mib * 1024 * 1024),
+        }
+    }
+
+    /**
+     * Get how long a game that pings the game socket can go without pinging before it's considered
+     * hung and stopped, or `None` to never stop hung games. If the value is not set in the
+     * environment, it will default to 60 seconds.
+     */
+    #[must_use]
+    pub fn hang_timeout() -> Option<Duration> {
+        match parse_var("DEVCADE_HANG_TIMEOUT_SECS", 60u64) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /**
+     * Get whether the screen is recorded while games are played. If the value is not set in the
+     * environment, it will default to false.
+     */
+    #[must_use]
+    pub fn record_gameplay() -> bool {
+        parse_var("DEVCADE_RECORD_GAMEPLAY", false)
+    }
+
+    /**
+     * Get how large a recording file can get before a new one is started. Only recordings made
+     * under X11 are split. If the value is not set in the environment, it will default to 100 MiB.
+     */
+    #[must_use]
+    pub fn recording_file_bytes() -> u64 {
+        parse_var("DEVCADE_RECORDING_FILE_MIB", 100u64) * 1024 * 1024
+    }
+
+    /**
+     * Get how much space recordings can take up before the oldest are deleted. If the value is not
+     * set in the environment, it will default to 2 GiB.
+     */
+    #[must_use]
+    pub fn recording_total_bytes() -> u64 {
+        parse_var("DEVCADE_RECORDING_TOTAL_MIB", 2048u64) * 1024 * 1024
+    }
+
+    /**
+     * Get the path of a `gamecontrollerdb.txt` of SDL controller mappings every game is run with,
+     * such as for the cabinet's controls. Games' own mappings take precedence. If the value is not
+     * set in the environment, games only get their own.
+     */
+    #[must_use]
+    pub fn controller_mappings() -> Option<String> {
+        env::var("DEVCADE_CONTROLLER_MAPPINGS")
+            .ok()
+            .filter(|path| !path.is_empty())
+    }
+
+    /**
+     * Get the PulseAudio (or PipeWire) sink the cabinet's volume is set on. If the value is not set
+     * in the environment, it will default to the default sink.
+     */
+    #[must_use]
+    pub fn audio_sink() -> String {
+        env::var("DEVCADE_AUDIO_SINK")
+            .ok()
+            .filter(|sink| !sink.is_empty())
+            .unwrap_or_else(|| String::from("@DEFAULT_SINK@"))
+    }
+
+    /**
+     * Get the GPU games render on unless they're set to use a specific one. If the value is not
+     * set in the environment or is `default`, games render wherever the system puts them.
+     */
+    #[must_use]
+    pub fn gpu() -> devcade_onboard_types::schema::GpuPreference {
+        use devcade_onboard_types::schema::GpuPreference;
+        let value = env::var("DEVCADE_GPU").unwrap_or_default();
+        match value.to_ascii_lowercase().as_str() {
+            "integrated" => GpuPreference::Integrated,
+            "discrete" => GpuPreference::Discrete,
+            "" | "default" => GpuPreference::Default,
+            other => {
+                tracing::warn!("Unknown DEVCADE_GPU '{}', using default", other);
+                GpuPreference::Default
+            }
+        }
+    }
+
+    /**
+     * Get the name this cabinet identifies itself by in reports to the API. If the value is not
+     * set in the environment, it will default to the machine's hostname.
+     */
+    #[must_use]
+    pub fn cabinet_name() -> String {
+        env::var("DEVCADE_CABINET_NAME")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .or_else(|| {
+                std::fs::read_to_string("/proc/sys/kernel/hostname")
+                    .ok()
+                    .map(|name| name.trim().to_string())
+            })
+            .unwrap_or_else(|| String::from("devcade"))
+    }
+
+    /**
+     * Get where this cabinet is, like the room it's in, for the API to pick a catalog by. If the
+     * value is not set in the environment, the location the cabinet was last given is kept.
+     */
+    #[must_use]
+    pub fn cabinet_location() -> Option<String> {
+        env::var("DEVCADE_CABINET_LOCATION")
+            .ok()
+            .filter(|location| !location.is_empty())
+    }
+
+    /**
+     * Get whether games flagged as broken on this cabinet are left out of game lists, instead of
+     * being shown with a warning. If the value is not set in the environment, it will default to
+     * false.
+     */
+    #[must_use]
+    pub fn hide_broken_games() -> bool {
+        parse_var("DEVCADE_HIDE_BROKEN_GAMES", false)
+    }
+
+    /**
+     * Get how long guest profiles last, or `None` if they only last until the current game exits.
+     * If the value is not set in the environment, it will default to 0 (until the game exits).
+     */
+    #[must_use]
+    pub fn guest_lifetime() -> Option<Duration> {
+        match parse_var("DEVCADE_GUEST_MINUTES", 0u64) {
+            0 => None,
+            minutes => Some(Duration::from_secs(minutes * 60)),
+        }
+    }
+
+    /**
+     * Get the publisher keys game bundles must be signed with, as `<key id>=<base64 Ed25519 public
+     * key>` entries. If the value is not set in the environment, no keys are pinned and games don't
+     * have to be signed.
+     */
+    #[must_use]
+    pub fn publisher_keys() -> Vec<String> {
+        parse_var("DEVCADE_PUBLISHER_KEYS", String::new())
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /**
+     * Get the keys onboard releases must be signed with to be installed by the self-updater, as
+     * `<key id>=<base64 Ed25519 public key>` entries. If the value is not set in the environment,
+     * no keys are pinned and the backend never updates itself.
+     */
+    #[must_use]
+    pub fn release_keys() -> Vec<String> {
+        parse_var("DEVCADE_RELEASE_KEYS", String::new())
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /**
+     * Get how often the backend checks for a new build of itself, or `None` if it doesn't update
+     * itself. If the value is not set in the environment, it will default to 0 (never).
+     */
+    #[must_use]
+    pub fn self_update_interval() -> Option<Duration> {
+        match parse_var("DEVCADE_SELF_UPDATE_HOURS", 0u64) {
+            0 => None,
+            hours => Some(Duration::from_secs(hours * 60 * 60)),
+        }
+    }
+
+    /**
+     * Get the systemd unit the backend runs as, which is restarted after it updates itself. If the
+     * value is not set in the environment, it will default to `devcade-onboard.service`.
+     */
+    #[must_use]
+    pub fn systemd_unit() -> String {
+        parse_var(
+            "DEVCADE_SYSTEMD_UNIT",
+            String::from("devcade-onboard.service"),
+        )
+    }
+
+    /**
+     * Get the cabinet secret save data is encrypted on disk with, or `None` if it isn't encrypted.
+     * If the value is not set in the environment, save data isn't encrypted.
+     */
+    #[must_use]
+    pub fn save_secret() -> Option<String> {
+        env::var("DEVCADE_SAVE_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+    }
+
+    /**
+     * Get whether clients that aren't running in flatpak may use the game socket as the running
+     * game, like games being developed outside a sandbox. If the value is not set in the
+     * environment, it will default to false.
+     */
+    #[must_use]
+    pub fn trust_unsandboxed_games() -> bool {
+        parse_var("DEVCADE_TRUST_UNSANDBOXED_GAMES", false)
+    }
+
+    /**
+     * Get how often changed saves are written to storage, or `None` to only write them when games
+     * exit or ask to. If the value is not set in the environment, it will default to 30 seconds.
+     */
+    #[must_use]
+    pub fn save_flush_interval() -> Option<Duration> {
+        match parse_var("DEVCADE_SAVE_FLUSH_SECONDS", 30u64) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /**
+     * Get how many groups can change before saves are written to storage without waiting for the
+     * interval, or `None` to always wait. If the value is not set in the environment, it will
+     * default to 32.
+     */
+    #[must_use]
+    pub fn save_flush_dirty_groups() -> Option<usize> {
+        match parse_var("DEVCADE_SAVE_FLUSH_DIRTY_GROUPS", 32usize) {
+            0 => None,
+            groups => Some(groups),
+        }
+    }
+
+    /**
+     * How logs are written to the console
+     */
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum LogFormat {
+        /**
+         * A line of text for each log
+         */
+        Text,
+        /**
+         * A JSON object for each log, with the spans it was logged in
+         */
+        Json,
+    }
+
+    /**
+     * Get how logs are written to the console. If the value is not set in the environment, it will
+     * default to text.
+     */
+    #[must_use]
+    pub fn log_format() -> LogFormat {
+        let value = env::var("DEVCADE_LOG_FORMAT").unwrap_or_default();
+        match value.to_ascii_lowercase().as_str() {
+            "json" => LogFormat::Json,
+            "" | "text" => LogFormat::Text,
+            other => {
+                tracing::warn!("Unknown DEVCADE_LOG_FORMAT '{}', using text", other);
+                LogFormat::Text
+            }
+        }
+    }
+
+    /**
+     * Get the directory game bundles can be exported into, such as where USB sticks are mounted.
+     * Bundles can be written anywhere under it, but nowhere else. If the value is not set in the
+     * environment, it will default to `/media`.
+     */
+    #[must_use]
+    pub fn export_dir() -> PathBuf {
+        env::var("DEVCADE_EXPORT_DIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(|| PathBuf::from("/media"), PathBuf::from)
+    }
+
+    /**
+     * Get the directory backend logs and game output are written to, if log files are turned on.
+     * If the value is not set in the environment, it will default to `.logs` in the devcade
+     * directory.
+     */
+    #[must_use]
+    pub fn log_dir() -> PathBuf {
+        env::var("DEVCADE_LOG_DIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(
+                || PathBuf::from(devcade_path()).join(crate::storage::LOG_FILES_DIR),
+                PathBuf::from,
+            )
+    }
+
+    /**
+     * Get how many log files are kept, counting the one being written to, or `None` to not write
+     * log files at all. If the value is not set in the environment, it will default to 0, which
+     * turns log files off.
+     */
+    #[must_use]
+    pub fn log_files_kept() -> Option<usize> {
+        match parse_var("DEVCADE_LOG_FILES", 0usize) {
+            0 => None,
+            files => Some(files),
+        }
+    }
+
+    /**
+     * Get how large a log file can get before a new one is started. If the value is not set in the
+     * environment, it will default to 10 MiB.
+     */
+    #[must_use]
+    pub fn log_file_max_bytes() -> u64 {
+        parse_var("DEVCADE_LOG_FILE_MIB", 10u64).max(1) * 1024 * 1024
+    }
+
+    /**
+     * Where warnings and errors are shipped to, so every cabinet's problems can be seen in one place
+     */
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum LogShipTarget {
+        /**
+         * A syslog server, sent RFC 5424 messages over UDP, as `host:port`
+         */
+        Syslog(String),
+        /**
+         * The push URL of a Loki server
+         */
+        Loki(String),
+    }
+
+    /**
+     * Get where warnings and errors are shipped, or `None` to keep them on the cabinet. Syslog
+     * servers are given as `syslog://<host>:<port>`, and Loki servers as the `http(s)://` URL of
+     * their push endpoint. If the value is not set in the environment, logs aren't shipped.
+     */
+    #[must_use]
+    pub fn log_ship_target() -> Option<LogShipTarget> {
+        let value = env::var("DEVCADE_LOG_SHIP_URL").unwrap_or_default();
+        if value.is_empty() {
+            return None;
+        }
+        if let Some(address) = value.strip_prefix("syslog://") {
+            return Some(LogShipTarget::Syslog(
+                address.trim_end_matches('/').to_string(),
+            ));
+        }
+        if value.starts_with("http://") || value.starts_with("https://") {
+            return Some(LogShipTarget::Loki(value));
+        }
+        tracing::warn!(
+            "Unknown scheme in DEVCADE_LOG_SHIP_URL '{}', not shipping logs",
+            value
+        );
+        None
+    }
+
+    /**
+     * Get how often buffered warnings and errors are shipped. If the value is not set in the
+     * environment, it will default to 10 seconds.
+     */
+    #[must_use]
+    pub fn log_ship_interval() -> Duration {
+        Duration::from_secs(parse_var("DEVCADE_LOG_SHIP_SECS", 10u64).max(1))
+    }
+
+    /**
+     * Get how long the cabinet has to sit idle before attract mode starts playing demo games, or
+     * `None` if it never does. If the value is not set in the environment, it will default to 0
+     * (never).
+     */
+    #[must_use]
+    pub fn attract_after() -> Option<Duration> {
+        match parse_var("DEVCADE_ATTRACT_MINUTES", 0u64) {
+            0 => None,
+            minutes => Some(Duration::from_secs(minutes * 60)),
+        }
+    }
+
+    /**
+     * Get how long each game plays for in attract mode before the next one is started. If the
+     * value is not set in the environment, it will default to 120 seconds.
+     */
+    #[must_use]
+    pub fn attract_game_length() -> Duration {
+        Duration::from_secs(parse_var("DEVCADE_ATTRACT_GAME_SECS", 120u64).max(10))
+    }
+
+    /**
+     * A daily stretch of local time, given in minutes since midnight. A window that ends before it
+     * starts runs past midnight.
+     */
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct UpdateWindow {
+        pub start: u32,
+        pub end: u32,
+    }
+
+    impl UpdateWindow {
+        /**
+         * Whether a time (in minutes since midnight) is inside the window
+         */
+        #[must_use]
+        pub fn contains(&self, minute: u32) -> bool {
+            if self.start <= self.end {
+                (self.start..self.end).contains(&minute)
+            } else {
+                minute >= self.start || minute < self.end
+            }
+        }
+    }
+
+    impl FromStr for UpdateWindow {
+        type Err = Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let parse_time = |time: &str| -> Result<u32, Error> {
+                let (hours, minutes) = time
+                    .trim()
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("Expected HH:MM, got '{time}'"))?;
+                let (hours, minutes): (u32, u32) = (hours.parse()?, minutes.parse()?);
+                if hours > 23 || minutes > 59 {
+                    return Err(anyhow!("'{time}' isn't a time of day"));
+                }
+                Ok(hours * 60 + minutes)
+            };
+            let (start, end) = s
+                .split_once('-')
+                .ok_or_else(|| anyhow!("Expected HH:MM-HH:MM, got '{s}'"))?;
+            Ok(Self {
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+            })
+        }
+    }
+
+    /**
+     * Get the off-hours installed games are checked for updates in, as `HH:MM-HH:MM` in local
+     * time. If the value is not set in the environment, games are only updated when they're
+     * launched.
+     */
+    #[must_use]
+    pub fn update_window() -> Option<UpdateWindow> {
+        let value = env::var("DEVCADE_UPDATE_WINDOW").unwrap_or_default();
+        if value.is_empty() {
+            return None;
+        }
+        match value.parse() {
+            Ok(window) => Some(window),
+            Err(e) => {
+                tracing::warn!(
+                    "Error parsing DEVCADE_UPDATE_WINDOW, not updating games: {}",
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /**
+     * The engine saves are stored in between runs
+     */
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum SaveStorage {
+        /**
+         * Each group is kept in a `.save` file of its own
+         */
+        Files,
+        /**
+         * Every group is kept in a SQLite database in the save directory
+         */
+        Sqlite,
+    }
+
+    /**
+     * Get the engine saves are stored in. If the value is not set in the environment, it will
+     * default to files.
+     */
+    #[must_use]
+    pub fn save_storage() -> SaveStorage {
+        let value = env::var("DEVCADE_SAVE_STORAGE").unwrap_or_default();
+        match value.to_ascii_lowercase().as_str() {
+            "sqlite" => SaveStorage::Sqlite,
+            "" | "files" => SaveStorage::Files,
+            other => {
+                tracing::warn!("Unknown DEVCADE_SAVE_STORAGE '{}', using files", other);
+                SaveStorage::Files
+            }
+        }
+    }
+
+    /**
+     * Get how often players' saves are backed up to the API, or `None` to never back them up. If
+     * the value is not set in the environment, saves aren't backed up.
+     */
+    #[must_use]
+    pub fn save_sync_interval() -> Option<Duration> {
+        match parse_var("DEVCADE_SAVE_SYNC_MINUTES", 0u64) {
+            0 => None,
+            minutes => Some(Duration::from_secs(minutes * 60)),
+        }
+    }
+
+    /**
+     * Get how often scores are uploaded to the API's leaderboards, or `None` to keep them on the
+     * cabinet. If the value is not set in the environment, it will default to 5 minutes.
+     */
+    #[must_use]
+    pub fn leaderboard_sync_interval() -> Option<Duration> {
+        match parse_var("DEVCADE_LEADERBOARD_SYNC_MINUTES", 5u64) {
+            0 => None,
+            minutes => Some(Duration::from_secs(minutes * 60)),
+        }
+    }
+
+    /**
+     * Get how often anonymous play events are uploaded to the API for game authors, or `None` to
+     * not collect them at all. If the value is not set in the environment, it will default to 0,
+     * which turns analytics off.
+     */
+    #[must_use]
+    pub fn analytics_upload_interval() -> Option<Duration> {
+        match parse_var("DEVCADE_ANALYTICS_MINUTES", 0u64) {
+            0 => None,
+            minutes => Some(Duration::from_secs(minutes * 60)),
+        }
+    }
+
+    /**
+     * Get how often unlocked achievements are uploaded to the API, or `None` to keep them on the
+     * cabinet. If the value is not set in the environment, it will default to 5 minutes.
+     */
+    #[must_use]
+    pub fn achievement_sync_interval() -> Option<Duration> {
+        match parse_var("DEVCADE_ACHIEVEMENT_SYNC_MINUTES", 5u64) {
+            0 => None,
+            minutes => Some(Duration::from_secs(minutes * 60)),
+        }
+    }
+
+    /**
+     * Get how many bytes of save data a game may store. Games are given the default quota unless
+     * they have an override, as `<game id>=<MiB>` entries in `DEVCADE_SAVE_QUOTA_OVERRIDES`. If
+     * the default is not set in the environment, it will default to 10 MiB.
+     */
+    #[must_use]
+    pub fn save_quota(game_id: &str) -> u64 {
+        let overrides = parse_var("DEVCADE_SAVE_QUOTA_OVERRIDES", String::new());
+        let mib = overrides
+            .split(',')
+            .filter_map(|entry| entry.split_once('='))
+            .find(|(id, _)| id.trim() == game_id)
+            .and_then(|(id, mib)| match mib.trim().parse::<u64>() {
+                Ok(mib) => Some(mib),
+                Err(_) => {
+                    tracing::warn!(
+                        "Ignoring bad save quota override for '{}' in DEVCADE_SAVE_QUOTA_OVERRIDES",
+                        id.trim()
+                    );
+                    None
+                }
+            })
+            .unwrap_or_else(|| parse_var("DEVCADE_SAVE_QUOTA_MB", 10u64));
+        mib * 1024 * 1024
+    }
+
+    /**
+     * Get how many earlier versions of each game are kept to roll back to. If the value is not set
+     * in the environment, it will default to 2.
+     */
+    #[must_use]
+    pub fn kept_versions() -> usize {
+        parse_var("DEVCADE_KEEP_VERSIONS", 2usize)
+    }
+
+    /**
+     * Get whether the API domain for the current environment (production or dev) is set, without
+     * panicking like `api_url` does when it isn't.
+     */
+    #[must_use]
+    pub fn api_configured() -> bool {
+        let name = if *PRODUCTION.lock().unwrap() {
+            "DEVCADE_API_DOMAIN"
+        } else {
+            "DEVCADE_DEV_API_DOMAIN"
+        };
+        env::var(name).is_ok_and(|domain| !domain.is_empty())
+    }
+
+    /**
+     * Get how many starts in a row the backend can fail to stay up for before it starts in safe
+     * mode, or 0 to never start in safe mode. If the value is not set in the environment, it will
+     * default to 3.
+     */
+    #[must_use]
+    pub fn crash_loop_starts() -> u32 {
+        parse_var("DEVCADE_CRASH_LOOP_STARTS", 3u32)
+    }
+
+    /**
+     * Get how many events a subscriber (such as a frontend connection) can fall behind by before
+     * its oldest ones are dropped. If the value is not set in the environment, it will default to
+     * 64.
+     */
+    #[must_use]
+    pub fn event_buffer() -> usize {
+        parse_var("DEVCADE_EVENT_BUFFER", 64usize).max(1)
+    }
+
+    /**
+     * Get how long a frontend connection can go without sending anything before it's assumed to
+     * have hung, or `None` to wait forever. The frontend pings every 5 seconds, so it has three
+     * tries. If the value is not set in the environment, it will default to 15 seconds.
+     */
+    #[must_use]
+    pub fn frontend_timeout() -> Option<Duration> {
+        match parse_var("DEVCADE_FRONTEND_TIMEOUT_SECS", 15u64) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /**
+     * Get how long a game's connection can go without sending anything before it's assumed to have
+     * hung, or `None` to wait forever. Games only talk to the backend when they save or load, so if
+     * the value is not set in the environment, it will default to 0 (wait forever).
+     */
+    #[must_use]
+    pub fn game_client_timeout() -> Option<Duration> {
+        match parse_var("DEVCADE_GAME_TIMEOUT_SECS", 0u64) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /**
+     * Get the locale user-facing messages and game metadata are requested in, e.g. `en` or
+     * `de-AT`. If it hasn't been set by the frontend or in the environment, it will default to
+     * `en`.
+     */
+    #[must_use]
+    pub fn locale() -> String {
+        if let Some(locale) = LOCALE.lock().unwrap().clone() {
+            return locale;
+        }
+        match env::var("DEVCADE_LOCALE") {
+            Ok(locale) if crate::i18n::is_valid_locale(locale.as_str()) => locale,
+            Ok(locale) if !locale.is_empty() => {
+                tracing::warn!("DEVCADE_LOCALE '{}' isn't a locale, using en", locale);
+                String::from("en")
+            }
+            _ => String::from("en"),
+        }
+    }
+
+    /**
+     * Sets the locale user-facing messages and game metadata are requested in.
+     *
+     * # Errors
+     * This function will return an error if the locale isn't a tag like `en` or `de-AT`.
+     */
+    pub fn set_locale(locale: String) -> Result<(), Error> {
+        if !crate::i18n::is_valid_locale(locale.as_str()) {
+            return Err(anyhow!(crate::i18n::tr(
+                "locale_invalid",
+                &[("locale", locale.as_str())]
+            )));
+        }
+        tracing::info!("Setting locale to {}", locale);
+        *LOCALE.lock().unwrap() = Some(locale);
+        Ok(())
+    }
+
+    /**
+     * Parse an optional environment variable, falling back to the default if it isn't set. If the
+     * value is set but can't be parsed, the error is logged and the default is used.
+     */
+    fn parse_var<T: FromStr>(name: &str, default: T) -> T
+    where
+        T::Err: Display,
+    {
+        match env::var(name) {
+            Ok(value) => match value.parse() {
+                Ok(value) => value,
+                Err(e) => {
+                    tracing::warn!("Error parsing {}, using default: {}", name, e);
+                    default
+                }
+            },
+            Err(_) => default,
+        }
     }
 }
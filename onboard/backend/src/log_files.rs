@@ -0,0 +1,174 @@
+use crate::env;
+use lazy_static::lazy_static;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing_subscriber::fmt::MakeWriter;
+
+/**
+ * The file (in the log directory) that backend logs are written to
+ */
+const LOG_FILE: &str = "backend.log";
+
+lazy_static! {
+    static ref SINK: Mutex<Option<RotatingFile>> = Mutex::new(None);
+}
+
+/**
+ * A log file that's moved aside once it reaches a size, keeping a few of the files before it.
+ * `backend.log` is written to, `backend.log.1` is the one before it, and so on.
+ */
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    /**
+     * How many files are kept, counting the one being written to
+     */
+    keep: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            written,
+            max_bytes,
+            keep,
+        })
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.written > 0 && self.written + bytes.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    /**
+     * Shift every file down by one, dropping the oldest, and start a new one
+     */
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        for n in (1..self.keep).rev() {
+            let from = rotated_path(&self.path, n - 1);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, n))?;
+            }
+        }
+        if self.keep <= 1 {
+            fs::remove_file(&self.path)?;
+        }
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+/**
+ * Get the path of a log file's `n`th rotation, where 0 is the file being written to
+ */
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    match n {
+        0 => path.to_path_buf(),
+        n => PathBuf::from(format!("{}.{n}", path.display())),
+    }
+}
+
+/**
+ * Start writing logs to `DEVCADE_LOG_DIR`, if log files are turned on. Returns whether they are.
+ */
+pub fn init() -> bool {
+    let Some(keep) = env::log_files_kept() else {
+        return false;
+    };
+    let path = env::log_dir().join(LOG_FILE);
+    match RotatingFile::open(path.clone(), env::log_file_max_bytes(), keep) {
+        Ok(file) => {
+            *SINK.lock().unwrap() = Some(file);
+            true
+        }
+        Err(e) => {
+            // Logging isn't set up yet, so this is the only place it can go
+            eprintln!("Couldn't open log file {}: {e}", path.display());
+            false
+        }
+    }
+}
+
+/**
+ * Write a line to the log file, like a line of a game's output. Does nothing if log files are off.
+ */
+pub fn write_line(line: &str) {
+    let mut sink = SINK.lock().unwrap();
+    if let Some(file) = sink.as_mut() {
+        // Logging about failing to log would only end up here again
+        let _ = file.write(format!("{line}\n").as_bytes());
+    }
+}
+
+/**
+ * Writes formatted logs to the log file
+ */
+pub struct LogFileWriter;
+
+impl Write for LogFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(file) = SINK.lock().unwrap().as_mut() {
+            file.write(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match SINK.lock().unwrap().as_mut() {
+            Some(file) => file.file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFileWriter {
+    type Writer = LogFileWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogFileWriter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_rotate_at_their_size() {
+        let dir = std::env::temp_dir().join(format!("devcade-log-files-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join(LOG_FILE);
+        let mut file = RotatingFile::open(path.clone(), 10, 3).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write(line.as_bytes()).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 2)).unwrap(),
+            "second\n"
+        );
+        assert!(!rotated_path(&path, 3).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+use crate::api;
+use crate::env::{self, LogShipTarget};
+use anyhow::Error;
+use lazy_static::lazy_static;
+use log::Level;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+
+/**
+ * How many lines are kept waiting to be shipped before the oldest are dropped
+ */
+const MAX_BUFFERED: usize = 2000;
+
+/**
+ * How many lines are shipped at once
+ */
+const BATCH_SIZE: usize = 200;
+
+/**
+ * The longest the shipper waits between tries while the server can't be reached
+ */
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/**
+ * What the backend calls itself in syslog messages and Loki labels
+ */
+const APP_NAME: &str = "devcade-onboard";
+
+/**
+ * The syslog facility lines are sent with (user-level messages)
+ */
+const SYSLOG_FACILITY: u8 = 1;
+
+lazy_static! {
+    static ref TARGET: Option<LogShipTarget> = env::log_ship_target();
+    static ref BUFFER: Mutex<VecDeque<ShippedLine>> = Mutex::new(VecDeque::new());
+}
+
+/**
+ * A warning or error waiting to be shipped
+ */
+#[derive(Clone, Debug)]
+struct ShippedLine {
+    timestamp_ms: u64,
+    level: Level,
+    source: String,
+    message: String,
+}
+
+/**
+ * Keep a warning or error to be shipped with the next batch. Does nothing for other levels, or if
+ * logs aren't shipped.
+ */
+pub fn queue(level: Level, source: &str, message: &str) {
+    if level > Level::Warn || TARGET.is_none() {
+        return;
+    }
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let mut buffer = BUFFER.lock().unwrap();
+    if buffer.len() >= MAX_BUFFERED {
+        buffer.pop_front();
+    }
+    buffer.push_back(ShippedLine {
+        timestamp_ms,
+        level,
+        source: source.to_string(),
+        message: message.to_string(),
+    });
+}
+
+/**
+ * Ship buffered lines every `DEVCADE_LOG_SHIP_SECS`. While the server can't be reached, lines stay
+ * buffered and the wait doubles after every failed try, up to 10 minutes. Returns immediately if
+ * logs aren't shipped.
+ */
+pub async fn run() {
+    let Some(target) = TARGET.as_ref() else {
+        return;
+    };
+    let period = env::log_ship_interval();
+    let mut wait = period;
+    loop {
+        tokio::time::sleep(wait).await;
+        wait = match ship(target).await {
+            Ok(()) => period,
+            Err(e) => {
+                let backoff = (wait * 2).min(MAX_BACKOFF.max(period));
+                // Not a warning, or it would be buffered to be shipped too
+                log::debug!("Couldn't ship logs, trying again in {backoff:?}: {e}");
+                backoff
+            }
+        };
+    }
+}
+
+/**
+ * Ship every buffered line, a batch at a time. A batch that can't be shipped is put back in front
+ * of anything logged since, as long as there's room.
+ */
+async fn ship(target: &LogShipTarget) -> Result<(), Error> {
+    loop {
+        let batch: Vec<ShippedLine> = {
+            let mut buffer = BUFFER.lock().unwrap();
+            let len = buffer.len().min(BATCH_SIZE);
+            buffer.drain(..len).collect()
+        };
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let result = match target {
+            LogShipTarget::Syslog(address) => send_syslog(address, &batch).await,
+            LogShipTarget::Loki(url) => api::push_logs(url, &loki_streams(&batch)).await,
+        };
+        if let Err(e) = result {
+            let mut buffer = BUFFER.lock().unwrap();
+            for line in batch.into_iter().rev() {
+                if buffer.len() >= MAX_BUFFERED {
+                    break;
+                }
+                buffer.push_front(line);
+            }
+            return Err(e);
+        }
+    }
+}
+
+async fn send_syslog(address: &str, batch: &[ShippedLine]) -> Result<(), Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(address).await?;
+    let hostname = env::cabinet_name();
+    for line in batch {
+        socket
+            .send(syslog_message(line, hostname.as_str()).as_bytes())
+            .await?;
+    }
+    Ok(())
+}
+
+/**
+ * Format a line as an RFC 5424 syslog message
+ */
+fn syslog_message(line: &ShippedLine, hostname: &str) -> String {
+    let severity = match line.level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    };
+    format!(
+        "<{}>1 {} {hostname} {APP_NAME} {} - - [{}] {}",
+        SYSLOG_FACILITY * 8 + severity,
+        rfc3339(line.timestamp_ms),
+        std::process::id(),
+        line.source,
+        line.message
+    )
+}
+
+/**
+ * Build a Loki push request for a batch, with a stream for each level
+ */
+fn loki_streams(batch: &[ShippedLine]) -> serde_json::Value {
+    let mut streams: BTreeMap<String, Vec<[String; 2]>> = BTreeMap::new();
+    for line in batch {
+        streams
+            .entry(line.level.as_str().to_ascii_lowercase())
+            .or_default()
+            .push([
+                (u128::from(line.timestamp_ms) * 1_000_000).to_string(),
+                format!("[{}] {}", line.source, line.message),
+            ]);
+    }
+    let cabinet = env::cabinet_name();
+    serde_json::json!({
+        "streams": streams
+            .into_iter()
+            .map(|(level, values)| serde_json::json!({
+                "stream": { "job": APP_NAME, "cabinet": cabinet, "level": level },
+                "values": values,
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/**
+ * Format a time in milliseconds since the unix epoch as an RFC 3339 UTC timestamp
+ */
+fn rfc3339(timestamp_ms: u64) -> String {
+    let secs = timestamp_ms / 1000;
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+    // Howard Hinnant's days-to-civil algorithm, for days since 1970-01-01
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        timestamp_ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syslog_messages_have_a_priority_and_timestamp() {
+        let line = ShippedLine {
+            timestamp_ms: 1_700_000_000_123,
+            level: Level::Warn,
+            source: String::from("backend::nfc"),
+            message: String::from("Reader went away"),
+        };
+        assert_eq!(
+            syslog_message(&line, "cabinet"),
+            format!(
+                "<12>1 2023-11-14T22:13:20.123Z cabinet devcade-onboard {} - - [backend::nfc] Reader went away",
+                std::process::id()
+            )
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use crate::env::{self, LogFormat};
+use crate::log_files::{self, LogFileWriter};
+use crate::log_shipping;
+use lazy_static::lazy_static;
+use log::{Level, LevelFilter};
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_log::{LogTracer, NormalizeEvent};
+use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+
+/**
+ * How many lines can be buffered for a subscriber before it starts missing them
+ */
+const STREAM_BUFFER_SIZE: usize = 1024;
+
+/**
+ * The most verbose level that can be streamed. Trace logs include things like game environments,
+ * so they're never streamed.
+ */
+const MAX_STREAM_LEVEL: LevelFilter = LevelFilter::Debug;
+
+/**
+ * How many of the most recent lines are kept in memory, for when something goes wrong before
+ * anyone was tailing the logs
+ */
+const RECENT_LINES: usize = 500;
+
+/**
+ * The most verbose level that's kept in memory
+ */
+const MAX_RECENT_LEVEL: LevelFilter = LevelFilter::Info;
+
+/**
+ * The most verbose level that's written to log files
+ */
+const MAX_FILE_LEVEL: tracing::level_filters::LevelFilter =
+    tracing::level_filters::LevelFilter::INFO;
+
+lazy_static! {
+    static ref LINES: broadcast::Sender<LogLine> = broadcast::channel(STREAM_BUFFER_SIZE).0;
+    static ref RECENT: Mutex<AllocRingBuffer<String>> =
+        Mutex::new(AllocRingBuffer::new(RECENT_LINES));
+}
+
+/**
+ * A single line of backend or game output, as sent to log subscribers
+ */
+#[derive(Clone, Debug, Serialize)]
+pub struct LogLine {
+    /**
+     * When the line was logged, in milliseconds since the unix epoch
+     */
+    pub timestamp_ms: u64,
+    pub level: Level,
+    /**
+     * Where the line came from: the module path for backend logs, or `game:<id>` for game output
+     */
+    pub source: String,
+    pub message: String,
+}
+
+/**
+ * A layer that streams events to anyone tailing the logs, keeps the most recent ones in memory,
+ * and queues warnings and errors to be shipped. Streaming ignores `RUST_LOG`, so subscribers can
+ * see debug logs even when the console only shows warnings.
+ */
+struct StreamLayer;
+
+impl<S: Subscriber> Layer<S> for StreamLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // Events from the `log` macros are normalized to where they were logged, not `log` itself
+        let metadata = event.normalized_metadata();
+        let metadata = metadata.as_ref().unwrap_or_else(|| event.metadata());
+        let level = as_log_level(*metadata.level());
+        let source = metadata.module_path().unwrap_or(metadata.target());
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+        let message = message.0;
+
+        if level <= MAX_RECENT_LEVEL {
+            RECENT
+                .lock()
+                .unwrap()
+                .push(format!("[{level} {source}] {message}"));
+        }
+        log_shipping::queue(level, source, message.as_str());
+        if level <= MAX_STREAM_LEVEL {
+            publish(level, source, message);
+        }
+    }
+}
+
+/**
+ * Collects an event's message, followed by any other fields as `name=value`
+ */
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, format!("{value:?}").as_str());
+        } else if !field.name().starts_with("log.") {
+            self.0
+                .push_str(format!(" {}={value:?}", field.name()).as_str());
+        }
+    }
+}
+
+fn as_log_level(level: tracing::Level) -> Level {
+    match level {
+        tracing::Level::ERROR => Level::Error,
+        tracing::Level::WARN => Level::Warn,
+        tracing::Level::INFO => Level::Info,
+        tracing::Level::DEBUG => Level::Debug,
+        tracing::Level::TRACE => Level::Trace,
+    }
+}
+
+/**
+ * Install the backend's logging. Logs are written to the console, filtered by `RUST_LOG` (errors
+ * only by default), as text or as JSON with the spans they were logged in, as picked by
+ * `DEVCADE_LOG_FORMAT`. If log files are turned on, info logs and above are written to them too,
+ * in the same format. Lines logged with the `log` macros are sent through `tracing` too. Must be
+ * called once at startup.
+ *
+ * # Panics
+ * This function panics if logging has already been installed.
+ */
+pub fn init() {
+    LogTracer::init().expect("Logger was already set");
+    let filter = EnvFilter::builder()
+        .with_default_directive(tracing::level_filters::LevelFilter::ERROR.into())
+        .from_env_lossy();
+    let format = env::log_format();
+    let console = match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    };
+    let file = log_files::init().then(|| match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(LogFileWriter)
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_writer(LogFileWriter)
+            .boxed(),
+    });
+    tracing_subscriber::registry()
+        .with(console.with_filter(filter))
+        .with(file.with_filter(MAX_FILE_LEVEL))
+        .with(StreamLayer.with_filter(tracing::level_filters::LevelFilter::DEBUG))
+        .init();
+}
+
+/**
+ * Send a line to everyone tailing the logs. Does nothing if nobody is subscribed.
+ */
+pub fn publish(level: Level, source: &str, message: String) {
+    if LINES.receiver_count() == 0 {
+        return;
+    }
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    // An error here only means the last subscriber just went away
+    let _ = LINES.send(LogLine {
+        timestamp_ms,
+        level,
+        source: source.to_string(),
+        message,
+    });
+}
+
+/**
+ * Subscribe to every line logged after this call.
+ */
+#[must_use]
+pub fn subscribe() -> broadcast::Receiver<LogLine> {
+    LINES.subscribe()
+}
+
+/**
+ * Get the most recent backend log lines at info level or above, oldest first.
+ */
+#[must_use]
+pub fn recent() -> Vec<String> {
+    RECENT.lock().unwrap().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_kept_with_their_fields() {
+        let subscriber = tracing_subscriber::registry().with(StreamLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(game_id = "pong", "Launching {}", "now");
+            tracing::debug!("Too verbose to keep");
+        });
+        let recent = recent();
+        assert!(recent
+            .iter()
+            .any(|line| line.ends_with("] Launching now game_id=\"pong\"")));
+        assert!(!recent.iter().any(|line| line.contains("Too verbose")));
+    }
+}
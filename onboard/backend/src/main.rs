@@ -1,8 +1,39 @@
-use backend::env::devcade_path;
-use backend::nfc::NFC_CLIENT;
-use backend::servers::path::{game_pipe, onboard_pipe};
+use backend::achievements;
+use backend::analytics;
+use backend::api::cache;
+use backend::attract;
+use backend::audio;
+use backend::broken_games;
+use backend::cabinet;
+use backend::config_reload;
+use backend::env::{self, devcade_path};
+use backend::favorites;
+use backend::guests;
+use backend::history;
+use backend::install_history;
+use backend::install_queue;
+use backend::install_state;
+use backend::installed_watcher;
+use backend::leaderboards;
+use backend::log_shipping;
+use backend::log_stream;
+use backend::migrations;
+use backend::nfc::NFC_CLIENTS;
+use backend::play_stats;
+use backend::profile;
+use backend::ratings;
+use backend::readiness;
+use backend::removal;
+use backend::safe_mode;
+use backend::save_flush;
+use backend::save_sync;
+use backend::self_update;
+use backend::servers::path::{control_pipe, game_pipe, onboard_pipe};
 use backend::servers::ThreadHandles;
-use log::{log, Level};
+use backend::storage;
+use backend::ticker;
+use backend::update_window;
+use std::path::Path;
 use tokio::fs;
 
 #[tokio::main]
@@ -15,36 +46,160 @@ async fn main() -> ! {
     match dotenvy::from_filename("../.env") {
         Ok(_) => (),
         Err(e) => {
-            log!(Level::Error, "Error loading .env file: {}", e);
+            tracing::error!("Error loading .env file: {}", e);
         }
     }
-    env_logger::init();
+    let config_path = env::config_path();
+    let config = env::load_config(config_path.as_path());
+    log_stream::init();
+    let config = config.expect("Invalid config file");
+    tracing::info!("Running as {}", env::environment().name());
+    tracing::info!(
+        "Read {} settings from {}",
+        config.vars().len(),
+        config_path.display()
+    );
+    for (name, value) in config.vars() {
+        if std::env::var(name).is_ok_and(|set| set != value) {
+            tracing::info!(
+                "{} is set in the environment, overriding the config file",
+                name
+            );
+        }
+    }
+
+    let attempts = safe_mode::begin_startup();
+    if safe_mode::in_crash_loop(attempts) {
+        safe_mode::run(attempts).await;
+    }
+
+    for warning in profile::validate().expect("Invalid hardware profile") {
+        tracing::warn!("Profile {}: {}", env::profile_name(), warning);
+    }
 
     fs::create_dir_all(devcade_path())
         .await
         .expect("Couldn't create devcade dir");
 
+    let layout_version = migrations::run_migrations(
+        Path::new(devcade_path().as_str()),
+        env::migrations_dry_run(),
+    )
+    .expect("Couldn't migrate devcade dir");
+    tracing::debug!("Devcade dir is at layout version {}", layout_version);
+    if !env::migrations_dry_run() {
+        // Pick up games added or removed while the backend wasn't running
+        if let Err(e) = storage::reconcile(Path::new(devcade_path().as_str())) {
+            tracing::warn!("Couldn't reconcile the devcade manifest: {}", e);
+        }
+    }
+
+    if let Err(e) = cabinet::load().await {
+        tracing::warn!("Couldn't load the cabinet's identity: {}", e);
+    }
+    install_history::load().await;
+    play_stats::load().await;
+    history::load().await;
+    analytics::load().await;
+    broken_games::load().await;
+    favorites::load().await;
+    ratings::load().await;
+    guests::load().await;
+    audio::load().await;
+    leaderboards::load().await;
+    achievements::load().await;
+
+    tokio::spawn(readiness::run());
+    tokio::spawn(cabinet::run());
+    tokio::spawn(cache::watch_events());
+    tokio::spawn(install_queue::run());
+    match install_state::recover().await {
+        Ok(game_ids) => {
+            for game_id in game_ids {
+                tokio::spawn(async move {
+                    if let Err(e) = install_queue::install(game_id.clone()).await {
+                        tracing::warn!("Couldn't resume install of {}: {}", game_id, e);
+                    }
+                });
+            }
+        }
+        Err(e) => tracing::warn!("Couldn't look for interrupted installs: {}", e),
+    }
+    tokio::spawn(removal::run());
+    tokio::spawn(ticker::run());
+    tokio::spawn(save_sync::run());
+    tokio::spawn(save_flush::run());
+    tokio::spawn(leaderboards::run());
+    tokio::spawn(achievements::run());
+    tokio::spawn(analytics::run());
+    tokio::spawn(ratings::run());
+    tokio::spawn(log_shipping::run());
+    tokio::spawn(config_reload::run());
+    tokio::spawn(self_update::run());
+    tokio::spawn(update_window::run());
+    tokio::spawn(attract::run());
+    tokio::spawn(save_flush::flush_on_terminate());
+    tokio::spawn(async {
+        if let Err(err) = installed_watcher::run().await {
+            tracing::error!("Sideloaded game watcher stopped: {}", err);
+        }
+    });
+
     let mut handles: ThreadHandles = ThreadHandles::new();
 
     handles.restart_onboard(onboard_pipe());
 
     handles.restart_game(game_pipe());
 
+    handles.restart_control(control_pipe());
+
+    let admin_address = env::admin_address();
+    if let Some(address) = admin_address {
+        handles.restart_admin(address);
+    }
+
+    let metrics_address = env::metrics_address();
+    if let Some(address) = metrics_address {
+        handles.restart_metrics(address);
+    }
+
+    tokio::spawn(safe_mode::mark_stable());
+
     // Main loop
     loop {
         tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
         // Check if any of the handles have finished
         if let Some(err) = handles.onboard_error() {
-            log!(Level::Error, "Onboard thread has panicked: {}", err);
+            tracing::error!("Onboard thread has panicked: {}", err);
             handles.restart_onboard(onboard_pipe());
         }
         if let Some(err) = handles.game_error() {
-            log!(Level::Error, "Game thread has panicked: {}", err);
+            tracing::error!("Game thread has panicked: {}", err);
             handles.restart_game(game_pipe());
         }
-        if let Some(err) = NFC_CLIENT.nfc_error() {
-            log!(Level::Error, "Gatekeeper thread has panicked: {:?}", err);
-            NFC_CLIENT.restart();
+        if let Some(err) = handles.control_error() {
+            tracing::error!("Control thread has panicked: {}", err);
+            handles.restart_control(control_pipe());
+        }
+        if let Some(err) = handles.admin_error() {
+            tracing::error!("Admin thread has panicked: {}", err);
+            // Unwrap rationale: the admin thread is only started when there's an address
+            handles.restart_admin(admin_address.unwrap());
+        }
+        if let Some(err) = handles.metrics_error() {
+            tracing::error!("Metrics thread has panicked: {}", err);
+            // Unwrap rationale: the metrics thread is only started when there's an address
+            handles.restart_metrics(metrics_address.unwrap());
+        }
+        for client in NFC_CLIENTS.iter() {
+            if let Some(err) = client.nfc_error() {
+                tracing::error!(
+                    "Gatekeeper thread for {} has panicked: {:?}",
+                    client.player(),
+                    err
+                );
+                client.restart();
+            }
         }
     }
 }
@@ -0,0 +1,195 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+lazy_static! {
+    static ref COUNTERS: Mutex<HashMap<Counter, u64>> = Mutex::new(HashMap::new());
+    static ref HISTOGRAMS: Mutex<HashMap<Histogram, Observations>> = Mutex::new(HashMap::new());
+}
+
+/**
+ * Something the backend counts, like bytes downloaded
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Counter {
+    DownloadBytes,
+    NfcFailures,
+    GameCrashes,
+}
+
+impl Counter {
+    const ALL: [Counter; 3] = [
+        Counter::DownloadBytes,
+        Counter::NfcFailures,
+        Counter::GameCrashes,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Counter::DownloadBytes => "devcade_download_bytes_total",
+            Counter::NfcFailures => "devcade_nfc_failures_total",
+            Counter::GameCrashes => "devcade_game_crashes_total",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Counter::DownloadBytes => "Bytes downloaded from the API",
+            Counter::NfcFailures => "Badge reads and user lookups that failed",
+            Counter::GameCrashes => "Game sessions that ended in a crash",
+        }
+    }
+}
+
+/**
+ * Something the backend times, like API requests
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Histogram {
+    ApiLatency,
+    InstallDuration,
+    SaveFlushDuration,
+}
+
+impl Histogram {
+    const ALL: [Histogram; 3] = [
+        Histogram::ApiLatency,
+        Histogram::InstallDuration,
+        Histogram::SaveFlushDuration,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Histogram::ApiLatency => "devcade_api_request_seconds",
+            Histogram::InstallDuration => "devcade_install_seconds",
+            Histogram::SaveFlushDuration => "devcade_save_flush_seconds",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Histogram::ApiLatency => "How long the API took to respond",
+            Histogram::InstallDuration => "How long flatpak took to install a game's bundle",
+            Histogram::SaveFlushDuration => "How long writing changed saves to storage took",
+        }
+    }
+
+    /**
+     * The upper bounds of the buckets observations are counted in, in seconds
+     */
+    fn buckets(self) -> &'static [f64] {
+        match self {
+            Histogram::ApiLatency => &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0],
+            Histogram::InstallDuration => &[5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0],
+            Histogram::SaveFlushDuration => &[0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0],
+        }
+    }
+}
+
+/**
+ * Everything observed by a histogram so far
+ */
+#[derive(Default)]
+struct Observations {
+    /**
+     * How many observations were at or under each bucket's bound, in the order of the bounds
+     */
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+/**
+ * Add to a counter
+ */
+pub fn count(counter: Counter, by: u64) {
+    *COUNTERS.lock().unwrap().entry(counter).or_default() += by;
+}
+
+/**
+ * Record how long something took in a histogram
+ */
+pub fn observe(histogram: Histogram, took: Duration) {
+    HISTOGRAMS
+        .lock()
+        .unwrap()
+        .entry(histogram)
+        .or_default()
+        .add(histogram, took.as_secs_f64());
+}
+
+impl Observations {
+    fn add(&mut self, histogram: Histogram, secs: f64) {
+        self.buckets.resize(histogram.buckets().len(), 0);
+        for (bound, count) in histogram.buckets().iter().zip(&mut self.buckets) {
+            if secs <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+}
+
+/**
+ * Render every metric in the Prometheus text format
+ */
+#[must_use]
+pub fn render() -> String {
+    let mut out = String::new();
+    let counters = COUNTERS.lock().unwrap();
+    for counter in Counter::ALL {
+        let name = counter.name();
+        let value = counters.get(&counter).copied().unwrap_or_default();
+        let _ = writeln!(out, "# HELP {name} {}", counter.help());
+        let _ = writeln!(out, "# TYPE {name} counter");
+        let _ = writeln!(out, "{name} {value}");
+    }
+    drop(counters);
+
+    let histograms = HISTOGRAMS.lock().unwrap();
+    for histogram in Histogram::ALL {
+        let empty = Observations::default();
+        write_histogram(
+            &mut out,
+            histogram,
+            histograms.get(&histogram).unwrap_or(&empty),
+        );
+    }
+    out
+}
+
+fn write_histogram(out: &mut String, histogram: Histogram, observations: &Observations) {
+    let name = histogram.name();
+    let _ = writeln!(out, "# HELP {name} {}", histogram.help());
+    let _ = writeln!(out, "# TYPE {name} histogram");
+    for (i, bound) in histogram.buckets().iter().enumerate() {
+        let count = observations.buckets.get(i).copied().unwrap_or_default();
+        let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {count}");
+    }
+    let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", observations.count);
+    let _ = writeln!(out, "{name}_sum {}", observations.sum);
+    let _ = writeln!(out, "{name}_count {}", observations.count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_histogram_buckets() {
+        let mut observations = Observations::default();
+        observations.add(Histogram::SaveFlushDuration, 0.003);
+        observations.add(Histogram::SaveFlushDuration, 10.0);
+        let mut rendered = String::new();
+        write_histogram(&mut rendered, Histogram::SaveFlushDuration, &observations);
+        assert!(rendered.contains("# TYPE devcade_save_flush_seconds histogram\n"));
+        assert!(rendered.contains("devcade_save_flush_seconds_bucket{le=\"0.001\"} 0\n"));
+        assert!(rendered.contains("devcade_save_flush_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(rendered.contains("devcade_save_flush_seconds_bucket{le=\"5\"} 1\n"));
+        assert!(rendered.contains("devcade_save_flush_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(rendered.contains("devcade_save_flush_seconds_count 2\n"));
+    }
+}
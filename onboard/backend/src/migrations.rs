@@ -0,0 +1,254 @@
+use crate::storage::{self, Manifest};
+use anyhow::{anyhow, Error};
+use std::path::Path;
+
+/**
+ * The file (relative to the devcade path) that stored the layout version before it moved into the
+ * manifest. It's still read from directories that haven't been migrated to a manifest yet.
+ */
+const LEGACY_VERSION_FILE: &str = "layout_version";
+
+/**
+ * A single numbered change to the on-disk layout of the devcade directory.
+ */
+struct Migration {
+    /**
+     * The layout version the directory will be at after this migration has run. Versions must be
+     * unique and increasing.
+     */
+    version: u32,
+
+    /**
+     * A short, human readable description of what the migration changes
+     */
+    description: &'static str,
+
+    /**
+     * Performs the migration on the devcade directory. When the second argument is true, this
+     * should only log what it would do without touching the filesystem.
+     */
+    run: fn(&Path, bool) -> Result<(), Error>,
+}
+
+/**
+ * All migrations, in the order they must be run
+ */
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description:
+            "Initial layout: one directory per game holding game.json, the bundle and assets",
+        run: |_, _| Ok(()),
+    },
+    Migration {
+        version: 2,
+        description: "Add manifest.json with the layout version and an inventory of every game",
+        run: |path, dry_run| {
+            if dry_run {
+                tracing::info!("Would inventory every game in {:?}", path);
+                return Ok(());
+            }
+            storage::reconcile(path)
+        },
+    },
+];
+
+/**
+ * Get the layout version the devcade directory is currently at, from its manifest or the legacy
+ * version file if it doesn't have one yet. A directory that has never been migrated is at version
+ * 0.
+ */
+fn current_version(devcade_path: &Path) -> Result<u32, Error> {
+    if let Some(manifest) = Manifest::read(devcade_path)? {
+        return Ok(manifest.layout_version);
+    }
+    let path = devcade_path.join(LEGACY_VERSION_FILE);
+    if !path.exists() {
+        return Ok(0);
+    }
+    let version = std::fs::read_to_string(&path)?;
+    version
+        .trim()
+        .parse()
+        .map_err(|e| anyhow!("Invalid layout version in {}: {}", path.display(), e))
+}
+
+/**
+ * Record the layout version in the manifest, removing the legacy version file now that it's been
+ * superseded
+ */
+fn store_version(devcade_path: &Path, version: u32) -> Result<(), Error> {
+    storage::update_manifest(devcade_path, |manifest| {
+        manifest.layout_version = version;
+        Ok(())
+    })?;
+    let legacy = devcade_path.join(LEGACY_VERSION_FILE);
+    if legacy.exists() {
+        std::fs::remove_file(legacy)?;
+    }
+    Ok(())
+}
+
+/**
+ * Run every migration newer than the current layout version of `devcade_path`, recording the new
+ * version after each one so an interrupted upgrade resumes where it left off. In dry-run mode
+ * migrations only log what they would do and the stored version is left untouched.
+ *
+ * Returns the layout version the directory is at afterwards.
+ *
+ * # Errors
+ * This function will return an error if the stored version can't be read or written, if the stored
+ * version is newer than this build knows about, or if any migration fails.
+ */
+pub fn run_migrations(devcade_path: &Path, dry_run: bool) -> Result<u32, Error> {
+    apply(devcade_path, MIGRATIONS, dry_run)
+}
+
+fn apply(devcade_path: &Path, migrations: &[Migration], dry_run: bool) -> Result<u32, Error> {
+    let mut version = current_version(devcade_path)?;
+    let latest = migrations.last().map_or(0, |m| m.version);
+    if version > latest {
+        return Err(anyhow!(
+            "Devcade directory is at layout version {version}, but this build only knows up to {latest}"
+        ));
+    }
+
+    let start = version;
+    for migration in migrations.iter().filter(|m| m.version > start) {
+        tracing::info!(
+            "{}Migrating devcade directory to layout version {}: {}",
+            if dry_run { "[dry run] " } else { "" },
+            migration.version,
+            migration.description
+        );
+        (migration.run)(devcade_path, dry_run)?;
+        if dry_run {
+            continue;
+        }
+        version = migration.version;
+        store_version(devcade_path, version)?;
+    }
+
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /**
+     * Create an empty directory to run migrations against
+     */
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("devcade-migrations-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn stored_version(dir: &Path) -> Option<u32> {
+        Manifest::read(dir)
+            .unwrap()
+            .map(|manifest| manifest.layout_version)
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            description: "Create a marker file",
+            run: |path, dry_run| {
+                if !dry_run {
+                    std::fs::write(path.join("one"), b"")?;
+                }
+                Ok(())
+            },
+        },
+        Migration {
+            version: 2,
+            description: "Fail while the interruption marker exists",
+            run: |path, _| {
+                if path.join("fail").exists() {
+                    return Err(anyhow!("Simulated interruption"));
+                }
+                Ok(())
+            },
+        },
+    ];
+
+    #[test]
+    fn fresh_directory_migrates_to_latest() {
+        let dir = temp_dir("fresh");
+        assert_eq!(run_migrations(&dir, false).unwrap(), 2);
+        assert_eq!(stored_version(&dir), Some(2));
+        // Running again is a no-op
+        assert_eq!(run_migrations(&dir, false).unwrap(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn interrupted_migration_resumes() {
+        let dir = temp_dir("resume");
+        std::fs::write(dir.join("fail"), b"").unwrap();
+        assert!(apply(&dir, TEST_MIGRATIONS, false).is_err());
+        // The first migration was recorded before the second failed
+        assert_eq!(stored_version(&dir), Some(1));
+
+        std::fs::remove_file(dir.join("one")).unwrap();
+        std::fs::remove_file(dir.join("fail")).unwrap();
+        assert_eq!(apply(&dir, TEST_MIGRATIONS, false).unwrap(), 2);
+        // The first migration wasn't run again
+        assert!(!dir.join("one").exists());
+        assert_eq!(stored_version(&dir), Some(2));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn newer_stored_version_is_an_error() {
+        let dir = temp_dir("newer");
+        store_version(&dir, 3).unwrap();
+        assert!(apply(&dir, TEST_MIGRATIONS, false).is_err());
+        assert_eq!(stored_version(&dir), Some(3));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dry_run_leaves_directory_untouched() {
+        let dir = temp_dir("dry-run");
+        assert_eq!(apply(&dir, TEST_MIGRATIONS, true).unwrap(), 0);
+        assert_eq!(stored_version(&dir), None);
+        assert!(!dir.join("one").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn legacy_version_moves_into_manifest() {
+        let dir = temp_dir("legacy");
+        std::fs::write(dir.join(LEGACY_VERSION_FILE), b"1").unwrap();
+        // Only the second migration runs, since the legacy file says the first already has
+        assert_eq!(apply(&dir, TEST_MIGRATIONS, false).unwrap(), 2);
+        assert!(!dir.join("one").exists());
+        assert!(!dir.join(LEGACY_VERSION_FILE).exists());
+        assert_eq!(stored_version(&dir), Some(2));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn manifest_migration_inventories_games() {
+        let dir = temp_dir("manifest");
+        std::fs::create_dir_all(dir.join("game")).unwrap();
+        std::fs::write(dir.join("game").join(storage::GAME_JSON), b"{}").unwrap();
+        // Directories without a game.json aren't games
+        std::fs::create_dir_all(dir.join("partial")).unwrap();
+        std::fs::write(dir.join(LEGACY_VERSION_FILE), b"1").unwrap();
+
+        assert_eq!(run_migrations(&dir, false).unwrap(), 2);
+        let manifest = Manifest::read(&dir).unwrap().unwrap();
+        assert_eq!(manifest.games.len(), 1);
+        let entry = &manifest.games["game"].files[storage::GAME_JSON];
+        assert_eq!(entry.size, 2);
+        assert_eq!(entry.sha256, sha256::digest("{}"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,65 +1,480 @@
+use crate::api::cache::TtlCache;
 use crate::api::current_game;
-use devcade_onboard_types::{Map, Value};
+use crate::attract;
+use crate::events;
+use crate::metrics::{self, Counter};
+use crate::nfc_mock::MockNfcClient;
+use devcade_onboard_types::schema::{NfcRealm, NfcStatus, ReaderStatus};
+use devcade_onboard_types::{Event, Map, Player, Value};
 use gatekeeper_members::{GateKeeperMemberListener, RealmType};
 use lazy_static::lazy_static;
 use ringbuffer::{AllocRingBuffer, RingBuffer};
 use std::any::Any;
+use std::fmt::Display;
+use std::path::Path;
 use std::sync::mpsc;
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::Arc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, PoisonError};
 use std::thread;
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
 use tokio::sync::oneshot;
 use tokio::sync::Mutex;
 
 type NfcCallback = oneshot::Sender<Option<String>>;
+
+/**
+ * How long to wait before the first attempt to reconnect a reader. Each failed attempt doubles the
+ * wait, up to `MAX_BACKOFF`.
+ */
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/**
+ * How often a connected reader that isn't open is checked for being unplugged
+ */
+const PRESENCE_INTERVAL: Duration = Duration::from_secs(5);
+
+/**
+ * How often readers are polled in subscription mode, between whatever time the poll itself takes
+ */
+const SUBSCRIBE_INTERVAL: Duration = Duration::from_millis(100);
+
+/**
+ * The connection string that selects a pretend reader, for machines without a real one
+ */
+pub const MOCK_DEVICE: &str = "mock";
+
+/**
+ * Something badges can be read from, either a real reader or a pretend one
+ */
+pub trait NfcBackend {
+    /**
+     * Check for a badge on the reader once, getting its association ID
+     */
+    fn poll_for_user(&mut self) -> Option<String>;
+
+    /**
+     * Look up the user a badge belongs to
+     */
+    fn fetch_user(&self, association_id: String) -> Option<Map<String, Value>>;
+}
+
+impl NfcBackend for GateKeeperMemberListener<'static> {
+    fn poll_for_user(&mut self) -> Option<String> {
+        GateKeeperMemberListener::poll_for_user(self)
+    }
+
+    fn fetch_user(&self, association_id: String) -> Option<Map<String, Value>> {
+        GateKeeperMemberListener::fetch_user(self, association_id)
+            .ok()
+            .and_then(|user| user["user"].as_object().cloned())
+    }
+}
+
+/**
+ * The parts of a user games are given: their username, display name and avatar. Guests also keep
+ * what marks them as guests. Everything else is only for the trusted frontend.
+ */
+const GAME_USER_FIELDS: [&str; 5] = ["uid", "cn", "avatar", "guest", "expires_at"];
+
+/**
+ * Where saves go when a game saves for a player who isn't logged in
+ */
+const GUEST_NAMESPACE: &str = "guest";
+
+/**
+ * A badge reader, and the thread that talks to it
+ */
 pub struct NfcClient {
+    player: Player,
     request_queue: Mutex<Sender<NfcRequest>>,
     thread: std::sync::Mutex<Option<JoinHandle<()>>>,
     receiver: Arc<std::sync::Mutex<Receiver<NfcRequest>>>,
+    status: Arc<std::sync::Mutex<NfcStatus>>,
+    /**
+     * Taps not yet given to the running game, in subscription mode
+     */
+    game_taps: Mutex<broadcast::Receiver<BadgeTap>>,
+    /**
+     * Users fetched from Gatekeeper, by badge handle
+     */
+    users: TtlCache<String, Map<String, Value>>,
+    /**
+     * A player who logged in without their badge, waiting to be given to the running game
+     */
+    login: std::sync::Mutex<Option<String>>,
+}
+
+/**
+ * A badge tapped on a reader, as sent to subscribers in subscription mode
+ */
+#[derive(Clone, Debug)]
+pub struct BadgeTap {
+    pub player: Player,
+    /**
+     * The handle the badge is known by to the game that was running when it was tapped
+     */
+    pub handle: String,
+    /**
+     * The game that was running when the badge was tapped, or `None` if it was tapped in the menu
+     */
+    pub game_id: Option<String>,
 }
 
 enum NfcRequest {
     Tags {
+        realm: NfcRealm,
         callback: NfcCallback,
     },
     User {
+        realm: NfcRealm,
         association_id: String,
         callback: oneshot::Sender<Option<Map<String, Value>>>,
     },
 }
 
+/**
+ * A request couldn't be answered because the reader's thread panicked. The thread is restarted by
+ * the main loop, so the request can be retried shortly.
+ */
+#[derive(Debug)]
+pub struct ReaderCrashed {
+    pub player: Player,
+}
+
+impl Display for ReaderCrashed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let player = self.player.to_string();
+        let message = crate::i18n::tr("nfc_reader_crashed", &[("player", player.as_str())]);
+        write!(f, "{message}")
+    }
+}
+
+impl std::error::Error for ReaderCrashed {}
+
+impl NfcRequest {
+    fn realm(&self) -> NfcRealm {
+        match self {
+            NfcRequest::Tags { realm, .. } | NfcRequest::User { realm, .. } => *realm,
+        }
+    }
+}
+
+/**
+ * A badge tapped on one of the readers. Games are only given a handle for the badge, so the real
+ * association ID is kept here to look the user up with later.
+ */
+struct Tap {
+    handle: String,
+    association_id: String,
+    /**
+     * The game the handle was made for, or `None` for the menu
+     */
+    game_id: Option<String>,
+    player: Player,
+    /**
+     * The realm the badge was read in, which its association ID belongs to
+     */
+    realm: NfcRealm,
+    /**
+     * When the badge was last tapped. Handles expire a while after this.
+     */
+    at: Instant,
+}
+
 lazy_static! {
-    pub static ref NFC_CLIENT: NfcClient = Default::default();
+    /**
+     * The badge reader for each player, in player order
+     */
+    pub static ref NFC_CLIENTS: [NfcClient; 2] =
+        [NfcClient::new(Player::P1), NfcClient::new(Player::P2)];
+
+    /**
+     * The most recent badge taps on any reader
+     */
+    static ref TAPS: std::sync::Mutex<AllocRingBuffer<Tap>> =
+        std::sync::Mutex::new(AllocRingBuffer::new(8));
+
+    /**
+     * Badge taps on any reader, in subscription mode
+     */
+    static ref TAP_CHANNEL: broadcast::Sender<BadgeTap> = broadcast::channel(16).0;
+}
+
+/**
+ * Subscribe to badge taps on any reader from now on. Taps are only sent in subscription mode, where
+ * readers are polled continuously instead of when a game asks for a tag.
+ */
+#[must_use]
+pub fn subscribe() -> broadcast::Receiver<BadgeTap> {
+    TAP_CHANNEL.subscribe()
+}
+
+/**
+ * Get how every badge reader has been doing, in player order
+ */
+#[must_use]
+pub fn nfc_status() -> Vec<NfcStatus> {
+    NFC_CLIENTS.iter().map(NfcClient::health).collect()
+}
+
+/**
+ * Get the badge reader for a player
+ */
+#[must_use]
+pub fn nfc_client(player: &Player) -> &'static NfcClient {
+    &NFC_CLIENTS[usize::from(u8::from(player.clone()))]
+}
+
+/**
+ * Look up the user behind a badge handle, on the reader the badge was tapped on.
+ *
+ * # Errors
+ * This function will return an error if the handle isn't from a recent tap, or the user can't be
+ * fetched.
+ */
+#[tracing::instrument(skip_all)]
+pub async fn get_user(handle: String) -> Result<Map<String, Value>, anyhow::Error> {
+    let tap = TAPS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|tap| tap.handle == handle && tap.at.elapsed() < crate::env::nfc_handle_ttl())
+        .map(|tap| (tap.association_id.clone(), tap.player.clone(), tap.realm));
+    let Some((association_id, player, realm)) = tap else {
+        return Err(anyhow::anyhow!(crate::i18n::tr("nfc_user_not_found", &[])));
+    };
+    let user = nfc_client(&player)
+        .get_user(handle, association_id, realm)
+        .await;
+    if user.is_err() {
+        metrics::count(Counter::NfcFailures, 1);
+    }
+    user
+}
+
+/**
+ * Strip a user down to what games are allowed to see, so they can't harvest members' personal
+ * details
+ */
+#[must_use]
+pub fn for_game(user: Map<String, Value>) -> Map<String, Value> {
+    user.into_iter()
+        .filter(|(field, _)| GAME_USER_FIELDS.contains(&field.as_str()))
+        .collect()
+}
+
+/**
+ * Get the save data namespace for a badge handle, or the guest namespace when nobody's logged in.
+ * Handles are the same every time a badge is tapped in a game, so they can key a player's saves
+ * without games learning who the player is.
+ *
+ * # Errors
+ * This function will return an error if the handle couldn't have come from a tap.
+ */
+pub fn save_namespace(handle: Option<&str>) -> Result<&str, anyhow::Error> {
+    match handle {
+        None => Ok(GUEST_NAMESPACE),
+        Some(handle)
+            if handle.len() == 64 && handle.bytes().all(|byte| byte.is_ascii_hexdigit()) =>
+        {
+            Ok(handle)
+        }
+        Some(_) => Err(anyhow::anyhow!(crate::i18n::tr("nfc_handle_invalid", &[]))),
+    }
 }
 
-impl Default for NfcClient {
-    fn default() -> Self {
+/**
+ * Get the handle a game knows a badge by, given the handle it was given somewhere else, like the
+ * menu. Returns `None` if the handle isn't from a recent tap.
+ */
+#[must_use]
+pub fn handle_in_game(handle: &str, game_id: &str) -> Option<String> {
+    TAPS.lock()
+        .unwrap()
+        .iter()
+        .find(|tap| tap.handle == handle && tap.at.elapsed() < crate::env::nfc_handle_ttl())
+        .map(|tap| game_handle(tap.association_id.as_str(), game_id))
+}
+
+/**
+ * Get the handle of the badge tapped most recently, if it was tapped recently enough to be used
+ */
+#[must_use]
+pub fn latest_handle() -> Option<String> {
+    TAPS.lock()
+        .unwrap()
+        .iter()
+        .filter(|tap| tap.at.elapsed() < crate::env::nfc_handle_ttl())
+        .max_by_key(|tap| tap.at)
+        .map(|tap| tap.handle.clone())
+}
+
+/**
+ * Whether a handle is one the running game was given for a badge tapped in this session
+ */
+#[must_use]
+pub fn tapped_this_session(handle: &str) -> bool {
+    TAPS.lock().unwrap().iter().any(|tap| tap.handle == handle)
+}
+
+/**
+ * Get the handles the running game was given for the badges tapped in this session
+ */
+#[must_use]
+pub fn session_handles() -> Vec<String> {
+    let mut handles: Vec<String> = TAPS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|tap| tap.handle.clone())
+        .collect();
+    handles.sort();
+    handles.dedup();
+    handles
+}
+
+/**
+ * Forget a badge handle, so it can't be used to look its user up any more
+ */
+pub fn revoke_association(handle: &str) {
+    let mut taps = TAPS.lock().unwrap();
+    let kept: Vec<Tap> = taps.drain().filter(|tap| tap.handle != handle).collect();
+    for tap in kept {
+        taps.push(tap);
+    }
+    for client in NFC_CLIENTS.iter() {
+        client.users.remove(&String::from(handle));
+    }
+}
+
+/**
+ * Forget every badge handle. Called when a game launches, so it's never given a handle made for the
+ * menu, and when it exits, so the next player can't replay handles from the last session.
+ */
+pub fn clear_associations() {
+    TAPS.lock().unwrap().clear();
+    for client in NFC_CLIENTS.iter() {
+        client.users.clear();
+        *client.login.lock().unwrap() = None;
+    }
+}
+
+/**
+ * Log a player in without their badge, as if it was tapped on their reader, and get the handle
+ * the running game knows them by. The game is given the handle the next time it asks the reader
+ * for a tag.
+ */
+pub fn login(association_id: String, player: &Player) -> String {
+    let handle = broadcast_tap(association_id, player, crate::env::nfc_realm());
+    if !crate::env::nfc_subscribe() {
+        *nfc_client(player).login.lock().unwrap() = Some(handle.clone());
+    }
+    handle
+}
+
+impl NfcClient {
+    fn new(player: Player) -> Self {
         let (tx, rx) = mpsc::channel();
         let rx = Arc::new(std::sync::Mutex::new(rx));
+        let status = Arc::new(std::sync::Mutex::new(NfcStatus::new(player.clone())));
 
         NfcClient {
-            thread: std::sync::Mutex::new(Some(NfcClient::start_thread(Arc::clone(&rx)))),
+            thread: std::sync::Mutex::new(Some(NfcClient::start_thread(
+                player.clone(),
+                Arc::clone(&rx),
+                Arc::clone(&status),
+            ))),
+            player,
             request_queue: tx.into(),
             receiver: rx,
+            status,
+            game_taps: subscribe().into(),
+            users: TtlCache::with_ttl(crate::env::nfc_user_cache_ttl),
+            login: std::sync::Mutex::new(None),
         }
     }
-}
-
-const NFC_DEVICE_NAME: &str = "pn532_uart:/dev/ttyACM0";
 
-impl NfcClient {
-    fn start_thread(rx: Arc<std::sync::Mutex<Receiver<NfcRequest>>>) -> JoinHandle<()> {
+    fn start_thread(
+        player: Player,
+        rx: Arc<std::sync::Mutex<Receiver<NfcRequest>>>,
+        status: Arc<std::sync::Mutex<NfcStatus>>,
+    ) -> JoinHandle<()> {
         thread::spawn(move || {
-            NfcClient::run(rx);
+            NfcClient::run(player, rx, status);
         })
     }
 
+    /**
+     * The player this reader belongs to
+     */
+    #[must_use]
+    pub fn player(&self) -> &Player {
+        &self.player
+    }
+
+    /**
+     * Whether this reader is connected, for the frontend to show
+     */
+    #[must_use]
+    pub fn status(&self) -> ReaderStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .status
+    }
+
+    /**
+     * How this reader has been doing, for diagnosing it
+     */
+    #[must_use]
+    pub fn health(&self) -> NfcStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /**
+     * Restart the reader's thread after it panicked. Requests that were waiting for the old thread
+     * are dropped, which fails them with `ReaderCrashed` instead of leaving them to be answered
+     * late.
+     */
     pub fn restart(&self) {
         let mut handle_guard = self.thread.lock().unwrap();
         assert!(handle_guard.is_none());
-        *handle_guard = Some(Self::start_thread(Arc::clone(&self.receiver)));
+        let receiver = self.receiver.lock().unwrap_or_else(PoisonError::into_inner);
+        let dropped = receiver.try_iter().count();
+        drop(receiver);
+        if dropped > 0 {
+            log::warn!(
+                "Dropped {dropped} requests for player {} reader after it crashed",
+                self.player
+            );
+        }
+        *handle_guard = Some(Self::start_thread(
+            self.player.clone(),
+            Arc::clone(&self.receiver),
+            Arc::clone(&self.status),
+        ));
+    }
+
+    /**
+     * Whether the reader's thread is running. It isn't between panicking and being restarted.
+     */
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .lock()
+            .is_ok_and(|handle| handle.as_ref().is_some_and(|handle| !handle.is_finished()))
+    }
+
+    fn crashed(&self) -> ReaderCrashed {
+        ReaderCrashed {
+            player: self.player.clone(),
+        }
     }
 
     pub fn nfc_error(&self) -> Option<Box<dyn Any + Send + 'static>> {
@@ -75,109 +490,534 @@ impl NfcClient {
         None
     }
 
-    fn run(rx: Arc<std::sync::Mutex<Receiver<NfcRequest>>>) {
-        let mut association_ids: AllocRingBuffer<(String, String)> = AllocRingBuffer::new(8);
+    fn run(
+        player: Player,
+        rx: Arc<std::sync::Mutex<Receiver<NfcRequest>>>,
+        status: Arc<std::sync::Mutex<NfcStatus>>,
+    ) {
+        let mut reader = Reader {
+            player,
+            status,
+            listener: None,
+            device: None,
+            last_used: Instant::now(),
+            backoff: MIN_BACKOFF,
+            retry_at: None,
+            realm: crate::env::nfc_realm(),
+        };
+        reader.connect();
         loop {
-            // Unwrap rationale: If the main thread is crashed, not much we can do
-            let mut callback = rx.lock().unwrap().recv().unwrap();
-            // Unwrap rationale: If we can't allocate memory, we're not long for this world anyways
-            let mut listener = match GateKeeperMemberListener::new(
-                NFC_DEVICE_NAME.to_string(),
-                RealmType::MemberProjects,
-            ) {
-                Some(listener) => listener,
-                None => {
-                    log::error!("Couldn't build Gatekeeper listener?");
-                    // Unwrap rationale: If the main thread is crashed, not much we can do
-                    match callback {
-                        NfcRequest::User { callback, .. } => callback.send(None).unwrap(),
-                        NfcRequest::Tags { callback } => callback.send(None).unwrap(),
-                    }
+            let timeout = reader
+                .next_wakeup()
+                .saturating_duration_since(Instant::now());
+            // A previous thread that panicked while waiting leaves the lock poisoned, but the
+            // receiver itself is still fine
+            let request = match rx
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv_timeout(timeout)
+            {
+                Ok(request) => request,
+                Err(RecvTimeoutError::Timeout) => {
+                    reader.tick();
                     continue;
                 }
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+            reader.last_used = Instant::now();
+            if request.realm() != reader.realm {
+                // The listener is tied to a realm, so it's reopened in the one asked for
+                reader.realm = request.realm();
+                reader.listener = None;
+            }
+            if !reader.check() || reader.listener.is_none() {
+                reader.connect();
+            }
+            let Some(listener) = reader.listener.as_mut() else {
+                log::warn!("Player {} reader is disconnected", reader.player);
+                // Unwrap rationale: If the main thread is crashed, not much we can do
+                match request {
+                    NfcRequest::User { callback, .. } => callback.send(None).unwrap(),
+                    NfcRequest::Tags { callback, .. } => callback.send(None).unwrap(),
+                }
+                continue;
             };
 
-            loop {
-                match callback {
-                    NfcRequest::User {
-                        callback,
-                        association_id: association_handle,
-                    } => {
-                        let association_id =
-                            (&association_ids)
-                                .into_iter()
-                                .find_map(|(handle, association_id)| {
-                                    match handle == &association_handle {
-                                        true => Some(association_id),
-                                        false => None,
-                                    }
-                                });
-                        callback
-                            .send(
-                                association_id
-                                    .and_then(|association_id| {
-                                        listener.fetch_user(association_id.clone()).ok()
-                                    })
-                                    .and_then(|user| user["user"].as_object().cloned()),
-                            )
-                            .unwrap();
-                    }
-                    NfcRequest::Tags { callback } => {
-                        let association_id =
-                            listener
-                                .poll_for_user()
-                                .map(|association_id| {
-                                    match (&association_ids).into_iter().find(
-                                        |(_, candidate_association_id)| {
-                                            candidate_association_id == &association_id
-                                        },
-                                    ) {
-                                        Some((handle, _)) => handle.clone(),
-                                        None => {
-                                            let game_uuid = current_game().unwrap().id;
-                                            let handle = sha256::digest(format!(
-                                                "{association_id}:{game_uuid}"
-                                            ));
-                                            association_ids.push((handle.clone(), association_id));
-                                            handle
-                                        }
-                                    }
-                                });
-                        // Unwrap rationale: If the main thread is crashed, not much we can do
-                        callback.send(association_id).unwrap();
+            match request {
+                NfcRequest::User {
+                    callback,
+                    association_id,
+                    ..
+                } => {
+                    let user = listener.fetch_user(association_id);
+                    if user.is_none() {
+                        reader.record(|health| {
+                            health.lookup_failures += 1;
+                            health.last_error = Some(String::from("Couldn't fetch a badge's user"));
+                        });
                     }
+                    // Unwrap rationale: If the main thread is crashed, not much we can do
+                    callback.send(user).unwrap();
                 }
-
-                if let Ok(new_request) = rx.lock().unwrap().recv_timeout(Duration::from_secs(30)) {
-                    callback = new_request;
-                } else {
-                    break;
+                NfcRequest::Tags { callback, realm } => {
+                    // Keep polling until the timeout, but always poll at least once
+                    let deadline = Instant::now() + crate::env::nfc_poll_timeout();
+                    let association_id = loop {
+                        let association_id = listener.poll_for_user();
+                        if association_id.is_some() || Instant::now() >= deadline {
+                            break association_id;
+                        }
+                    };
+                    reader.record_poll(association_id.is_some());
+                    let handle = association_id
+                        .map(|association_id| tap(association_id, &reader.player, realm));
+                    // Unwrap rationale: If the main thread is crashed, not much we can do
+                    callback.send(handle).unwrap();
                 }
             }
         }
     }
-    pub async fn submit(&self) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    /**
+     * Read a badge in a realm, getting the handle the running game knows it by. In subscription
+     * mode, badges read in the default realm come from the taps the reader already saw.
+     */
+    pub async fn submit(
+        &self,
+        realm: NfcRealm,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        if crate::env::nfc_subscribe() && realm == crate::env::nfc_realm() {
+            return Ok(self.next_game_tap().await);
+        }
+        if realm == crate::env::nfc_realm() {
+            if let Some(handle) = self.login.lock().unwrap().take() {
+                return Ok(Some(handle));
+            }
+        }
+        if !self.is_running() {
+            return Err(Box::new(self.crashed()));
+        }
         let (tx, rx) = oneshot::channel();
 
-        self.request_queue
-            .lock()
-            .await
-            .send(NfcRequest::Tags { callback: tx })?;
-        Ok(rx.await?)
+        self.request_queue.lock().await.send(NfcRequest::Tags {
+            realm,
+            callback: tx,
+        })?;
+        // The callback is only dropped unanswered if the thread panicked
+        rx.await.map_err(|_| Box::new(self.crashed()) as _)
     }
-    pub async fn get_user(
+    /**
+     * Take the oldest tap on this reader the running game hasn't been given yet. Taps from before
+     * the game launched are skipped, since their handles belong to whatever was running then.
+     */
+    async fn next_game_tap(&self) -> Option<String> {
+        let game_id = current_game().map(|game| game.id);
+        let mut taps = self.game_taps.lock().await;
+        loop {
+            match taps.try_recv() {
+                Ok(tap) if tap.player == self.player && tap.game_id == game_id => {
+                    return Some(tap.handle)
+                }
+                Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => {}
+                Err(_) => return None,
+            }
+        }
+    }
+
+    async fn get_user(
         &self,
+        handle: String,
         association_id: String,
+        realm: NfcRealm,
     ) -> Result<Map<String, Value>, anyhow::Error> {
+        if let Some(user) = self.users.get(&handle) {
+            return Ok(user);
+        }
+        if !self.is_running() {
+            return Err(self.crashed().into());
+        }
         let (tx, rx) = oneshot::channel();
 
         self.request_queue.lock().await.send(NfcRequest::User {
+            realm,
             association_id,
             callback: tx,
         })?;
-        match rx.await? {
-            Some(user) => Ok(user),
-            None => Err(anyhow::anyhow!("User not found with that association ID")),
+        // The callback is only dropped unanswered if the thread panicked
+        match rx.await.map_err(|_| self.crashed())? {
+            Some(user) => {
+                self.users.insert(handle, user.clone());
+                Ok(user)
+            }
+            None => Err(anyhow::anyhow!(crate::i18n::tr("nfc_user_not_found", &[]))),
+        }
+    }
+}
+
+/**
+ * A reader as seen from its thread. The listener is only kept open while the reader is in use, so
+ * other programs can use it in between.
+ */
+struct Reader {
+    player: Player,
+    status: Arc<std::sync::Mutex<NfcStatus>>,
+    listener: Option<Box<dyn NfcBackend>>,
+    /**
+     * The connection string the reader was last found at, which can differ from the configured one
+     * if it was re-enumerated after being plugged back in
+     */
+    device: Option<String>,
+    last_used: Instant,
+    backoff: Duration,
+    /**
+     * When to next try reconnecting the reader, or `None` if it's only opened when requested
+     */
+    retry_at: Option<Instant>,
+    /**
+     * The realm the listener is opened in
+     */
+    realm: NfcRealm,
+}
+
+impl Reader {
+    /**
+     * Open the reader, trying the configured device and then any that look like it was
+     * re-enumerated as. If it can't be opened, another attempt is scheduled with backoff.
+     */
+    fn connect(&mut self) {
+        self.listener = None;
+        let Some(configured) = crate::env::nfc_device(&self.player) else {
+            self.set_status(ReaderStatus::Disconnected);
+            self.device = None;
+            self.retry_at = None;
+            return;
+        };
+        let taken: Vec<String> = [Player::P1, Player::P2]
+            .iter()
+            .filter(|player| **player != self.player)
+            .filter_map(crate::env::nfc_device)
+            .collect();
+        for device in candidates(configured.as_str(), &serial_ports(), &taken) {
+            if !device_present(device.as_str()) {
+                continue;
+            }
+            if let Some(listener) = self.open(device.as_str()) {
+                if device != configured {
+                    log::info!(
+                        "Player {} reader moved from {configured} to {device}",
+                        self.player
+                    );
+                }
+                self.listener = Some(listener);
+                self.record(|health| health.device = Some(device.clone()));
+                self.device = Some(device);
+                self.backoff = MIN_BACKOFF;
+                self.retry_at = None;
+                self.set_status(ReaderStatus::Connected);
+                return;
+            }
+        }
+        log::error!(
+            "Couldn't open player {} reader at {configured}, retrying in {}s",
+            self.player,
+            self.backoff.as_secs()
+        );
+        self.record(|health| {
+            health.open_failures += 1;
+            health.last_error = Some(format!("Couldn't open the reader at {configured}"));
+        });
+        self.device = None;
+        self.retry_at = Some(Instant::now() + self.backoff);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        self.set_status(ReaderStatus::Disconnected);
+    }
+
+    fn open(&self, device: &str) -> Option<Box<dyn NfcBackend>> {
+        if device == MOCK_DEVICE {
+            return MockNfcClient::new(&self.player)
+                .map(|mock| Box::new(mock) as Box<dyn NfcBackend>);
+        }
+        GateKeeperMemberListener::new(String::from(device), realm_type(self.realm))
+            .map(|listener| Box::new(listener) as Box<dyn NfcBackend>)
+    }
+
+    /**
+     * Check the reader is still plugged in, marking it disconnected if it isn't
+     */
+    fn check(&mut self) -> bool {
+        let present = self.device.as_deref().is_some_and(device_present);
+        if !present && self.device.is_some() {
+            log::warn!("Player {} reader was unplugged", self.player);
+            self.listener = None;
+            self.device = None;
+            self.backoff = MIN_BACKOFF;
+            self.retry_at = Some(Instant::now());
+            self.record(|health| {
+                health.last_error = Some(String::from("The reader was unplugged"))
+            });
+            self.set_status(ReaderStatus::Disconnected);
+        }
+        present
+    }
+
+    /**
+     * When the thread should next wake up if no requests come in
+     */
+    fn next_wakeup(&self) -> Instant {
+        if self.device.is_none() {
+            return self
+                .retry_at
+                .unwrap_or_else(|| Instant::now() + MAX_BACKOFF);
+        }
+        if crate::env::nfc_subscribe() {
+            return Instant::now() + SUBSCRIBE_INTERVAL;
+        }
+        if self.listener.is_some() {
+            return self.last_used + crate::env::nfc_idle_timeout();
+        }
+        Instant::now() + PRESENCE_INTERVAL
+    }
+
+    /**
+     * Do whatever is due when no requests came in: release an idle reader (or in subscription
+     * mode, poll it), notice it was unplugged, or try to reconnect it
+     */
+    fn tick(&mut self) {
+        if self.device.is_none() {
+            if self
+                .retry_at
+                .is_some_and(|retry_at| Instant::now() >= retry_at)
+            {
+                self.connect();
+            }
+            return;
+        }
+        if !self.check() {
+            return;
         }
+        if crate::env::nfc_subscribe() {
+            if self.realm != crate::env::nfc_realm() {
+                self.realm = crate::env::nfc_realm();
+                self.listener = None;
+            }
+            if self.listener.is_none() {
+                self.connect();
+            }
+            if let Some(listener) = self.listener.as_mut() {
+                let association_id = listener.poll_for_user();
+                self.record_poll(association_id.is_some());
+                if let Some(association_id) = association_id {
+                    broadcast_tap(association_id, &self.player, self.realm);
+                }
+            }
+            return;
+        }
+        if self.listener.is_some() && self.last_used.elapsed() >= crate::env::nfc_idle_timeout() {
+            self.listener = None;
+        }
+    }
+
+    fn set_status(&self, status: ReaderStatus) {
+        let mut health = self.status.lock().unwrap_or_else(PoisonError::into_inner);
+        if status == ReaderStatus::Disconnected {
+            health.device = None;
+        }
+        if health.status != status {
+            health.status = status;
+            events::emit(Event::ReaderStatus(self.player.clone(), status));
+        }
+    }
+
+    fn record(&self, update: impl FnOnce(&mut NfcStatus)) {
+        update(&mut self.status.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    fn record_poll(&self, tapped: bool) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.record(|health| {
+            health.last_poll_at = Some(now);
+            if tapped {
+                health.last_tap_at = Some(now);
+            }
+        });
+    }
+}
+
+/**
+ * Whether the device in a libnfc connection string (`driver:port`) exists. Only ports that are
+ * device nodes can be checked, anything else is assumed to be there.
+ */
+fn device_present(device: &str) -> bool {
+    match device.split(':').nth(1) {
+        Some(port) if port.starts_with("/dev/") => Path::new(port).exists(),
+        _ => true,
+    }
+}
+
+/**
+ * List the serial ports a USB reader can show up as
+ */
+fn serial_ports() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir("/dev") else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with("ttyACM") || name.starts_with("ttyUSB"))
+        .map(|name| format!("/dev/{name}"))
+        .collect()
+}
+
+/**
+ * Get the connection strings to try for a reader, configured one first. A USB serial reader that's
+ * unplugged and plugged back in can come back with a different number (`/dev/ttyACM0` becoming
+ * `/dev/ttyACM1`), so ports of the same kind are tried after it, other than ones another reader is
+ * configured with.
+ */
+fn candidates(configured: &str, ports: &[String], taken: &[String]) -> Vec<String> {
+    let mut candidates = vec![String::from(configured)];
+    let mut parts = configured.splitn(3, ':');
+    let (Some(driver), Some(port)) = (parts.next(), parts.next()) else {
+        return candidates;
+    };
+    let rest = parts.next();
+    let kind = port.trim_end_matches(|c: char| c.is_ascii_digit());
+    if kind == port || !kind.starts_with("/dev/tty") {
+        return candidates;
+    }
+    let mut ports: Vec<&String> = ports
+        .iter()
+        .filter(|other| other.as_str() != port && other.starts_with(kind))
+        .filter(|other| other[kind.len()..].chars().all(|c| c.is_ascii_digit()))
+        .filter(|other| {
+            !taken
+                .iter()
+                .any(|device| device_port(device) == other.as_str())
+        })
+        .collect();
+    ports.sort();
+    candidates.extend(ports.into_iter().map(|other| match rest {
+        Some(rest) => format!("{driver}:{other}:{rest}"),
+        None => format!("{driver}:{other}"),
+    }));
+    candidates
+}
+
+fn device_port(device: &str) -> &str {
+    device.split(':').nth(1).unwrap_or_default()
+}
+
+/**
+ * Tell subscribers about a badge tapped on a player's reader
+ */
+fn broadcast_tap(association_id: String, player: &Player, realm: NfcRealm) -> String {
+    let handle = tap(association_id, player, realm);
+    log::debug!("Badge tapped on player {player} reader");
+    attract::activity();
+    events::emit(Event::BadgeTapped(player.clone(), handle.clone()));
+    // An error here only means there are no subscribers right now
+    let _ = TAP_CHANNEL.send(BadgeTap {
+        player: player.clone(),
+        handle: handle.clone(),
+        game_id: current_game().map(|game| game.id),
+    });
+    handle
+}
+
+/**
+ * Remember a badge tapped on a player's reader, and get the handle the running game knows it by.
+ * Handles are different for every game, so games can't track players across each other. Badges
+ * tapped in the menu get handles of their own.
+ */
+fn tap(association_id: String, player: &Player, realm: NfcRealm) -> String {
+    let mut taps = TAPS.lock().unwrap();
+    let game_id = current_game().map(|game| game.id);
+    if let Some(tap) = taps.iter_mut().find(|tap| {
+        tap.association_id == association_id && tap.realm == realm && tap.game_id == game_id
+    }) {
+        // The same badge can move between readers
+        tap.player = player.clone();
+        tap.at = Instant::now();
+        return tap.handle.clone();
+    }
+    let handle = game_handle(
+        association_id.as_str(),
+        game_id.as_deref().unwrap_or_default(),
+    );
+    taps.push(Tap {
+        handle: handle.clone(),
+        association_id,
+        game_id,
+        player: player.clone(),
+        realm,
+        at: Instant::now(),
+    });
+    handle
+}
+
+fn game_handle(association_id: &str, game_uuid: &str) -> String {
+    sha256::digest(format!("{association_id}:{game_uuid}"))
+}
+
+fn realm_type(realm: NfcRealm) -> RealmType {
+    match realm {
+        NfcRealm::MemberProjects => RealmType::MemberProjects,
+        NfcRealm::Drink => RealmType::Drink,
+        NfcRealm::Door => RealmType::Door,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn games_only_see_allowed_user_fields() {
+        let user = serde_json::json!({
+            "uid": "alice",
+            "cn": "Alice",
+            "mail": "alice@example.com",
+            "groups": ["member"],
+        });
+        let Value::Object(user) = user else {
+            unreachable!()
+        };
+        let fields: Vec<String> = for_game(user).keys().cloned().collect();
+        assert_eq!(fields, vec!["cn", "uid"]);
+    }
+
+    #[test]
+    fn saves_are_namespaced_by_handle() {
+        let handle = sha256::digest("alice:game");
+        assert_eq!(save_namespace(Some(handle.as_str())).unwrap(), handle);
+        assert_eq!(save_namespace(None).unwrap(), GUEST_NAMESPACE);
+        assert!(save_namespace(Some("../../other-game")).is_err());
+    }
+
+    #[test]
+    fn renumbered_ports_are_tried_after_the_configured_one() {
+        let ports = [
+            "/dev/ttyACM1",
+            "/dev/ttyUSB0",
+            "/dev/ttyACM3",
+            "/dev/ttyACM2",
+        ]
+        .map(String::from);
+        let taken = [String::from("pn532_uart:/dev/ttyACM2")];
+        assert_eq!(
+            candidates("pn532_uart:/dev/ttyACM0", &ports, &taken),
+            vec![
+                "pn532_uart:/dev/ttyACM0",
+                "pn532_uart:/dev/ttyACM1",
+                "pn532_uart:/dev/ttyACM3"
+            ]
+        );
+        assert_eq!(
+            candidates("pn532_i2c:/dev/i2c-1", &ports, &[]),
+            vec!["pn532_i2c:/dev/i2c-1"]
+        );
     }
 }
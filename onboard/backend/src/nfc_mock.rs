@@ -0,0 +1,135 @@
+use crate::nfc::NfcBackend;
+use crate::storage;
+use devcade_onboard_types::{Map, Player, Value};
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/**
+ * How long a poll waits when no tap is waiting, like a real reader does
+ */
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/**
+ * A pretend badge reader for machines without one. Taps are simulated by writing association IDs
+ * to a FIFO in the devcade directory, one per line, e.g. `echo alice > ~/.devcade/nfc-mock-p1`.
+ * Users are made up from the association ID.
+ */
+pub struct MockNfcClient {
+    fifo: File,
+    buffer: Vec<u8>,
+}
+
+/**
+ * Get the FIFO a player's taps are simulated through
+ */
+#[must_use]
+pub fn fifo_path(player: &Player) -> PathBuf {
+    storage::root().join(format!("nfc-mock-{}", player.to_string().to_lowercase()))
+}
+
+impl MockNfcClient {
+    /**
+     * Open the FIFO for a player's pretend reader, creating it if it doesn't exist. Returns `None`
+     * (after logging why) if it can't be opened.
+     */
+    #[must_use]
+    pub fn new(player: &Player) -> Option<Self> {
+        let path = fifo_path(player);
+        let is_fifo = std::fs::metadata(&path).is_ok_and(|meta| meta.file_type().is_fifo());
+        if !is_fifo {
+            let _ = std::fs::remove_file(&path);
+            let Ok(c_path) = std::ffi::CString::new(path.to_string_lossy().as_bytes()) else {
+                return None;
+            };
+            // SAFETY: the path is a valid nul terminated string
+            if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+                log::error!(
+                    "Couldn't create mock NFC FIFO {:?}: {}",
+                    path,
+                    std::io::Error::last_os_error()
+                );
+                return None;
+            }
+        }
+        // Opening for writing too means the FIFO never reports end of file when a writer closes
+        let fifo = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path);
+        match fifo {
+            Ok(fifo) => {
+                log::info!("Mock NFC reader for {player} reads taps from {:?}", path);
+                Some(MockNfcClient {
+                    fifo,
+                    buffer: vec![],
+                })
+            }
+            Err(e) => {
+                log::error!("Couldn't open mock NFC FIFO {:?}: {e}", path);
+                None
+            }
+        }
+    }
+}
+
+impl NfcBackend for MockNfcClient {
+    fn poll_for_user(&mut self) -> Option<String> {
+        let mut chunk = [0; 256];
+        loop {
+            match self.fifo.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Couldn't read mock NFC FIFO: {e}");
+                    break;
+                }
+            }
+        }
+        let tap = next_tap(&mut self.buffer);
+        if tap.is_none() {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        tap
+    }
+
+    fn fetch_user(&self, association_id: String) -> Option<Map<String, Value>> {
+        let mut user = Map::new();
+        user.insert(String::from("uid"), Value::from(association_id.clone()));
+        user.insert(String::from("cn"), Value::from(association_id));
+        Some(user)
+    }
+}
+
+/**
+ * Take the first non-empty line out of what's been read from the FIFO, if a whole one has arrived
+ */
+fn next_tap(buffer: &mut Vec<u8>) -> Option<String> {
+    while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+        let line: Vec<u8> = buffer.drain(..=end).collect();
+        let line = String::from_utf8_lossy(&line).trim().to_string();
+        if !line.is_empty() {
+            return Some(line);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taps_are_whole_lines() {
+        let mut buffer = b"\nalice\nbo".to_vec();
+        assert_eq!(next_tap(&mut buffer), Some(String::from("alice")));
+        assert_eq!(next_tap(&mut buffer), None);
+        buffer.extend_from_slice(b"b\n");
+        assert_eq!(next_tap(&mut buffer), Some(String::from("bob")));
+        assert!(buffer.is_empty());
+    }
+}
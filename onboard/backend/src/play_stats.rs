@@ -0,0 +1,227 @@
+use crate::atomic;
+use crate::storage;
+use anyhow::Error;
+use devcade_onboard_types::schema::{GamePlays, GameSession};
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * The file (relative to the devcade path) that play stats are stored in
+ */
+const STATS_FILE: &str = "play_stats.json";
+
+/**
+ * The file (relative to the devcade path) that recent launches are stored in
+ */
+const LAUNCHES_FILE: &str = "launches.json";
+
+/**
+ * How long launches are kept for, in seconds
+ */
+const LAUNCH_HISTORY_SECS: u64 = 90 * 24 * 60 * 60;
+
+lazy_static! {
+    static ref STATS: Mutex<HashMap<String, GameStats>> = Mutex::new(HashMap::new());
+    static ref LAUNCHES: Mutex<Vec<Launch>> = Mutex::new(Vec::new());
+    // Held from updating the stats until they're written, so an older copy can't be written last
+    static ref WRITING: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+/**
+ * How much a single game has been played on this cabinet
+ */
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+pub struct GameStats {
+    pub sessions: u64,
+    pub total_play_ms: u64,
+    /**
+     * When the game was last launched, in seconds since the unix epoch
+     */
+    pub last_played: u64,
+}
+
+/**
+ * A single launch of a game, kept for a while so recent plays can be counted
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Launch {
+    game_id: String,
+    /**
+     * When the game was launched, in seconds since the unix epoch
+     */
+    started_at: u64,
+    duration_ms: u64,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    /**
+     * The handles of the badges tapped while the game ran
+     */
+    players: Vec<String>,
+}
+
+fn stats_path() -> PathBuf {
+    storage::root().join(STATS_FILE)
+}
+
+fn launches_path() -> PathBuf {
+    storage::root().join(LAUNCHES_FILE)
+}
+
+/**
+ * Load play stats and recent launches from the devcade directory. Missing or unreadable stats are
+ * logged and replaced with empty ones.
+ */
+pub async fn load() {
+    *STATS.lock().unwrap() = read_or_default(&stats_path(), "play stats").await;
+    *LAUNCHES.lock().unwrap() = read_or_default(&launches_path(), "launches").await;
+}
+
+async fn read_or_default<T: DeserializeOwned + Default>(path: &Path, what: &str) -> T {
+    match tokio::fs::read_to_string(path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(value) => value,
+            Err(e) => {
+                log::warn!("Ignoring invalid {what} at {:?}: {e}", path);
+                T::default()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => T::default(),
+        Err(e) => {
+            log::warn!("Couldn't read {what} at {:?}: {e}", path);
+            T::default()
+        }
+    }
+}
+
+/**
+ * Add a finished session to its game's stats and the recent launches, and persist them. Launches
+ * older than 90 days are forgotten.
+ *
+ * # Errors
+ * This function will return an error if the stats can't be written.
+ */
+pub async fn record(session: &GameSession) -> Result<(), Error> {
+    let _writing = WRITING.lock().await;
+    let stats = {
+        let mut stats = STATS.lock().unwrap();
+        let game = stats.entry(session.game_id.clone()).or_default();
+        game.sessions += 1;
+        game.total_play_ms += session.duration_ms;
+        game.last_played = game.last_played.max(session.started_at);
+        stats.clone()
+    };
+    let launches = {
+        let mut launches = LAUNCHES.lock().unwrap();
+        let oldest = now().saturating_sub(LAUNCH_HISTORY_SECS);
+        launches.retain(|launch| launch.started_at >= oldest);
+        launches.push(Launch {
+            game_id: session.game_id.clone(),
+            started_at: session.started_at,
+            duration_ms: session.duration_ms,
+            exit_code: session.exit_code,
+            signal: session.signal,
+            players: session.players.clone(),
+        });
+        launches.clone()
+    };
+    atomic::write_async(stats_path(), serde_json::to_string(&stats)?).await?;
+    atomic::write_async(launches_path(), serde_json::to_string(&launches)?).await?;
+    Ok(())
+}
+
+/**
+ * Get the games launched most in the last few days, most played first, for a "popular on this
+ * cabinet" row. Ties go to whichever was played for longer.
+ */
+#[must_use]
+pub fn popular(days: u32, limit: usize) -> Vec<GamePlays> {
+    let since = now().saturating_sub(u64::from(days) * 24 * 60 * 60);
+    let launches = LAUNCHES.lock().unwrap().clone();
+    let stats = STATS.lock().unwrap().clone();
+    rank(&launches, &stats, since, limit)
+}
+
+fn rank(
+    launches: &[Launch],
+    stats: &HashMap<String, GameStats>,
+    since: u64,
+    limit: usize,
+) -> Vec<GamePlays> {
+    let mut plays: HashMap<&str, GamePlays> = HashMap::new();
+    for launch in launches.iter().filter(|launch| launch.started_at >= since) {
+        let game = plays
+            .entry(launch.game_id.as_str())
+            .or_insert_with(|| GamePlays {
+                game_id: launch.game_id.clone(),
+                total_plays: stats.get(&launch.game_id).map_or(0, |stats| stats.sessions),
+                ..GamePlays::default()
+            });
+        game.plays += 1;
+        game.play_ms += launch.duration_ms;
+    }
+    let mut plays: Vec<GamePlays> = plays.into_values().collect();
+    plays.sort_by(|a, b| (b.plays, b.play_ms, &a.game_id).cmp(&(a.plays, a.play_ms, &b.game_id)));
+    plays.truncate(limit);
+    plays
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/**
+ * Get the play stats for a game, or `None` if it has never been played here
+ */
+#[must_use]
+pub fn stats(game_id: &str) -> Option<GameStats> {
+    STATS.lock().unwrap().get(game_id).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn launch(game_id: &str, started_at: u64, duration_ms: u64) -> Launch {
+        Launch {
+            game_id: game_id.to_string(),
+            started_at,
+            duration_ms,
+            exit_code: Some(0),
+            signal: None,
+            players: vec![],
+        }
+    }
+
+    #[test]
+    fn ranks_games_by_recent_plays() {
+        let launches = vec![
+            launch("old", 10, 1000),
+            launch("old", 20, 1000),
+            launch("a", 100, 1000),
+            launch("b", 110, 500),
+            launch("b", 120, 500),
+            launch("c", 130, 2000),
+        ];
+        let stats = HashMap::from([(
+            String::from("b"),
+            GameStats {
+                sessions: 40,
+                ..GameStats::default()
+            },
+        )]);
+        let ranked = rank(&launches, &stats, 100, 10);
+        let ids: Vec<&str> = ranked.iter().map(|game| game.game_id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "c", "a"]);
+        assert_eq!(ranked[0].plays, 2);
+        assert_eq!(ranked[0].total_plays, 40);
+        assert_eq!(rank(&launches, &stats, 100, 1).len(), 1);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::api;
+use crate::env;
+use crate::install_queue;
+use crate::storage;
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::task::AbortHandle;
+
+/**
+ * How long a game has to stay hovered before its assets are prefetched, so scrolling past games
+ * doesn't fetch every one of them
+ */
+const ASSET_DELAY: Duration = Duration::from_millis(250);
+
+/**
+ * How long a game has to stay hovered before it's speculatively installed
+ */
+const INSTALL_DELAY: Duration = Duration::from_secs(2);
+
+lazy_static! {
+    static ref CURRENT: Mutex<Option<Prefetch>> = Mutex::new(None);
+}
+
+/**
+ * The prefetch for the game the user is currently hovering
+ */
+struct Prefetch {
+    game_id: String,
+    task: AbortHandle,
+    /**
+     * The install job started speculatively for the game, if any
+     */
+    install_job: Option<u32>,
+}
+
+/**
+ * Tell the backend which game the user is hovering in the menu, or `None` if they aren't hovering
+ * one. The game's metadata and assets are fetched in the background, and if speculative installs
+ * are enabled and the queue and disk allow it, the game is queued for install. Any prefetch for the
+ * previously hovered game is stopped, and a speculative install nobody has asked for since is
+ * cancelled.
+ */
+pub fn hint(game_id: Option<String>) {
+    let mut current = CURRENT.lock().unwrap();
+    if current.as_ref().map(|prefetch| &prefetch.game_id) == game_id.as_ref() {
+        return;
+    }
+    if let Some(old) = current.take() {
+        old.task.abort();
+        if let Some(job_id) = old.install_job {
+            install_queue::cancel_if_unwaited(job_id);
+        }
+    }
+    *current = game_id.map(|game_id| Prefetch {
+        task: tokio::spawn(prefetch(game_id.clone())).abort_handle(),
+        game_id,
+        install_job: None,
+    });
+}
+
+async fn prefetch(game_id: String) {
+    tokio::time::sleep(ASSET_DELAY).await;
+    log::debug!("Prefetching game {game_id}");
+    if let Err(e) = api::get_game(game_id.as_str()).await {
+        log::debug!("Couldn't prefetch game {game_id}: {e}");
+        return;
+    }
+    if let Err(e) = api::download_icon(game_id.clone()).await {
+        log::debug!("Couldn't prefetch icon for {game_id}: {e}");
+    }
+    if let Err(e) = api::download_banner(game_id.clone()).await {
+        log::debug!("Couldn't prefetch banner for {game_id}: {e}");
+    }
+
+    if !env::prefetch_installs() {
+        return;
+    }
+    tokio::time::sleep(INSTALL_DELAY.saturating_sub(ASSET_DELAY)).await;
+    if !install_allowed(game_id.as_str()).await {
+        return;
+    }
+
+    // The job is queued under the same lock `hint` uses, so it's always recorded before the hint
+    // can change and cancel it
+    let mut current = CURRENT.lock().unwrap();
+    if let Some(prefetch) = current.as_mut().filter(|p| p.game_id == game_id) {
+        log::info!("Speculatively installing game {game_id}");
+        prefetch.install_job = Some(install_queue::enqueue(game_id));
+    }
+}
+
+/**
+ * Check whether a game should be speculatively installed. Games are only installed when they
+ * aren't already up to date, nothing else is being installed (so a speculative download never
+ * competes for bandwidth), and the disk has room to spare.
+ */
+async fn install_allowed(game_id: &str) -> bool {
+    if !install_queue::queue_status().is_empty() {
+        log::debug!("Not installing {game_id} speculatively, the install queue is busy");
+        return false;
+    }
+    let min_free = env::prefetch_min_free_bytes();
+    match storage::free_space(&storage::root()) {
+        Some(free) if free >= min_free => {}
+        Some(free) => {
+            log::debug!("Not installing {game_id} speculatively, only {free} bytes free");
+            return false;
+        }
+        None => {
+            log::debug!("Not installing {game_id} speculatively, free space is unknown");
+            return false;
+        }
+    }
+    match api::installed_version(game_id).await {
+        Ok((_, Some(_))) => false,
+        Ok((_, None)) => true,
+        Err(e) => {
+            log::debug!("Couldn't check installed version of {game_id}: {e}");
+            false
+        }
+    }
+}
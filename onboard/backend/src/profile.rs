@@ -0,0 +1,119 @@
+use crate::env::{self, DisplayServer};
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::Player;
+use std::path::Path;
+
+/**
+ * The hardware and deployment a backend is running on, bundling the settings that differ between
+ * them. One is picked with `DEVCADE_PROFILE`, and individual settings can still be overridden by
+ * their own environment variables.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Profile {
+    pub name: &'static str,
+    /**
+     * The libnfc connection string of the badge reader, or `None` if there isn't one
+     */
+    pub nfc_device: Option<&'static str>,
+    /**
+     * The devices games are given in their sandbox, as flatpak `--device` names
+     */
+    pub devices: &'static [&'static str],
+    /**
+     * The display server games are run under, or `None` to detect it
+     */
+    pub display_server: Option<DisplayServer>,
+}
+
+/**
+ * Every known profile. The first is used when `DEVCADE_PROFILE` isn't set.
+ */
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        name: "cabinet-v2",
+        nfc_device: Some("pn532_uart:/dev/ttyACM0"),
+        devices: &["dri"],
+        display_server: None,
+    },
+    Profile {
+        name: "mini-pi",
+        nfc_device: Some("pn532_i2c:/dev/i2c-1"),
+        // Controllers are plugged straight into the Pi rather than going through an encoder
+        devices: &["dri", "input"],
+        display_server: Some(DisplayServer::Wayland),
+    },
+    Profile {
+        name: "dev-laptop",
+        nfc_device: None,
+        devices: &["dri"],
+        display_server: None,
+    },
+];
+
+/**
+ * Get the profile selected by `DEVCADE_PROFILE`. An unknown profile is caught by `validate` at
+ * startup, so this falls back to the default one.
+ */
+#[must_use]
+pub fn current() -> &'static Profile {
+    let name = env::profile_name();
+    find(name.as_str()).unwrap_or(&PROFILES[0])
+}
+
+fn find(name: &str) -> Option<&'static Profile> {
+    PROFILES.iter().find(|profile| profile.name == name)
+}
+
+/**
+ * Check the selected profile against the machine. Devices the profile expects but that don't exist
+ * are returned as warnings, since a reader or GPU can be plugged in later.
+ *
+ * # Errors
+ * This function will return an error if `DEVCADE_PROFILE` doesn't name a known profile.
+ */
+pub fn validate() -> Result<Vec<String>, Error> {
+    let name = env::profile_name();
+    let profile = find(name.as_str()).ok_or_else(|| {
+        let known: Vec<&str> = PROFILES.iter().map(|profile| profile.name).collect();
+        anyhow!(
+            "Unknown DEVCADE_PROFILE '{name}', expected one of {}",
+            known.join(", ")
+        )
+    })?;
+
+    let mut warnings = Vec::new();
+    for player in [Player::P1, Player::P2] {
+        if let Some(path) = env::nfc_device(&player)
+            .as_deref()
+            .and_then(|device| device.split_once(':'))
+            .map(|(_, path)| path)
+        {
+            if !Path::new(path).exists() {
+                warnings.push(format!("NFC reader {path} for {player} doesn't exist"));
+            }
+        }
+    }
+    for device in profile.devices {
+        let path = match *device {
+            "dri" => "/dev/dri",
+            "input" => "/dev/input",
+            _ => continue,
+        };
+        if !Path::new(path).exists() {
+            warnings.push(format!("Device {path} for --device={device} doesn't exist"));
+        }
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_names_are_unique() {
+        for (i, profile) in PROFILES.iter().enumerate() {
+            assert_eq!(find(profile.name), Some(&PROFILES[i]));
+        }
+    }
+}
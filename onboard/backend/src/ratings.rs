@@ -0,0 +1,198 @@
+use crate::api;
+use crate::atomic;
+use crate::i18n::tr;
+use crate::nfc;
+use crate::storage;
+use anyhow::{anyhow, Error};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/**
+ * The file (relative to the devcade path) that ratings waiting to be submitted are stored in
+ */
+const QUEUE_FILE: &str = "ratings.json";
+
+/**
+ * How many ratings can wait to be submitted before the oldest are dropped
+ */
+const MAX_QUEUED: usize = 1000;
+
+/**
+ * How often ratings that couldn't be submitted are tried again
+ */
+const RETRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+lazy_static! {
+    static ref QUEUE: Mutex<Vec<PendingRating>> = Mutex::new(Vec::new());
+    // Held while submitting, so a rating isn't submitted twice at once
+    static ref SUBMITTING: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+/**
+ * A rating waiting to be submitted to the API
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct PendingRating {
+    game_id: String,
+    handle: String,
+    stars: u8,
+    rated_at: u64,
+}
+
+fn queue_path() -> PathBuf {
+    storage::root().join(QUEUE_FILE)
+}
+
+/**
+ * Load the ratings that weren't submitted before the backend last stopped. Missing or unreadable
+ * ratings are logged and dropped.
+ */
+pub async fn load() {
+    let path = queue_path();
+    let queue = match tokio::fs::read_to_string(&path).await {
+        Ok(json) => match serde_json::from_str(json.as_str()) {
+            Ok(queue) => queue,
+            Err(e) => {
+                log::warn!("Ignoring invalid ratings queue at {:?}: {e}", path);
+                Vec::new()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            log::warn!("Couldn't read ratings queue at {:?}: {e}", path);
+            Vec::new()
+        }
+    };
+    *QUEUE.lock().unwrap() = queue;
+}
+
+/**
+ * Rate a game for the badge tapped most recently. The rating is queued and submitted in the
+ * background, so it isn't lost while the cabinet is offline. Rating a game again replaces a rating
+ * that hasn't been submitted yet.
+ *
+ * # Errors
+ * This function will return an error if the game ID is invalid, the stars aren't from 1 to 5,
+ * nobody has tapped their badge, or the queue can't be written.
+ */
+pub async fn rate(game_id: String, stars: u8) -> Result<(), Error> {
+    api::check_game_id(game_id.as_str())?;
+    if !(1..=5).contains(&stars) {
+        return Err(anyhow!(tr(
+            "rating_invalid",
+            &[("stars", stars.to_string().as_str())]
+        )));
+    }
+    let handle = nfc::latest_handle().ok_or_else(|| anyhow!(tr("rating_needs_badge", &[])))?;
+    let rating = PendingRating {
+        game_id,
+        handle,
+        stars,
+        rated_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+    log::info!("Game {} was rated {stars} stars", rating.game_id);
+    let queue = {
+        let mut queue = QUEUE.lock().unwrap();
+        enqueue(&mut queue, rating);
+        queue.clone()
+    };
+    atomic::write_async(queue_path(), serde_json::to_string(&queue)?).await?;
+    tokio::spawn(async {
+        if let Err(e) = submit().await {
+            log::info!("Couldn't submit ratings yet, they'll be tried again: {e}");
+        }
+    });
+    Ok(())
+}
+
+/**
+ * Add a rating to the end of the queue, replacing the badge's earlier rating of the same game and
+ * dropping the oldest ratings if the queue is full
+ */
+fn enqueue(queue: &mut Vec<PendingRating>, rating: PendingRating) {
+    queue.retain(|queued| queued.game_id != rating.game_id || queued.handle != rating.handle);
+    queue.push(rating);
+    let overflow = queue.len().saturating_sub(MAX_QUEUED);
+    queue.drain(..overflow);
+}
+
+/**
+ * Submit queued ratings every 10 minutes. This should be spawned as a task at startup.
+ */
+pub async fn run() {
+    let mut interval = tokio::time::interval(RETRY_INTERVAL);
+    loop {
+        interval.tick().await;
+        match submit().await {
+            Ok(0) => {}
+            Ok(submitted) => log::info!("Submitted {submitted} game ratings"),
+            Err(e) => log::warn!("Couldn't submit game ratings: {e}"),
+        }
+    }
+}
+
+/**
+ * Submit queued ratings oldest first, getting how many were. Submitting stops at the first failure,
+ * since the API is most likely unreachable.
+ *
+ * # Errors
+ * This function will return an error if a rating can't be submitted, or the queue can't be
+ * written.
+ */
+pub async fn submit() -> Result<usize, Error> {
+    let _submitting = SUBMITTING.lock().await;
+    let pending = QUEUE.lock().unwrap().clone();
+    let mut submitted = vec![];
+    let mut result = Ok(());
+    for rating in pending {
+        match api::submit_rating(
+            rating.game_id.as_str(),
+            rating.handle.as_str(),
+            rating.stars,
+            rating.rated_at,
+        )
+        .await
+        {
+            Ok(()) => submitted.push(rating),
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+    if !submitted.is_empty() {
+        let queue = {
+            let mut queue = QUEUE.lock().unwrap();
+            queue.retain(|rating| !submitted.contains(rating));
+            queue.clone()
+        };
+        atomic::write_async(queue_path(), serde_json::to_string(&queue)?).await?;
+    }
+    result.map(|()| submitted.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rating_again_replaces_the_pending_rating() {
+        let rating = |game_id: &str, handle: &str, stars: u8| PendingRating {
+            game_id: game_id.to_string(),
+            handle: handle.to_string(),
+            stars,
+            rated_at: 0,
+        };
+        let mut queue = vec![];
+        enqueue(&mut queue, rating("a", "alice", 2));
+        enqueue(&mut queue, rating("a", "bob", 3));
+        enqueue(&mut queue, rating("a", "alice", 5));
+        assert_eq!(queue, [rating("a", "bob", 3), rating("a", "alice", 5)]);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::api;
+use crate::env;
+use crate::events;
+use crate::storage;
+use devcade_onboard_types::schema::{CheckStatus, Readiness, ReadinessCheck};
+use devcade_onboard_types::{Event, Player};
+use lazy_static::lazy_static;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/**
+ * How long the API has to answer before it's reported as unreachable
+ */
+const API_TIMEOUT: Duration = Duration::from_secs(5);
+
+/**
+ * Checks that stop games from being installed when they fail
+ */
+const INSTALL_CHECKS: &[&str] = &["devcade_path", "flatpak"];
+
+lazy_static! {
+    static ref LATEST: Mutex<Readiness> = Mutex::new(Readiness::default());
+}
+
+/**
+ * Get the result of the startup self-check. Until it finishes, nothing has been checked and
+ * `checked_at` is `None`.
+ */
+#[must_use]
+pub fn latest() -> Readiness {
+    LATEST.lock().unwrap().clone()
+}
+
+/**
+ * Get why games can't be installed, if a check they depend on failed at startup
+ */
+#[must_use]
+pub fn install_problem() -> Option<String> {
+    install_blocker(&LATEST.lock().unwrap().checks)
+}
+
+fn install_blocker(checks: &[ReadinessCheck]) -> Option<String> {
+    checks
+        .iter()
+        .find(|check| {
+            check.status == CheckStatus::Failed && INSTALL_CHECKS.contains(&check.name.as_str())
+        })
+        .map(|check| check.detail.clone())
+}
+
+/**
+ * Check everything the cabinet needs to run, log anything that's wrong, and push the result to the
+ * frontend. This should be spawned as a task at startup.
+ */
+pub async fn run() {
+    let readiness = check().await;
+    for check in &readiness.checks {
+        match check.status {
+            CheckStatus::Passed => log::debug!("Self-check {}: {}", check.name, check.detail),
+            CheckStatus::Warning => log::warn!("Self-check {}: {}", check.name, check.detail),
+            CheckStatus::Failed => log::error!("Self-check {}: {}", check.name, check.detail),
+        }
+    }
+    log::info!("Startup self-check: {readiness}");
+    *LATEST.lock().unwrap() = readiness.clone();
+    events::emit(Event::ReadinessChecked(readiness));
+}
+
+/**
+ * Run every check
+ */
+pub async fn check() -> Readiness {
+    let mut checks = vec![check_api().await, check_devcade_path()];
+    checks.push(check_program(
+        "flatpak",
+        "flatpak",
+        CheckStatus::Failed,
+        "games can't be installed or run",
+    ));
+    checks.push(check_program(
+        "flatpak_builder",
+        "flatpak-builder",
+        CheckStatus::Warning,
+        "games can't be built on the cabinet",
+    ));
+    for player in [Player::P1, Player::P2] {
+        checks.extend(check_nfc_device(&player));
+    }
+    summarize(checks)
+}
+
+/**
+ * Collect the results of the checks. The cabinet is ready unless a check failed; warnings don't
+ * stop it.
+ */
+fn summarize(checks: Vec<ReadinessCheck>) -> Readiness {
+    Readiness {
+        ready: checks
+            .iter()
+            .all(|check| check.status != CheckStatus::Failed),
+        checked_at: Some(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        ),
+        checks,
+    }
+}
+
+fn result(name: &str, status: CheckStatus, detail: impl Into<String>) -> ReadinessCheck {
+    ReadinessCheck {
+        name: name.to_string(),
+        status,
+        detail: detail.into(),
+    }
+}
+
+async fn check_api() -> ReadinessCheck {
+    if !env::api_configured() {
+        return result("api", CheckStatus::Failed, "The API domain isn't set");
+    }
+    match api::api_reachable(API_TIMEOUT).await {
+        Ok(()) => result("api", CheckStatus::Passed, env::api_url()),
+        // Installed games can still be played while the API is down
+        Err(e) => result(
+            "api",
+            CheckStatus::Warning,
+            format!("{} can't be reached: {e}", env::api_url()),
+        ),
+    }
+}
+
+fn check_devcade_path() -> ReadinessCheck {
+    let root = storage::root();
+    match storage::check_writable(root.as_path()) {
+        Ok(()) => result(
+            "devcade_path",
+            CheckStatus::Passed,
+            root.display().to_string(),
+        ),
+        Err(e) => result(
+            "devcade_path",
+            CheckStatus::Failed,
+            format!("{} isn't writable: {e}", root.display()),
+        ),
+    }
+}
+
+/**
+ * Check a program is on the `PATH`, reporting `missing` with what goes wrong without it
+ */
+fn check_program(name: &str, program: &str, missing: CheckStatus, without: &str) -> ReadinessCheck {
+    match find_program(program) {
+        Some(path) => result(name, CheckStatus::Passed, path),
+        None => result(
+            name,
+            missing,
+            format!("{program} isn't installed, {without}"),
+        ),
+    }
+}
+
+fn find_program(program: &str) -> Option<String> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+        .map(|candidate| candidate.display().to_string())
+}
+
+/**
+ * Check a player's badge reader exists, if it has one. A missing reader is only a warning, since
+ * it can be plugged in later.
+ */
+fn check_nfc_device(player: &Player) -> Option<ReadinessCheck> {
+    let device = env::nfc_device(player)?;
+    let name = format!("nfc_{}", player.to_string().to_ascii_lowercase());
+    let path = device
+        .split_once(':')
+        .map(|(_, path)| path)
+        .filter(|path| path.starts_with('/'));
+    Some(match path {
+        Some(path) if !Path::new(path).exists() => result(
+            name.as_str(),
+            CheckStatus::Warning,
+            format!("Reader {path} doesn't exist"),
+        ),
+        _ => result(name.as_str(), CheckStatus::Passed, device),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_dont_stop_the_cabinet_being_ready() {
+        let readiness = summarize(vec![
+            result("api", CheckStatus::Warning, "Can't be reached"),
+            result("flatpak_builder", CheckStatus::Warning, "Isn't installed"),
+        ]);
+        assert!(readiness.ready);
+        assert!(readiness.checked_at.is_some());
+        assert_eq!(install_blocker(&readiness.checks), None);
+    }
+
+    #[test]
+    fn failed_checks_are_reported_with_why() {
+        let missing = check_program(
+            "flatpak",
+            "devcade-no-such-program",
+            CheckStatus::Failed,
+            "games can't be installed or run",
+        );
+        assert_eq!(missing.status, CheckStatus::Failed);
+        assert_eq!(
+            missing.detail,
+            "devcade-no-such-program isn't installed, games can't be installed or run"
+        );
+
+        let readiness = summarize(vec![
+            result("api", CheckStatus::Passed, "devcade.csh.rit.edu"),
+            result("devcade_path", CheckStatus::Passed, "/tmp/devcade"),
+            missing,
+        ]);
+        assert!(!readiness.ready);
+        let names: Vec<&str> = readiness
+            .checks
+            .iter()
+            .map(|check| check.name.as_str())
+            .collect();
+        assert_eq!(names, ["api", "devcade_path", "flatpak"]);
+        assert_eq!(
+            install_blocker(&readiness.checks).as_deref(),
+            Some("devcade-no-such-program isn't installed, games can't be installed or run")
+        );
+        // Checks that don't stop installs aren't blamed for them
+        let readiness = summarize(vec![result("api", CheckStatus::Failed, "Not set")]);
+        assert!(!readiness.ready);
+        assert_eq!(install_blocker(&readiness.checks), None);
+    }
+}
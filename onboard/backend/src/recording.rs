@@ -0,0 +1,181 @@
+use crate::env::{self, DisplayServer};
+use crate::storage::{self, RECORDINGS_DIR};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tokio::process::{Child, Command};
+
+/**
+ * How long a recorder has to finish writing its file after being asked to stop
+ */
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
+/**
+ * A recorder capturing a game session
+ */
+pub struct Recording {
+    game_id: String,
+    child: Child,
+}
+
+fn recordings_dir() -> PathBuf {
+    storage::root().join(RECORDINGS_DIR)
+}
+
+/**
+ * Start recording the screen for a game session, if recording is enabled. Recordings are named
+ * after the game and its log session, so a glitch seen in a recording can be matched to the game's
+ * output. Returns `None` (after logging why) if the recorder couldn't be started.
+ */
+pub async fn start(game_id: &str, session: &str) -> Option<Recording> {
+    if !env::record_gameplay() {
+        return None;
+    }
+    let dir = recordings_dir();
+    if let Err(e) = fs::create_dir_all(&dir).await {
+        log::warn!("Couldn't create recordings dir {:?}: {e}", dir);
+        return None;
+    }
+    prune().await;
+
+    let name = format!("{game_id}-{session}");
+    let mut command = match env::display_server() {
+        // splitmuxsink starts a new file whenever one reaches the size limit
+        DisplayServer::X11 => {
+            let mut command = Command::new("gst-launch-1.0");
+            command
+                .arg("-e")
+                .args(["ximagesrc", "use-damage=false", "!", "videoconvert", "!"])
+                .args(["x264enc", "tune=zerolatency", "speed-preset=ultrafast", "!"])
+                .args(["h264parse", "!", "splitmuxsink"])
+                .arg(format!(
+                    "location={}",
+                    dir.join(format!("{name}-%05d.mp4")).display()
+                ))
+                .arg(format!("max-size-bytes={}", env::recording_file_bytes()));
+            command
+        }
+        // wf-recorder can't split files, so a session is one file, kept in check by pruning
+        DisplayServer::Wayland => {
+            let mut command = Command::new("wf-recorder");
+            command
+                .arg("-y")
+                .arg("-f")
+                .arg(dir.join(format!("{name}.mp4")));
+            command
+        }
+    };
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn();
+    match child {
+        Ok(child) => {
+            log::info!("Recording game {game_id} to {name}");
+            Some(Recording {
+                game_id: String::from(game_id),
+                child,
+            })
+        }
+        Err(e) => {
+            log::warn!("Couldn't start recording game {game_id}: {e}");
+            None
+        }
+    }
+}
+
+/**
+ * Stop a recording once its game has exited. Recorders are interrupted rather than killed, so they
+ * finish writing the file and it can be played back.
+ */
+pub async fn stop(recording: Option<Recording>) {
+    let Some(mut recording) = recording else {
+        return;
+    };
+    if let Some(pid) = recording.child.id() {
+        // SAFETY: kill has no memory safety requirements, at worst the pid is already gone
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT) };
+    }
+    match tokio::time::timeout(STOP_GRACE, recording.child.wait()).await {
+        Ok(Ok(status)) if !status.success() => log::warn!(
+            "Recorder for game {} exited with {status}",
+            recording.game_id
+        ),
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => log::warn!("Couldn't stop recording game {}: {e}", recording.game_id),
+        Err(_) => {
+            log::warn!(
+                "Recorder for game {} didn't finish in time, killing it",
+                recording.game_id
+            );
+            if let Err(e) = recording.child.kill().await {
+                log::warn!("Couldn't kill recorder: {e}");
+            }
+        }
+    }
+    prune().await;
+}
+
+/**
+ * Delete the oldest recordings until they take up no more than the limit
+ */
+async fn prune() {
+    let dir = recordings_dir();
+    let mut entries = match fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("Couldn't list recordings in {:?}: {e}", dir);
+            return;
+        }
+    };
+    let mut files = vec![];
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+        if metadata.is_file() {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((entry.path(), metadata.len(), modified));
+        }
+    }
+    for path in over_limit(files, env::recording_total_bytes()) {
+        log::debug!("Deleting old recording {:?}", path);
+        if let Err(e) = fs::remove_file(&path).await {
+            log::warn!("Couldn't delete old recording {:?}: {e}", path);
+        }
+    }
+}
+
+/**
+ * Get the files to delete so the newest ones add up to no more than the limit
+ */
+fn over_limit(mut files: Vec<(PathBuf, u64, SystemTime)>, limit: u64) -> Vec<PathBuf> {
+    files.sort_by_key(|(_, _, modified)| std::cmp::Reverse(*modified));
+    let mut total = 0;
+    files
+        .into_iter()
+        .filter_map(|(path, size, _)| {
+            total += size;
+            (total > limit).then_some(path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_recordings_are_pruned_first() {
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let files = vec![
+            (PathBuf::from("old.mp4"), 400, at(1)),
+            (PathBuf::from("new.mp4"), 400, at(3)),
+            (PathBuf::from("middle.mp4"), 400, at(2)),
+        ];
+        assert_eq!(over_limit(files, 1000), vec![PathBuf::from("old.mp4")]);
+    }
+}
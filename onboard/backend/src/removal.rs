@@ -0,0 +1,172 @@
+use crate::api;
+use crate::env;
+use crate::events;
+use crate::i18n::tr;
+use crate::play_stats;
+use crate::storage::{self, GAME_JSON};
+use anyhow::Error;
+use devcade_onboard_types::{schema::RemovalCandidate, Event};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/**
+ * How often games are checked for automatic removal
+ */
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+const BYTES_PER_MIB: u64 = 1024 * 1024;
+
+/**
+ * Find installed games that are at least `DEVCADE_REMOVAL_MIN_MB` large and haven't been played in
+ * `DEVCADE_REMOVAL_UNPLAYED_DAYS`, largest first. Games that have never been played are measured
+ * from when they were installed, and the running game is never suggested.
+ *
+ * # Errors
+ * This function will return an error if the manifest can't be read.
+ */
+pub async fn candidates() -> Result<Vec<RemovalCandidate>, Error> {
+    let manifest = storage::manifest().await?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let current = api::current_game().map(|game| game.id);
+
+    let mut candidates = Vec::new();
+    for (game_id, inventory) in manifest.games {
+        if current.as_ref() == Some(&game_id) {
+            continue;
+        }
+        let size_bytes = inventory.files.values().map(|file| file.size).sum();
+        let last_played = play_stats::stats(game_id.as_str()).map(|stats| stats.last_played);
+        let since = match last_played {
+            Some(last_played) => last_played,
+            None => installed_at(game_id.as_str()).await.unwrap_or(now),
+        };
+        if let Some(candidate) = check(game_id, size_bytes, last_played, since, now) {
+            candidates.push(candidate);
+        }
+    }
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.size_bytes));
+    Ok(candidates)
+}
+
+/**
+ * Decide whether a game should be suggested for removal, given when it was last played (or
+ * installed, if it never has been)
+ */
+fn check(
+    game_id: String,
+    size_bytes: u64,
+    last_played: Option<u64>,
+    since: u64,
+    now: u64,
+) -> Option<RemovalCandidate> {
+    let idle_secs = now.saturating_sub(since);
+    if size_bytes < env::removal_min_bytes() || idle_secs < env::removal_unplayed_after().as_secs()
+    {
+        return None;
+    }
+    let size_mib = size_bytes / BYTES_PER_MIB;
+    let idle_days = idle_secs / SECS_PER_DAY;
+    let key = match last_played {
+        Some(_) => "removal_not_played",
+        None => "removal_never_played",
+    };
+    let reason = tr(
+        key,
+        &[
+            ("size_mib", size_mib.to_string().as_str()),
+            ("days", idle_days.to_string().as_str()),
+        ],
+    );
+    Some(RemovalCandidate {
+        game_id,
+        size_bytes,
+        last_played,
+        reason,
+    })
+}
+
+/**
+ * When a game was installed, in seconds since the unix epoch, from when its `game.json` was written
+ */
+async fn installed_at(game_id: &str) -> Option<u64> {
+    let modified = tokio::fs::metadata(storage::game_file(game_id, GAME_JSON))
+        .await
+        .ok()?
+        .modified()
+        .ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+/**
+ * Uninstall games suggested for removal once a day, if `DEVCADE_AUTO_REMOVE_UNPLAYED` is set. Each
+ * removal is logged and announced with a `GameAutoRemoved` event so operators can see what
+ * happened. Returns immediately if automatic removal is disabled.
+ */
+pub async fn run() {
+    if !env::auto_remove_unplayed() {
+        return;
+    }
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let candidates = match candidates().await {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                log::warn!("Couldn't find games to remove: {e}");
+                continue;
+            }
+        };
+        for candidate in candidates {
+            match api::uninstall_game(candidate.game_id.clone()).await {
+                Ok(()) => {
+                    log::warn!(
+                        "Automatically removed game {}: {}",
+                        candidate.game_id,
+                        candidate.reason
+                    );
+                    events::emit(Event::GameAutoRemoved(candidate));
+                }
+                Err(e) => log::warn!("Couldn't remove game {}: {e}", candidate.game_id),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000 * SECS_PER_DAY;
+
+    #[test]
+    fn suggests_large_unplayed_games() {
+        let candidate = check(
+            String::from("big"),
+            600 * BYTES_PER_MIB,
+            Some(NOW - 100 * SECS_PER_DAY),
+            NOW - 100 * SECS_PER_DAY,
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(candidate.reason, "600 MiB and not played in 100 days");
+    }
+
+    #[test]
+    fn keeps_small_or_recent_games() {
+        let old = NOW - 100 * SECS_PER_DAY;
+        assert!(check(String::from("small"), BYTES_PER_MIB, None, old, NOW).is_none());
+        let recent = NOW - 10 * SECS_PER_DAY;
+        assert!(check(
+            String::from("recent"),
+            600 * BYTES_PER_MIB,
+            None,
+            recent,
+            NOW
+        )
+        .is_none());
+    }
+}
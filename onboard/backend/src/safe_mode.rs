@@ -0,0 +1,290 @@
+use crate::atomic;
+use crate::env;
+use crate::i18n::tr;
+use crate::install_history::HISTORY_FILE;
+use crate::log_stream;
+use crate::profile;
+use crate::servers::path::onboard_pipe;
+use crate::servers::{next_line, open_server, write_line};
+use crate::storage;
+use anyhow::Error;
+use devcade_onboard_types::schema::Diagnostics;
+use devcade_onboard_types::{Request, RequestBody, Response, ResponseBody};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::io::{Lines, WriteHalf};
+use tokio::sync::Mutex;
+
+/**
+ * The file (relative to the devcade path) counting how many times in a row the backend has started
+ * without staying up
+ */
+const ATTEMPTS_FILE: &str = "startup_attempts";
+
+/**
+ * The file (relative to the devcade path) the last panic is written to
+ */
+const PANIC_FILE: &str = "last_panic.txt";
+
+/**
+ * How long the backend has to stay up for a start to count as successful
+ */
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/**
+ * Files (relative to the devcade path) that are rebuilt if they're missing, so resetting caches
+ * moves them aside instead of deleting them
+ */
+const CACHE_FILES: [&str; 2] = [storage::MANIFEST_FILE, HISTORY_FILE];
+
+/**
+ * Directories (relative to the devcade path) that only hold temporary files
+ */
+const TMP_DIRS: [&str; 2] = [storage::SESSION_TMP_DIR, storage::VALIDATE_TMP_DIR];
+
+static SAFE_MODE: AtomicBool = AtomicBool::new(false);
+
+/**
+ * Count this start towards the crash loop and record any panics from here on. Must be called once
+ * at startup, before anything that could panic. Returns how many times in a row (including this
+ * one) the backend has started without staying up.
+ */
+pub fn begin_startup() -> u32 {
+    let attempts = record_attempt();
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // Nothing useful can be done if this fails, the default hook still prints the panic
+        let _ = std::fs::write(storage::root().join(PANIC_FILE), info.to_string());
+        default_hook(info);
+    }));
+
+    attempts
+}
+
+/**
+ * Count a start towards the crash loop, returning how many starts in a row there have now been
+ */
+fn record_attempt() -> u32 {
+    let root = storage::root();
+    if let Err(err) = std::fs::create_dir_all(&root) {
+        tracing::warn!("Couldn't create devcade dir: {}", err);
+    }
+    let attempts = read_attempts().saturating_add(1);
+    if let Err(err) = atomic::write(&root.join(ATTEMPTS_FILE), attempts.to_string()) {
+        tracing::warn!("Couldn't record startup attempt: {}", err);
+    }
+    attempts
+}
+
+fn read_attempts() -> u32 {
+    std::fs::read_to_string(storage::root().join(ATTEMPTS_FILE))
+        .ok()
+        .and_then(|attempts| attempts.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/**
+ * Whether the backend has failed to stay up so many times in a row that it should start in safe
+ * mode
+ */
+#[must_use]
+pub fn in_crash_loop(attempts: u32) -> bool {
+    past_limit(attempts, env::crash_loop_starts())
+}
+
+/**
+ * Whether a number of starts in a row is past the limit, where a limit of 0 is never reached
+ */
+fn past_limit(attempts: u32, limit: u32) -> bool {
+    limit != 0 && attempts > limit
+}
+
+/**
+ * Stop counting this start towards the crash loop once the backend has stayed up for a while.
+ * Should be spawned once everything has started.
+ */
+pub async fn mark_stable() {
+    tokio::time::sleep(STABLE_AFTER).await;
+    clear_attempts();
+    tracing::debug!("Backend is stable, cleared startup attempts");
+}
+
+fn clear_attempts() {
+    if let Err(err) = std::fs::remove_file(storage::root().join(ATTEMPTS_FILE)) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("Couldn't clear startup attempts: {}", err);
+        }
+    }
+}
+
+/**
+ * Whether the backend is running in safe mode
+ */
+#[must_use]
+pub fn active() -> bool {
+    SAFE_MODE.load(Ordering::Relaxed)
+}
+
+/**
+ * What the backend knows about its own health
+ */
+#[must_use]
+pub fn diagnostics() -> Diagnostics {
+    Diagnostics {
+        safe_mode: active(),
+        startup_attempts: read_attempts(),
+        last_panic: std::fs::read_to_string(storage::root().join(PANIC_FILE)).ok(),
+        config_problems: config_problems(),
+    }
+}
+
+/**
+ * Find problems with the backend's configuration that could stop it from starting
+ */
+fn config_problems() -> Vec<String> {
+    let mut problems = vec![];
+    match profile::validate() {
+        Ok(warnings) => problems.extend(
+            warnings
+                .into_iter()
+                .map(|warning| format!("Profile {}: {}", env::profile_name(), warning)),
+        ),
+        Err(err) => problems.push(format!("Invalid profile {}: {}", env::profile_name(), err)),
+    }
+    if !env::api_configured() {
+        problems.push(String::from("The API domain isn't set"));
+    }
+    if let Err(err) = storage::check_writable(storage::root().as_path()) {
+        problems.push(format!(
+            "Devcade dir {} isn't writable: {}",
+            storage::root().display(),
+            err
+        ));
+    }
+    problems
+}
+
+/**
+ * Move aside the files the backend rebuilds on startup and clear out temporary files, in case one
+ * of them is what's crashing it
+ */
+fn reset_caches() -> Result<(), Error> {
+    let root = storage::root();
+    for file in CACHE_FILES {
+        let path = root.join(file);
+        if path.exists() {
+            std::fs::rename(&path, root.join(format!("{file}.bak")))?;
+            tracing::info!("Moved aside {}", path.display());
+        }
+    }
+    for dir in TMP_DIRS {
+        remove_dir(&root.join(dir))?;
+    }
+    Ok(())
+}
+
+fn remove_dir(path: &Path) -> Result<(), Error> {
+    match std::fs::remove_dir_all(path) {
+        Ok(()) => {
+            tracing::info!("Removed {}", path.display());
+            Ok(())
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/**
+ * Run in safe mode: only the onboard socket is served, and only requests that help work out why
+ * the backend keeps crashing are handled. Nothing else (games, the API, the NFC reader) is touched.
+ *
+ * This function never returns. Exiting safe mode exits the backend, so it's started normally.
+ */
+pub async fn run(attempts: u32) -> ! {
+    SAFE_MODE.store(true, Ordering::Relaxed);
+    tracing::error!(
+        "Backend failed to stay up {} times in a row, starting in safe mode",
+        attempts - 1
+    );
+    if let Ok(panic) = std::fs::read_to_string(storage::root().join(PANIC_FILE)) {
+        tracing::error!("Last panic: {}", panic);
+    }
+
+    open_server(
+        onboard_pipe().as_str(),
+        async move |mut lines: Lines<_>, writer: WriteHalf<_>| {
+            let timeout = env::frontend_timeout();
+            let writer = Mutex::new(writer);
+            while let Some(line) = next_line(&mut lines, timeout).await? {
+                let command: Request = serde_json::from_str(&line)?;
+                tracing::debug!("Handling command in safe mode: {}", command);
+                let exiting = matches!(command.body, RequestBody::ExitSafeMode);
+                let response = Response {
+                    request_id: command.request_id,
+                    body: handle(command.body),
+                };
+                let mut response = serde_json::to_vec(&response)?;
+                response.push(b'\n');
+                write_line(&writer, &response, timeout).await?;
+                if exiting {
+                    exit();
+                }
+            }
+            Ok(())
+        },
+    )
+    .await
+}
+
+fn handle(body: RequestBody) -> ResponseBody {
+    match body {
+        RequestBody::Ping => ResponseBody::Pong,
+        RequestBody::GetDiagnostics => ResponseBody::Diagnostics(diagnostics()),
+        RequestBody::GetBackendLogs => ResponseBody::BackendLogs(log_stream::recent()),
+        RequestBody::ResetCaches => match reset_caches() {
+            Ok(()) => ResponseBody::Ok,
+            Err(err) => ResponseBody::Err(err.to_string()),
+        },
+        RequestBody::ExitSafeMode => ResponseBody::Ok,
+        _ => ResponseBody::Err(tr("safe_mode_unavailable", &[])),
+    }
+}
+
+/**
+ * Leave safe mode by exiting, so whatever supervises the backend starts it again normally
+ */
+fn exit() -> ! {
+    clear_attempts();
+    tracing::info!("Exiting safe mode");
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_are_counted_until_the_backend_is_stable() {
+        storage::test_root();
+        clear_attempts();
+        assert_eq!(record_attempt(), 1);
+        assert_eq!(record_attempt(), 2);
+        assert_eq!(read_attempts(), 2);
+        // What `mark_stable` does once the backend has stayed up
+        clear_attempts();
+        assert_eq!(read_attempts(), 0);
+        assert_eq!(record_attempt(), 1);
+        clear_attempts();
+    }
+
+    #[test]
+    fn safe_mode_starts_once_the_limit_is_passed() {
+        assert!(!past_limit(1, 3));
+        assert!(!past_limit(3, 3));
+        assert!(past_limit(4, 3));
+        // A limit of 0 turns safe mode off
+        assert!(!past_limit(u32::MAX, 0));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::env;
+use anyhow::{anyhow, Error};
+use lazy_static::lazy_static;
+use openssl::hash::MessageDigest;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+/**
+ * What encrypted save files start with, so files saved before encryption was turned on can still
+ * be read
+ */
+const MAGIC: &[u8] = b"DEVCADE-ENC1";
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/**
+ * Mixed into the cabinet secret when deriving the key, so the same secret used for something else
+ * doesn't give the same key
+ */
+const SALT: &[u8] = b"devcade-save-data";
+
+/**
+ * How many rounds of PBKDF2 the key is derived with. This only happens once, at startup.
+ */
+const ITERATIONS: usize = 100_000;
+
+lazy_static! {
+    /**
+     * The key save data is encrypted with, or `None` if it isn't encrypted
+     */
+    static ref KEY: Option<[u8; 32]> = env::save_secret().and_then(|secret| {
+        match derive_key(secret.as_str()) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                log::error!("Couldn't derive the save data key, saves won't be encrypted: {e}");
+                None
+            }
+        }
+    });
+}
+
+/**
+ * Whether save data is encrypted on disk
+ */
+#[must_use]
+pub fn enabled() -> bool {
+    KEY.is_some()
+}
+
+/**
+ * Get what to write to disk for some save data, encrypting it if `DEVCADE_SAVE_SECRET` is set
+ *
+ * # Errors
+ * This function will return an error if the data can't be encrypted.
+ */
+pub fn seal(plain: Vec<u8>) -> Result<Vec<u8>, Error> {
+    match KEY.as_ref() {
+        Some(key) => seal_with(key, plain.as_slice()),
+        None => Ok(plain),
+    }
+}
+
+/**
+ * Get the save data in something read from disk, decrypting it if it was encrypted. Files that
+ * weren't encrypted are read as they are, and are encrypted the next time they're written.
+ *
+ * # Errors
+ * This function will return an error if the data is encrypted and there's no key, or if it can't
+ * be decrypted with the key, like when it's been tampered with.
+ */
+pub fn open(data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if !data.starts_with(MAGIC) {
+        return Ok(data);
+    }
+    let key = KEY
+        .as_ref()
+        .ok_or_else(|| anyhow!("Save data is encrypted, but DEVCADE_SAVE_SECRET isn't set"))?;
+    open_with(key, data.as_slice())
+}
+
+fn derive_key(secret: &str) -> Result<[u8; 32], Error> {
+    let mut key = [0; 32];
+    openssl::pkcs5::pbkdf2_hmac(
+        secret.as_bytes(),
+        SALT,
+        ITERATIONS,
+        MessageDigest::sha256(),
+        &mut key,
+    )?;
+    Ok(key)
+}
+
+fn seal_with(key: &[u8; 32], plain: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut nonce = [0; NONCE_LEN];
+    openssl::rand::rand_bytes(&mut nonce)?;
+    let mut tag = [0; TAG_LEN];
+    let encrypted = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        MAGIC,
+        plain,
+        &mut tag,
+    )?;
+    Ok([MAGIC, &nonce, encrypted.as_slice(), &tag].concat())
+}
+
+fn open_with(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, Error> {
+    let sealed = &data[MAGIC.len()..];
+    if sealed.len() < NONCE_LEN + TAG_LEN {
+        return Err(anyhow!("Encrypted save data is truncated"));
+    }
+    let (nonce, rest) = sealed.split_at(NONCE_LEN);
+    let (encrypted, tag) = rest.split_at(rest.len() - TAG_LEN);
+    decrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        MAGIC,
+        encrypted,
+        tag,
+    )
+    .map_err(|_| anyhow!("Couldn't decrypt save data, the secret may have changed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_data_only_opens_with_the_same_key() {
+        let key = derive_key("cabinet secret").unwrap();
+        let sealed = seal_with(&key, b"{\"level\":\"5\"}").unwrap();
+        assert!(sealed.starts_with(MAGIC));
+        assert_eq!(open_with(&key, &sealed).unwrap(), b"{\"level\":\"5\"}");
+
+        let other = derive_key("another secret").unwrap();
+        assert!(open_with(&other, &sealed).is_err());
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open_with(&key, &tampered).is_err());
+        // Files from before encryption was turned on are read as they are
+        assert_eq!(open(b"{}".to_vec()).unwrap(), b"{}");
+    }
+}
@@ -0,0 +1,39 @@
+use crate::api;
+use crate::env;
+use tokio::signal::unix::{signal, SignalKind};
+
+/**
+ * Write changed saves to storage every `DEVCADE_SAVE_FLUSH_SECONDS`, so they survive the cabinet
+ * losing power. Returns immediately if saves are only written when games exit.
+ */
+pub async fn run() {
+    let Some(period) = env::save_flush_interval() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = api::persistence_flush().await {
+            log::warn!("Couldn't flush changed saves: {e}");
+        }
+    }
+}
+
+/**
+ * Write changed saves to storage when the backend is asked to stop, then exit
+ */
+pub async fn flush_on_terminate() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            log::warn!("Couldn't listen for SIGTERM, saves may be lost when stopping: {e}");
+            return;
+        }
+    };
+    terminate.recv().await;
+    log::info!("Stopping, flushing changed saves first");
+    if let Err(e) = api::persistence_flush().await {
+        log::error!("Couldn't flush changed saves before stopping: {e}");
+    }
+    std::process::exit(0);
+}
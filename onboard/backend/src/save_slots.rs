@@ -0,0 +1,190 @@
+use crate::api;
+use crate::i18n::tr;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::SaveSlot;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * The group (under the game's ID) each slot's label, timestamp, and thumbnail are kept in, by slot
+ * ID. Each slot's own data is kept in the groups under `slots/<slot id>/`.
+ */
+const SLOTS_GROUP: &str = "slots";
+
+/**
+ * How many hex digits slot IDs have
+ */
+const ID_LEN: usize = 16;
+
+/**
+ * The longest label a slot can have, in characters
+ */
+const MAX_LABEL_LEN: usize = 64;
+
+/**
+ * The largest thumbnail a slot can have, in bytes of base64
+ */
+const MAX_THUMBNAIL_LEN: usize = 256 * 1024;
+
+/**
+ * List a game's save slots, most recently saved first
+ *
+ * # Errors
+ * This function will return an error if the slots can't be read.
+ */
+pub async fn list(game_id: &str) -> Result<Vec<SaveSlot>, Error> {
+    let group = format!("{game_id}/{SLOTS_GROUP}");
+    let mut slots = vec![];
+    let mut cursor = None;
+    loop {
+        let page = api::persistence_scan(group.as_str(), "", cursor).await?;
+        for (id, slot) in page.entries {
+            match serde_json::from_str::<SaveSlot>(slot.as_str()) {
+                Ok(slot) => slots.push(slot),
+                Err(e) => log::warn!("Save slot {id} of {game_id} is corrupt: {e}"),
+            }
+        }
+        cursor = page.cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    slots.sort_by_key(|slot| std::cmp::Reverse(slot.updated_at));
+    Ok(slots)
+}
+
+/**
+ * Create a save slot for a game. The game saves the slot's data in groups under
+ * `slots/<slot id>/`.
+ *
+ * # Errors
+ * This function will return an error if the label or thumbnail isn't allowed, or if the slot can't
+ * be saved.
+ */
+pub async fn create(
+    game_id: &str,
+    label: &str,
+    thumbnail: Option<String>,
+) -> Result<SaveSlot, Error> {
+    let slot = SaveSlot {
+        id: new_id()?,
+        label: check_label(label)?,
+        updated_at: now(),
+        thumbnail: check_thumbnail(thumbnail)?,
+    };
+    write(game_id, &slot).await?;
+    log::info!("Created save slot {} for {game_id}", slot.id);
+    Ok(slot)
+}
+
+/**
+ * Change a save slot's label and thumbnail, marking it as just saved
+ *
+ * # Errors
+ * This function will return an error if there's no such slot, if the label or thumbnail isn't
+ * allowed, or if the slot can't be saved.
+ */
+pub async fn update(
+    game_id: &str,
+    slot_id: &str,
+    label: &str,
+    thumbnail: Option<String>,
+) -> Result<SaveSlot, Error> {
+    get(game_id, slot_id).await?;
+    let slot = SaveSlot {
+        id: slot_id.to_string(),
+        label: check_label(label)?,
+        updated_at: now(),
+        thumbnail: check_thumbnail(thumbnail)?,
+    };
+    write(game_id, &slot).await?;
+    Ok(slot)
+}
+
+/**
+ * Delete a save slot and everything the game saved in it
+ *
+ * # Errors
+ * This function will return an error if there's no such slot, or if it can't be deleted.
+ */
+pub async fn delete(game_id: &str, slot_id: &str) -> Result<(), Error> {
+    get(game_id, slot_id).await?;
+    log::info!("Deleting save slot {slot_id} of {game_id}");
+    api::persistence_clear(format!("{game_id}/{SLOTS_GROUP}/{slot_id}").as_str()).await?;
+    api::persistence_delete(format!("{game_id}/{SLOTS_GROUP}").as_str(), slot_id).await
+}
+
+/**
+ * Get one of a game's save slots. Slot IDs name directories, so only ones shaped like those from
+ * `create` are looked up.
+ */
+async fn get(game_id: &str, slot_id: &str) -> Result<SaveSlot, Error> {
+    let not_found = || anyhow!(tr("save_slot_not_found", &[("slot", slot_id)]));
+    if slot_id.len() != ID_LEN || !slot_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(not_found());
+    }
+    let slot = api::persistence_load(format!("{game_id}/{SLOTS_GROUP}").as_str(), slot_id)
+        .await
+        .map_err(|_| not_found())?;
+    Ok(serde_json::from_str(slot.as_str())?)
+}
+
+async fn write(game_id: &str, slot: &SaveSlot) -> Result<(), Error> {
+    api::persistence_save(
+        format!("{game_id}/{SLOTS_GROUP}").as_str(),
+        slot.id.as_str(),
+        serde_json::to_string(slot)?.as_str(),
+    )
+    .await
+}
+
+fn check_label(label: &str) -> Result<String, Error> {
+    let label = label.trim();
+    if label.is_empty()
+        || label.chars().count() > MAX_LABEL_LEN
+        || label.chars().any(char::is_control)
+    {
+        return Err(anyhow!(tr(
+            "save_slot_label_invalid",
+            &[("max", MAX_LABEL_LEN.to_string().as_str())]
+        )));
+    }
+    Ok(label.to_string())
+}
+
+fn check_thumbnail(thumbnail: Option<String>) -> Result<Option<String>, Error> {
+    match thumbnail {
+        Some(thumbnail) if thumbnail.len() > MAX_THUMBNAIL_LEN => Err(anyhow!(tr(
+            "save_slot_thumbnail_too_big",
+            &[("max", MAX_THUMBNAIL_LEN.to_string().as_str())]
+        ))),
+        thumbnail => Ok(thumbnail.filter(|thumbnail| !thumbnail.is_empty())),
+    }
+}
+
+fn new_id() -> Result<String, Error> {
+    let mut bytes = [0; ID_LEN / 2];
+    openssl::rand::rand_bytes(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_labels_and_thumbnails() {
+        assert_eq!(check_label(" Level 3 ").unwrap(), "Level 3");
+        assert!(check_label("").is_err());
+        assert!(check_label("a\tb").is_err());
+        assert!(check_label(&"x".repeat(MAX_LABEL_LEN + 1)).is_err());
+        assert_eq!(check_thumbnail(Some(String::new())).unwrap(), None);
+        assert!(check_thumbnail(Some("A".repeat(MAX_THUMBNAIL_LEN + 1))).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use crate::api::{self, save_storage::Storage, SaveBackup};
+use crate::atomic;
+use crate::env;
+use crate::i18n::tr;
+use crate::nfc;
+use crate::save_crypto;
+use anyhow::{anyhow, Error};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use lazy_static::lazy_static;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+/**
+ * The extension groups have in backups, matching the files they're kept in by default
+ */
+const SAVE_EXTENSION: &str = "save";
+
+lazy_static! {
+    /**
+     * A digest of each player's saves as they were last backed up, by game ID and handle, so saves
+     * that haven't changed aren't uploaded again
+     */
+    static ref BACKED_UP: Mutex<HashMap<(String, String), String>> = Mutex::new(HashMap::new());
+}
+
+/**
+ * Back players' saves up to the API every `DEVCADE_SAVE_SYNC_MINUTES`. Returns immediately if
+ * saves aren't backed up.
+ */
+pub async fn run() {
+    let Some(period) = env::save_sync_interval() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        match backup().await {
+            Ok(0) => {}
+            Ok(uploaded) => log::info!("Backed up saves of {uploaded} players"),
+            Err(e) => log::warn!("Couldn't back up saves: {e}"),
+        }
+    }
+}
+
+/**
+ * Back up the saves of every player whose saves changed since they were last backed up, getting
+ * how many were. Only players who logged in have their saves backed up, not guests.
+ *
+ * # Errors
+ * This function will return an error if the saves can't be read. Players whose saves can't be
+ * uploaded are logged and tried again next time.
+ */
+pub async fn backup() -> Result<usize, Error> {
+    let backups = api::persistence_rewrite(|storage, root| read_all(storage, root)).await?;
+    let mut uploaded = 0;
+    for (player, backup) in backups {
+        let digest = sha256::digest(serde_json::to_string(&backup.files)?);
+        if BACKED_UP.lock().unwrap().get(&player) == Some(&digest) {
+            continue;
+        }
+        let (game_id, handle) = &player;
+        match api::upload_saves(game_id, handle, &backup).await {
+            Ok(()) => {
+                BACKED_UP.lock().unwrap().insert(player, digest);
+                uploaded += 1;
+            }
+            Err(e) => log::warn!("Couldn't back up saves of {game_id} for {handle}: {e}"),
+        }
+    }
+    Ok(uploaded)
+}
+
+/**
+ * Replace a player's saves for a game with the last backup from any cabinet, given the handle the
+ * frontend was given for their badge.
+ *
+ * # Errors
+ * This function will return an error if the handle isn't from a recent tap, if there's no backup,
+ * or if the saves can't be written.
+ */
+pub async fn restore(game_id: String, handle: String) -> Result<(), Error> {
+    api::check_game_id(game_id.as_str())?;
+    let handle = nfc::handle_in_game(handle.as_str(), game_id.as_str())
+        .ok_or_else(|| anyhow!(tr("nfc_user_not_found", &[])))?;
+    let backup = api::download_saves(game_id.as_str(), handle.as_str()).await?;
+    let mut files = vec![];
+    for (path, contents) in &backup.files {
+        let path = relative_path(path).ok_or_else(|| anyhow!("Bad path '{path}' in backup"))?;
+        files.push((path, STANDARD.decode(contents)?));
+    }
+
+    log::info!("Restoring saves of {game_id} for {handle}");
+    let dir = PathBuf::from(format!("{game_id}/users/{handle}"));
+    api::persistence_rewrite(move |storage, root| {
+        let dir = root.join(dir);
+        storage.remove_all(&dir)?;
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        for (path, contents) in files {
+            let path = dir.join(path);
+            if path.extension().is_some_and(|ext| ext == SAVE_EXTENSION) {
+                let group = path.with_extension("").to_string_lossy().to_string();
+                storage.write(&[(group, serde_json::from_slice(&contents)?)])?;
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            atomic::write(&path, save_crypto::seal(contents)?)?;
+        }
+        Ok(())
+    })
+    .await?;
+
+    let digest = sha256::digest(serde_json::to_string(&backup.files)?);
+    BACKED_UP.lock().unwrap().insert((game_id, handle), digest);
+    Ok(())
+}
+
+/**
+ * Read the saves of every logged in player, by game ID and handle. Groups are backed up as the
+ * JSON of a `.save` file, whichever storage they're in, so backups restore on any cabinet.
+ */
+fn read_all(
+    storage: &mut dyn Storage,
+    root: &Path,
+) -> Result<BTreeMap<(String, String), SaveBackup>, Error> {
+    let mut backups: BTreeMap<(String, String), SaveBackup> = BTreeMap::new();
+    for (group, _) in storage.groups(root)? {
+        let Some((player, path)) = Path::new(&group)
+            .strip_prefix(root)
+            .ok()
+            .and_then(player_path)
+        else {
+            continue;
+        };
+        let values = match storage.read(group.as_str()) {
+            Ok(Some(values)) => values,
+            Ok(None) => continue,
+            Err(e) => {
+                log::warn!("Couldn't back up {group}: {e}");
+                continue;
+            }
+        };
+        backups.entry(player).or_default().files.insert(
+            format!("{path}.{SAVE_EXTENSION}"),
+            STANDARD.encode(serde_json::to_vec(&values)?),
+        );
+    }
+    for game in subdirectories(root) {
+        for player in subdirectories(&game.join("users")) {
+            let (Some(game_id), Some(handle)) = (file_name(&game), file_name(&player)) else {
+                continue;
+            };
+            if nfc::save_namespace(Some(handle.as_str())).is_err() {
+                continue;
+            }
+            let backup = backups.entry((game_id, handle)).or_default();
+            read_files(&player, &player, backup);
+        }
+    }
+    Ok(backups)
+}
+
+/**
+ * Get the player a group under the save directory belongs to, and its path under their directory
+ */
+fn player_path(group: &Path) -> Option<((String, String), String)> {
+    let mut parts = group.iter().map(|part| part.to_str());
+    let (Some(Some(game_id)), Some(Some("users")), Some(Some(handle))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    // Guests have nobody to restore their saves to
+    nfc::save_namespace(Some(handle)).ok()?;
+    let path = parts.collect::<Option<Vec<_>>>()?.join("/");
+    (!path.is_empty()).then(|| ((game_id.to_string(), handle.to_string()), path))
+}
+
+fn read_files(root: &Path, dir: &Path, backup: &mut SaveBackup) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            read_files(root, &path, backup);
+            continue;
+        }
+        // Groups are read through the storage, which may still keep them in files
+        if path.extension().is_some_and(|ext| ext == SAVE_EXTENSION) {
+            continue;
+        }
+        let (Ok(relative), Ok(contents)) = (path.strip_prefix(root), std::fs::read(&path)) else {
+            continue;
+        };
+        // Backups are decrypted, so they can be restored on cabinets with a different secret
+        let contents = match save_crypto::open(contents) {
+            Ok(contents) => contents,
+            Err(e) => {
+                log::warn!("Couldn't back up {:?}: {e}", path);
+                continue;
+            }
+        };
+        backup.files.insert(
+            relative.to_string_lossy().to_string(),
+            STANDARD.encode(contents),
+        );
+    }
+}
+
+fn subdirectories(dir: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn file_name(path: &Path) -> Option<String> {
+    Some(path.file_name()?.to_str()?.to_string())
+}
+
+/**
+ * Check a path from a backup stays inside the player's save directory
+ */
+fn relative_path(path: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(path);
+    let inside = path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    inside.then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backups_cant_escape_the_save_directory() {
+        assert_eq!(
+            relative_path("progress.blobs/abc"),
+            Some(PathBuf::from("progress.blobs/abc"))
+        );
+        assert!(relative_path("../other-player/progress.save").is_none());
+        assert!(relative_path("/etc/passwd").is_none());
+        assert!(relative_path("").is_none());
+    }
+
+    #[test]
+    fn only_players_groups_are_backed_up() {
+        let handle = "a".repeat(64);
+        let group = format!("game/users/{handle}/levels/progress");
+        assert_eq!(
+            player_path(Path::new(&group)),
+            Some((
+                (String::from("game"), handle),
+                String::from("levels/progress")
+            ))
+        );
+        assert!(player_path(Path::new("game/users/guest/progress")).is_none());
+        assert!(player_path(Path::new("game/progress")).is_none());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::env;
+use crate::i18n::tr;
+use crate::servers::write_line;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::SaveChange;
+use devcade_onboard_types::{Event, Response, ResponseBody, EVENT_REQUEST_ID};
+use lazy_static::lazy_static;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::WriteHalf;
+use tokio::net::UnixStream;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/**
+ * How many groups and key prefixes one connection can watch at once
+ */
+const MAX_WATCHES: usize = 64;
+
+lazy_static! {
+    /**
+     * Every change to a saved value, by its full group (starting with the game's ID). Connections
+     * pick out the ones they're watching.
+     */
+    static ref CHANGES: broadcast::Sender<SaveChange> = broadcast::channel(env::event_buffer()).0;
+}
+
+/**
+ * Tell connections watching a key that its value changed. Pass `None` when the value was deleted.
+ */
+pub fn notify(group: &str, key: &str, value: Option<&str>, blob: bool) {
+    // An error here only means nobody is watching right now
+    let _ = CHANGES.send(SaveChange {
+        group: group.to_string(),
+        key: key.to_string(),
+        value: value.filter(|_| !blob).map(str::to_string),
+        blob,
+        reset: false,
+    });
+}
+
+/**
+ * Tell connections watching any group under a namespace (like a game's ID, or `""` for every
+ * game) that its values were all deleted or replaced at once, so they should load them again.
+ */
+pub fn reset(namespace: &str) {
+    let _ = CHANGES.send(SaveChange {
+        group: namespace.to_string(),
+        reset: true,
+        ..SaveChange::default()
+    });
+}
+
+/**
+ * A key prefix in a group that a connection is watching
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Watch {
+    /**
+     * The group's full name, starting with the game's ID
+     */
+    group: String,
+    /**
+     * The group's name as the connection gave it, which changes are sent with
+     */
+    name: String,
+    prefix: String,
+}
+
+/**
+ * The keys one connection is watching
+ */
+#[derive(Default)]
+pub struct Watches(Mutex<Vec<Watch>>);
+
+impl Watches {
+    /**
+     * Start watching keys starting with a prefix. `group` is the group's full name, and `name` is
+     * what the connection calls it. Watching the same keys twice does nothing.
+     *
+     * # Errors
+     * This function will return an error if the connection is already watching too many keys.
+     */
+    pub fn watch(&self, group: String, name: String, prefix: String) -> Result<(), Error> {
+        let mut watches = self.0.lock().unwrap();
+        let watch = Watch {
+            group,
+            name,
+            prefix,
+        };
+        if watches.contains(&watch) {
+            return Ok(());
+        }
+        if watches.len() >= MAX_WATCHES {
+            return Err(anyhow!(tr(
+                "save_watch_limit",
+                &[("max", MAX_WATCHES.to_string().as_str())]
+            )));
+        }
+        watches.push(watch);
+        Ok(())
+    }
+
+    /**
+     * Stop watching keys starting with a prefix. Keys that weren't being watched are ignored.
+     */
+    pub fn unwatch(&self, group: &str, prefix: &str) {
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|watch| watch.group != group || watch.prefix != prefix);
+    }
+
+    /**
+     * Get a change the way the connection should be sent it, if it's watching the change's key. A
+     * reset is sent once for each watched group under its namespace.
+     */
+    fn matching(&self, change: &SaveChange) -> Vec<SaveChange> {
+        let mut sent: Vec<SaveChange> = vec![];
+        for watch in self.0.lock().unwrap().iter() {
+            let watched = if change.reset {
+                change.group.is_empty()
+                    || watch.group == change.group
+                    || watch.group.starts_with(&format!("{}/", change.group))
+            } else {
+                watch.group == change.group && change.key.starts_with(&watch.prefix)
+            };
+            if watched && !sent.iter().any(|change| change.group == watch.name) {
+                sent.push(SaveChange {
+                    group: watch.name.clone(),
+                    ..change.clone()
+                });
+            }
+        }
+        sent
+    }
+}
+
+/**
+ * Push changes to the keys a connection is watching until it's closed. A connection that can't
+ * keep up is sent an `Event::Gap` in place of the changes it missed.
+ */
+pub async fn forward(
+    watches: Arc<Watches>,
+    writer: Arc<tokio::sync::Mutex<WriteHalf<UnixStream>>>,
+    timeout: Option<Duration>,
+) {
+    let mut changes = CHANGES.subscribe();
+    loop {
+        let events = match changes.recv().await {
+            Ok(change) => watches
+                .matching(&change)
+                .into_iter()
+                .map(Event::SaveChanged)
+                .collect(),
+            Err(RecvError::Lagged(missed)) => {
+                log::warn!("A connection watching saves missed {missed} changes");
+                vec![Event::Gap(missed)]
+            }
+            Err(RecvError::Closed) => unreachable!("The change sender is never dropped"),
+        };
+        for event in events {
+            let response = Response {
+                request_id: EVENT_REQUEST_ID,
+                body: ResponseBody::Event(event),
+            };
+            log::trace!("Sending: {response}");
+            let mut response = match serde_json::to_vec(&response) {
+                Ok(response) => response,
+                Err(err) => {
+                    log::error!("Couldn't serialize save change: {err}");
+                    continue;
+                }
+            };
+            response.push(b'\n');
+            if let Err(err) = write_line(&writer, &response, timeout).await {
+                log::debug!("Stopped sending save changes: {err}");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_sent_with_the_watchers_group_name() {
+        let watches = Watches::default();
+        watches
+            .watch("pong/scores".into(), "scores".into(), "p1-".into())
+            .unwrap();
+        let change = |group: &str, key: &str| SaveChange {
+            group: group.to_string(),
+            key: key.to_string(),
+            value: Some(String::from("10")),
+            blob: false,
+            reset: false,
+        };
+        let sent = watches.matching(&change("pong/scores", "p1-high"));
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].group, "scores");
+        assert_eq!(sent[0].value.as_deref(), Some("10"));
+        assert!(watches
+            .matching(&change("pong/scores", "p2-high"))
+            .is_empty());
+        assert!(watches
+            .matching(&change("tetris/scores", "p1-high"))
+            .is_empty());
+        watches.unwatch("pong/scores", "p1-");
+        assert!(watches
+            .matching(&change("pong/scores", "p1-high"))
+            .is_empty());
+    }
+
+    #[test]
+    fn resets_reach_every_watched_group_under_the_namespace() {
+        let watches = Watches::default();
+        for (group, name, prefix) in [
+            ("pong/scores", "scores", "p1-"),
+            ("pong/scores", "scores", "p2-"),
+            ("pong/settings", "settings", ""),
+            ("pongo/scores", "scores", ""),
+        ] {
+            watches
+                .watch(group.into(), name.into(), prefix.into())
+                .unwrap();
+        }
+        let reset = |namespace: &str| SaveChange {
+            group: namespace.to_string(),
+            reset: true,
+            ..SaveChange::default()
+        };
+        let groups = |sent: Vec<SaveChange>| -> Vec<String> {
+            sent.into_iter().map(|change| change.group).collect()
+        };
+        assert_eq!(
+            groups(watches.matching(&reset("pong"))),
+            ["scores", "settings"]
+        );
+        assert_eq!(
+            groups(watches.matching(&reset("pong/settings"))),
+            ["settings"]
+        );
+        assert_eq!(groups(watches.matching(&reset(""))), ["scores", "settings"]);
+        assert!(watches.matching(&reset("tetris")).is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::api;
+use crate::atomic;
+use crate::env;
+use crate::signing::{self, DetachedSignature};
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command;
+
+/**
+ * The version of the running backend
+ */
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/**
+ * How often to check whether the game that's running has exited, when an update is waiting for it
+ */
+const IDLE_POLL: Duration = Duration::from_secs(60);
+
+/**
+ * An onboard release, as published by the API. The signature is of the binary's SHA-256 hash, the
+ * same way game bundles are signed.
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Release {
+    pub version: String,
+    pub sha256: String,
+    pub signature: DetachedSignature,
+}
+
+/**
+ * Check for a new build of the backend every `DEVCADE_SELF_UPDATE_HOURS`, install it once no game
+ * is running, and restart into it. Returns immediately if the backend doesn't update itself, and
+ * should be spawned as a task at startup.
+ */
+pub async fn run() {
+    let Some(period) = env::self_update_interval() else {
+        return;
+    };
+    if env::release_keys().is_empty() {
+        log::warn!("Not updating the backend, DEVCADE_RELEASE_KEYS isn't set");
+        return;
+    }
+    loop {
+        match update().await {
+            Ok(Some(version)) => restart(version.as_str()).await,
+            Ok(None) => log::debug!("The backend is up to date ({CURRENT_VERSION})"),
+            Err(e) => log::warn!("Couldn't update the backend, trying again in {period:?}: {e}"),
+        }
+        tokio::time::sleep(period).await;
+    }
+}
+
+/**
+ * Download, check and install the newest release if it's newer than the running backend. The new
+ * binary replaces the running one, which is kept next to it with a `.old` extension to go back to
+ * by hand.
+ *
+ * # Errors
+ * This function will return an error if the release can't be downloaded, doesn't match its hash or
+ * signature, or the binary can't be replaced. The running binary is untouched if it fails.
+ */
+pub async fn update() -> Result<Option<String>, Error> {
+    if !env::api_configured() {
+        return Ok(None);
+    }
+    let release = api::latest_release().await?;
+    if !newer(release.version.as_str(), CURRENT_VERSION) {
+        return Ok(None);
+    }
+    log::info!(
+        "Downloading onboard {} (running {CURRENT_VERSION})",
+        release.version
+    );
+    let binary = api::download_release(release.version.as_str()).await?;
+    let digest = sha256::digest(binary.as_slice());
+    if !digest.eq_ignore_ascii_case(release.sha256.as_str()) {
+        return Err(anyhow!(
+            "Onboard {} doesn't match its published hash",
+            release.version
+        ));
+    }
+    signing::verify_release(digest.as_str(), &release.signature)
+        .map_err(|e| anyhow!("Onboard {} isn't signed properly: {e}", release.version))?;
+
+    wait_for_idle().await;
+    let exe = std::env::current_exe()?;
+    tokio::task::spawn_blocking(move || install(exe.as_path(), binary)).await??;
+    log::info!("Installed onboard {}", release.version);
+    Ok(Some(release.version))
+}
+
+/**
+ * Wait until no game is running, so an update never cuts a game off
+ */
+async fn wait_for_idle() {
+    let mut waiting = false;
+    while api::current_game().is_some() {
+        if !waiting {
+            log::info!("Waiting for the running game to exit before updating");
+            waiting = true;
+        }
+        tokio::time::sleep(IDLE_POLL).await;
+    }
+}
+
+fn install(exe: &Path, binary: Vec<u8>) -> Result<(), Error> {
+    std::fs::copy(exe, sibling(exe, "old"))?;
+    atomic::write_executable(exe, binary)
+}
+
+fn sibling(exe: &Path, extension: &str) -> PathBuf {
+    let mut name = exe.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{extension}"));
+    exe.with_file_name(name)
+}
+
+/**
+ * Write anything waiting to be saved, then have systemd restart the backend into the new build. If
+ * the restart fails, the new build is used the next time the backend starts.
+ */
+async fn restart(version: &str) {
+    if let Err(e) = api::persistence_flush().await {
+        log::warn!("Couldn't flush saves before restarting: {e}");
+    }
+    let unit = env::systemd_unit();
+    log::info!("Restarting {unit} into onboard {version}");
+    match Command::new("systemctl")
+        .args(["restart", unit.as_str()])
+        .status()
+        .await
+    {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::warn!(
+            "Couldn't restart {unit} ({status}), onboard {version} will run after the next restart"
+        ),
+        Err(e) => log::warn!(
+            "Couldn't run systemctl, onboard {version} will run after the next restart: {e}"
+        ),
+    }
+}
+
+/**
+ * Whether `candidate` is a newer version than `current`. Versions are compared by their dotted
+ * numbers, ignoring a leading `v` and anything after a `-` or `+`; a version that can't be read is
+ * never newer.
+ */
+fn newer(candidate: &str, current: &str) -> bool {
+    match (parse_version(candidate), parse_version(current)) {
+        (Some(candidate), Some(current)) => candidate > current,
+        _ => false,
+    }
+}
+
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_compare_by_their_numbers() {
+        assert!(newer("0.10.0", "0.9.3"));
+        assert!(newer("v1.0.0", "0.9.3"));
+        assert!(newer("1.0.1-rc1", "1.0.0"));
+        assert!(!newer("1.0.0", "1.0.0"));
+        assert!(!newer("0.9.0", "1.0.0"));
+        assert!(!newer("latest", "1.0.0"));
+    }
+}
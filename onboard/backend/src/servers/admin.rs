@@ -0,0 +1,304 @@
+use crate::api;
+use crate::command::handle;
+use crate::env;
+use crate::events;
+use crate::game_logs::game_logs;
+use crate::install_queue;
+use crate::log_stream;
+use crate::nfc;
+use crate::readiness;
+use crate::servers::http::{self, HttpRequest, HttpResponse};
+use crate::storage;
+use devcade_onboard_types::schema::{CorruptGame, DevcadeGame, InstallJob, NfcStatus, Readiness};
+use devcade_onboard_types::RequestBody;
+use log::LevelFilter;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::Instrument;
+
+/**
+ * The dashboard page, served at `/`
+ */
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/**
+ * How long a client has to send its request before the connection is dropped
+ */
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/**
+ * How often a comment is sent on an idle log stream, so proxies and the client don't time it out
+ */
+const TAIL_KEEPALIVE: Duration = Duration::from_secs(15);
+
+/**
+ * What the admin server sends back for a request
+ */
+enum Reply {
+    Response(HttpResponse),
+    /**
+     * Stream log lines at or above the level, from sources starting with the prefix (if any)
+     */
+    TailLogs(LevelFilter, Option<String>),
+}
+
+/**
+ * A snapshot of what the cabinet is doing, shown on the dashboard
+ */
+#[derive(Serialize)]
+struct Status {
+    uptime_secs: u64,
+    current_game: Option<DevcadeGame>,
+    queue: Vec<InstallJob>,
+    installed_games: usize,
+    corrupt_games: Vec<CorruptGame>,
+    /**
+     * How many times each game has crashed in a row
+     */
+    crash_counts: HashMap<String, u32>,
+    /**
+     * How many events each event subscriber has had dropped for falling behind
+     */
+    dropped_events: BTreeMap<String, u64>,
+    /**
+     * When the game list was last fetched from the API, in seconds since the unix epoch
+     */
+    last_api_sync: Option<u64>,
+    readers: Vec<NfcStatus>,
+    /**
+     * Bytes free on the disk games are installed to
+     */
+    disk_free_bytes: Option<u64>,
+    /**
+     * What the startup self-check found
+     */
+    readiness: Readiness,
+}
+
+/**
+ * Main function for the admin HTTP server. This serves the dashboard and the JSON API behind it,
+ * as well as `/healthz` and `/status` for monitoring. Anyone on the network can check `/healthz`.
+ * The cabinet's status needs the status token (so monitoring doesn't need to hold the admin token)
+ * or the admin token, and its logs (which can hold player data) and operator actions need the
+ * admin token.
+ *
+ * This function will never return unless it panics and should be spawned as a thread.
+ */
+pub async fn main(address: SocketAddr) -> ! {
+    let started = Instant::now();
+    let listener = TcpListener::bind(address)
+        .await
+        .unwrap_or_else(|e| panic!("Couldn't bind admin server to {address}: {e}"));
+    tracing::info!("Serving admin dashboard on http://{address}");
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("Couldn't accept admin connection: {e}");
+                continue;
+            }
+        };
+        tokio::spawn(
+            async move {
+                if let Err(e) = handle_connection(stream, started).await {
+                    tracing::debug!("Admin connection from {peer} failed: {e}");
+                }
+            }
+            .instrument(tracing::debug_span!("admin_client", %peer)),
+        );
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, started: Instant) -> Result<(), anyhow::Error> {
+    let reply = match tokio::time::timeout(REQUEST_TIMEOUT, http::read_request(&mut stream)).await {
+        Ok(Ok(request)) => {
+            tracing::debug!("Admin request: {} {}", request.method, request.path);
+            route(request, started).await
+        }
+        Ok(Err(e)) => Reply::Response(HttpResponse::error(400, e.to_string().as_str())),
+        Err(_) => Reply::Response(HttpResponse::error(
+            400,
+            "Timed out waiting for the request",
+        )),
+    };
+    match reply {
+        Reply::Response(response) => http::write_response(&mut stream, &response).await,
+        Reply::TailLogs(level, source) => tail_logs(&mut stream, level, source).await,
+    }
+}
+
+async fn route(request: HttpRequest, started: Instant) -> Reply {
+    if request.method == "GET" && request.path == "/api/logs/tail" {
+        if let Err(response) = authorize(&request) {
+            return Reply::Response(response);
+        }
+        let level = match request
+            .query
+            .get("level")
+            .map(|level| level.parse::<LevelFilter>())
+        {
+            Some(Ok(level)) => level,
+            Some(Err(e)) => {
+                return Reply::Response(HttpResponse::error(400, e.to_string().as_str()))
+            }
+            None => LevelFilter::Info,
+        };
+        return Reply::TailLogs(level, request.query.get("source").cloned());
+    }
+    Reply::Response(respond(request, started).await)
+}
+
+async fn respond(request: HttpRequest, started: Instant) -> HttpResponse {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => HttpResponse::new(200, "text/html; charset=utf-8", DASHBOARD),
+        ("GET", "/healthz") => HttpResponse::new(200, "text/plain; charset=utf-8", "ok\n"),
+        ("GET", "/api/status" | "/status") => {
+            if let Err(response) = authorize_status(&request) {
+                return response;
+            }
+            HttpResponse::json(200, &status(started).await)
+        }
+        ("GET", "/api/logs") => {
+            if let Err(response) = authorize(&request) {
+                return response;
+            }
+            let Some(game_id) = request.query.get("game") else {
+                return HttpResponse::error(400, "Missing 'game' parameter");
+            };
+            let session = request.query.get("session").cloned();
+            match game_logs(game_id, session).await {
+                Ok(lines) => HttpResponse::json(200, &lines),
+                Err(e) => HttpResponse::error(404, e.to_string().as_str()),
+            }
+        }
+        ("POST", "/api/command") => {
+            if let Err(response) = authorize(&request) {
+                return response;
+            }
+            let command: RequestBody = match serde_json::from_slice(&request.body) {
+                Ok(command) => command,
+                Err(e) => return HttpResponse::error(400, e.to_string().as_str()),
+            };
+            tracing::info!("Handling operator command: {command}");
+            HttpResponse::json(200, &handle(command).await)
+        }
+        (
+            _,
+            "/" | "/healthz" | "/status" | "/api/status" | "/api/logs" | "/api/logs/tail"
+            | "/api/command",
+        ) => HttpResponse::error(405, "Method not allowed"),
+        _ => HttpResponse::error(404, "Not found"),
+    }
+}
+
+/**
+ * Stream backend and game logs to a client as server-sent events until it disconnects. Each line is
+ * sent as a JSON `log` event, and a `gap` event says how many lines were skipped if the client
+ * falls behind.
+ */
+async fn tail_logs(
+    stream: &mut TcpStream,
+    level: LevelFilter,
+    source: Option<String>,
+) -> Result<(), anyhow::Error> {
+    let mut lines = log_stream::subscribe();
+    http::write_event_stream_head(&mut *stream).await?;
+    tracing::info!("Streaming logs at level {level} to an admin client");
+    loop {
+        let line = match tokio::time::timeout(TAIL_KEEPALIVE, lines.recv()).await {
+            Ok(Ok(line)) => line,
+            Ok(Err(RecvError::Lagged(missed))) => {
+                http::write_event(&mut *stream, "gap", missed.to_string().as_str()).await?;
+                continue;
+            }
+            Ok(Err(RecvError::Closed)) => return Ok(()),
+            Err(_) => {
+                http::write_event(&mut *stream, "keepalive", "").await?;
+                continue;
+            }
+        };
+        if line.level > level {
+            continue;
+        }
+        if let Some(source) = &source {
+            if !line.source.starts_with(source.as_str()) {
+                continue;
+            }
+        }
+        let data = serde_json::to_string(&line)?;
+        http::write_event(&mut *stream, "log", data.as_str()).await?;
+    }
+}
+
+/**
+ * Check that a request carries the admin token, either as a bearer token or a `token` query
+ * parameter (browsers can't set headers on event streams). Everything but `/healthz` and the
+ * dashboard page is refused outright if no token is configured.
+ */
+fn authorize(request: &HttpRequest) -> Result<(), HttpResponse> {
+    let Some(expected) = env::admin_token() else {
+        return Err(HttpResponse::error(
+            403,
+            "The admin API is disabled, set DEVCADE_ADMIN_TOKEN to enable it",
+        ));
+    };
+    match request_token(request) {
+        Some(token) if http::token_matches(token, expected.as_str()) => Ok(()),
+        _ => Err(HttpResponse::error(401, "Missing or invalid admin token")),
+    }
+}
+
+/**
+ * Check that a request carries the status token or the admin token. The status token only lets
+ * the cabinet's status be read, so it can be given to monitoring.
+ */
+fn authorize_status(request: &HttpRequest) -> Result<(), HttpResponse> {
+    let Some(expected) = env::status_token() else {
+        return authorize(request);
+    };
+    match request_token(request) {
+        Some(token) if http::token_matches(token, expected.as_str()) => Ok(()),
+        _ => authorize(request)
+            .map_err(|_| HttpResponse::error(401, "Missing or invalid status token")),
+    }
+}
+
+/**
+ * Get the token a request was sent with, either as a bearer token or a `token` query parameter
+ */
+fn request_token(request: &HttpRequest) -> Option<&str> {
+    request
+        .bearer_token()
+        .or(request.query.get("token").map(String::as_str))
+}
+
+async fn status(started: Instant) -> Status {
+    let (installed_games, corrupt_games) = match api::game_list_from_fs().await {
+        Ok(installed) => (installed.games.len(), installed.corrupt),
+        Err(e) => (
+            0,
+            vec![CorruptGame {
+                path: env::devcade_path(),
+                error: e.to_string(),
+            }],
+        ),
+    };
+    Status {
+        uptime_secs: started.elapsed().as_secs(),
+        current_game: api::current_game(),
+        queue: install_queue::queue_status(),
+        installed_games,
+        corrupt_games,
+        crash_counts: api::crash_counts(),
+        dropped_events: events::dropped(),
+        last_api_sync: api::last_api_sync(),
+        readers: nfc::nfc_status(),
+        disk_free_bytes: storage::free_space(&storage::root()),
+        readiness: readiness::latest(),
+    }
+}
@@ -0,0 +1,73 @@
+use crate::command::handle;
+use crate::log_stream;
+use crate::servers::{open_server, write_line};
+use devcade_onboard_types::{Request, RequestBody, Response, ResponseBody};
+use log::LevelFilter;
+use tokio::io::{Lines, WriteHalf};
+use tokio::net::UnixStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+
+/**
+ * Main function for the control server. This takes the same requests as the onboard server, one
+ * at a time, from maintainers running devcadectl on the cabinet. Unlike the frontend, clients
+ * aren't sent events and aren't expected to ping, and they can stream the backend's logs with
+ * `TailLogs`.
+ *
+ * This function will never return unless it panics and should be spawned as a thread.
+ */
+pub async fn main(command_pipe: &str) -> ! {
+    tracing::info!("Starting control server at {}", command_pipe);
+
+    open_server(
+        command_pipe,
+        async move |mut lines: Lines<_>, writer: WriteHalf<_>| {
+            let writer = Mutex::new(writer);
+            while let Some(line) = lines.next_line().await? {
+                let command: Request = serde_json::from_str(&line)?;
+                tracing::info!("Handling control command: {command}");
+                let body = match command.body {
+                    RequestBody::TailLogs(level) => match level.parse::<LevelFilter>() {
+                        Ok(level) => return tail_logs(&writer, command.request_id, level).await,
+                        Err(e) => ResponseBody::Err(e.to_string()),
+                    },
+                    body => handle(body).await,
+                };
+                respond(&writer, command.request_id, body).await?;
+            }
+            Ok(())
+        },
+    )
+    .await
+}
+
+async fn respond(
+    writer: &Mutex<WriteHalf<UnixStream>>,
+    request_id: u32,
+    body: ResponseBody,
+) -> Result<(), anyhow::Error> {
+    let mut response = serde_json::to_vec(&Response { request_id, body })?;
+    response.push(b'\n');
+    write_line(writer, &response, None).await
+}
+
+/**
+ * Send backend and game logs at or above a level to a client until it disconnects. A client that
+ * falls behind is told how many lines it missed.
+ */
+async fn tail_logs(
+    writer: &Mutex<WriteHalf<UnixStream>>,
+    request_id: u32,
+    level: LevelFilter,
+) -> Result<(), anyhow::Error> {
+    let mut lines = log_stream::subscribe();
+    loop {
+        let line = match lines.recv().await {
+            Ok(line) if line.level > level => continue,
+            Ok(line) => format!("[{} {}] {}", line.level, line.source, line.message),
+            Err(RecvError::Lagged(missed)) => format!("... {missed} lines skipped ..."),
+            Err(RecvError::Closed) => return Ok(()),
+        };
+        respond(writer, request_id, ResponseBody::LogLine(line)).await?;
+    }
+}
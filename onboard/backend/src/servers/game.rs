@@ -1,66 +1,278 @@
-use crate::command::handle;
-use crate::servers::open_server;
-use anyhow::anyhow;
+use crate::api;
+use crate::command::{handle, save_error};
+use crate::env;
+use crate::i18n::tr;
+use crate::nfc;
+use crate::save_watch::{self, Watches};
+use crate::servers::{next_line, open_peer_server, read_bytes, write_bytes, write_line};
+use crate::watchdog;
+use anyhow::{anyhow, Error};
 use devcade_onboard_types::{Request, RequestBody, Response, ResponseBody};
 use futures_util::future;
 use std::sync::Arc;
-use tokio::io::{AsyncWriteExt, Lines, WriteHalf};
+use tokio::io::{Lines, WriteHalf};
 use tokio::sync::Mutex;
 use tokio::task;
+use tracing::Instrument;
 
 pub async fn main(command_pipe: &str) -> ! {
-    log::info!("Starting save/load process");
-    log::debug!("Opened command pipe at {}", command_pipe);
+    tracing::info!("Starting save/load process");
+    tracing::debug!("Opened command pipe at {}", command_pipe);
 
-    open_server(
+    open_peer_server(
         command_pipe,
-        async move |mut lines: Lines<_>, writer: WriteHalf<_>| {
+        async move |mut lines: Lines<_>, writer: WriteHalf<_>, peer: Option<i32>| {
+            let timeout = env::game_client_timeout();
             let writer = Arc::new(Mutex::new(writer));
             let mut handles = vec![];
-            log::debug!("New client connected to game socket");
-            while let Some(line) = lines.next_line().await? {
+            let watches = Arc::new(Watches::default());
+            let changes = task::spawn(save_watch::forward(
+                watches.clone(),
+                writer.clone(),
+                timeout,
+            ));
+            let app_id = peer.and_then(flatpak_app_id);
+            tracing::debug!(
+                "New client connected to game socket (pid {peer:?}, app {})",
+                app_id.as_deref().unwrap_or("unsandboxed")
+            );
+            while let Some(line) = next_line(&mut lines, timeout).await? {
                 let command: Request = serde_json::from_str(&line)?;
-
-                let writer = writer.clone();
-
-                handles.push(task::spawn(async move {
-                    let body: ResponseBody = match &command.body {
-                        RequestBody::Ping => {
-                            log::trace!("Handling command: {command}");
-                            handle(command.body).await
-                        }
-                        RequestBody::Save(_, _, _)
-                        | RequestBody::Load(_, _)
-                        | RequestBody::Flush
-                        | RequestBody::GetNfcTag(_)
-                        | RequestBody::GetNfcUser(_) => {
-                            log::debug!("Handling command: {command}");
-                            handle(command.body).await
-                        }
-                        // Don't allow game save/load to (for example) download a game, launch a game,
-                        // etc. If games could launch other games, it would update the 'current game' in
-                        // crate::api and allow games to corrupt other games' save data (possibly
-                        // maliciously!)
-                        _ => anyhow!("Invalid command: {}", command).into(),
-                    };
+                let allowed = check_peer(app_id.as_deref());
+                // Blobs are sent straight after their request, so they're read before the next one
+                let blob = match &command.body {
+                    RequestBody::SaveBlob(_, _, length) => {
+                        let game_id = api::current_game().map(|game| game.id).unwrap_or_default();
+                        // Blobs from clients that aren't allowed are drained without being kept
+                        let limit = match allowed {
+                            Ok(()) => env::save_quota(game_id.as_str()),
+                            Err(_) => 0,
+                        };
+                        read_bytes(&mut lines, *length, limit, timeout).await?
+                    }
+                    _ => None,
+                };
+                if let Err(err) = allowed {
+                    tracing::warn!("Refused command from pid {peer:?}: {command} ({err})");
                     let response = Response {
                         request_id: command.request_id,
-                        body,
+                        body: err.into(),
                     };
-                    log::debug!("Sending: {response}");
                     let mut response = serde_json::to_vec(&response)?;
                     response.push(b'\n');
+                    write_line(&writer, &response, timeout).await?;
+                    continue;
+                }
+
+                let writer = writer.clone();
+                let watches = watches.clone();
+                let span = tracing::debug_span!(
+                    "request",
+                    id = command.request_id,
+                    app = app_id.as_deref().unwrap_or("unsandboxed")
+                );
+
+                handles.push(task::spawn(
+                    async move {
+                        let body: ResponseBody = match &command.body {
+                            RequestBody::Ping => {
+                                tracing::trace!("Handling command: {command}");
+                                watchdog::ping();
+                                handle(command.body).await
+                            }
+                            RequestBody::Save(_, _, _)
+                            | RequestBody::Load(_, _)
+                            | RequestBody::Flush
+                            | RequestBody::SaveForUser(_, _, _, _)
+                            | RequestBody::LoadForUser(_, _, _)
+                            | RequestBody::SaveWithTtl(_, _, _, _)
+                            | RequestBody::ListKeys(_, _)
+                            | RequestBody::Scan(_, _, _)
+                            | RequestBody::Delete(_, _)
+                            | RequestBody::ClearNamespace
+                            | RequestBody::ListSlots
+                            | RequestBody::CreateSlot(_, _)
+                            | RequestBody::UpdateSlot(_, _, _)
+                            | RequestBody::DeleteSlot(_)
+                            | RequestBody::SubmitScore(_, _, _)
+                            | RequestBody::UnlockAchievement(_, _)
+                            | RequestBody::GetAchievements(_)
+                            | RequestBody::GetNfcTag(_)
+                            | RequestBody::GetNfcTagInRealm(_, _)
+                            | RequestBody::CreateGuest(_) => {
+                                tracing::debug!("Handling command: {command}");
+                                handle(command.body).await
+                            }
+                            RequestBody::SaveBlob(group, key, _) => {
+                                tracing::debug!("Handling command: {command}");
+                                match (running_game_id(), blob) {
+                                    (Ok(game_id), Some(blob)) => {
+                                        let group = format!("{game_id}/{group}");
+                                        match api::persistence_save_blob(&group, key, blob).await {
+                                            Ok(()) => ResponseBody::Ok,
+                                            Err(err) => save_error(err),
+                                        }
+                                    }
+                                    // Too big to ever fit, so it wasn't kept
+                                    (Ok(game_id), None) => ResponseBody::QuotaExceeded(
+                                        api::game_save_usage(game_id.as_str()).await,
+                                    ),
+                                    (Err(err), _) => err.into(),
+                                }
+                            }
+                            RequestBody::LoadBlob(group, key) => {
+                                tracing::debug!("Handling command: {command}");
+                                let loaded = match running_game_id() {
+                                    Ok(game_id) => {
+                                        let group = format!("{game_id}/{group}");
+                                        api::persistence_load_blob(&group, key).await
+                                    }
+                                    Err(err) => Err(err),
+                                };
+                                match loaded {
+                                    Ok((length, bytes)) => {
+                                        let response = Response {
+                                            request_id: command.request_id,
+                                            body: ResponseBody::Blob(length),
+                                        };
+                                        tracing::debug!("Sending: {response}");
+                                        let mut response = serde_json::to_vec(&response)?;
+                                        response.push(b'\n');
+                                        return write_bytes(&writer, &response, bytes, timeout)
+                                            .await;
+                                    }
+                                    Err(err) => err.into(),
+                                }
+                            }
+                            // Games name groups without their ID, and are sent changes the same way
+                            RequestBody::WatchKeys(group, prefix) => {
+                                tracing::debug!("Handling command: {command}");
+                                let watched = running_game_id().and_then(|game_id| {
+                                    let full = format!("{game_id}/{group}");
+                                    watches.watch(full, group.clone(), prefix.clone())
+                                });
+                                match watched {
+                                    Ok(()) => ResponseBody::Ok,
+                                    Err(err) => err.into(),
+                                }
+                            }
+                            RequestBody::UnwatchKeys(group, prefix) => {
+                                tracing::debug!("Handling command: {command}");
+                                match running_game_id() {
+                                    Ok(game_id) => {
+                                        watches
+                                            .unwatch(format!("{game_id}/{group}").as_str(), prefix);
+                                        ResponseBody::Ok
+                                    }
+                                    Err(err) => err.into(),
+                                }
+                            }
+                            // Games only get the parts of a user they need, the frontend gets it
+                            // all
+                            RequestBody::GetNfcUser(association_id) => {
+                                tracing::debug!("Handling command: {command}");
+                                match api::nfc_user(association_id.clone()).await {
+                                    Ok(user) => ResponseBody::NfcUser(nfc::for_game(user)),
+                                    Err(err) => err.into(),
+                                }
+                            }
+                            // Don't allow game save/load to (for example) download a game, launch a
+                            // game, etc. If games could launch other games, it would update the
+                            // 'current game' in crate::api and allow games to corrupt other games'
+                            // save data (possibly maliciously!)
+                            _ => anyhow!("Invalid command: {}", command).into(),
+                        };
+                        let response = Response {
+                            request_id: command.request_id,
+                            body,
+                        };
+                        tracing::debug!("Sending: {response}");
+                        let mut response = serde_json::to_vec(&response)?;
+                        response.push(b'\n');
 
-                    let mut writer = writer.lock().await;
-                    writer.write_all(&response).await?;
-                    Ok(()) as Result<(), anyhow::Error>
-                }));
+                        write_line(&writer, &response, timeout).await
+                    }
+                    .instrument(span),
+                ));
             }
 
+            changes.abort();
             future::join_all(handles).await;
-            log::info!("Game thread disconnecting");
+            tracing::info!("Game thread disconnecting");
             Ok(())
         },
     )
     .await
 }
+
+/**
+ * Check a client may act as the running game. Games are run in flatpak, so clients are only
+ * allowed while the app they're running in is the running game's. Clients outside flatpak are
+ * refused unless `DEVCADE_TRUST_UNSANDBOXED_GAMES` is set.
+ */
+fn check_peer(app_id: Option<&str>) -> Result<(), Error> {
+    let Some(app_id) = app_id else {
+        return if env::trust_unsandboxed_games() {
+            Ok(())
+        } else {
+            Err(anyhow!(tr("game_socket_unsandboxed", &[])))
+        };
+    };
+    let running = api::current_game().and_then(|game| game.flatpak_app_id);
+    if running.as_deref() == Some(app_id) {
+        Ok(())
+    } else {
+        Err(anyhow!(tr("game_socket_not_running", &[("app", app_id)])))
+    }
+}
+
+/**
+ * Get the running game's ID, which the groups games name are kept under
+ *
+ * # Errors
+ * This function will return an error if no game is running.
+ */
+fn running_game_id() -> Result<String, Error> {
+    api::current_game()
+        .map(|game| game.id)
+        .ok_or_else(|| anyhow!(tr("no_game_running", &[])))
+}
+
+/**
+ * Get the flatpak app a process is running in, from the info flatpak puts at the root of every
+ * sandbox. Processes that aren't in a sandbox don't have one.
+ */
+fn flatpak_app_id(pid: i32) -> Option<String> {
+    let info = std::fs::read_to_string(format!("/proc/{pid}/root/.flatpak-info")).ok()?;
+    app_name(info.as_str())
+}
+
+/**
+ * Get the app's name from the `[Application]` group of a `.flatpak-info` file
+ */
+fn app_name(info: &str) -> Option<String> {
+    let mut in_application = false;
+    for line in info.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_application = line == "[Application]";
+        } else if let Some(name) = line.strip_prefix("name=").filter(|_| in_application) {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_app_name_from_flatpak_info() {
+        let info = "[Application]\nname=edu.rit.csh.devcade.Tetris\nruntime=runtime/x\n\n[Instance]\nname=other\n";
+        assert_eq!(
+            app_name(info).as_deref(),
+            Some("edu.rit.csh.devcade.Tetris")
+        );
+        assert_eq!(app_name("[Instance]\nname=other\n"), None);
+    }
+}
@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Error};
+use std::collections::HashMap;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/**
+ * The most header data read from a single request before it's rejected
+ */
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+
+/**
+ * The largest request body accepted
+ */
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/**
+ * A parsed HTTP/1.1 request. Only what the backend's small HTTP servers need is supported: no
+ * chunked bodies and no keep-alive.
+ */
+#[derive(Debug, Default)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /**
+     * Header values, keyed by the lowercased header name
+     */
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /**
+     * Get the token from an `Authorization: Bearer <token>` header, if there is one
+     */
+    #[must_use]
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.headers
+            .get("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+    }
+}
+
+/**
+ * A response to an HTTP request
+ */
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    #[must_use]
+    pub fn new(status: u16, content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    /**
+     * A JSON response, or a 500 if the value can't be serialized
+     */
+    #[must_use]
+    pub fn json<T: serde::Serialize>(status: u16, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Self::new(status, "application/json", body),
+            Err(e) => Self::error(500, e.to_string().as_str()),
+        }
+    }
+
+    /**
+     * A plain text error response
+     */
+    #[must_use]
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", format!("{message}\n"))
+    }
+}
+
+/**
+ * Read a single request from a connection
+ *
+ * # Errors
+ * This function will return an error if the connection fails, or if the request is malformed or
+ * too large.
+ */
+pub async fn read_request<R: AsyncRead + Unpin>(stream: R) -> Result<HttpRequest, Error> {
+    let mut reader = BufReader::new(stream);
+    let mut budget = MAX_HEADER_BYTES;
+    let request_line = read_header_line(&mut reader, &mut budget).await?;
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(anyhow!("Malformed request line '{request_line}'"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = HttpRequest {
+        method: method.to_string(),
+        path: percent_decode(path),
+        query: parse_query(query),
+        ..Default::default()
+    };
+
+    loop {
+        let header = read_header_line(&mut reader, &mut budget).await?;
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(anyhow!("Malformed header '{header}'"));
+        };
+        request
+            .headers
+            .insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    let length: usize = match request.headers.get("content-length") {
+        Some(length) => length.parse()?,
+        None => 0,
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(anyhow!("Request body is too large ({length} bytes)"));
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body).await?;
+    Ok(request)
+}
+
+/**
+ * Read a single line of the request head, without its line ending, counting it against the header
+ * budget
+ */
+async fn read_header_line<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+    budget: &mut usize,
+) -> Result<String, Error> {
+    let mut line = String::new();
+    let read = reader.take(*budget as u64).read_line(&mut line).await?;
+    *budget -= read;
+    if !line.ends_with('\n') {
+        return Err(anyhow!("Request headers are too large or incomplete"));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+/**
+ * Start a server-sent event stream on a connection. Events can be written to the connection with
+ * `write_event` until the client goes away.
+ *
+ * # Errors
+ * This function will return an error if the connection fails.
+ */
+pub async fn write_event_stream_head<W: AsyncWrite + Unpin>(mut stream: W) -> Result<(), Error> {
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        )
+        .await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/**
+ * Write a single server-sent event. `data` must not contain newlines.
+ *
+ * # Errors
+ * This function will return an error if the connection fails.
+ */
+pub async fn write_event<W: AsyncWrite + Unpin>(
+    mut stream: W,
+    event: &str,
+    data: &str,
+) -> Result<(), Error> {
+    stream
+        .write_all(format!("event: {event}\ndata: {data}\n\n").as_bytes())
+        .await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/**
+ * Write a response to a connection. The connection is always closed after the response.
+ *
+ * # Errors
+ * This function will return an error if the connection fails.
+ */
+pub async fn write_response<W: AsyncWrite + Unpin>(
+    mut stream: W,
+    response: &HttpResponse,
+) -> Result<(), Error> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/**
+ * Compare a token against the expected one without short-circuiting on the first difference, so
+ * the comparison time doesn't leak how much of the token was right
+ */
+#[must_use]
+pub fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn parses_request() {
+        let raw = b"POST /api/logs?game=a%20b&session=1 HTTP/1.1\r\nAuthorization: Bearer abc\r\nContent-Length: 2\r\n\r\n{}";
+        let request = read_request(&raw[..]).await.unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/logs");
+        assert_eq!(request.query["game"], "a b");
+        assert_eq!(request.query["session"], "1");
+        assert_eq!(request.bearer_token(), Some("abc"));
+        assert_eq!(request.body, b"{}");
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_headers() {
+        let mut raw = b"GET / HTTP/1.1\r\nX-Padding: ".to_vec();
+        raw.extend(vec![b'a'; MAX_HEADER_BYTES]);
+        raw.extend(b"\r\n\r\n");
+        assert!(read_request(&raw[..]).await.is_err());
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secreT", "secret"));
+        assert!(!token_matches("secret2", "secret"));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::metrics;
+use crate::servers::http::{self, HttpRequest, HttpResponse};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::Instrument;
+
+/**
+ * How long a client has to send its request before the connection is dropped
+ */
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/**
+ * Main function for the metrics server. This serves the backend's metrics at `/metrics` for
+ * Prometheus to scrape.
+ *
+ * This function will never return unless it panics and should be spawned as a thread.
+ */
+pub async fn main(address: SocketAddr) -> ! {
+    let listener = TcpListener::bind(address)
+        .await
+        .unwrap_or_else(|e| panic!("Couldn't bind metrics server to {address}: {e}"));
+    tracing::info!("Serving metrics on http://{address}/metrics");
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("Couldn't accept metrics connection: {e}");
+                continue;
+            }
+        };
+        tokio::spawn(
+            async move {
+                if let Err(e) = handle_connection(stream).await {
+                    tracing::debug!("Metrics connection from {peer} failed: {e}");
+                }
+            }
+            .instrument(tracing::debug_span!("metrics_client", %peer)),
+        );
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream) -> Result<(), anyhow::Error> {
+    let response =
+        match tokio::time::timeout(REQUEST_TIMEOUT, http::read_request(&mut stream)).await {
+            Ok(Ok(request)) => respond(&request),
+            Ok(Err(e)) => HttpResponse::error(400, e.to_string().as_str()),
+            Err(_) => HttpResponse::error(400, "Timed out waiting for the request"),
+        };
+    http::write_response(&mut stream, &response).await
+}
+
+fn respond(request: &HttpRequest) -> HttpResponse {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => HttpResponse::new(
+            200,
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics::render(),
+        ),
+        (_, "/metrics") => HttpResponse::error(405, "Only GET is allowed"),
+        _ => HttpResponse::error(404, "Not found"),
+    }
+}
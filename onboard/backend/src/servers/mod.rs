@@ -1,15 +1,21 @@
 use anyhow::anyhow;
 use futures_util::future;
 use futures_util::FutureExt;
-use log::{log, Level};
 use std::fs::remove_file;
 use std::future::Future;
+use std::net::SocketAddr;
 use std::process::{Command, Stdio};
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, BufReader, Lines, ReadHalf, WriteHalf};
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, Lines,
+    ReadHalf, WriteHalf,
+};
 use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::Mutex;
 use tokio::task;
 use tokio::task::JoinError;
+use tracing::Instrument;
 
 /**
  * Module for getting the paths to the pipes that the servers use to communicate
@@ -32,6 +38,14 @@ pub mod path {
     pub fn game_pipe() -> String {
         format!("{}/game.sock", devcade_path())
     }
+
+    /**
+     * Get the path to the socket that devcadectl talks to the backend through
+     */
+    #[must_use]
+    pub fn control_pipe() -> String {
+        format!("{}/control.sock", devcade_path())
+    }
 }
 
 /**
@@ -45,6 +59,27 @@ pub mod onboard;
  * */
 pub mod game;
 
+/**
+ * The control server lets maintainers drive the backend from a shell on the cabinet, with
+ * devcadectl
+ */
+pub mod control;
+
+/**
+ * The admin server serves a web dashboard for operators on the local network
+ */
+pub mod admin;
+
+/**
+ * The metrics server serves the backend's metrics for Prometheus to scrape
+ */
+pub mod metrics;
+
+/**
+ * Minimal HTTP/1.1 request parsing and response writing for the backend's HTTP servers
+ */
+pub mod http;
+
 /**
  * A struct to hold the handles to the threads spawned by the backend.
  */
@@ -62,6 +97,18 @@ pub struct ThreadHandles {
      * The handle to the gatekeeper thread (handles authentication for CSH users)
      */
     gatekeeper: Option<tokio::task::JoinHandle<()>>,
+    /**
+     * The handle to the control server thread (handles devcadectl)
+     */
+    control: Option<tokio::task::JoinHandle<()>>,
+    /**
+     * The handle to the admin HTTP server thread (serves the operator dashboard)
+     */
+    admin: Option<tokio::task::JoinHandle<()>>,
+    /**
+     * The handle to the metrics HTTP server thread
+     */
+    metrics: Option<tokio::task::JoinHandle<()>>,
 }
 
 impl ThreadHandles {
@@ -74,6 +121,9 @@ impl ThreadHandles {
             onboard: None,
             game_sl: None,
             gatekeeper: None,
+            control: None,
+            admin: None,
+            metrics: None,
         }
     }
 
@@ -81,7 +131,7 @@ impl ThreadHandles {
      * Restart the onboard server thread with the given pipe
      */
     pub fn restart_onboard(&mut self, command_pipe: String) {
-        log!(Level::Info, "Starting onboard thread ...");
+        tracing::info!("Starting onboard thread ...");
         self.onboard = Some(tokio::spawn(async move {
             onboard::main(command_pipe.as_str()).await;
         }));
@@ -91,12 +141,42 @@ impl ThreadHandles {
      * Restart the save / load server thread with the given pipe
      * */
     pub fn restart_game(&mut self, command_pipe: String) {
-        log!(Level::Info, "Starting game thread ...");
+        tracing::info!("Starting game thread ...");
         self.game_sl = Some(tokio::spawn(async move {
             game::main(command_pipe.as_str()).await;
         }));
     }
 
+    /**
+     * Restart the control server thread with the given pipe
+     */
+    pub fn restart_control(&mut self, command_pipe: String) {
+        tracing::info!("Starting control thread ...");
+        self.control = Some(tokio::spawn(async move {
+            control::main(command_pipe.as_str()).await;
+        }));
+    }
+
+    /**
+     * Restart the admin HTTP server thread on the given address
+     */
+    pub fn restart_admin(&mut self, address: SocketAddr) {
+        tracing::info!("Starting admin thread ...");
+        self.admin = Some(tokio::spawn(async move {
+            admin::main(address).await;
+        }));
+    }
+
+    /**
+     * Restart the metrics HTTP server thread on the given address
+     */
+    pub fn restart_metrics(&mut self, address: SocketAddr) {
+        tracing::info!("Starting metrics thread ...");
+        self.metrics = Some(tokio::spawn(async move {
+            metrics::main(address).await;
+        }));
+    }
+
     /**
      * Check if the onboard server thread has errored and return the error if it has
      */
@@ -123,6 +203,45 @@ impl ThreadHandles {
         None
     }
 
+    /**
+     * Check if the control thread has errored and return the error if it has
+     */
+    pub fn control_error(&mut self) -> Option<JoinError> {
+        if let Some(handle) = &self.control {
+            if handle.is_finished() {
+                let handle = self.control.take().unwrap();
+                return handle.now_or_never()?.err();
+            }
+        }
+        None
+    }
+
+    /**
+     * Check if the admin thread has errored and return the error if it has
+     */
+    pub fn admin_error(&mut self) -> Option<JoinError> {
+        if let Some(handle) = &self.admin {
+            if handle.is_finished() {
+                let handle = self.admin.take().unwrap();
+                return handle.now_or_never()?.err();
+            }
+        }
+        None
+    }
+
+    /**
+     * Check if the metrics thread has errored and return the error if it has
+     */
+    pub fn metrics_error(&mut self) -> Option<JoinError> {
+        if let Some(handle) = &self.metrics {
+            if handle.is_finished() {
+                let handle = self.metrics.take().unwrap();
+                return handle.now_or_never()?.err();
+            }
+        }
+        None
+    }
+
     /**
      * Check if the gatekeeper thread has errored and return the error if it has
      */
@@ -150,6 +269,24 @@ where
         + Sync
         + 'a + 'static,
     U: Future<Output = Result<(), anyhow::Error>> + Send + Sync + 'a + 'static,
+{
+    open_peer_server(path, move |lines, writer, _peer| {
+        handle_client(lines, writer)
+    })
+    .await
+}
+
+/**
+ * Like `open_server`, but clients are also given the process ID of whoever connected, if the
+ * kernel knows it, so they can check who they're talking to
+ */
+pub async fn open_peer_server<'a, T, U>(path: &str, handle_client: T) -> !
+where
+    T: (Fn(Lines<BufReader<ReadHalf<UnixStream>>>, WriteHalf<UnixStream>, Option<i32>) -> U)
+        + Send
+        + Sync
+        + 'a + 'static,
+    U: Future<Output = Result<(), anyhow::Error>> + Send + Sync + 'a + 'static,
 {
     let listener = bind_listener(path).unwrap();
     let handle_client = Arc::new(handle_client);
@@ -157,20 +294,139 @@ where
     let mut handles = vec![];
     while let Ok((stream, _address)) = listener.accept().await {
         let handle_client = handle_client.clone();
-        handles.push(task::spawn(async move {
-            let (reader, writer) = tokio::io::split(stream);
-            let reader = BufReader::new(reader);
+        let peer = stream.peer_cred().ok().and_then(|cred| cred.pid());
+        // Everything logged while serving a client is grouped under the socket and its process
+        let span = tracing::info_span!("client", socket = path, pid = ?peer);
+        handles.push(task::spawn(
+            async move {
+                let (reader, writer) = tokio::io::split(stream);
+                let reader = BufReader::new(reader);
 
-            match handle_client(reader.lines(), writer).await {
-                Ok(()) => log::info!("Finished handling connections from client"),
-                Err(err) => log::error!("Finished handling connections from client: {:?}", err),
+                match handle_client(reader.lines(), writer, peer).await {
+                    Ok(()) => tracing::info!("Finished handling connections from client"),
+                    Err(err) => {
+                        tracing::error!("Finished handling connections from client: {:?}", err)
+                    }
+                }
             }
-        }));
+            .instrument(span),
+        ));
     }
     future::join_all(handles).await;
     panic!("Looks like our server stopped serving?! This shouldn't happen.");
 }
 
+/**
+ * Read the next line a client sent. Clients with a timeout are expected to send something at least
+ * that often (a `Ping` if nothing else), and are assumed to have hung if they don't.
+ *
+ * # Errors
+ * This function will return an error if the read fails or the client times out.
+ */
+pub async fn next_line<R: AsyncBufRead + Unpin>(
+    lines: &mut Lines<R>,
+    timeout: Option<Duration>,
+) -> Result<Option<String>, anyhow::Error> {
+    let Some(timeout) = timeout else {
+        return Ok(lines.next_line().await?);
+    };
+    match tokio::time::timeout(timeout, lines.next_line()).await {
+        Ok(line) => Ok(line?),
+        Err(_) => Err(anyhow!(
+            "Client sent nothing for {}s, assuming it hung",
+            timeout.as_secs()
+        )),
+    }
+}
+
+/**
+ * Write a line to a client. A client that stops reading fills up its socket and blocks writes
+ * (along with everyone waiting on the writer), so with a timeout the write is given up on instead.
+ *
+ * # Errors
+ * This function will return an error if the write fails or times out.
+ */
+pub async fn write_line(
+    writer: &Mutex<WriteHalf<UnixStream>>,
+    line: &[u8],
+    timeout: Option<Duration>,
+) -> Result<(), anyhow::Error> {
+    let write = async { writer.lock().await.write_all(line).await };
+    match timeout {
+        None => Ok(write.await?),
+        Some(timeout) => match tokio::time::timeout(timeout, write).await {
+            Ok(written) => Ok(written?),
+            Err(_) => Err(anyhow!(
+                "Client didn't read for {}s, assuming it hung",
+                timeout.as_secs()
+            )),
+        },
+    }
+}
+
+/**
+ * Read the raw bytes a client sent after a request, like a blob being saved. Anything over the
+ * limit is read and thrown away instead of being kept in memory, and `None` is returned.
+ *
+ * # Errors
+ * This function will return an error if the client disconnects or stops sending before all of the
+ * bytes arrive.
+ */
+pub async fn read_bytes<R: AsyncBufRead + Unpin>(
+    lines: &mut Lines<R>,
+    length: u64,
+    limit: u64,
+    timeout: Option<Duration>,
+) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    let reader = lines.get_mut();
+    let read = async {
+        if length > limit {
+            tokio::io::copy(&mut reader.take(length), &mut tokio::io::sink()).await?;
+            return Ok(None);
+        }
+        let mut bytes = vec![0; usize::try_from(length)?];
+        reader.read_exact(&mut bytes).await?;
+        Ok(Some(bytes))
+    };
+    match timeout {
+        None => read.await,
+        Some(timeout) => tokio::time::timeout(timeout, read)
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("Client stopped sending partway through a blob"))),
+    }
+}
+
+/**
+ * Write a line to a client followed by raw bytes, like a blob being loaded. Nothing else is written
+ * to the client in between.
+ *
+ * # Errors
+ * This function will return an error if the write fails or times out.
+ */
+pub async fn write_bytes(
+    writer: &Mutex<WriteHalf<UnixStream>>,
+    line: &[u8],
+    mut bytes: impl AsyncRead + Unpin,
+    timeout: Option<Duration>,
+) -> Result<(), anyhow::Error> {
+    let write = async {
+        let mut writer = writer.lock().await;
+        writer.write_all(line).await?;
+        tokio::io::copy(&mut bytes, &mut *writer).await?;
+        Ok::<(), std::io::Error>(())
+    };
+    match timeout {
+        None => Ok(write.await?),
+        Some(timeout) => match tokio::time::timeout(timeout, write).await {
+            Ok(written) => Ok(written?),
+            Err(_) => Err(anyhow!(
+                "Client didn't read for {}s, assuming it hung",
+                timeout.as_secs()
+            )),
+        },
+    }
+}
+
 fn bind_listener(path: &str) -> Result<UnixListener, anyhow::Error> {
     match UnixListener::bind(path) {
         Ok(l) => Ok(l),
@@ -186,7 +442,7 @@ fn bind_listener(path: &str) -> Result<UnixListener, anyhow::Error> {
                     // lsof returns success if any process is using this file
                     Err(anyhow!("Failed to bind listener to path {}: {}", path, e))
                 } else {
-                    log::debug!("Socket was not closed correctly in last shutdown. Removing");
+                    tracing::debug!("Socket was not closed correctly in last shutdown. Removing");
                     remove_file(path)?;
                     bind_listener(path)
                 }
@@ -195,3 +451,21 @@ fn bind_listener(path: &str) -> Result<UnixListener, anyhow::Error> {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn bytes_are_read_between_lines() {
+        let sent: &[u8] = b"save\n\0\x01\nbig\nsave\n0123456789load\n";
+        let mut lines = BufReader::new(sent).lines();
+        assert_eq!(next_line(&mut lines, None).await.unwrap().unwrap(), "save");
+        let bytes = read_bytes(&mut lines, 7, 16, None).await.unwrap();
+        assert_eq!(bytes.unwrap(), b"\0\x01\nbig\n");
+        assert_eq!(next_line(&mut lines, None).await.unwrap().unwrap(), "save");
+        // Too big, so it's skipped over without being kept
+        assert!(read_bytes(&mut lines, 10, 4, None).await.unwrap().is_none());
+        assert_eq!(next_line(&mut lines, None).await.unwrap().unwrap(), "load");
+    }
+}
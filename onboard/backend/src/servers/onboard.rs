@@ -1,12 +1,34 @@
 use crate::command::handle;
-use crate::servers::open_server;
-use devcade_onboard_types::{Request, RequestBody, Response, ResponseBody};
+use crate::env;
+use crate::events;
+use crate::save_watch::{self, Watches};
+use crate::servers::{next_line, open_server, write_line};
+use devcade_onboard_types::{
+    Event, Request, RequestBody, Response, ResponseBody, EVENT_REQUEST_ID,
+};
 use futures_util::future;
-use log::{log, Level};
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncWriteExt, Lines, WriteHalf};
+use std::time::Duration;
+use tokio::io::{Lines, WriteHalf};
+use tokio::net::UnixStream;
 use tokio::sync::Mutex;
 use tokio::task;
+use tracing::Instrument;
+
+/**
+ * Counts up to give every frontend connection its own ID
+ */
+static NEXT_CLIENT: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    /**
+     * The connection of the primary frontend, the oldest one still connected. Other clients (such
+     * as dcu) can connect too, but losing them isn't worth announcing.
+     */
+    static ref PRIMARY: std::sync::Mutex<Option<u64>> = std::sync::Mutex::new(None);
+}
 
 /**
  * Main function for the onboard process. This function handles all communication to/from the onboard
@@ -18,50 +40,151 @@ pub async fn main(command_pipe: &str) -> ! {
     // Vector for holding all the response futures so we can continue to read from the command pipe
     // while we wait for handle to finish.
 
-    log!(Level::Info, "Starting onboard process");
+    tracing::info!("Starting onboard process");
 
     let command_pipe_path = command_pipe;
 
-    log!(Level::Debug, "Opened command pipe at {}", command_pipe_path);
+    tracing::debug!("Opened command pipe at {}", command_pipe_path);
 
     open_server(
         command_pipe_path,
         async move |mut lines: Lines<_>, writer: WriteHalf<_>| {
+            let timeout = env::frontend_timeout();
+            let client = connected();
             let writer = Arc::new(Mutex::new(writer));
             let mut handles = vec![];
-            while let Some(line) = lines.next_line().await? {
-                log::trace!("Received onboard command: {line}");
-                let command: Request = serde_json::from_str(&line)?;
+            let events = task::spawn(forward_events(client, writer.clone(), timeout));
+            let watches = Arc::new(Watches::default());
+            let changes = task::spawn(save_watch::forward(
+                watches.clone(),
+                writer.clone(),
+                timeout,
+            ));
+            let result: Result<(), anyhow::Error> = async {
+                while let Some(line) = next_line(&mut lines, timeout).await? {
+                    tracing::trace!("Received onboard command: {line}");
+                    let command: Request = serde_json::from_str(&line)?;
 
-                if let RequestBody::Ping = &command.body {
-                    log!(Level::Trace, "Handling command: {}", command);
-                } else {
-                    log!(Level::Debug, "Handling command: {}", command);
-                }
+                    if let RequestBody::Ping = &command.body {
+                        tracing::trace!("Handling command: {}", command);
+                    } else {
+                        tracing::debug!("Handling command: {}", command);
+                    }
 
-                let writer = writer.clone();
+                    let writer = writer.clone();
+                    let watches = watches.clone();
+                    let span = tracing::debug_span!("request", client, id = command.request_id);
 
-                handles.push(task::spawn(async move {
-                    let body = handle(command.body).await;
-                    let response = Response {
-                        request_id: command.request_id,
-                        body,
-                    };
-                    match &response.body {
-                        ResponseBody::Pong => log::trace!("Sending: {response}"),
-                        _ => log::debug!("Sending: {response}"),
-                    }
-                    let mut response = serde_json::to_vec(&response)?;
-                    response.push(b'\n');
+                    handles.push(task::spawn(
+                        async move {
+                            let body = match command.body {
+                                // The frontend names groups in full, starting with the game's ID
+                                RequestBody::WatchKeys(group, prefix) => {
+                                    match watches.watch(group.clone(), group, prefix) {
+                                        Ok(()) => ResponseBody::Ok,
+                                        Err(err) => err.into(),
+                                    }
+                                }
+                                RequestBody::UnwatchKeys(group, prefix) => {
+                                    watches.unwatch(group.as_str(), prefix.as_str());
+                                    ResponseBody::Ok
+                                }
+                                body => handle(body).await,
+                            };
+                            let response = Response {
+                                request_id: command.request_id,
+                                body,
+                            };
+                            match &response.body {
+                                ResponseBody::Pong => tracing::trace!("Sending: {response}"),
+                                _ => tracing::debug!("Sending: {response}"),
+                            }
+                            let mut response = serde_json::to_vec(&response)?;
+                            response.push(b'\n');
 
-                    let mut writer = writer.lock().await;
-                    writer.write_all(&response).await?;
-                    Ok(()) as Result<(), anyhow::Error>
-                }));
+                            write_line(&writer, &response, timeout).await
+                        }
+                        .instrument(span),
+                    ));
+                }
+                Ok(())
             }
+            .await;
+            // Stop queueing events for the client, but let commands it already sent finish, since
+            // stopping one partway (such as an install) could leave things half done
+            events.abort();
+            changes.abort();
+            disconnected(client, &result);
             future::join_all(handles).await;
-            Ok(())
+            result
         },
     )
     .await
 }
+
+/**
+ * Give a new frontend connection an ID, making it the primary frontend if there isn't one
+ */
+fn connected() -> u64 {
+    let client = NEXT_CLIENT.fetch_add(1, Ordering::Relaxed);
+    let mut primary = PRIMARY.lock().unwrap();
+    if primary.is_none() {
+        tracing::info!("Frontend connection {client} is the primary frontend");
+        *primary = Some(client);
+    }
+    client
+}
+
+/**
+ * Forget a closed frontend connection. If it was the primary frontend, a `FrontendLost` event is
+ * emitted and the next frontend to connect becomes the primary one.
+ */
+fn disconnected(client: u64, result: &Result<(), anyhow::Error>) {
+    let mut primary = PRIMARY.lock().unwrap();
+    if *primary != Some(client) {
+        return;
+    }
+    *primary = None;
+    let reason = match result {
+        Ok(()) => String::from("The frontend closed its connection"),
+        Err(err) => err.to_string(),
+    };
+    tracing::warn!("Lost the primary frontend: {reason}");
+    events::emit(Event::FrontendLost(reason));
+}
+
+/**
+ * Push every event emitted by the backend to a connected frontend until the connection is closed.
+ * A frontend that can't keep up is sent an `Event::Gap` in place of the events it missed.
+ */
+#[tracing::instrument(skip(writer, timeout))]
+async fn forward_events(
+    client: u64,
+    writer: Arc<Mutex<WriteHalf<UnixStream>>>,
+    timeout: Option<Duration>,
+) {
+    let mut events = events::subscribe(format!("Frontend connection {client}"));
+    loop {
+        let event = events.recv().await;
+        let response = Response {
+            request_id: EVENT_REQUEST_ID,
+            body: ResponseBody::Event(event),
+        };
+        match &response.body {
+            ResponseBody::Event(Event::SessionStats(_)) => tracing::trace!("Sending: {response}"),
+            _ => tracing::debug!("Sending: {response}"),
+        }
+        let mut response = match serde_json::to_vec(&response) {
+            Ok(response) => response,
+            Err(err) => {
+                tracing::error!("Couldn't serialize event: {err}");
+                continue;
+            }
+        };
+        response.push(b'\n');
+        if let Err(err) = write_line(&writer, &response, timeout).await {
+            tracing::debug!("Stopped sending events to frontend: {err}");
+            return;
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::atomic;
 use crate::command::handle;
 use crate::servers::open_server;
 use anyhow::anyhow;
@@ -21,25 +22,25 @@ lazy_static! {
 }
 
 pub async fn main(command_pipe: &str) -> ! {
-    log::info!("Starting save/load process");
-    log::debug!("Opened command pipe at {}", command_pipe);
+    tracing::info!("Starting save/load process");
+    tracing::debug!("Opened command pipe at {}", command_pipe);
 
     open_server(command_pipe, async move |mut lines, writer| {
         let writer = Arc::new(Mutex::new(writer));
         let mut handles = vec![];
-        log::debug!("New client connected to persistence socket");
+        tracing::debug!("New client connected to persistence socket");
         while let Some(line) = lines.next_line().await? {
             let command: Request = serde_json::from_str(&line)?;
 
             match &command.body {
                 RequestBody::Save(_, _, _) | RequestBody::Load(_, _) | RequestBody::Flush => {
-                    log::debug!("Handling command: {}", command);
+                    tracing::debug!("Handling command: {}", command);
                 }
                 RequestBody::Ping => {
-                    log::trace!("Handling command: {}", command);
+                    tracing::trace!("Handling command: {}", command);
                 }
                 _ => {
-                    log::warn!("Invalid command from game: {}", command);
+                    tracing::warn!("Invalid command from game: {}", command);
                 }
             }
 
@@ -61,7 +62,7 @@ pub async fn main(command_pipe: &str) -> ! {
                     request_id: command.request_id,
                     body,
                 };
-                log::debug!("Sending: {response}");
+                tracing::debug!("Sending: {response}");
                 let mut response = serde_json::to_vec(&response)?;
                 response.push(b'\n');
 
@@ -72,7 +73,7 @@ pub async fn main(command_pipe: &str) -> ! {
         }
 
         future::join_all(handles).await;
-        log::info!("Persistence thread disconnecting");
+        tracing::info!("Persistence thread disconnecting");
         Ok(())
     })
     .await
@@ -81,7 +82,7 @@ pub async fn main(command_pipe: &str) -> ! {
 // currently saves to the devcade machine (or local machine if running locally) in the future,
 // should ideally use a remote database / something else.
 pub async fn save(group: &str, key: &str, value: &str) -> Result<(), anyhow::Error> {
-    log::trace!("saving data to {}/{} ({})", group, key, value);
+    tracing::trace!("saving data to {}/{} ({})", group, key, value);
     let (path, group) = from_group(group);
     let full_key = format!("{}/{}", path, group);
 
@@ -101,7 +102,7 @@ pub async fn save(group: &str, key: &str, value: &str) -> Result<(), anyhow::Err
  * group will start with a game_id, but can be further subdivided by the game to
  * */
 pub async fn load(group: &str, key: &str) -> Result<String, anyhow::Error> {
-    log::trace!("loading data from {}/{}", group, key);
+    tracing::trace!("loading data from {}/{}", group, key);
     let (path, file_name) = from_group(group);
     let full_key = format!("{}/{}", path, file_name);
 
@@ -122,7 +123,7 @@ pub async fn flush() -> Result<(), anyhow::Error> {
     let mut data = DB.lock().await;
     let mut mod_list = DB_MODIFIED.lock().await;
 
-    log::debug!(
+    tracing::debug!(
         "Flushing data in db to file ({} modified groups)",
         mod_list.len()
     );
@@ -130,13 +131,13 @@ pub async fn flush() -> Result<(), anyhow::Error> {
     for key in mod_list.iter() {
         let inner = get_submap_or_load(&mut data, key.clone()).await?;
         let file_name = format!("{}.save", key);
-        log::debug!("Flushing to {}", file_name);
+        tracing::debug!("Flushing to {}", file_name);
         let path = Path::new(&file_name);
         let dir = path.parent().expect("path failed to have parents");
         if !dir.exists() {
             fs::create_dir_all(dir).await?;
         }
-        fs::write(path, serde_json::to_string(inner)?.as_bytes()).await?;
+        atomic::write_async(path, serde_json::to_string(inner)?).await?;
     }
 
     mod_list.clear();
@@ -151,7 +152,7 @@ pub async fn flush() -> Result<(), anyhow::Error> {
  * a time.
  * */
 pub async fn clear_db() -> Result<(), anyhow::Error> {
-    log::info!("Flushing and clearing DB cache");
+    tracing::info!("Flushing and clearing DB cache");
     flush().await?;
 
     let mut data = DB.lock().await;
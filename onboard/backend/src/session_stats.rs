@@ -0,0 +1,215 @@
+use crate::api;
+use crate::env;
+use crate::events;
+use devcade_onboard_types::schema::SessionStats;
+use devcade_onboard_types::Event;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+lazy_static! {
+    /**
+     * The last sample taken of the running game, or `None` if no game is running
+     */
+    static ref LATEST: Mutex<Option<SessionStats>> = Mutex::new(None);
+}
+
+/**
+ * The parts of `/proc/<pid>/stat` that are used
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProcStat {
+    pub ppid: u32,
+    /**
+     * The process's state, such as `R` (running), `S` (sleeping) or `D` (waiting on IO)
+     */
+    pub state: char,
+    /**
+     * CPU time used in user and kernel mode, in clock ticks
+     */
+    pub ticks: u64,
+    /**
+     * Resident memory, in pages
+     */
+    pub rss_pages: u64,
+}
+
+/**
+ * Get the last sample taken of the running game, or `None` if no game is running
+ */
+#[must_use]
+pub fn latest() -> Option<SessionStats> {
+    LATEST.lock().unwrap().clone()
+}
+
+/**
+ * Sample a game's resource usage until it exits, sending each sample to the frontend. If the game
+ * uses more memory than it's allowed, it's stopped before it can freeze the cabinet. Should be
+ * spawned when the game is launched, with the pid of `flatpak run`, and aborted once the game has
+ * exited.
+ */
+pub async fn monitor(game_id: String, pid: u32) {
+    let Some(interval) = env::session_stats_interval() else {
+        return;
+    };
+    let mut last_ticks = None;
+    let mut last_sampled = Instant::now();
+    loop {
+        tokio::time::sleep(interval).await;
+        let Ok(Some((ticks, rss_pages, processes))) =
+            tokio::task::spawn_blocking(move || sample_tree(pid)).await
+        else {
+            break;
+        };
+        let elapsed = last_sampled.elapsed().as_secs_f32();
+        last_sampled = Instant::now();
+        let cpu_percent = match last_ticks {
+            Some(last_ticks) if elapsed > 0.0 => {
+                ticks.saturating_sub(last_ticks) as f32 / clock_ticks() as f32 / elapsed * 100.0
+            }
+            _ => 0.0,
+        };
+        last_ticks = Some(ticks);
+
+        let stats = SessionStats {
+            game_id: game_id.clone(),
+            cpu_percent,
+            rss_bytes: rss_pages * page_size(),
+            gpu_percent: gpu_busy(),
+            processes,
+        };
+        log::trace!("Session stats: {stats}");
+        let over_limit = env::game_memory_limit().is_some_and(|limit| stats.rss_bytes > limit);
+        *LATEST.lock().unwrap() = Some(stats.clone());
+        events::emit(Event::SessionStats(stats.clone()));
+
+        if over_limit {
+            log::warn!("Game {game_id} ran away with memory ({stats}), stopping it");
+            if let Err(e) = api::stop_current_game().await {
+                log::warn!("Couldn't stop game {game_id}: {e}");
+            }
+            break;
+        }
+    }
+    clear();
+}
+
+/**
+ * Forget the last sample, once the game has exited
+ */
+pub fn clear() {
+    *LATEST.lock().unwrap() = None;
+}
+
+/**
+ * Add up the CPU ticks and resident pages of a process and all its descendants, such as the
+ * sandbox and the game inside it. Returns `None` if the process is gone.
+ */
+fn sample_tree(root: u32) -> Option<(u64, u64, u32)> {
+    let tree = process_tree(root)?;
+    let ticks = tree.iter().map(|(_, stat)| stat.ticks).sum();
+    let rss_pages = tree.iter().map(|(_, stat)| stat.rss_pages).sum();
+    Some((ticks, rss_pages, tree.len() as u32))
+}
+
+/**
+ * Get a process and all its descendants, with their stats. Returns `None` if the process is gone.
+ */
+#[must_use]
+pub fn process_tree(root: u32) -> Option<Vec<(u32, ProcStat)>> {
+    let mut stats = HashMap::new();
+    for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        // Processes can exit while they're being listed
+        if let Some(stat) = std::fs::read_to_string(entry.path().join("stat"))
+            .ok()
+            .and_then(|stat| parse_stat(stat.as_str()))
+        {
+            stats.insert(pid, stat);
+        }
+    }
+    let mut tree = vec![(root, *stats.get(&root)?)];
+    let mut parents = vec![root];
+    while let Some(parent) = parents.pop() {
+        for (pid, stat) in &stats {
+            if stat.ppid == parent {
+                tree.push((*pid, *stat));
+                parents.push(*pid);
+            }
+        }
+    }
+    Some(tree)
+}
+
+/**
+ * Parse `/proc/<pid>/stat`. The command name is in brackets and can contain spaces (or brackets),
+ * so fields are counted from the last closing bracket.
+ */
+fn parse_stat(stat: &str) -> Option<ProcStat> {
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    // Fields after the name start at the state, field 3 in proc(5)
+    let field = |n: usize| fields.get(n - 3)?.parse::<u64>().ok();
+    Some(ProcStat {
+        ppid: field(4)? as u32,
+        state: fields.first()?.chars().next()?,
+        ticks: field(14)? + field(15)?,
+        rss_pages: field(24)?,
+    })
+}
+
+/**
+ * How busy the GPU is, for drivers that report it (such as amdgpu). If there's more than one, the
+ * busiest is used.
+ */
+fn gpu_busy() -> Option<u8> {
+    std::fs::read_dir("/sys/class/drm")
+        .ok()?
+        .flatten()
+        .filter_map(|card| {
+            std::fs::read_to_string(card.path().join("device/gpu_busy_percent"))
+                .ok()?
+                .trim()
+                .parse()
+                .ok()
+        })
+        .max()
+}
+
+fn clock_ticks() -> u64 {
+    // SAFETY: sysconf has no memory safety requirements
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as u64,
+        _ => 100,
+    }
+}
+
+fn page_size() -> u64 {
+    // SAFETY: sysconf has no memory safety requirements
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as u64,
+        _ => 4096,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat_fields_are_counted_after_the_name() {
+        let stat = "4242 (Game (x86) v1) S 4200 4242 4200 0 -1 4194560 1 0 0 0 \
+            150 50 0 0 20 0 8 0 12345 123456789 3072 18446744073709551615";
+        assert_eq!(
+            parse_stat(stat),
+            Some(ProcStat {
+                ppid: 4200,
+                state: 'S',
+                ticks: 200,
+                rss_pages: 3072,
+            })
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use crate::api::STAGING_PREFIX;
+use crate::env;
+use crate::i18n::tr;
+use crate::storage::{self, BUNDLE, SIGNATURE};
+use anyhow::{anyhow, Error};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use devcade_onboard_types::schema::GameSignature;
+use openssl::pkey::{Id, PKey};
+use openssl::sign::Verifier;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/**
+ * A game bundle's detached signature, as published by the API next to the bundle and kept next to
+ * it in the game's directory. The signature is an Ed25519 signature of `message`, so a bundle is
+ * hashed rather than held in memory to check it.
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DetachedSignature {
+    pub signer: String,
+    pub key_id: String,
+    pub signed_at: u64,
+    /**
+     * The base64 encoded signature
+     */
+    pub signature: String,
+}
+
+/**
+ * What a release's signature is for, in place of a game ID
+ */
+const RELEASE_SUBJECT: &str = "onboard";
+
+/**
+ * What's signed for a bundle: the ID of the game it's for (as the API knows it), its SHA-256 hash
+ * and when it was signed. The ID is signed so one game's signed bundle can't be passed off as
+ * another's.
+ */
+fn message(game_id: &str, digest: &str, signed_at: u64) -> String {
+    format!("{game_id}:{digest}:{signed_at}")
+}
+
+/**
+ * Get the pinned publisher keys from `DEVCADE_PUBLISHER_KEYS`, by key ID
+ */
+fn publisher_keys() -> HashMap<String, Vec<u8>> {
+    parse_keys(env::publisher_keys(), "publisher")
+}
+
+/**
+ * Read `<key id>=<base64 key>` entries into keys by ID. Entries that can't be read are logged and
+ * left out.
+ */
+fn parse_keys(entries: Vec<String>, kind: &str) -> HashMap<String, Vec<u8>> {
+    let mut keys = HashMap::new();
+    for entry in entries {
+        let decoded = entry
+            .split_once('=')
+            .and_then(|(key_id, key)| Some((key_id, STANDARD.decode(key.trim()).ok()?)));
+        match decoded {
+            Some((key_id, key)) => {
+                keys.insert(key_id.trim().to_string(), key);
+            }
+            None => log::error!("Ignoring {kind} key '{entry}', expected <key id>=<base64 key>"),
+        }
+    }
+    keys
+}
+
+/**
+ * Whether games have to be signed by a pinned publisher key to be installed or launched. They do
+ * once any keys are pinned.
+ */
+#[must_use]
+pub fn required() -> bool {
+    !env::publisher_keys().is_empty()
+}
+
+/**
+ * Check a detached signature of a game's bundle with the given SHA-256 hash against a set of keys
+ *
+ * # Errors
+ * This function will return an error if the key isn't in the set, or the signature doesn't match.
+ */
+fn verify_with(
+    keys: &HashMap<String, Vec<u8>>,
+    game_id: &str,
+    digest: &str,
+    signature: &DetachedSignature,
+) -> Result<(), Error> {
+    let key = keys
+        .get(signature.key_id.as_str())
+        .ok_or_else(|| anyhow!("Key {} isn't a pinned publisher key", signature.key_id))?;
+    let key = PKey::public_key_from_raw_bytes(key, Id::ED25519)?;
+    let signed = STANDARD.decode(signature.signature.as_str())?;
+    let message = message(game_id, digest, signature.signed_at);
+    if !Verifier::new_without_digest(&key)?.verify_oneshot(&signed, message.as_bytes())? {
+        return Err(anyhow!("The signature doesn't match the bundle"));
+    }
+    Ok(())
+}
+
+/**
+ * Check that an installed game's bundle is signed by a pinned publisher key and hasn't been changed
+ * since. This does nothing if signatures aren't required.
+ *
+ * # Errors
+ * This function will return an error if the game isn't signed, or its bundle doesn't match its
+ * signature.
+ */
+pub async fn check(game_id: &str) -> Result<(), Error> {
+    if !required() {
+        return Ok(());
+    }
+    let signature = read(game_id)
+        .await
+        .ok_or_else(|| anyhow!(tr("game_unsigned", &[("game_id", game_id)])))?;
+    let bundle = storage::game_file(game_id, BUNDLE);
+    let digest =
+        tokio::task::spawn_blocking(move || sha256::try_digest(bundle.as_path())).await??;
+    // Staging games are signed with the ID the staging API knows them by
+    let api_id = game_id.strip_prefix(STAGING_PREFIX).unwrap_or(game_id);
+    verify_with(&publisher_keys(), api_id, digest.as_str(), &signature).map_err(|e| {
+        anyhow!(tr(
+            "game_signature_invalid",
+            &[("game_id", game_id), ("error", e.to_string().as_str())]
+        ))
+    })
+}
+
+/**
+ * Check an onboard release with the given SHA-256 hash is signed by a key in
+ * `DEVCADE_RELEASE_KEYS`
+ *
+ * # Errors
+ * This function will return an error if no release keys are pinned, the key isn't pinned, or the
+ * signature doesn't match.
+ */
+pub fn verify_release(digest: &str, signature: &DetachedSignature) -> Result<(), Error> {
+    let keys = parse_keys(env::release_keys(), "release");
+    if keys.is_empty() {
+        return Err(anyhow!("No release keys are pinned"));
+    }
+    verify_with(&keys, RELEASE_SUBJECT, digest, signature)
+}
+
+/**
+ * Get the signature kept with an installed game's bundle
+ */
+pub async fn read(game_id: &str) -> Option<DetachedSignature> {
+    let json = tokio::fs::read_to_string(storage::game_file(game_id, SIGNATURE))
+        .await
+        .ok()?;
+    serde_json::from_str(json.as_str()).ok()
+}
+
+/**
+ * Describe an installed game's signature for the frontend. The bundle isn't hashed for this, so a
+ * trusted key doesn't mean the bundle still matches; `check` is what's run before launching.
+ */
+pub async fn info(game_id: &str) -> Option<GameSignature> {
+    let signature = read(game_id).await?;
+    Some(GameSignature {
+        key_trusted: publisher_keys().contains_key(signature.key_id.as_str()),
+        signer: signature.signer,
+        key_id: signature.key_id,
+        signed_at: signature.signed_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::sign::Signer;
+
+    #[test]
+    fn only_matching_signatures_from_pinned_keys_verify() {
+        let private = PKey::generate_ed25519().unwrap();
+        let keys = HashMap::from([(String::from("csh"), private.raw_public_key().unwrap())]);
+        let digest = sha256::digest("bundle");
+        let sign = |digest: &str| {
+            Signer::new_without_digest(&private)
+                .unwrap()
+                .sign_oneshot_to_vec(message("tetris", digest, 1700000000).as_bytes())
+                .unwrap()
+        };
+        let mut signature = DetachedSignature {
+            signer: String::from("Devcade"),
+            key_id: String::from("csh"),
+            signed_at: 1700000000,
+            signature: STANDARD.encode(sign(digest.as_str())),
+        };
+        assert!(verify_with(&keys, "tetris", digest.as_str(), &signature).is_ok());
+
+        // A changed bundle
+        let tampered = sha256::digest("tampered");
+        assert!(verify_with(&keys, "tetris", tampered.as_str(), &signature).is_err());
+
+        // The same signed bundle passed off as another game
+        assert!(verify_with(&keys, "pong", digest.as_str(), &signature).is_err());
+
+        // A re-dated signature
+        signature.signed_at += 1;
+        assert!(verify_with(&keys, "tetris", digest.as_str(), &signature).is_err());
+        signature.signed_at -= 1;
+
+        // A key that isn't pinned
+        signature.key_id = String::from("someone-else");
+        assert!(verify_with(&keys, "tetris", digest.as_str(), &signature).is_err());
+    }
+}
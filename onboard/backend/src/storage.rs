@@ -0,0 +1,554 @@
+use crate::atomic;
+use crate::env::devcade_path;
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::{FileDrift, GameDrift};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/**
+ * The manifest describing the devcade directory, relative to the devcade path
+ */
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/**
+ * A game's metadata, relative to its game directory
+ */
+pub const GAME_JSON: &str = "game.json";
+
+/**
+ * A game's downloaded flatpak bundle, relative to its game directory
+ */
+pub const BUNDLE: &str = "bundle.flatpak";
+
+/**
+ * A game's icon, relative to its game directory
+ */
+pub const ICON: &str = "icon.png";
+
+/**
+ * A game's banner, relative to its game directory
+ */
+pub const BANNER: &str = "banner.png";
+
+/**
+ * The directory a game's captured output is kept in, relative to its game directory. Logs change
+ * constantly, so they aren't part of a game's inventory.
+ */
+pub const LOGS_DIR: &str = "logs";
+
+/**
+ * A JSON map of environment variables in a game directory, set when the game is run. Operators
+ * write it by hand to override the game's metadata, so it isn't part of a game's inventory and
+ * survives reinstalls.
+ */
+pub const ENV_OVERRIDES: &str = "env.json";
+
+/**
+ * A JSON list of permissions (such as `["network"]`) to give a game on top of the ones in its
+ * metadata. Like `ENV_OVERRIDES`, operators write it by hand for games they trust, and it survives
+ * reinstalls.
+ */
+pub const PERMISSION_OVERRIDES: &str = "permissions.json";
+
+/**
+ * The GPU a game renders on (such as `"discrete"`), in its game directory. It's set over IPC rather
+ * than by hand, but like `ENV_OVERRIDES` it's the operator's choice, so it survives reinstalls.
+ */
+pub const GPU_PREFERENCE: &str = "gpu.json";
+
+/**
+ * The SDL controller mappings the API has for a game, in its game directory. They're fetched again
+ * with every install, so they aren't part of a game's inventory.
+ */
+pub const CONTROLLER_MAPPINGS: &str = "controller_mappings.json";
+
+/**
+ * SDL controller mappings (in `gamecontrollerdb.txt` format) in a game directory, which override
+ * the ones from the API. Like `ENV_OVERRIDES`, operators write it by hand, and it survives
+ * reinstalls.
+ */
+pub const CONTROLLER_OVERRIDES: &str = "gamecontrollerdb.txt";
+
+/**
+ * The directory (relative to the devcade path) games get their per-session temporary directories
+ * in. It's hidden so it can't be mistaken for a game.
+ */
+pub const SESSION_TMP_DIR: &str = ".session-tmp";
+
+/**
+ * The directory (relative to the devcade path) bundles are downloaded to while they're validated.
+ * It's hidden for the same reason as `SESSION_TMP_DIR`.
+ */
+pub const VALIDATE_TMP_DIR: &str = ".validate-tmp";
+
+/**
+ * The directory (relative to the devcade path) gameplay recordings are kept in. It's hidden for
+ * the same reason as `SESSION_TMP_DIR`.
+ */
+pub const RECORDINGS_DIR: &str = ".recordings";
+
+/**
+ * The directory (relative to the devcade path) backend log files are kept in by default. It's
+ * hidden for the same reason as `SESSION_TMP_DIR`.
+ */
+pub const LOG_FILES_DIR: &str = ".logs";
+
+/**
+ * What happened the last time a game's bundle was installed, relative to its game directory. It's
+ * rewritten by every install, so it isn't part of a game's inventory.
+ */
+pub const INSTALL_REPORT: &str = "install_report.json";
+
+/**
+ * How far an install in progress has got, relative to its game directory. It only exists while the
+ * game is being installed, so it isn't part of a game's inventory.
+ */
+pub const INSTALL_STATE: &str = "install_state.json";
+
+/**
+ * The detached signature of a game's bundle, relative to its game directory
+ */
+pub const SIGNATURE: &str = "bundle.sig.json";
+
+/**
+ * The directory (relative to a game directory) earlier versions of the game are kept in, one
+ * directory per version named after its hash. Only the version in use is part of the inventory.
+ */
+pub const VERSIONS_DIR: &str = "versions";
+
+/**
+ * The hash of the version a game was rolled back from, relative to its game directory. While it
+ * exists, that version isn't installed again.
+ */
+pub const ROLLED_BACK: &str = "rolled_back.json";
+
+/**
+ * Every file that makes up an installed game, and is tracked in the manifest
+ */
+pub const GAME_FILES: &[&str] = &[GAME_JSON, BUNDLE, SIGNATURE, ICON, BANNER];
+
+lazy_static! {
+    /**
+     * Held while the manifest is being read and rewritten, so concurrent updates aren't lost
+     */
+    static ref MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/**
+ * The contents of `manifest.json`: the layout version of the devcade directory and an inventory of
+ * every installed game
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /**
+     * The layout version the directory was last migrated to
+     */
+    pub layout_version: u32,
+
+    /**
+     * The files belonging to each installed game, keyed by game ID
+     */
+    pub games: BTreeMap<String, GameInventory>,
+}
+
+/**
+ * The files belonging to a single installed game
+ */
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameInventory {
+    /**
+     * Each file present in the game directory, keyed by its path relative to the game directory
+     */
+    pub files: BTreeMap<String, FileEntry>,
+}
+
+/**
+ * A single file in a game's inventory
+ */
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    /**
+     * Read the manifest from a devcade directory, or `None` if the directory doesn't have one yet.
+     *
+     * # Errors
+     * This function will return an error if the manifest exists but can't be read or parsed.
+     */
+    pub fn read(root: &Path) -> Result<Option<Self>, Error> {
+        let path = root.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let json = std::fs::read_to_string(&path)?;
+        serde_json::from_str(json.as_str())
+            .map(Some)
+            .map_err(|e| anyhow!("Invalid manifest {}: {}", path.display(), e))
+    }
+
+    /**
+     * Write the manifest to a devcade directory. The manifest is written to a temporary file and
+     * renamed into place, so a crash never leaves a half written manifest behind.
+     *
+     * # Errors
+     * This function will return an error if the manifest can't be written.
+     */
+    pub fn write(&self, root: &Path) -> Result<(), Error> {
+        atomic::write(
+            &root.join(MANIFEST_FILE),
+            serde_json::to_string_pretty(self)?,
+        )
+    }
+}
+
+/**
+ * Get the number of bytes available to unprivileged users on the filesystem containing `path`
+ */
+#[must_use]
+pub fn free_space(path: &Path) -> Option<u64> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs is plain old data, so all zeroes is a valid value to be overwritten
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is a valid nul-terminated string and `stat` is a valid statvfs to write to
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)] // The field types differ between platforms
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/**
+ * Get the devcade directory, where all games and backend state are kept
+ */
+#[must_use]
+pub fn root() -> PathBuf {
+    PathBuf::from(devcade_path())
+}
+
+/**
+ * Check that files can be created in a directory, by writing and removing an empty one
+ *
+ * # Errors
+ * This function will return an error if the file can't be written.
+ */
+pub fn check_writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(".write-probe");
+    std::fs::write(&probe, b"")?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/**
+ * Get the directory a game is installed in
+ */
+#[must_use]
+pub fn game_dir(game_id: &str) -> PathBuf {
+    root().join(game_id)
+}
+
+/**
+ * Get the temporary directory a game's running session writes to. Only one game runs at a time, so
+ * each game only ever has one.
+ */
+#[must_use]
+pub fn session_tmp_dir(game_id: &str) -> PathBuf {
+    root().join(SESSION_TMP_DIR).join(game_id)
+}
+
+/**
+ * Get the path a game's bundle is downloaded to while it's validated, outside of its game directory
+ * so an installed copy is left alone
+ */
+#[must_use]
+pub fn validate_bundle_path(game_id: &str) -> PathBuf {
+    root()
+        .join(VALIDATE_TMP_DIR)
+        .join(format!("{game_id}.flatpak"))
+}
+
+/**
+ * Get the path of one of a game's files, such as `GAME_JSON` or `BUNDLE`
+ */
+#[must_use]
+pub fn game_file(game_id: &str, file: &str) -> PathBuf {
+    game_dir(game_id).join(file)
+}
+
+/**
+ * Read the manifest from a devcade directory, update it, and write it back, all while holding the
+ * manifest lock. A directory without a manifest starts from an empty one.
+ *
+ * # Errors
+ * This function will return an error if the manifest can't be read or written, or if `update`
+ * fails.
+ */
+pub fn update_manifest<T>(
+    root: &Path,
+    update: impl FnOnce(&mut Manifest) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let _guard = MANIFEST_LOCK.lock().unwrap();
+    let mut manifest = Manifest::read(root)?.unwrap_or_default();
+    let result = update(&mut manifest)?;
+    manifest.write(root)?;
+    Ok(result)
+}
+
+/**
+ * Hash every tracked file present in a game directory
+ *
+ * # Errors
+ * This function will return an error if a file exists but can't be read.
+ */
+pub fn inventory(game_dir: &Path) -> Result<GameInventory, Error> {
+    let mut inventory = GameInventory::default();
+    for file in GAME_FILES {
+        let path = game_dir.join(file);
+        if !path.exists() {
+            continue;
+        }
+        inventory.files.insert(file.to_string(), hash_file(&path)?);
+    }
+    Ok(inventory)
+}
+
+fn hash_file(path: &Path) -> Result<FileEntry, Error> {
+    Ok(FileEntry {
+        size: std::fs::metadata(path)?.len(),
+        sha256: sha256::try_digest(path)?,
+    })
+}
+
+/**
+ * Bring a devcade directory's inventory in line with what's on disk. Game directories holding a
+ * `game.json` that aren't in the manifest are inventoried, and games whose directory is gone are
+ * dropped. Games already in the manifest aren't re-hashed.
+ *
+ * # Errors
+ * This function will return an error if the directory or manifest can't be read or written.
+ */
+pub fn reconcile(root: &Path) -> Result<(), Error> {
+    let mut on_disk = Vec::new();
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && entry.path().join(GAME_JSON).exists() {
+            if let Some(name) = entry.file_name().to_str() {
+                on_disk.push(name.to_string());
+            }
+        }
+    }
+
+    update_manifest(root, |manifest| {
+        manifest.games.retain(|game_id, _| {
+            let keep = on_disk.contains(game_id);
+            if !keep {
+                log::info!("Dropping {game_id} from the manifest, its directory is gone");
+            }
+            keep
+        });
+        for game_id in on_disk {
+            if manifest.games.contains_key(&game_id) {
+                continue;
+            }
+            log::info!("Adding {game_id} to the manifest");
+            match inventory(&root.join(&game_id)) {
+                Ok(inventory) => {
+                    manifest.games.insert(game_id, inventory);
+                }
+                Err(e) => log::warn!("Couldn't inventory {game_id}: {e}"),
+            }
+        }
+        Ok(())
+    })
+}
+
+/**
+ * Re-inventory an installed game and record it in the manifest
+ *
+ * # Errors
+ * This function will return an error if the game's files or the manifest can't be read or written.
+ */
+pub async fn record_game(game_id: &str) -> Result<(), Error> {
+    let game_id = game_id.to_string();
+    tokio::task::spawn_blocking(move || {
+        let root = root();
+        let inventory = inventory(&root.join(&game_id))?;
+        update_manifest(&root, |manifest| {
+            manifest.games.insert(game_id, inventory);
+            Ok(())
+        })
+    })
+    .await?
+}
+
+/**
+ * Get whether a game is in the manifest, meaning it's installed and its files are inventoried
+ *
+ * # Errors
+ * This function will return an error if the manifest can't be read.
+ */
+pub fn is_inventoried(game_id: &str) -> Result<bool, Error> {
+    let _guard = MANIFEST_LOCK.lock().unwrap();
+    Ok(Manifest::read(&root())?.is_some_and(|manifest| manifest.games.contains_key(game_id)))
+}
+
+/**
+ * Update a single file in an installed game's inventory, such as after downloading its icon
+ *
+ * # Errors
+ * This function will return an error if the game isn't in the manifest, or the file or the
+ * manifest can't be read or written.
+ */
+pub async fn record_file(game_id: &str, file: &'static str) -> Result<(), Error> {
+    let game_id = game_id.to_string();
+    tokio::task::spawn_blocking(move || {
+        let root = root();
+        let entry = hash_file(&root.join(&game_id).join(file))?;
+        update_manifest(&root, |manifest| {
+            let inventory = manifest
+                .games
+                .get_mut(&game_id)
+                .ok_or_else(|| anyhow!("Game {game_id} isn't in the manifest"))?;
+            inventory.files.insert(file.to_string(), entry);
+            Ok(())
+        })
+    })
+    .await?
+}
+
+/**
+ * Remove a game from the manifest, after its directory has been deleted
+ *
+ * # Errors
+ * This function will return an error if the manifest can't be read or written.
+ */
+pub async fn forget_game(game_id: &str) -> Result<(), Error> {
+    let game_id = game_id.to_string();
+    tokio::task::spawn_blocking(move || {
+        update_manifest(&root(), |manifest| {
+            manifest.games.remove(&game_id);
+            Ok(())
+        })
+    })
+    .await?
+}
+
+/**
+ * Compare a game directory's files against its recorded inventory. Only tracked files are compared.
+ *
+ * # Errors
+ * This function will return an error if a file exists but can't be read.
+ */
+pub fn drift(
+    game_dir: &Path,
+    recorded: &GameInventory,
+) -> Result<BTreeMap<String, FileDrift>, Error> {
+    let current = inventory(game_dir)?;
+    let mut drift = BTreeMap::new();
+    for (file, entry) in &recorded.files {
+        match current.files.get(file) {
+            Some(current) if current.sha256 == entry.sha256 => {}
+            Some(_) => {
+                drift.insert(file.clone(), FileDrift::Modified);
+            }
+            None => {
+                drift.insert(file.clone(), FileDrift::Missing);
+            }
+        }
+    }
+    for file in current.files.keys() {
+        if !recorded.files.contains_key(file) {
+            drift.insert(file.clone(), FileDrift::Added);
+        }
+    }
+    Ok(drift)
+}
+
+/**
+ * Hash every installed game's files and compare them against the manifest, returning the games
+ * that changed since they were recorded. Games that can't be read are logged and skipped.
+ *
+ * # Errors
+ * This function will return an error if the manifest can't be read.
+ */
+pub async fn check_integrity() -> Result<Vec<GameDrift>, Error> {
+    let manifest = manifest().await?;
+    tokio::task::spawn_blocking(move || {
+        let root = root();
+        let mut drifted = Vec::new();
+        for (game_id, recorded) in manifest.games {
+            match drift(&root.join(&game_id), &recorded) {
+                Ok(files) if files.is_empty() => {}
+                Ok(files) => {
+                    log::warn!("Installed files of {game_id} were modified: {files:?}");
+                    drifted.push(GameDrift { game_id, files });
+                }
+                Err(e) => log::warn!("Couldn't check the files of {game_id}: {e}"),
+            }
+        }
+        Ok(drifted)
+    })
+    .await?
+}
+
+/**
+ * Get the current manifest of the devcade directory
+ *
+ * # Errors
+ * This function will return an error if the manifest can't be read.
+ */
+pub async fn manifest() -> Result<Manifest, Error> {
+    tokio::task::spawn_blocking(|| {
+        let _guard = MANIFEST_LOCK.lock().unwrap();
+        Ok(Manifest::read(&root())?.unwrap_or_default())
+    })
+    .await?
+}
+
+/**
+ * Point the devcade directory at a scratch directory shared by the whole test run, so tests that go
+ * through `root` don't touch a real one
+ */
+#[cfg(test)]
+pub(crate) fn test_root() -> PathBuf {
+    static SET: std::sync::Once = std::sync::Once::new();
+    let dir = std::env::temp_dir().join(format!("devcade-root-{}", std::process::id()));
+    SET.call_once(|| {
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_var("DEVCADE_PATH", &dir);
+    });
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_drift() {
+        let dir = std::env::temp_dir().join(format!("devcade-storage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(GAME_JSON), b"{}").unwrap();
+        std::fs::write(dir.join(BUNDLE), b"bundle").unwrap();
+        let recorded = inventory(&dir).unwrap();
+        assert!(drift(&dir, &recorded).unwrap().is_empty());
+
+        std::fs::write(dir.join(GAME_JSON), b"{\"id\":\"x\"}").unwrap();
+        std::fs::remove_file(dir.join(BUNDLE)).unwrap();
+        std::fs::write(dir.join(ICON), b"icon").unwrap();
+        let drift = drift(&dir, &recorded).unwrap();
+        assert_eq!(drift[GAME_JSON], FileDrift::Modified);
+        assert_eq!(drift[BUNDLE], FileDrift::Missing);
+        assert_eq!(drift[ICON], FileDrift::Added);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use crate::events;
+use devcade_onboard_types::schema::{LeaderboardEntry, TickerEvent, TickerItem};
+use devcade_onboard_types::{Event, Player};
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * How many of the most recent items the ticker keeps
+ */
+const MAX_ITEMS: usize = 50;
+
+lazy_static! {
+    static ref ITEMS: Mutex<VecDeque<TickerItem>> = Mutex::new(VecDeque::new());
+}
+
+/**
+ * Get the ticker's most recent items, oldest first
+ */
+#[must_use]
+pub fn items() -> Vec<TickerItem> {
+    ITEMS.lock().unwrap().iter().cloned().collect()
+}
+
+/**
+ * Add a badge read on a player's reader to the ticker. Only the reader is kept, never the badge.
+ */
+pub fn badged_in(player: Player) {
+    push(TickerEvent::BadgedIn(player));
+}
+
+/**
+ * Add a score that took first place on a cabinet's board to the ticker, without who got it
+ */
+pub fn high_score(entry: &LeaderboardEntry) {
+    push(TickerEvent::HighScore {
+        game_id: entry.game_id.clone(),
+        board: entry.board.clone(),
+        score: entry.score,
+    });
+}
+
+/**
+ * Add installed games and unlocked achievements to the ticker as they happen. This never returns,
+ * and should be spawned as a task at startup.
+ */
+pub async fn run() -> ! {
+    let mut events = events::subscribe("ticker");
+    loop {
+        if let Some(event) = ticker_event(events.recv().await) {
+            push(event);
+        }
+    }
+}
+
+/**
+ * Get what the ticker shows for an event, or `None` if it isn't shown. Hidden achievements aren't
+ * named, so the ticker doesn't spoil them.
+ */
+fn ticker_event(event: Event) -> Option<TickerEvent> {
+    match event {
+        Event::GameInstalled(game_id) => Some(TickerEvent::GameInstalled(game_id)),
+        Event::AchievementUnlocked(game_id, achievement) => {
+            Some(TickerEvent::AchievementUnlocked {
+                game_id,
+                name: Some(achievement.name).filter(|_| !achievement.hidden),
+            })
+        }
+        _ => None,
+    }
+}
+
+/**
+ * Add an item to the end of the ticker, dropping the oldest once it's full, and push it to the
+ * frontend
+ */
+fn push(event: TickerEvent) {
+    let item = TickerItem {
+        event,
+        at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+    {
+        let mut items = ITEMS.lock().unwrap();
+        items.push_back(item.clone());
+        if items.len() > MAX_ITEMS {
+            items.pop_front();
+        }
+    }
+    events::emit(Event::Ticker(item));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use devcade_onboard_types::schema::Achievement;
+
+    #[test]
+    fn only_interesting_events_reach_the_ticker() {
+        assert!(matches!(
+            ticker_event(Event::GameInstalled(String::from("pong"))),
+            Some(TickerEvent::GameInstalled(game_id)) if game_id == "pong"
+        ));
+        assert!(ticker_event(Event::GameRemoved(String::from("pong"))).is_none());
+        assert!(ticker_event(Event::GameUpdated(String::from("pong"))).is_none());
+    }
+
+    #[test]
+    fn hidden_achievements_arent_named() {
+        let achievement = Achievement {
+            name: String::from("Secret ending"),
+            ..Achievement::default()
+        };
+        assert!(matches!(
+            ticker_event(Event::AchievementUnlocked(String::from("pong"), achievement.clone())),
+            Some(TickerEvent::AchievementUnlocked { name: Some(name), .. }) if name == "Secret ending"
+        ));
+        let hidden = Achievement {
+            hidden: true,
+            ..achievement
+        };
+        assert!(matches!(
+            ticker_event(Event::AchievementUnlocked(String::from("pong"), hidden)),
+            Some(TickerEvent::AchievementUnlocked { name: None, .. })
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::api;
+use crate::env::{self, UpdateWindow};
+use crate::install_queue;
+use anyhow::Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/**
+ * How often the scheduler checks whether the update window has opened
+ */
+const CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/**
+ * How long the API has to answer before a sweep is skipped
+ */
+const API_TIMEOUT: Duration = Duration::from_secs(10);
+
+/**
+ * Once a night, during `DEVCADE_UPDATE_WINDOW`, check every installed game's hash against the API
+ * and install the games that changed. Returns immediately if there's no window, and should be
+ * spawned as a task at startup.
+ */
+pub async fn run() {
+    let Some(window) = env::update_window() else {
+        return;
+    };
+    // Whether the window that's open now has already been swept
+    let mut swept = false;
+    loop {
+        if !window.contains(local_minute()) {
+            swept = false;
+        } else if !swept {
+            match sweep(&window).await {
+                Ok(()) => swept = true,
+                Err(e) => log::warn!("Couldn't check games for updates: {e}"),
+            }
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+/**
+ * Install updates for every installed game that's out of date, one at a time, until the window
+ * closes. The game that's running is left alone.
+ *
+ * # Errors
+ * This function will return an error if the API can't be reached, or installed games can't be
+ * listed.
+ */
+async fn sweep(window: &UpdateWindow) -> Result<(), Error> {
+    api::api_reachable(API_TIMEOUT).await?;
+    let installed = api::game_list_from_fs().await?.games;
+    log::info!("Checking {} installed games for updates", installed.len());
+
+    let (mut updated, mut failed) = (0, 0);
+    for game in installed {
+        if !window.contains(local_minute()) {
+            log::info!("The update window closed, leaving the remaining games for tomorrow");
+            break;
+        }
+        if api::current_game().is_some_and(|current| current.id == game.id) {
+            continue;
+        }
+        match api::installed_version(game.id.as_str()).await {
+            Ok((latest, None)) if latest.hash.is_some() => {}
+            Ok(_) => continue,
+            Err(e) => {
+                log::warn!("Couldn't check {} for updates: {e}", game.id);
+                failed += 1;
+                continue;
+            }
+        }
+        log::info!("Updating {} in the update window", game.id);
+        match install_queue::install(game.id.clone()).await {
+            Ok(_) => updated += 1,
+            Err(e) => {
+                log::warn!("Couldn't update {}: {e}", game.id);
+                failed += 1;
+            }
+        }
+    }
+    log::info!("Update window: {updated} games updated, {failed} failed");
+    Ok(())
+}
+
+/**
+ * Get the local time of day, in minutes since midnight
+ */
+fn local_minute() -> u32 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        // Without the timezone, UTC is the best guess
+        return (now.rem_euclid(86_400) / 60) as u32;
+    }
+    (tm.tm_hour * 60 + tm.tm_min) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_can_run_past_midnight() {
+        let night: UpdateWindow = "22:30-05:00".parse().unwrap();
+        assert_eq!(night.start, 22 * 60 + 30);
+        assert!(night.contains(23 * 60));
+        assert!(night.contains(60));
+        assert!(!night.contains(5 * 60));
+        assert!(!night.contains(12 * 60));
+
+        let morning: UpdateWindow = "03:00-06:00".parse().unwrap();
+        assert!(morning.contains(4 * 60));
+        assert!(!morning.contains(23 * 60));
+
+        assert!("25:00-06:00".parse::<UpdateWindow>().is_err());
+        assert!("03:00".parse::<UpdateWindow>().is_err());
+    }
+}
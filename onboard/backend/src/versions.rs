@@ -0,0 +1,212 @@
+use crate::atomic;
+use crate::env;
+use crate::i18n::tr;
+use crate::storage::{self, BUNDLE, GAME_JSON, ROLLED_BACK, SIGNATURE, VERSIONS_DIR};
+use anyhow::{anyhow, Error};
+use devcade_onboard_types::schema::DevcadeGame;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/**
+ * Get the directory an earlier version of a game is kept in
+ */
+#[must_use]
+pub fn version_dir(game_id: &str, hash: &str) -> PathBuf {
+    storage::game_dir(game_id).join(VERSIONS_DIR).join(hash)
+}
+
+/**
+ * Hashes come from the API and name a directory, so they can only be used if they can't reach
+ * outside of it
+ */
+fn is_hash_safe(hash: &str) -> bool {
+    !hash.is_empty()
+        && hash
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/**
+ * Keep the installed version of a game before a new one replaces it. Its game.json and signature
+ * are copied and its bundle is hard linked (or copied, if it can't be), since the new bundle is written to a new
+ * file. Versions beyond the `DEVCADE_KEEP_VERSIONS` most recent are removed.
+ *
+ * # Errors
+ * This function will return an error if the version can't be copied.
+ */
+pub async fn archive(game_id: &str) -> Result<(), Error> {
+    let game_dir = storage::game_dir(game_id);
+    let Ok(json) = tokio::fs::read_to_string(game_dir.join(GAME_JSON)).await else {
+        return Ok(());
+    };
+    let game: DevcadeGame = serde_json::from_str(json.as_str())?;
+    let Some(hash) = game.hash.filter(|hash| is_hash_safe(hash)) else {
+        log::debug!("Not keeping the installed version of {game_id}, it has no usable hash");
+        return Ok(());
+    };
+    if env::kept_versions() == 0 || !game_dir.join(BUNDLE).exists() {
+        return Ok(());
+    }
+    let dir = version_dir(game_id, hash.as_str());
+    if dir.join(GAME_JSON).exists() {
+        return Ok(());
+    }
+    log::info!("Keeping version {hash} of {game_id} to roll back to");
+    tokio::fs::create_dir_all(&dir).await?;
+    if tokio::fs::hard_link(game_dir.join(BUNDLE), dir.join(BUNDLE))
+        .await
+        .is_err()
+    {
+        tokio::fs::copy(game_dir.join(BUNDLE), dir.join(BUNDLE)).await?;
+    }
+    if game_dir.join(SIGNATURE).exists() {
+        tokio::fs::copy(game_dir.join(SIGNATURE), dir.join(SIGNATURE)).await?;
+    }
+    // Written last, so a version without its game.json is known to be incomplete
+    atomic::write_async(dir.join(GAME_JSON), json).await?;
+    prune(game_id).await;
+    Ok(())
+}
+
+/**
+ * Get the kept versions of a game, newest first
+ */
+pub async fn list(game_id: &str) -> Vec<DevcadeGame> {
+    let mut versions: Vec<(SystemTime, DevcadeGame)> = Vec::new();
+    let Ok(mut entries) = tokio::fs::read_dir(storage::game_dir(game_id).join(VERSIONS_DIR)).await
+    else {
+        return Vec::new();
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path().join(GAME_JSON);
+        let Ok(json) = tokio::fs::read_to_string(&path).await else {
+            continue;
+        };
+        let (Ok(game), Ok(meta)) = (
+            serde_json::from_str::<DevcadeGame>(json.as_str()),
+            tokio::fs::metadata(&path).await,
+        ) else {
+            log::warn!("Ignoring unreadable kept version at {:?}", path);
+            continue;
+        };
+        versions.push((meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), game));
+    }
+    versions.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    versions.into_iter().map(|(_, game)| game).collect()
+}
+
+/**
+ * Remove a game's oldest kept versions, and any left incomplete by an interrupted `archive`
+ */
+async fn prune(game_id: &str) {
+    let keep: Vec<String> = list(game_id)
+        .await
+        .into_iter()
+        .filter_map(|game| game.hash)
+        .take(env::kept_versions())
+        .collect();
+    let Ok(mut entries) = tokio::fs::read_dir(storage::game_dir(game_id).join(VERSIONS_DIR)).await
+    else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if keep.contains(&name) {
+            continue;
+        }
+        log::info!("Removing kept version {name} of {game_id}");
+        if let Err(e) = tokio::fs::remove_dir_all(entry.path()).await {
+            log::warn!("Couldn't remove kept version {name} of {game_id}: {e}");
+        }
+    }
+}
+
+/**
+ * Get the hash of the version a game was rolled back from, which shouldn't be installed again
+ */
+pub async fn rolled_back_from(game_id: &str) -> Option<String> {
+    let json = tokio::fs::read_to_string(storage::game_file(game_id, ROLLED_BACK))
+        .await
+        .ok()?;
+    serde_json::from_str(json.as_str()).ok()
+}
+
+/**
+ * Remember that a game was rolled back from a version, so it isn't updated straight back to it
+ *
+ * # Errors
+ * This function will return an error if the file can't be written.
+ */
+pub async fn set_rolled_back_from(game_id: &str, hash: &str) -> Result<(), Error> {
+    atomic::write_async(
+        storage::game_file(game_id, ROLLED_BACK),
+        serde_json::to_string(hash)?,
+    )
+    .await
+}
+
+/**
+ * Forget that a game was rolled back, once a newer version has been installed
+ *
+ * # Errors
+ * This function will return an error if the file exists but can't be removed.
+ */
+pub async fn clear_rolled_back(game_id: &str) -> Result<(), Error> {
+    match tokio::fs::remove_file(storage::game_file(game_id, ROLLED_BACK)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/**
+ * Copy a kept version's bundle into its game directory, next to the current bundle. It's moved into
+ * place with `std::fs::rename` once the current version has been archived.
+ *
+ * # Errors
+ * This function will return an error if the version isn't kept or can't be copied.
+ */
+pub async fn stage_bundle(game_id: &str, hash: &str) -> Result<PathBuf, Error> {
+    let kept = version_dir(game_id, hash).join(BUNDLE);
+    if !kept.exists() {
+        return Err(anyhow!(tr(
+            "version_not_kept",
+            &[("game_id", game_id), ("hash", hash)]
+        )));
+    }
+    let staged = storage::game_file(game_id, BUNDLE).with_extension("flatpak.part");
+    tokio::fs::copy(&kept, &staged).await?;
+    Ok(staged)
+}
+
+/**
+ * Put a kept version's signature back in its game directory, or remove the current one if the
+ * version wasn't signed
+ *
+ * # Errors
+ * This function will return an error if the signature can't be copied or removed.
+ */
+pub async fn restore_signature(game_id: &str, hash: &str) -> Result<(), Error> {
+    let kept = version_dir(game_id, hash).join(SIGNATURE);
+    let signature = storage::game_file(game_id, SIGNATURE);
+    if kept.exists() {
+        let json = tokio::fs::read_to_string(&kept).await?;
+        return atomic::write_async(&signature, json).await;
+    }
+    match tokio::fs::remove_file(&signature).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_cant_leave_versions_dir() {
+        assert!(is_hash_safe("3f9a0c"));
+        assert!(!is_hash_safe(""));
+        assert!(!is_hash_safe(".."));
+        assert!(!is_hash_safe("a/b"));
+    }
+}
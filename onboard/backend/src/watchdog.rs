@@ -0,0 +1,137 @@
+use crate::api;
+use crate::env;
+use crate::events;
+use crate::game_logs;
+use crate::session_stats::{self, ProcStat};
+use devcade_onboard_types::Event;
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/**
+ * How often the running game is checked
+ */
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /**
+     * When the running game last pinged the backend, or `None` if it hasn't since it launched
+     */
+    static ref LAST_PING: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/**
+ * Record that the running game pinged the game socket. Games don't have to ping, but once one
+ * does, it's expected to keep pinging at least as often as the hang timeout.
+ */
+pub fn ping() {
+    *LAST_PING.lock().unwrap() = Some(Instant::now());
+}
+
+/**
+ * Watch a game until it exits, stopping it if it hangs so the cabinet returns to the menu. A game
+ * has hung if it pinged the game socket and then stopped pinging for longer than the hang timeout.
+ * Games that never ping are left alone, since an event-driven game waiting on its menu uses no CPU
+ * either. Should be spawned when the game is launched, with the pid of `flatpak run`, and stops by
+ * itself once the game has exited.
+ */
+pub async fn watch(game_id: String, pid: u32) {
+    let Some(timeout) = env::hang_timeout() else {
+        return;
+    };
+    *LAST_PING.lock().unwrap() = None;
+    let mut last_ticks = None;
+    let mut last_progress = Instant::now();
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        let Ok(Some(tree)) =
+            tokio::task::spawn_blocking(move || session_stats::process_tree(pid)).await
+        else {
+            return;
+        };
+        let ticks: u64 = tree.iter().map(|(_, stat)| stat.ticks).sum();
+        if last_ticks != Some(ticks) {
+            last_ticks = Some(ticks);
+            last_progress = Instant::now();
+        }
+        let since_ping = LAST_PING.lock().unwrap().map(|ping| ping.elapsed());
+        if let Some(reason) = hang_reason(since_ping, last_progress.elapsed(), timeout) {
+            stop_hung(game_id, tree, reason).await;
+            return;
+        }
+    }
+}
+
+/**
+ * Describe why a game is considered hung, or `None` if it isn't. Using no CPU only goes into the
+ * description, as a hint to whether the game deadlocked or is stuck in a loop.
+ */
+fn hang_reason(
+    since_ping: Option<Duration>,
+    since_progress: Duration,
+    timeout: Duration,
+) -> Option<String> {
+    let since_ping = since_ping.filter(|since_ping| *since_ping > timeout)?;
+    let mut reason = format!("Stopped pinging {}s ago", since_ping.as_secs());
+    if since_progress > timeout {
+        reason += &format!(" and used no CPU for {}s", since_progress.as_secs());
+    }
+    Some(reason)
+}
+
+/**
+ * Record what a hung game's processes were doing in its logs, then stop it
+ */
+async fn stop_hung(game_id: String, tree: Vec<(u32, ProcStat)>, reason: String) {
+    log::warn!("Game {game_id} hung ({reason}), stopping it");
+    let mut snapshot = vec![format!("Game hung: {reason}")];
+    if let Some(stats) = session_stats::latest() {
+        snapshot.push(format!("Last stats: {stats}"));
+    }
+    for (pid, stat) in tree {
+        let proc_file = |name: &str| {
+            std::fs::read_to_string(format!("/proc/{pid}/{name}"))
+                .map(|value| value.trim().to_string())
+                .unwrap_or_else(|_| String::from("?"))
+        };
+        snapshot.push(format!(
+            "Process {pid} ({}) state {}, waiting in {}",
+            proc_file("comm"),
+            stat.state,
+            proc_file("wchan")
+        ));
+    }
+    for line in &snapshot {
+        log::warn!("{line}");
+        game_logs::note(game_id.as_str(), line).await;
+    }
+
+    events::emit(Event::GameHung(game_id.clone(), reason));
+    if let Err(e) = api::stop_current_game().await {
+        log::warn!("Couldn't stop hung game {game_id}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn games_hang_when_they_stop_pinging() {
+        let timeout = Duration::from_secs(30);
+        let secs = Duration::from_secs;
+        assert_eq!(hang_reason(None, secs(5), timeout), None);
+        assert_eq!(hang_reason(Some(secs(10)), secs(5), timeout), None);
+        // Idle games that never pinged, like one sitting on its menu, aren't hung
+        assert_eq!(hang_reason(None, secs(120), timeout), None);
+        assert_eq!(hang_reason(Some(secs(10)), secs(120), timeout), None);
+        assert_eq!(
+            hang_reason(Some(secs(31)), secs(5), timeout).as_deref(),
+            Some("Stopped pinging 31s ago")
+        );
+        assert_eq!(
+            hang_reason(Some(secs(31)), secs(40), timeout).as_deref(),
+            Some("Stopped pinging 31s ago and used no CPU for 40s")
+        );
+    }
+}
@@ -27,6 +27,7 @@ def main():
     os.chdir("../backend")
     subprocess.run("cargo build -r", shell=True)
     shutil.move("./target/release/backend", f"{out_path}/")
+    shutil.move("./target/release/devcadectl", f"{out_path}/")
     
     os.chdir("..")
     # copy onboard shell script (definitely should add this to git lmao)
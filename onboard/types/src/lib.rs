@@ -57,10 +57,18 @@ pub struct Request {
 #[serde(tag = "type", content = "data")]
 pub enum RequestBody {
     Ping, // Used to check if the backend is alive
+    GetDiagnostics,
+    GetReadiness,
+    GetCabinet,
+    GetBackendLogs,
+    TailLogs(String), // Level to stream logs at or above, only on the control socket
+    ResetCaches,      // Only in safe mode
+    ExitSafeMode,     // Only in safe mode
 
     // --- Onboard backend ---
     GetGameList,
     GetGameListFromFs,
+    GetInstalledGames,
     GetGame(String),        // String is the game ID
     DownloadGame(String),   // String is the game ID
     DownloadIcon(String),   // String is the game ID
@@ -70,24 +78,118 @@ pub enum RequestBody {
     GetTag(String),             // String is the tag name
     GetGameListFromTag(String), // String is the tag name
 
+    GetCollections,
+    GetCollectionGames(String), // String is the collection ID
+
     GetUser(String), // String is the user ID
 
     SetProduction(bool), // Sets prod / dev api url
+    SetLocale(String),   // Sets the language of backend messages and game metadata, e.g. "en"
+    SetStaffMode(bool),  // Shows staging games alongside production ones
+
+    GetAudioSettings,
+    SetMasterVolume(u8),             // Volume from 0 to 100
+    ChangeMasterVolume(i8), // Percentage points to turn the volume up (or down if negative)
+    SetMuteInMenu(bool),    // Mutes sound while the menu is showing
+    SetGameVolumeOffset(String, i8), // Game ID, percentage points louder than the master volume
 
-    LaunchGame(String), // String is the game
+    LaunchGame(String),                      // String is the game
+    LaunchGameWithArgs(String, Vec<String>), // Game ID, arguments passed to the game (e.g. a mode)
     KillGame,
+    StopGame, // Kills the running game, flushes its saves and returns to the menu
+    HoverGame(Option<String>), // String is the hovered game ID, None when nothing is hovered
+    GetGameLogs(String, Option<String>), // Game ID, session (latest if None)
+    GetSessionStats,
+    GetGameTrustInfo(String), // String is the game ID
+    GetRemovalCandidates,
+    UninstallGame(String), // String is the game ID
+    CheckIntegrity,
+    ReinstallGame(String),          // String is the game ID
+    RollbackGame(String),           // String is the game ID
+    FlagGameBroken(String, String), // Game ID, note describing what's wrong
+    ClearBrokenFlag(String),        // String is the game ID
+    GetBrokenGames,
+    GetGpuPreference(String),                // String is the game ID
+    SetGpuPreference(String, GpuPreference), // Game ID, GPU it should render on
+
+    GetQueueStatus,
+    GetInstallReport(String),     // String is the game ID
+    ValidateGame(String),         // String is the game ID
+    ExportBundle(String, String), // Game ID, where to write the bundle
+    MoveInstallJob(u32, usize),   // Job ID, new position in the queue
+    CancelInstallJob(u32),        // Job ID
+    // ---
+
+    // --- Ticker ---
+    GetTicker, // The cabinet's recent activity, for the marquee ticker
     // ---
 
     // --- Persistence ---
     Save(String, String, String), // Group, Key, Value
     Load(String, String),         // Group, Key
     Flush,
+    SaveForUser(Option<String>, String, String, String), // Badge handle (None for guests), Group, Key, Value
+    LoadForUser(Option<String>, String, String), // Badge handle (None for guests), Group, Key
+    GetSaveUsage,
+    ListKeys(String, String),                 // Group, Key prefix
+    Scan(String, String, Option<String>),     // Group, Key prefix, Cursor from the last page
+    Delete(String, String),                   // Group, Key
+    ClearNamespace,                           // Clears all of the running game's save data
+    ClearGameSaves(String, Option<String>),   // Game ID, Badge handle (None for every player)
+    SaveWithTtl(String, String, String, u64), // Group, Key, Value, Seconds until it expires
+    RestoreSaves(String, String),             // Game ID, Badge handle
+    SaveBlob(String, String, u64), // Group, Key, Length of the raw bytes sent after the request
+    LoadBlob(String, String),      // Group, Key
+    WatchKeys(String, String),     // Group, Key prefix (changes are pushed as events)
+    UnwatchKeys(String, String),   // Group, Key prefix
+    GetSaveCacheStats,
+    ListSlots,
+    CreateSlot(String, Option<String>), // Label, Thumbnail (base64 PNG)
+    UpdateSlot(String, String, Option<String>), // Slot ID, Label, Thumbnail (base64 PNG)
+    DeleteSlot(String),                 // Slot ID
+    ListGameSlots(String),              // Game ID
+    DeleteGameSlot(String, String),     // Game ID, Slot ID
+    // ---
+
+    // --- Leaderboards ---
+    SubmitScore(String, Option<String>, i64), // Board, Badge handle (None for guests), Score
+    GetLeaderboard(String, String, u32),      // Game ID, Board, How many of the best scores
+    ListLeaderboards(String),                 // Game ID
+    // ---
+
+    // --- Achievements ---
+    UnlockAchievement(String, Option<String>), // Achievement ID, Badge handle (None for guests)
+    GetAchievements(Option<String>), // The running game's, for a badge handle (None for guests)
+    GetGameAchievements(String, Option<String>), // Game ID, Badge handle from the menu (None for guests)
+    // ---
+
+    // --- Play stats ---
+    GetPopularGames(u32, u32), // How many days back to count plays, How many games
+    RecentlyPlayed(Option<String>, u32), // Badge handle from the menu (None for the whole cabinet), How many games
+    // ---
+
+    // --- Favorites ---
+    Favorite(String, String),   // Game ID, Badge handle from the menu
+    Unfavorite(String, String), // Game ID, Badge handle from the menu
+    ListFavorites(String),      // Badge handle from the menu
+    // ---
+
+    // --- Ratings ---
+    RateGame(String, u8), // Game ID, Stars from 1 to 5, given by the badge tapped most recently
     // ---
 
     // --- Gatekeeper ---
     GetNfcTag(Player), // u8 is the index of the reader. Right now just 0.
-    GetNfcUser(String), // String is the association ID
-                       // ---
+    GetNfcTagInRealm(Player, NfcRealm), // Reads the badge in a realm other than the cabinet's
+    GetNfcUser(String), // String is the association ID or guest ID
+    GetReaderStatus(Player),
+    GetNfcStatus,
+    StartLogin(Player, LoginMethod),
+    FinishLogin(String, Option<String>), // Login ID, the PIN for PIN logins
+    CreateGuest(String),                 // String is the name the guest entered
+    GetGuests,
+    MergeGuest(String, String, MergeConflict), // Guest ID, association ID, what to keep on conflicts
+                                               // ---
 }
 
 impl RequestBody {
@@ -97,8 +199,16 @@ impl RequestBody {
     pub fn variants() -> Vec<Self> {
         vec![
             Self::Ping,
+            Self::GetDiagnostics,
+            Self::GetReadiness,
+            Self::GetCabinet,
+            Self::GetBackendLogs,
+            Self::TailLogs(String::new()),
+            Self::ResetCaches,
+            Self::ExitSafeMode,
             Self::GetGameList,
             Self::GetGameListFromFs,
+            Self::GetInstalledGames,
             Self::GetGame(String::new()),
             Self::DownloadGame(String::new()),
             Self::DownloadIcon(String::new()),
@@ -106,18 +216,97 @@ impl RequestBody {
             Self::GetTagList,
             Self::GetTag(String::new()),
             Self::GetGameListFromTag(String::new()),
+            Self::GetCollections,
+            Self::GetCollectionGames(String::new()),
             Self::SetProduction(false),
+            Self::SetLocale(String::new()),
+            Self::SetStaffMode(false),
+            Self::GetAudioSettings,
+            Self::SetMasterVolume(0),
+            Self::ChangeMasterVolume(0),
+            Self::SetMuteInMenu(false),
+            Self::SetGameVolumeOffset(String::new(), 0),
             Self::LaunchGame(String::new()),
+            Self::LaunchGameWithArgs(String::new(), Vec::new()),
             Self::KillGame,
+            Self::StopGame,
+            Self::HoverGame(None),
+            Self::GetGameLogs(String::new(), None),
+            Self::GetSessionStats,
+            Self::GetGameTrustInfo(String::new()),
+            Self::GetRemovalCandidates,
+            Self::UninstallGame(String::new()),
+            Self::CheckIntegrity,
+            Self::ReinstallGame(String::new()),
+            Self::RollbackGame(String::new()),
+            Self::FlagGameBroken(String::new(), String::new()),
+            Self::ClearBrokenFlag(String::new()),
+            Self::GetBrokenGames,
+            Self::GetGpuPreference(String::new()),
+            Self::SetGpuPreference(String::new(), GpuPreference::Default),
+            Self::GetQueueStatus,
+            Self::GetInstallReport(String::new()),
+            Self::ValidateGame(String::new()),
+            Self::ExportBundle(String::new(), String::new()),
+            Self::MoveInstallJob(0, 0),
+            Self::CancelInstallJob(0),
+            Self::GetTicker,
             Self::Save(String::new(), String::new(), String::new()),
             Self::Load(String::new(), String::new()),
             Self::Flush,
+            Self::SaveForUser(None, String::new(), String::new(), String::new()),
+            Self::LoadForUser(None, String::new(), String::new()),
+            Self::GetSaveUsage,
+            Self::ListKeys(String::new(), String::new()),
+            Self::Scan(String::new(), String::new(), None),
+            Self::Delete(String::new(), String::new()),
+            Self::ClearNamespace,
+            Self::ClearGameSaves(String::new(), None),
+            Self::SaveWithTtl(String::new(), String::new(), String::new(), 0),
+            Self::RestoreSaves(String::new(), String::new()),
+            Self::SaveBlob(String::new(), String::new(), 0),
+            Self::LoadBlob(String::new(), String::new()),
+            Self::WatchKeys(String::new(), String::new()),
+            Self::UnwatchKeys(String::new(), String::new()),
+            Self::GetSaveCacheStats,
+            Self::ListSlots,
+            Self::CreateSlot(String::new(), None),
+            Self::UpdateSlot(String::new(), String::new(), None),
+            Self::DeleteSlot(String::new()),
+            Self::ListGameSlots(String::new()),
+            Self::DeleteGameSlot(String::new(), String::new()),
+            Self::SubmitScore(String::new(), None, 0),
+            Self::GetLeaderboard(String::new(), String::new(), 0),
+            Self::ListLeaderboards(String::new()),
+            Self::UnlockAchievement(String::new(), None),
+            Self::GetAchievements(None),
+            Self::GetGameAchievements(String::new(), None),
+            Self::GetPopularGames(0, 0),
+            Self::RecentlyPlayed(None, 0),
+            Self::Favorite(String::new(), String::new()),
+            Self::Unfavorite(String::new(), String::new()),
+            Self::ListFavorites(String::new()),
+            Self::RateGame(String::new(), 0),
             Self::GetNfcTag(Player::P1),
+            Self::GetNfcTagInRealm(Player::P1, NfcRealm::default()),
             Self::GetNfcUser(String::new()),
+            Self::GetReaderStatus(Player::P1),
+            Self::GetNfcStatus,
+            Self::StartLogin(Player::P1, LoginMethod::Qr),
+            Self::FinishLogin(String::new(), None),
+            Self::CreateGuest(String::new()),
+            Self::GetGuests,
+            Self::MergeGuest(String::new(), String::new(), MergeConflict::default()),
         ]
     }
 }
 
+/**
+ * The request ID used for responses that carry an [`Event`] pushed by the backend, rather than
+ * answering a request.
+ */
+pub const EVENT_REQUEST_ID: u32 = u32::MAX;
+
 /**
  * A response sent by the backend to the frontend.
  */
@@ -135,24 +324,70 @@ pub struct Response {
  */
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
+#[allow(clippy::large_enum_variant)] // Responses are sent once and dropped, boxing wouldn't save much
 pub enum ResponseBody {
     Pong,
+    Diagnostics(Diagnostics),
+    Readiness(Readiness),
+    Cabinet(CabinetIdentity),
+    BackendLogs(Vec<String>),
+    LogLine(String), // One line of a log stream started by TailLogs
 
     Ok,
     Err(String),
 
     GameList(Vec<DevcadeGame>),
     Game(DevcadeGame),
+    InstalledGames(InstalledGames),
+    Ticker(Vec<TickerItem>), // Oldest first
 
     TagList(Vec<Tag>),
     Tag(Tag),
+    Collections(Vec<Collection>),
 
     User(User),
 
     Object(String),
+    QuotaExceeded(SaveUsage), // The save was refused, nothing was written
+    SaveUsage(Vec<SaveUsage>),
+    Keys(Vec<String>),
+    SavePage(SavePage),
+    Blob(u64), // Length of the raw bytes sent after the response
+    SaveCacheStats(SaveCacheStats),
+    Slots(Vec<SaveSlot>),
+    Slot(SaveSlot),
+    ScoreRank(u32), // Where a submitted score ranks on this cabinet, starting at 1
+    Leaderboard(Vec<LeaderboardEntry>),
+    Leaderboards(Vec<String>),
+    Achievements(Vec<AchievementStatus>),
+    PopularGames(Vec<GamePlays>), // Most played first
+    RecentGames(Vec<RecentGame>), // Most recently played first
+    Favorites(Vec<Favorite>),     // Most recently favorited first
 
     NfcTag(Option<String>),
     NfcUser(Map<String, Value>),
+    ReaderStatus(ReaderStatus),
+    NfcStatus(Vec<NfcStatus>), // One for each reader, in player order
+    LoginChallenge(LoginChallenge),
+    Guest(GuestProfile),
+    Guests(Vec<GuestProfile>),
+    GuestMerge(GuestMerge),
+
+    GameLogs(Vec<String>),
+    GameTrustInfo(GameTrustInfo),
+    RemovalCandidates(Vec<RemovalCandidate>),
+    Integrity(Vec<GameDrift>), // Only games whose files changed
+    BrokenGames(Vec<BrokenFlag>),
+    GpuPreference(GpuPreference),
+    SessionStats(Option<SessionStats>), // None if no game is running
+    AudioSettings(AudioSettings),
+
+    QueueStatus(Vec<InstallJob>),
+    InstallReport(InstallReport),
+    BundleValidation(BundleValidation),
+    BundleExported(String), // Path the bundle was written to
+
+    Event(Event),
 
     #[serde(skip)]
     InternalGame(JoinHandle<ExitStatus>),
@@ -171,17 +406,65 @@ impl ResponseBody {
     pub fn variants() -> Vec<Self> {
         vec![
             Self::Pong,
+            Self::Diagnostics(Diagnostics::default()),
+            Self::Readiness(Readiness::default()),
+            Self::Cabinet(CabinetIdentity::default()),
+            Self::BackendLogs(Vec::new()),
+            Self::LogLine(String::new()),
             Self::Ok,
             Self::Err(String::new()),
             Self::GameList(Vec::new()),
             Self::Game(DevcadeGame::default()),
+            Self::InstalledGames(InstalledGames::default()),
+            Self::Ticker(Vec::new()),
             Self::TagList(Vec::new()),
             Self::Tag(Tag::default()),
+            Self::Collections(Vec::new()),
             Self::User(User::default()),
             Self::Object(String::from("")),
+            Self::QuotaExceeded(SaveUsage::default()),
+            Self::SaveUsage(Vec::new()),
+            Self::Keys(Vec::new()),
+            Self::SavePage(SavePage::default()),
+            Self::Blob(0),
+            Self::SaveCacheStats(SaveCacheStats::default()),
+            Self::Slots(Vec::new()),
+            Self::Slot(SaveSlot::default()),
+            Self::ScoreRank(0),
+            Self::Leaderboard(Vec::new()),
+            Self::Leaderboards(Vec::new()),
+            Self::Achievements(Vec::new()),
+            Self::PopularGames(Vec::new()),
+            Self::RecentGames(Vec::new()),
+            Self::Favorites(Vec::new()),
             Self::InternalGame(std::thread::spawn(|| std::process::exit(0))),
             Self::NfcTag(None),
             Self::NfcUser(Map::default()),
+            Self::ReaderStatus(ReaderStatus::Disconnected),
+            Self::NfcStatus(Vec::new()),
+            Self::LoginChallenge(LoginChallenge {
+                id: String::new(),
+                player: Player::P1,
+                method: LoginMethod::Qr,
+                url: None,
+                expires_at: 0,
+            }),
+            Self::Guest(GuestProfile::default()),
+            Self::Guests(Vec::new()),
+            Self::GuestMerge(GuestMerge::default()),
+            Self::GameLogs(Vec::new()),
+            Self::GameTrustInfo(GameTrustInfo::default()),
+            Self::RemovalCandidates(Vec::new()),
+            Self::Integrity(Vec::new()),
+            Self::BrokenGames(Vec::new()),
+            Self::GpuPreference(GpuPreference::Default),
+            Self::SessionStats(None),
+            Self::AudioSettings(AudioSettings::default()),
+            Self::QueueStatus(Vec::new()),
+            Self::InstallReport(InstallReport::default()),
+            Self::BundleValidation(BundleValidation::default()),
+            Self::BundleExported(String::new()),
+            Self::Event(Event::GameUpdated(String::new())),
         ]
     }
 }
@@ -190,8 +473,16 @@ impl Display for RequestBody {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
             Self::Ping => write!(f, "Ping"),
+            Self::GetDiagnostics => write!(f, "Get backend diagnostics"),
+            Self::GetReadiness => write!(f, "Get the startup self-check"),
+            Self::GetCabinet => write!(f, "Get the cabinet's identity"),
+            Self::GetBackendLogs => write!(f, "Get recent backend logs"),
+            Self::TailLogs(level) => write!(f, "Stream backend logs at level {level}"),
+            Self::ResetCaches => write!(f, "Reset backend caches"),
+            Self::ExitSafeMode => write!(f, "Exit safe mode"),
             Self::GetGameList => write!(f, "Get Game List"),
             Self::GetGameListFromFs => write!(f, "Get Game List From Filesystem"),
+            Self::GetInstalledGames => write!(f, "Get installed games and corrupt entries"),
             Self::GetGame(game_id) => {
                 write!(f, "Get Game object with id '{game_id}'")
             }
@@ -207,9 +498,67 @@ impl Display for RequestBody {
             Self::LaunchGame(game_id) => {
                 write!(f, "Launch game with id '{game_id}'")
             }
+            Self::LaunchGameWithArgs(game_id, args) => {
+                write!(f, "Launch game with id '{game_id}' and args {args:?}")
+            }
             Self::KillGame => {
                 write!(f, "Kill currently running game")
             }
+            Self::StopGame => {
+                write!(f, "Stop currently running game and return to the menu")
+            }
+            Self::HoverGame(game_id) => match game_id {
+                Some(game_id) => write!(f, "User is hovering game with id '{game_id}'"),
+                None => write!(f, "User isn't hovering a game"),
+            },
+            Self::GetGameLogs(game_id, session) => match session {
+                Some(session) => write!(f, "Get logs for game '{game_id}' session '{session}'"),
+                None => write!(f, "Get latest logs for game '{game_id}'"),
+            },
+            Self::GetSessionStats => write!(f, "Get resource usage of the running game"),
+            Self::GetGameTrustInfo(game_id) => {
+                write!(f, "Get trust info for game with id '{game_id}'")
+            }
+            Self::GetRemovalCandidates => write!(f, "Get games suggested for removal"),
+            Self::UninstallGame(game_id) => {
+                write!(f, "Uninstall game with id '{game_id}'")
+            }
+            Self::CheckIntegrity => write!(f, "Check installed games for modified files"),
+            Self::ReinstallGame(game_id) => {
+                write!(f, "Reinstall game with id '{game_id}'")
+            }
+            Self::RollbackGame(game_id) => {
+                write!(
+                    f,
+                    "Roll back game with id '{game_id}' to its previous version"
+                )
+            }
+            Self::FlagGameBroken(game_id, note) => {
+                write!(f, "Flag game with id '{game_id}' as broken: {note}")
+            }
+            Self::ClearBrokenFlag(game_id) => {
+                write!(f, "Clear broken flag on game with id '{game_id}'")
+            }
+            Self::GetBrokenGames => write!(f, "Get games flagged as broken"),
+            Self::GetGpuPreference(game_id) => write!(f, "Get GPU preference of game {game_id}"),
+            Self::SetGpuPreference(game_id, gpu) => {
+                write!(f, "Set GPU preference of game {game_id} to {gpu:?}")
+            }
+            Self::GetQueueStatus => write!(f, "Get install queue status"),
+            Self::GetInstallReport(game_id) => {
+                write!(f, "Get install report for game with id '{game_id}'")
+            }
+            Self::ValidateGame(game_id) => {
+                write!(f, "Validate bundle of game with id '{game_id}'")
+            }
+            Self::ExportBundle(game_id, dest) => {
+                write!(f, "Export bundle of game with id '{game_id}' to '{dest}'")
+            }
+            Self::MoveInstallJob(job_id, position) => {
+                write!(f, "Move install job {job_id} to position {position}")
+            }
+            Self::CancelInstallJob(job_id) => write!(f, "Cancel install job {job_id}"),
+            Self::GetTicker => write!(f, "Get the cabinet's recent activity"),
             Self::SetProduction(prod) => {
                 write!(
                     f,
@@ -217,21 +566,242 @@ impl Display for RequestBody {
                     if *prod { "production" } else { "development" }
                 )
             }
+            Self::SetLocale(locale) => write!(f, "Set locale to '{locale}'"),
+            Self::SetStaffMode(staff) => write!(f, "Set staff mode to {staff}"),
+            Self::GetAudioSettings => write!(f, "Get audio settings"),
+            Self::SetMasterVolume(volume) => write!(f, "Set master volume to {volume}"),
+            Self::ChangeMasterVolume(change) => write!(f, "Change master volume by {change}"),
+            Self::SetMuteInMenu(mute) => write!(f, "Set mute in menu to {mute}"),
+            Self::SetGameVolumeOffset(game_id, offset) => {
+                write!(f, "Set volume offset of game {game_id} to {offset}")
+            }
             Self::GetTagList => write!(f, "Get Tag List"),
             Self::GetTag(tag_name) => write!(f, "Get Tag with name '{tag_name}'"),
             Self::GetGameListFromTag(tag_name) => {
                 write!(f, "Get Game List from Tag with name '{tag_name}'")
             }
+            Self::GetCollections => write!(f, "Get curated collections"),
+            Self::GetCollectionGames(collection_id) => {
+                write!(f, "Get games in collection '{collection_id}'")
+            }
             Self::GetUser(uid) => write!(f, "Get User with id '{uid}'"),
             Self::Save(group, key, _value) => write!(f, "Save value to {group}/{key}"),
             Self::Load(group, key) => write!(f, "Load value from {group}/{key}"),
             Self::Flush => write!(f, "Flush cached save data"),
+            Self::SaveForUser(handle, group, key, _value) => write!(
+                f,
+                "Save value to {group}/{key} for {}",
+                handle.as_deref().unwrap_or("guest")
+            ),
+            Self::LoadForUser(handle, group, key) => write!(
+                f,
+                "Load value from {group}/{key} for {}",
+                handle.as_deref().unwrap_or("guest")
+            ),
+            Self::GetSaveUsage => write!(f, "Get how much save data each game has"),
+            Self::GetSaveCacheStats => write!(f, "Get how much save data is waiting to be written"),
+            Self::ListSlots => write!(f, "List the running game's save slots"),
+            Self::CreateSlot(label, _) => write!(f, "Create save slot '{label}'"),
+            Self::UpdateSlot(slot_id, label, _) => {
+                write!(f, "Update save slot {slot_id} to '{label}'")
+            }
+            Self::DeleteSlot(slot_id) => write!(f, "Delete save slot {slot_id}"),
+            Self::ListGameSlots(game_id) => write!(f, "List save slots of game '{game_id}'"),
+            Self::DeleteGameSlot(game_id, slot_id) => {
+                write!(f, "Delete save slot {slot_id} of game '{game_id}'")
+            }
+            Self::SubmitScore(board, handle, score) => write!(
+                f,
+                "Submit score {score} to board '{board}' for {}",
+                handle.as_deref().unwrap_or("guest")
+            ),
+            Self::GetLeaderboard(game_id, board, limit) => {
+                write!(
+                    f,
+                    "Get top {limit} scores of game '{game_id}' board '{board}'"
+                )
+            }
+            Self::ListLeaderboards(game_id) => write!(f, "List leaderboards of game '{game_id}'"),
+            Self::UnlockAchievement(achievement_id, handle) => write!(
+                f,
+                "Unlock achievement '{achievement_id}' for {}",
+                handle.as_deref().unwrap_or("guest")
+            ),
+            Self::GetAchievements(handle) => write!(
+                f,
+                "Get the running game's achievements for {}",
+                handle.as_deref().unwrap_or("guest")
+            ),
+            Self::GetGameAchievements(game_id, handle) => write!(
+                f,
+                "Get achievements of game '{game_id}' for {}",
+                handle.as_deref().unwrap_or("guest")
+            ),
+            Self::GetPopularGames(days, limit) => {
+                write!(
+                    f,
+                    "Get the {limit} most played games of the last {days} days"
+                )
+            }
+            Self::RecentlyPlayed(handle, limit) => write!(
+                f,
+                "Get the {limit} games played most recently by {}",
+                if handle.is_some() {
+                    "a badge"
+                } else {
+                    "anyone"
+                }
+            ),
+            Self::Favorite(game_id, _) => write!(f, "Favorite game '{game_id}'"),
+            Self::Unfavorite(game_id, _) => write!(f, "Unfavorite game '{game_id}'"),
+            Self::ListFavorites(_) => write!(f, "List favorite games"),
+            Self::RateGame(game_id, stars) => write!(f, "Rate game '{game_id}' {stars} stars"),
+            Self::Delete(group, key) => write!(f, "Delete value at {group}/{key}"),
+            Self::ClearNamespace => write!(f, "Clear the running game's save data"),
+            Self::SaveWithTtl(group, key, _value, ttl) => {
+                write!(f, "Save value to {group}/{key} for {ttl}s")
+            }
+            Self::RestoreSaves(game_id, handle) => {
+                write!(
+                    f,
+                    "Restore backed up saves of game '{game_id}' for {handle}"
+                )
+            }
+            Self::SaveBlob(group, key, length) => {
+                write!(f, "Save {length} byte blob to {group}/{key}")
+            }
+            Self::LoadBlob(group, key) => write!(f, "Load blob from {group}/{key}"),
+            Self::WatchKeys(group, prefix) => {
+                write!(f, "Watch keys in {group} starting '{prefix}'")
+            }
+            Self::UnwatchKeys(group, prefix) => {
+                write!(f, "Stop watching keys in {group} starting '{prefix}'")
+            }
+            Self::ClearGameSaves(game_id, handle) => match handle {
+                Some(handle) => write!(f, "Clear save data of game '{game_id}' for {handle}"),
+                None => write!(f, "Clear all save data of game '{game_id}'"),
+            },
+            Self::ListKeys(group, prefix) => write!(f, "List keys in {group} starting '{prefix}'"),
+            Self::Scan(group, prefix, cursor) => write!(
+                f,
+                "Scan values in {group} starting '{prefix}' from {cursor:?}"
+            ),
             Self::GetNfcTag(player) => {
                 write!(f, "Get NFC tags for player '{player}'")
             }
+            Self::GetNfcTagInRealm(player, realm) => {
+                write!(f, "Get NFC tags for player '{player}' in realm {realm:?}")
+            }
             Self::GetNfcUser(association_id) => {
                 write!(f, "Get NFC users for association ID '{association_id}'")
             }
+            Self::GetReaderStatus(player) => write!(f, "Get status of player '{player}' reader"),
+            Self::GetNfcStatus => write!(f, "Get health of the badge readers"),
+            Self::StartLogin(player, method) => {
+                write!(f, "Start {method:?} login for player '{player}'")
+            }
+            Self::FinishLogin(id, _) => write!(f, "Finish login '{id}'"),
+            Self::CreateGuest(name) => write!(f, "Create guest profile named '{name}'"),
+            Self::GetGuests => write!(f, "Get active guest profiles"),
+            Self::MergeGuest(guest_id, association_id, conflict) => write!(
+                f,
+                "Merge guest '{guest_id}' into association ID '{association_id}' ({conflict:?})"
+            ),
+        }
+    }
+}
+
+/**
+ * An event broadcast by the backend. Events aren't responses to any request, they're pushed to
+ * interested clients whenever something happens on the machine.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum Event {
+    GameUpdated(String),   // String is the game ID
+    GameInstalled(String), // String is the game ID
+    GameRemoved(String),   // String is the game ID
+    GameExited(GameSession),
+    InstallProgress(InstallJob),
+    InstallLog(String, String), // Game ID, line flatpak reported while installing it
+    SessionEnding(String, u64), // Game ID, seconds until the game is stopped
+    GameAutoRemoved(RemovalCandidate),
+    GuestMergeOffered(String, Vec<GuestProfile>), // Association ID that badged in, guests to offer
+    FrontendLost(String), // Why the primary frontend's connection was dropped
+    Gap(u64),             // How many events were dropped because the client fell behind
+    GameHung(String, String), // Game ID, why it was considered hung (it's stopped, then exits)
+    SessionStats(SessionStats),
+    ReaderStatus(Player, ReaderStatus), // Sent when a badge reader connects or disconnects
+    BadgeTapped(Player, String),        // Player whose reader it was, handle for the badge
+    AchievementUnlocked(String, Achievement), // Game ID, the achievement, for showing a toast
+    ReadinessChecked(Readiness),        // Sent when the startup self-check finishes
+    AttractMode(Option<String>),        // Demo game now playing, None when input ends attract mode
+    GameCrashed {
+        game_id: String,
+        code: Option<i32>,
+        signal: Option<i32>,
+    },
+    Ticker(TickerItem),      // Something for the marquee ticker happened
+    SaveChanged(SaveChange), // Only sent to connections watching the key
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            Self::GameUpdated(game_id) => write!(f, "Game with id '{game_id}' was updated"),
+            Self::GameInstalled(game_id) => write!(f, "Game with id '{game_id}' was installed"),
+            Self::GameRemoved(game_id) => write!(f, "Game with id '{game_id}' was removed"),
+            Self::GameExited(session) => write!(f, "Game {session}"),
+            Self::SessionStats(stats) => write!(f, "Session stats: {stats}"),
+            Self::GameHung(game_id, reason) => write!(f, "Game '{game_id}' hung: {reason}"),
+            Self::ReaderStatus(player, status) => {
+                write!(f, "Player '{player}' reader is {status:?}")
+            }
+            Self::BadgeTapped(player, _) => write!(f, "Badge tapped on player '{player}' reader"),
+            Self::Ticker(item) => write!(f, "Ticker: {}", item.event),
+            Self::GameCrashed {
+                game_id,
+                code,
+                signal,
+            } => write!(
+                f,
+                "Game with id '{game_id}' crashed (code {code:?}, signal {signal:?})"
+            ),
+            Self::SessionEnding(game_id, secs) => write!(
+                f,
+                "Game with id '{game_id}' will be stopped in {secs}s for reaching the session limit"
+            ),
+            Self::GameAutoRemoved(candidate) => write!(
+                f,
+                "Game with id '{}' was automatically removed: {}",
+                candidate.game_id, candidate.reason
+            ),
+            Self::GuestMergeOffered(association_id, guests) => write!(
+                f,
+                "Association ID '{association_id}' badged in with {} guests to merge",
+                guests.len()
+            ),
+            Self::Gap(missed) => write!(f, "Missed {missed} events"),
+            Self::SaveChanged(change) => write!(f, "Saved value changed: {change}"),
+            Self::AchievementUnlocked(game_id, achievement) => write!(
+                f,
+                "Achievement '{}' of game '{game_id}' unlocked",
+                achievement.name
+            ),
+            Self::FrontendLost(reason) => write!(f, "Lost the primary frontend: {reason}"),
+            Self::ReadinessChecked(readiness) => write!(f, "Startup self-check: {readiness}"),
+            Self::AttractMode(game_id) => match game_id {
+                Some(game_id) => write!(f, "Attract mode is playing game with id '{game_id}'"),
+                None => write!(f, "Attract mode ended"),
+            },
+            Self::InstallLog(game_id, line) => {
+                write!(f, "Installing game with id '{game_id}': {line}")
+            }
+            Self::InstallProgress(job) => write!(
+                f,
+                "Install job {} for game '{}' is {:?} (eta {:?}s)",
+                job.id, job.game_id, job.phase, job.eta_secs
+            ),
         }
     }
 }
@@ -257,6 +827,16 @@ impl Display for ResponseBody {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
             Self::Pong => write!(f, "Pong"),
+            Self::Diagnostics(diagnostics) => write!(
+                f,
+                "Got diagnostics (safe mode {}, {} config problems)",
+                diagnostics.safe_mode,
+                diagnostics.config_problems.len()
+            ),
+            Self::Readiness(readiness) => write!(f, "Got startup self-check: {readiness}"),
+            Self::Cabinet(cabinet) => write!(f, "Got cabinet '{}' ({})", cabinet.name, cabinet.id),
+            Self::BackendLogs(lines) => write!(f, "Got {} lines of backend logs", lines.len()),
+            Self::LogLine(line) => write!(f, "{line}"),
             Self::Ok => write!(f, "Ok"),
             Self::Err(err) => write!(f, "Err: {err}"),
             Self::GameList(games) => {
@@ -265,21 +845,121 @@ impl Display for ResponseBody {
             Self::Game(DevcadeGame { id, .. }) => {
                 write!(f, "Downloaded game with id '{}'", id)
             }
+            Self::InstalledGames(InstalledGames { games, corrupt }) => write!(
+                f,
+                "Got {} installed games and {} corrupt entries",
+                games.len(),
+                corrupt.len()
+            ),
+            Self::Ticker(items) => write!(f, "Got {} ticker items", items.len()),
             Self::InternalGame(_) => write!(f, "Launched game"),
             Self::TagList(tags) => {
                 write!(f, "Got tag list with {} tags", tags.len())
             }
             Self::Tag(Tag { name, .. }) => write!(f, "Got tag with name '{name}'"),
+            Self::Collections(collections) => {
+                write!(f, "Got {} collections", collections.len())
+            }
             Self::User(User { id, .. }) => write!(f, "Got user with id '{id}'"),
             Self::Object(value) => {
-                write!(f, "Got Save data object ({} bytes)", value.bytes().len())
+                write!(f, "Got Save data object ({} bytes)", value.len())
+            }
+            Self::QuotaExceeded(usage) => write!(
+                f,
+                "Game '{}' is out of save space ({} of {} bytes used)",
+                usage.game_id, usage.used_bytes, usage.quota_bytes
+            ),
+            Self::SaveUsage(games) => write!(f, "Got save data usage of {} games", games.len()),
+            Self::Keys(keys) => write!(f, "Got {} save keys", keys.len()),
+            Self::Blob(length) => write!(f, "Got {length} byte blob"),
+            Self::Slots(slots) => write!(f, "Got {} save slots", slots.len()),
+            Self::ScoreRank(rank) => write!(f, "Score ranks #{rank}"),
+            Self::Leaderboard(entries) => write!(f, "Got {} scores", entries.len()),
+            Self::Leaderboards(boards) => write!(f, "Got {} leaderboards", boards.len()),
+            Self::Achievements(achievements) => {
+                write!(f, "Got {} achievements", achievements.len())
             }
+            Self::PopularGames(games) => write!(f, "Got {} popular games", games.len()),
+            Self::RecentGames(games) => write!(f, "Got {} recently played games", games.len()),
+            Self::Favorites(favorites) => write!(f, "Got {} favorite games", favorites.len()),
+            Self::Slot(slot) => write!(f, "Got save slot {} ('{}')", slot.id, slot.label),
+            Self::SaveCacheStats(stats) => write!(
+                f,
+                "Got save cache stats ({} of {} cached groups not written)",
+                stats.dirty_groups, stats.cached_groups
+            ),
+            Self::SavePage(page) => write!(
+                f,
+                "Got {} saved values (last page: {})",
+                page.entries.len(),
+                page.cursor.is_none()
+            ),
             Self::NfcTag(tag_id) => {
                 write!(f, "Got NFC tag ID '{tag_id:?}'")
             }
             Self::NfcUser(user) => {
                 write!(f, "Got NFC user '{:?}'", user["uid"].as_str())
             }
+            Self::ReaderStatus(status) => write!(f, "Reader status: {status:?}"),
+            Self::NfcStatus(readers) => write!(f, "Got health of {} badge readers", readers.len()),
+            Self::LoginChallenge(login) => {
+                write!(f, "Started {:?} login '{}'", login.method, login.id)
+            }
+            Self::Guest(guest) => write!(f, "Got guest profile with id '{}'", guest.id),
+            Self::Guests(guests) => write!(f, "Got {} active guest profiles", guests.len()),
+            Self::GuestMerge(merge) => write!(
+                f,
+                "Merged {} values from guest '{}' ({} conflicts)",
+                merge.moved,
+                merge.guest_id,
+                merge.conflicts.len()
+            ),
+            Self::GameLogs(lines) => write!(f, "Got {} lines of game logs", lines.len()),
+            Self::GameTrustInfo(info) => {
+                write!(f, "Got trust info for game with id '{}'", info.game_id)
+            }
+            Self::RemovalCandidates(candidates) => {
+                write!(f, "Got {} games suggested for removal", candidates.len())
+            }
+            Self::Integrity(drifted) => {
+                write!(
+                    f,
+                    "Found {} installed games with modified files",
+                    drifted.len()
+                )
+            }
+            Self::GpuPreference(gpu) => write!(f, "GPU preference: {gpu:?}"),
+            Self::SessionStats(Some(stats)) => write!(f, "Session stats: {stats}"),
+            Self::SessionStats(None) => write!(f, "No game is running"),
+            Self::AudioSettings(settings) => write!(
+                f,
+                "Got audio settings (master volume {}, {} game offsets)",
+                settings.master_volume,
+                settings.game_offsets.len()
+            ),
+            Self::BrokenGames(flags) => {
+                write!(f, "Got {} games flagged as broken", flags.len())
+            }
+            Self::QueueStatus(jobs) => write!(f, "Got install queue with {} jobs", jobs.len()),
+            Self::InstallReport(report) => write!(
+                f,
+                "Got install report for game with id '{}' ({} lines)",
+                report.game_id,
+                report.lines.len()
+            ),
+            Self::BundleValidation(validation) => write!(
+                f,
+                "Validated bundle of game with id '{}' ({} of {} checks passed)",
+                validation.game_id,
+                validation
+                    .checks
+                    .iter()
+                    .filter(|check| check.passed)
+                    .count(),
+                validation.checks.len()
+            ),
+            Self::BundleExported(path) => write!(f, "Exported bundle to '{path}'"),
+            Self::Event(event) => write!(f, "Event: {event}"),
         }
     }
 }
@@ -1,4 +1,7 @@
+use crate::Player;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
 
 /**
  * A tag from the Devcade API that is associated with a game. Used to categorize games.
@@ -16,6 +19,40 @@ pub struct Tag {
     pub name: String,
 }
 
+/**
+ * A curated collection of games from the Devcade API, like the games committee's "Featured this
+ * week" shelf.
+ */
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Collection {
+    /**
+     * The collection's ID, which uniquely identifies it.
+     */
+    pub id: String,
+
+    /**
+     * The collection's name, as shown on its shelf.
+     */
+    pub name: String,
+
+    /**
+     * The collection's description.
+     */
+    #[serde(default)]
+    pub description: String,
+
+    /**
+     * The IDs of the games in the collection, in the order they're shown.
+     */
+    pub game_ids: Vec<String>,
+
+    /**
+     * Whether the collection is the one featured in the menu right now.
+     */
+    #[serde(default)]
+    pub featured: bool,
+}
+
 /**
  * A user from the Devcade API that is associated with a game. Used to identify the author of a game.
  * The user type is used to determine whether the user is a CSH member or a Google user.
@@ -124,6 +161,300 @@ pub struct DevcadeGame {
 
     /// Flatpak app id for the game
     pub flatpak_app_id: Option<String>,
+
+    /**
+     * Environment variables the game is run with, such as `SDL_VIDEODRIVER` or debug flags.
+     */
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /**
+     * The file the game is started with, relative to the flatpak's `/app`, when the flatpak's own
+     * command isn't the right one. If neither is set or works, the entry point is guessed from the
+     * game's files.
+     */
+    pub entrypoint: Option<String>,
+
+    /**
+     * Sandbox permissions the game needs beyond displaying graphics and reading input. Anything
+     * not listed here is taken away when the game is run.
+     */
+    #[serde(default)]
+    pub permissions: Vec<GamePermission>,
+
+    /**
+     * Which catalog the game came from. Staging games are only shown in staff mode, and should be
+     * badged so they aren't mistaken for published ones.
+     */
+    #[serde(default)]
+    pub channel: GameChannel,
+
+    /**
+     * Which way up the game was made to be played. Landscape games are letterboxed on the
+     * cabinet's portrait screen.
+     */
+    #[serde(default)]
+    pub orientation: GameOrientation,
+
+    /**
+     * The achievements players can unlock in the game.
+     */
+    #[serde(default)]
+    pub achievements: Vec<Achievement>,
+
+    /**
+     * How players have rated the game, or `None` if the ratings couldn't be fetched.
+     */
+    #[serde(default)]
+    pub rating: Option<GameRating>,
+}
+
+/**
+ * How players have rated a game, from every cabinet
+ */
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameRating {
+    /**
+     * The average rating, from 1 to 5 stars, or 0 if nobody has rated the game.
+     */
+    pub average: f32,
+
+    /**
+     * How many players have rated the game.
+     */
+    pub count: u64,
+}
+
+/**
+ * Which way up a game was made to be played
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameOrientation {
+    /**
+     * Taller than it is wide, like the cabinet's screen.
+     */
+    #[default]
+    Portrait,
+
+    /**
+     * Wider than it is tall, usually 16:9.
+     */
+    Landscape,
+}
+
+/**
+ * A catalog games are published in
+ */
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameChannel {
+    /**
+     * Games published to the public menu.
+     */
+    #[default]
+    Production,
+
+    /**
+     * Uploads authors are testing on real hardware before publishing them.
+     */
+    Staging,
+}
+
+/**
+ * A sandbox permission a game can ask for
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamePermission {
+    /**
+     * Access to the network.
+     */
+    Network,
+
+    /**
+     * Playing sound through PulseAudio.
+     */
+    Audio,
+
+    /**
+     * Sharing memory with the host over IPC, which some X11 games need.
+     */
+    Ipc,
+
+    /**
+     * A permission this version of the backend doesn't know about. It's never granted.
+     */
+    #[serde(other)]
+    Unknown,
+}
+
+/**
+ * Whether a badge reader can be used
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReaderStatus {
+    /**
+     * The reader was opened and is ready for badges.
+     */
+    Connected,
+
+    /**
+     * The reader isn't configured, is unplugged or couldn't be opened. The backend keeps trying to
+     * reconnect to it.
+     */
+    #[default]
+    Disconnected,
+}
+
+/**
+ * A way for a player to log in without a badge, for when the reader is flaky
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginMethod {
+    /**
+     * The player scans a QR code and confirms on their phone.
+     */
+    Qr,
+
+    /**
+     * The player enters the login PIN they set up on their account.
+     */
+    Pin,
+}
+
+/**
+ * A login in progress, for the frontend to walk the player through
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LoginChallenge {
+    /**
+     * Identifies the login when finishing it.
+     */
+    pub id: String,
+
+    pub player: Player,
+
+    pub method: LoginMethod,
+
+    /**
+     * What to show as a QR code, for QR logins.
+     */
+    pub url: Option<String>,
+
+    /**
+     * When the login can no longer be finished, in seconds since the epoch.
+     */
+    pub expires_at: u64,
+}
+
+/**
+ * A Gatekeeper realm badges can be read in. Each realm has its own keys, and gives a badge a
+ * different association ID.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NfcRealm {
+    /**
+     * For member projects like the cabinet, and what badges are read in unless asked otherwise.
+     */
+    #[default]
+    MemberProjects,
+
+    /**
+     * For drink credit purchases.
+     */
+    Drink,
+
+    /**
+     * For door locks.
+     */
+    Door,
+}
+
+/**
+ * How a badge reader has been doing, so the frontend can tell players when it's offline instead of
+ * taps silently doing nothing
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NfcStatus {
+    pub player: Player,
+
+    pub status: ReaderStatus,
+
+    /**
+     * The connection string the reader was opened with, if it's connected.
+     */
+    pub device: Option<String>,
+
+    /**
+     * When the reader was last polled for a badge, in seconds since the epoch.
+     */
+    pub last_poll_at: Option<u64>,
+
+    /**
+     * When a badge was last read, in seconds since the epoch.
+     */
+    pub last_tap_at: Option<u64>,
+
+    /**
+     * How many times the reader couldn't be opened since the backend started.
+     */
+    pub open_failures: u32,
+
+    /**
+     * How many times a badge's user couldn't be fetched since the backend started.
+     */
+    pub lookup_failures: u32,
+
+    /**
+     * The last thing that went wrong with the reader.
+     */
+    pub last_error: Option<String>,
+}
+
+impl NfcStatus {
+    /**
+     * The status of a reader that hasn't been opened yet
+     */
+    #[must_use]
+    pub fn new(player: Player) -> Self {
+        Self {
+            player,
+            status: ReaderStatus::Disconnected,
+            device: None,
+            last_poll_at: None,
+            last_tap_at: None,
+            open_failures: 0,
+            lookup_failures: 0,
+            last_error: None,
+        }
+    }
+}
+
+/**
+ * Which GPU a game renders on, for cabinets with hybrid graphics
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GpuPreference {
+    /**
+     * Whatever the cabinet is configured to use by default.
+     */
+    #[default]
+    Default,
+
+    /**
+     * The integrated GPU, which some games only work properly on.
+     */
+    Integrated,
+
+    /**
+     * The discrete GPU, through PRIME render offload.
+     */
+    Discrete,
 }
 
 /**
@@ -142,3 +473,1095 @@ pub struct MinimalGame {
     pub hash: String,
     pub description: String,
 }
+
+/**
+ * A game directory that couldn't be read when scanning the filesystem for installed games
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct CorruptGame {
+    /**
+     * Path to the entry that couldn't be read, usually the game's `game.json` file.
+     */
+    pub path: String,
+
+    /**
+     * Why the entry couldn't be read.
+     */
+    pub error: String,
+}
+
+/**
+ * The result of scanning the filesystem for installed games
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct InstalledGames {
+    /**
+     * Every game with a valid `game.json`.
+     */
+    pub games: Vec<DevcadeGame>,
+
+    /**
+     * Every game directory that couldn't be read or has a malformed `game.json`.
+     */
+    pub corrupt: Vec<CorruptGame>,
+}
+
+/**
+ * The phase an install job is currently in
+ */
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum InstallPhase {
+    /**
+     * The job is waiting for the jobs ahead of it to finish.
+     */
+    #[default]
+    Queued,
+
+    /**
+     * The game's bundle is being downloaded from the API.
+     */
+    Downloading,
+
+    /**
+     * The downloaded bundle is being installed with flatpak.
+     */
+    Installing,
+}
+
+/**
+ * A pending or in-progress game install in the backend's install queue
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct InstallJob {
+    /**
+     * A unique ID for this job, used to reorder or cancel it.
+     */
+    pub id: u32,
+
+    /**
+     * The ID of the game being installed.
+     */
+    pub game_id: String,
+
+    /**
+     * The phase the job is currently in.
+     */
+    pub phase: InstallPhase,
+
+    /**
+     * Estimated seconds until the job finishes, if an estimate is available. Downloads are
+     * estimated from the throughput so far, and installs from how long past installs of similarly
+     * sized bundles took.
+     */
+    pub eta_secs: Option<u64>,
+}
+
+/**
+ * A single run of a game, from launch until it exited
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct GameSession {
+    /**
+     * The ID of the game that was run.
+     */
+    pub game_id: String,
+
+    /**
+     * The session the game's captured output was logged under.
+     */
+    pub log_session: String,
+
+    /**
+     * When the game was launched, in seconds since the unix epoch.
+     */
+    pub started_at: u64,
+
+    /**
+     * How long the game ran for, in milliseconds.
+     */
+    pub duration_ms: u64,
+
+    /**
+     * The game's exit code, if it exited on its own.
+     */
+    pub exit_code: Option<i32>,
+
+    /**
+     * The signal that terminated the game, if it was killed by one.
+     */
+    pub signal: Option<i32>,
+
+    /**
+     * Whether the backend stopped the game for reaching the maximum session length.
+     */
+    pub time_limited: bool,
+
+    /**
+     * Whether the game was stopped or killed from the menu.
+     */
+    pub stopped: bool,
+
+    /**
+     * The handles the game knew the badges tapped while it ran by.
+     */
+    #[serde(default)]
+    pub players: Vec<String>,
+}
+
+impl GameSession {
+    /**
+     * Whether the game quit normally, as opposed to exiting with an error code or being killed by a
+     * signal.
+     */
+    pub fn exited_cleanly(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /**
+     * Whether the game crashed: it didn't quit normally, and wasn't stopped by the backend or from
+     * the menu.
+     */
+    pub fn crashed(&self) -> bool {
+        !self.exited_cleanly() && !self.time_limited && !self.stopped
+    }
+}
+
+impl Display for GameSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let id = &self.game_id;
+        let secs = self.duration_ms / 1000;
+        if self.time_limited {
+            return write!(f, "'{id}' was stopped at the session limit after {secs}s");
+        }
+        if self.stopped {
+            return write!(f, "'{id}' was stopped from the menu after {secs}s");
+        }
+        match (self.exit_code, self.signal) {
+            (Some(code), _) => write!(f, "'{id}' exited with code {code} after {secs}s"),
+            (None, Some(signal)) => write!(f, "'{id}' was killed by signal {signal} after {secs}s"),
+            (None, None) => write!(f, "'{id}' exited after {secs}s"),
+        }
+    }
+}
+
+/**
+ * Who signed a game's bundle, and whether the signing key is trusted by this cabinet
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct GameSignature {
+    /**
+     * The name of whoever signed the bundle.
+     */
+    pub signer: String,
+
+    /**
+     * The ID of the key the bundle was signed with.
+     */
+    pub key_id: String,
+
+    /**
+     * When the bundle was signed, in seconds since the unix epoch.
+     */
+    pub signed_at: u64,
+
+    /**
+     * Whether the key is in the cabinet's trusted set.
+     */
+    pub key_trusted: bool,
+}
+
+/**
+ * What the backend knows about whether a game can be trusted, for display in the frontend
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct GameTrustInfo {
+    /**
+     * The ID of the game.
+     */
+    pub game_id: String,
+
+    /**
+     * The game's signature, or `None` if the game isn't signed.
+     */
+    pub signature: Option<GameSignature>,
+
+    /**
+     * Whether the game is installed.
+     */
+    pub installed: bool,
+
+    /**
+     * Whether the installed copy is the latest published version of the game.
+     */
+    pub installed_matches: bool,
+}
+
+/**
+ * An installed game that's taking up a lot of space without being played, suggested for removal
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct RemovalCandidate {
+    /**
+     * The ID of the game.
+     */
+    pub game_id: String,
+
+    /**
+     * How much space the game's files take up, in bytes.
+     */
+    pub size_bytes: u64,
+
+    /**
+     * When the game was last played on this cabinet, in seconds since the unix epoch, or `None` if
+     * it never has been.
+     */
+    pub last_played: Option<u64>,
+
+    /**
+     * Why the game is suggested for removal, for display to the operator.
+     */
+    pub reason: String,
+}
+
+/**
+ * How an installed game's file differs from what was recorded when the game was installed
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileDrift {
+    /**
+     * The file's contents changed.
+     */
+    Modified,
+
+    /**
+     * The file was recorded, but has since been deleted.
+     */
+    Missing,
+
+    /**
+     * The file exists, but wasn't there when the game was installed.
+     */
+    Added,
+}
+
+/**
+ * An installed game whose files no longer match what was recorded when it was installed
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct GameDrift {
+    /**
+     * The ID of the game.
+     */
+    pub game_id: String,
+
+    /**
+     * Every file that changed, and how.
+     */
+    pub files: BTreeMap<String, FileDrift>,
+}
+
+/**
+ * A game staff have flagged as broken on this cabinet, such as one that needs hardware the cabinet
+ * doesn't have
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct BrokenFlag {
+    /**
+     * The ID of the game.
+     */
+    pub game_id: String,
+
+    /**
+     * What's wrong with the game, as written by whoever flagged it.
+     */
+    pub note: String,
+
+    /**
+     * When the game was flagged, in seconds since the unix epoch.
+     */
+    pub flagged_at: u64,
+}
+
+/**
+ * Something that happened on the cabinet, for the marquee ticker. The ticker is shown to anyone
+ * walking past, so nothing in it says who a player is.
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TickerEvent {
+    GameInstalled(String), // Game ID
+    BadgedIn(Player),      // Player whose reader it was
+    HighScore {
+        game_id: String,
+        board: String,
+        score: i64,
+    },
+    /**
+     * Achievements are the challenges games set players, so this is the ticker's "challenge
+     * completed"
+     */
+    AchievementUnlocked {
+        game_id: String,
+        /**
+         * The achievement's name, or `None` if it's hidden
+         */
+        name: Option<String>,
+    },
+}
+
+impl Display for TickerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GameInstalled(game_id) => write!(f, "Game '{game_id}' was installed"),
+            Self::BadgedIn(player) => write!(f, "Someone badged in on player '{player}' reader"),
+            Self::HighScore {
+                game_id,
+                board,
+                score,
+            } => write!(
+                f,
+                "New high score of {score} on '{game_id}' board '{board}'"
+            ),
+            Self::AchievementUnlocked { game_id, name } => write!(
+                f,
+                "Achievement {} of game '{game_id}' was unlocked",
+                name.as_deref().unwrap_or("(hidden)")
+            ),
+        }
+    }
+}
+
+/**
+ * An item in the marquee ticker's feed
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TickerItem {
+    pub event: TickerEvent,
+
+    /**
+     * When it happened, in seconds since the unix epoch.
+     */
+    pub at: u64,
+}
+
+/**
+ * A game played recently, for "jump back in" tiles in the menu
+ */
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentGame {
+    /**
+     * The ID of the game.
+     */
+    pub game_id: String,
+
+    /**
+     * When the game was last launched, in seconds since the unix epoch.
+     */
+    pub played_at: u64,
+}
+
+/**
+ * A game a badged user marked as a favorite, to show first in the menu when they tap in
+ */
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Favorite {
+    /**
+     * The ID of the game.
+     */
+    pub game_id: String,
+
+    /**
+     * When the game was made a favorite, in seconds since the unix epoch.
+     */
+    pub favorited_at: u64,
+}
+
+/**
+ * A temporary profile for a player without a badge, usable wherever a badged user's association ID
+ * is (for example to key scores or saves on)
+ */
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestProfile {
+    /**
+     * The guest's ID, always starting with `guest-` so it can't be mistaken for a badged user.
+     */
+    pub id: String,
+
+    /**
+     * The name the guest entered, such as their initials.
+     */
+    pub name: String,
+
+    /**
+     * When the profile was created, in seconds since the unix epoch.
+     */
+    pub created_at: u64,
+
+    /**
+     * When the profile expires, in seconds since the unix epoch, or `None` if it only lasts until
+     * the current game exits.
+     */
+    pub expires_at: Option<u64>,
+}
+
+/**
+ * What to keep when a guest and the account they're merged into both have saved data under the same
+ * key
+ */
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeConflict {
+    /**
+     * Keep the account's data and drop the guest's.
+     */
+    #[default]
+    KeepAccount,
+
+    /**
+     * Replace the account's data with the guest's.
+     */
+    KeepGuest,
+}
+
+/**
+ * The result of merging a guest's saved data into a badged user's, also kept as an audit entry
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct GuestMerge {
+    /**
+     * The ID of the guest whose data was merged.
+     */
+    pub guest_id: String,
+
+    /**
+     * The association ID the data was merged into.
+     */
+    pub association_id: String,
+
+    /**
+     * Which data was kept when both had data under the same key.
+     */
+    pub conflict: MergeConflict,
+
+    /**
+     * When the merge happened, in seconds since the unix epoch.
+     */
+    pub merged_at: u64,
+
+    /**
+     * How many saved values were moved to the account.
+     */
+    pub moved: u64,
+
+    /**
+     * Every `group/key` both had data under.
+     */
+    pub conflicts: Vec<String>,
+}
+
+/**
+ * What happened the last time a game's flatpak bundle was installed, kept so failed installs can be
+ * debugged from the cabinet
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct InstallReport {
+    /**
+     * The ID of the game.
+     */
+    pub game_id: String,
+
+    /**
+     * When the install started, in seconds since the unix epoch.
+     */
+    pub started_at: u64,
+
+    /**
+     * When the install finished, in seconds since the unix epoch.
+     */
+    pub finished_at: u64,
+
+    /**
+     * Why the install failed, or `None` if it succeeded.
+     */
+    pub error: Option<String>,
+
+    /**
+     * Every step flatpak reported, in order.
+     */
+    pub lines: Vec<String>,
+}
+
+/**
+ * One check made on a game's bundle while validating it
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct BundleCheck {
+    /**
+     * What was checked, such as `arch` or `permissions`.
+     */
+    pub name: String,
+
+    /**
+     * Whether the bundle passed the check.
+     */
+    pub passed: bool,
+
+    /**
+     * What was found, or why the check failed.
+     */
+    pub detail: String,
+}
+
+/**
+ * The result of checking a game's bundle without installing it, so authors can see why an upload
+ * won't install or run
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct BundleValidation {
+    /**
+     * The ID of the game.
+     */
+    pub game_id: String,
+
+    /**
+     * Every check that was made, in order. Checks that depend on one that failed are skipped.
+     */
+    pub checks: Vec<BundleCheck>,
+}
+
+/**
+ * What the backend knows about its own health, so a cabinet that won't start properly can be
+ * diagnosed on-screen
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Diagnostics {
+    /**
+     * Whether the backend is in safe mode, after failing to start too many times in a row. Only
+     * recovery requests are handled in safe mode.
+     */
+    pub safe_mode: bool,
+
+    /**
+     * How many times in a row the backend has started without staying up.
+     */
+    pub startup_attempts: u32,
+
+    /**
+     * The last panic the backend hit, if it's been recorded.
+     */
+    pub last_panic: Option<String>,
+
+    /**
+     * Problems found with the backend's configuration.
+     */
+    pub config_problems: Vec<String>,
+}
+
+/**
+ * Who a cabinet is, so the API can tell cabinets apart for stats, remote management, and catalogs
+ * for where they are
+ */
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CabinetIdentity {
+    /**
+     * A UUID made when the backend first started on the cabinet, which never changes.
+     */
+    pub id: String,
+
+    /**
+     * What the cabinet is called, like its hostname.
+     */
+    pub name: String,
+
+    /**
+     * Where the cabinet is, like a room, if it's been set.
+     */
+    pub location: Option<String>,
+
+    /**
+     * When the cabinet was last registered with the API, in seconds since the epoch.
+     */
+    pub registered_at: Option<u64>,
+}
+
+/**
+ * How one of the startup self-checks went
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Passed,
+
+    /**
+     * Something is wrong, but the cabinet can still be played, like the API being unreachable.
+     */
+    Warning,
+
+    /**
+     * Something is wrong that stops the cabinet from working, like flatpak not being installed.
+     */
+    Failed,
+}
+
+/**
+ * One of the things checked when the backend starts
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReadinessCheck {
+    /**
+     * What was checked, e.g. `api` or `flatpak`.
+     */
+    pub name: String,
+
+    pub status: CheckStatus,
+
+    /**
+     * What was found, for showing to whoever is fixing the cabinet.
+     */
+    pub detail: String,
+}
+
+/**
+ * Whether the cabinet is set up well enough to run, checked once when the backend starts so
+ * problems are reported up front instead of when a game is first downloaded
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Readiness {
+    /**
+     * Whether every check passed or only warned.
+     */
+    pub ready: bool,
+
+    /**
+     * When the checks finished, in seconds since the epoch, or `None` if they're still running.
+     */
+    pub checked_at: Option<u64>,
+
+    pub checks: Vec<ReadinessCheck>,
+}
+
+impl Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.checked_at.is_none() {
+            return write!(f, "still checking");
+        }
+        let failed = self
+            .checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Failed)
+            .count();
+        let warnings = self
+            .checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Warning)
+            .count();
+        write!(
+            f,
+            "{} ({failed} failed, {warnings} warnings)",
+            if self.ready { "ready" } else { "not ready" }
+        )
+    }
+}
+
+/**
+ * How loud the cabinet is
+ */
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AudioSettings {
+    /**
+     * The volume everything plays at, from 0 to 100.
+     */
+    pub master_volume: u8,
+
+    /**
+     * Whether sound is muted while the menu is showing, so the cabinet is quiet between games.
+     */
+    pub mute_in_menu: bool,
+
+    /**
+     * How much louder or quieter (in percentage points) each game plays than the master volume,
+     * for games that are mixed too loud or too quiet. Games without an offset aren't included.
+     */
+    pub game_offsets: BTreeMap<String, i8>,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master_volume: 50,
+            mute_in_menu: false,
+            game_offsets: BTreeMap::new(),
+        }
+    }
+}
+
+/**
+ * How much of the cabinet the running game is using
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionStats {
+    /**
+     * The running game's ID.
+     */
+    pub game_id: String,
+
+    /**
+     * CPU used by the game's processes since the last sample, where 100 is one core fully busy.
+     */
+    pub cpu_percent: f32,
+
+    /**
+     * Memory used by the game's processes, in bytes.
+     */
+    pub rss_bytes: u64,
+
+    /**
+     * How busy the GPU is, if the driver reports it. This is for the whole GPU, not just the game.
+     */
+    pub gpu_percent: Option<u8>,
+
+    /**
+     * How many processes the game is running, including the sandbox.
+     */
+    pub processes: u32,
+}
+
+impl Display for SessionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' using {:.0}% CPU and {} MiB in {} processes",
+            self.game_id,
+            self.cpu_percent,
+            self.rss_bytes / (1024 * 1024),
+            self.processes
+        )?;
+        match self.gpu_percent {
+            Some(gpu) => write!(f, ", GPU {gpu}% busy"),
+            None => Ok(()),
+        }
+    }
+}
+
+/**
+ * How much save data a game has stored, and how much it's allowed
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct SaveUsage {
+    /**
+     * The game's ID.
+     */
+    pub game_id: String,
+
+    /**
+     * Roughly how many bytes of save data the game has, across every group and player.
+     */
+    pub used_bytes: u64,
+
+    /**
+     * How many bytes of save data the game may store.
+     */
+    pub quota_bytes: u64,
+}
+
+/**
+ * A saved value that changed, sent to connections watching its key
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct SaveChange {
+    /**
+     * The group the value is in, as the watcher named it.
+     */
+    pub group: String,
+
+    /**
+     * The value's key.
+     */
+    pub key: String,
+
+    /**
+     * The new value, or `None` if it was deleted or saved as a blob.
+     */
+    pub value: Option<String>,
+
+    /**
+     * Whether the value was saved as a blob, which has to be loaded with `LoadBlob`.
+     */
+    pub blob: bool,
+
+    /**
+     * Whether the group's values were all deleted or replaced at once, such as when a game's saves
+     * are cleared or restored from a backup. `key` is empty, and any watched values should be
+     * loaded again.
+     */
+    #[serde(default)]
+    pub reset: bool,
+}
+
+impl Display for SaveChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.reset {
+            return write!(f, "{} was reset", self.group);
+        }
+        let change = match (&self.value, self.blob) {
+            (_, true) => "saved as a blob",
+            (Some(_), false) => "saved",
+            (None, false) => "deleted",
+        };
+        write!(f, "{}/{} was {change}", self.group, self.key)
+    }
+}
+
+/**
+ * One page of a game's saved values, from scanning a group
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct SavePage {
+    /**
+     * The values on this page, by key.
+     */
+    pub entries: BTreeMap<String, String>,
+
+    /**
+     * What to scan from for the next page, or `None` if this is the last one.
+     */
+    pub cursor: Option<String>,
+}
+
+/**
+ * What's in the save cache, and how much of it hasn't been written to storage yet
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct SaveCacheStats {
+    /**
+     * How many groups are cached.
+     */
+    pub cached_groups: u64,
+
+    /**
+     * How many values the cached groups hold.
+     */
+    pub cached_values: u64,
+
+    /**
+     * How many cached groups have changed since they were last written.
+     */
+    pub dirty_groups: u64,
+
+    /**
+     * Roughly how many bytes of keys and values the changed groups hold.
+     */
+    pub dirty_bytes: u64,
+
+    /**
+     * How long the oldest change that hasn't been written has been waiting, in seconds, or `None`
+     * if everything is written.
+     */
+    pub oldest_dirty_secs: Option<u64>,
+
+    /**
+     * How many times the cache has been written since the backend started.
+     */
+    pub flushes: u64,
+
+    /**
+     * How many times writing the cache failed since the backend started.
+     */
+    pub failed_flushes: u64,
+
+    /**
+     * When the cache was last written, as a Unix timestamp, or `None` if it hasn't been yet.
+     */
+    pub last_flush: Option<u64>,
+}
+
+/**
+ * A save slot a game keeps a save in, so players can keep more than one
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct SaveSlot {
+    /**
+     * The slot's ID. The game's data for the slot is saved in groups under `slots/<id>/`.
+     */
+    pub id: String,
+
+    /**
+     * What the slot is called on the save screens, like the level or the player's name.
+     */
+    pub label: String,
+
+    /**
+     * When the slot was last saved, as a Unix timestamp.
+     */
+    pub updated_at: u64,
+
+    /**
+     * A picture of the game when the slot was saved, as a base64 PNG, if the game took one.
+     */
+    pub thumbnail: Option<String>,
+}
+
+/**
+ * A score a player got on one of a game's leaderboards
+ */
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    /**
+     * The game's ID.
+     */
+    pub game_id: String,
+
+    /**
+     * The name of the game's board the score is on, like `high-scores`.
+     */
+    pub board: String,
+
+    /**
+     * The handle the game knows the player's badge by, or `None` for guests.
+     */
+    pub handle: Option<String>,
+
+    /**
+     * The name the player is shown by, or empty if they couldn't be looked up or are a guest.
+     */
+    pub name: String,
+
+    /**
+     * The score. Higher scores rank first.
+     */
+    pub score: i64,
+
+    /**
+     * When the score was submitted, as a Unix timestamp.
+     */
+    pub submitted_at: u64,
+}
+
+/**
+ * An achievement a game declares in its metadata, which players unlock by playing
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Achievement {
+    /**
+     * The achievement's ID, which the game unlocks it by.
+     */
+    pub id: String,
+
+    /**
+     * What the achievement is called, as shown in its toast and on the achievements screen.
+     */
+    pub name: String,
+
+    /**
+     * How to unlock the achievement.
+     */
+    #[serde(default)]
+    pub description: String,
+
+    /**
+     * Whether the achievement's name and description are kept secret until it's unlocked.
+     */
+    #[serde(default)]
+    pub hidden: bool,
+
+    /**
+     * A picture for the achievement, as a base64 PNG, if it has one.
+     */
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+/**
+ * An achievement a player unlocked, as uploaded to the API
+ */
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AchievementUnlock {
+    /**
+     * The game's ID.
+     */
+    pub game_id: String,
+
+    /**
+     * The ID of the achievement, as the game declares it.
+     */
+    pub achievement_id: String,
+
+    /**
+     * The handle the game knows the player's badge by.
+     */
+    pub handle: String,
+
+    /**
+     * When the achievement was unlocked, as a Unix timestamp.
+     */
+    pub unlocked_at: u64,
+}
+
+/**
+ * One of a game's achievements, and whether a player has unlocked it
+ */
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct AchievementStatus {
+    pub achievement: Achievement,
+
+    /**
+     * When the player unlocked the achievement, as a Unix timestamp, or `None` if they haven't.
+     */
+    pub unlocked_at: Option<u64>,
+}
+
+/**
+ * How much a game has been played on this cabinet, recently and in total
+ */
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GamePlays {
+    /**
+     * The game's ID.
+     */
+    pub game_id: String,
+
+    /**
+     * How many times the game was launched in the period asked about.
+     */
+    pub plays: u64,
+
+    /**
+     * How long the game was played for in the period asked about, in milliseconds.
+     */
+    pub play_ms: u64,
+
+    /**
+     * How many times the game has ever been launched on this cabinet.
+     */
+    pub total_plays: u64,
+}
+
+/**
+ * A game being played, as uploaded to the API's analytics. Nothing in it identifies the players.
+ */
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayEvent {
+    /**
+     * The game's ID.
+     */
+    pub game_id: String,
+
+    /**
+     * When the game was launched, as a Unix timestamp rounded down to the hour.
+     */
+    pub started_at: u64,
+
+    /**
+     * How long the game was played for, in seconds.
+     */
+    pub duration_secs: u64,
+
+    /**
+     * Whether the game crashed.
+     */
+    pub crashed: bool,
+
+    /**
+     * How many badges were tapped while the game was played.
+     */
+    pub players: u32,
+}